@@ -0,0 +1,35 @@
+//! Time how long the shell takes to launch a trivial program, by feeding it a
+//! script that runs `/bin/true` over and over. Run with `cargo bench`.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+const LAUNCHES: usize = 1000;
+
+fn main() {
+    let script = "/bin/true\n".repeat(LAUNCHES);
+
+    let mut shell = Command::new(env!("CARGO_BIN_EXE_sig-systems-shell"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("failed to start the shell");
+
+    let start = Instant::now();
+    shell
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    let status = shell.wait().unwrap();
+    let elapsed = start.elapsed();
+    assert!(status.success(), "shell exited with {status}");
+
+    println!(
+        "{LAUNCHES} launches of /bin/true in {:.3}s, {:.1}µs each",
+        elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() * 1e6 / LAUNCHES as f64
+    );
+}
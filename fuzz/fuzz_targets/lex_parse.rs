@@ -0,0 +1,24 @@
+//! Feed arbitrary input through the lexer and parser, which have to turn
+//! anything at all into either a command or a parse error. Run with
+//! `cargo fuzz run lex_parse`.
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use libfuzzer_sys::fuzz_target;
+use sig_systems_shell::parser::Command;
+
+fuzz_target!(|data: &[u8]| {
+    // Lines reach the parser as strings, so only valid UTF-8 is interesting
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = Command::parse(input);
+
+    // Aliases are lexed and spliced in as the command is parsed
+    let aliases = BTreeMap::from([
+        ("ll".to_string(), "ls -l |".to_string()),
+        ("g".to_string(), input.to_string()),
+    ]);
+    let _ = Command::parse_with_aliases(format!("g {input}; ll"), &aliases);
+});
@@ -0,0 +1,170 @@
+//! With `set -o activity`, a foreground command that's gone quiet for
+//! `$ACTIVITY_DELAY` seconds, 2 by default, gets a dim line where its output
+//! would go saying what it's doing: using the CPU, waiting, blocked on IO or
+//! stopped. That tells a command that's slow from one that's hung. The line
+//! is taken away as soon as the command writes anything, and when it
+//! finishes.
+//!
+//! What the command is doing is sampled from `/proc`, so elsewhere there's
+//! nothing to show.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::platform;
+use crate::shell::ShellState;
+
+const DEFAULT_DELAY: f64 = 2.0;
+/// How often the command is looked at
+const TICK: Duration = Duration::from_millis(250);
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// The indicator for a job in the foreground, until dropped.
+pub(crate) struct Indicator {
+    done: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Indicator {
+    /// Start watching the job led by `pgid`, if `activity` is set and there's
+    /// a terminal to show it on.
+    pub(crate) fn start(shell: &ShellState, pgid: u32) -> Option<Indicator> {
+        if !shell.options.activity || !io::stderr().is_terminal() {
+            return None;
+        }
+        let delay = shell
+            .variables
+            .get("ACTIVITY_DELAY")
+            .and_then(|seconds| seconds.parse::<f64>().ok())
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+            .unwrap_or(DEFAULT_DELAY);
+        let delay = Duration::from_secs_f64(delay);
+
+        let done = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = thread::spawn({
+            let done = Arc::clone(&done);
+            move || watch(pgid, delay, &done)
+        });
+        Some(Indicator {
+            done,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Indicator {
+    fn drop(&mut self) {
+        let (lock, finished) = &*self.done;
+        *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        finished.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A job's processes taken together, at one moment.
+struct Sample {
+    at: Instant,
+    cpu: Duration,
+    written: u64,
+    state: &'static str,
+}
+
+impl Sample {
+    fn take(pgid: u32) -> Option<Sample> {
+        let processes = platform::job_processes(pgid);
+        if processes.is_empty() {
+            return None;
+        }
+        let any = |state: &str| processes.iter().any(|info| info.state == state);
+        let state = if any("running") {
+            "running"
+        } else if any("blocked") {
+            "blocked on IO"
+        } else if processes.iter().all(|info| info.state == "stopped") {
+            "stopped"
+        } else {
+            "waiting"
+        };
+        Some(Sample {
+            at: Instant::now(),
+            cpu: processes.iter().map(|info| info.cpu).sum(),
+            written: processes.iter().map(|info| info.written).sum(),
+            state,
+        })
+    }
+}
+
+/// Sample the job every tick until `done`, drawing the indicator whenever
+/// it's been quiet for `delay`.
+fn watch(pgid: u32, delay: Duration, done: &(Mutex<bool>, Condvar)) {
+    let (lock, finished) = done;
+    let mut last: Option<Sample> = None;
+    let mut quiet_since = Instant::now();
+    let mut shown = false;
+    let mut frame = 0;
+
+    let mut guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    while !*guard {
+        guard = match finished.wait_timeout(guard, TICK) {
+            Ok((guard, _)) => guard,
+            Err(e) => e.into_inner().0,
+        };
+        if *guard {
+            break;
+        }
+
+        let Some(sample) = Sample::take(pgid) else {
+            continue;
+        };
+        let wrote = last
+            .as_ref()
+            .is_some_and(|last| sample.written != last.written);
+        if wrote {
+            quiet_since = sample.at;
+            // Whatever it wrote went over the start of the line; clear the rest
+            if shown {
+                clear();
+                shown = false;
+            }
+        }
+        if let Some(last) = last
+            .as_ref()
+            .filter(|_| !wrote && quiet_since.elapsed() >= delay)
+        {
+            let wall = sample.at.duration_since(last.at).as_secs_f64();
+            let used = sample.cpu.saturating_sub(last.cpu).as_secs_f64();
+            let text = match sample.state {
+                "running" => {
+                    frame = (frame + 1) % SPINNER.len();
+                    let percent = (100.0 * used / wall.max(f64::EPSILON)).round();
+                    format!("{} running, {percent}% CPU", SPINNER[frame])
+                }
+                state => format!("· {state}"),
+            };
+            draw(&text);
+            shown = true;
+        }
+        last = Some(sample);
+    }
+    if shown {
+        clear();
+    }
+}
+
+/// Show `text` dimmed at the cursor, leaving the cursor where it was.
+fn draw(text: &str) {
+    let width = text.chars().count();
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "\x1b[K\x1b[2m{text}\x1b[0m\x1b[{width}D");
+    let _ = stderr.flush();
+}
+
+fn clear() {
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "\x1b[K");
+    let _ = stderr.flush();
+}
@@ -0,0 +1,442 @@
+//! Integer arithmetic, as in `$(( ))`, `let` and `declare -i`.
+//!
+//! Expressions follow C's operators and precedence. Names refer to shell
+//! variables, whose values are themselves evaluated as expressions, with unset
+//! or empty ones counting as 0.
+
+use std::fmt;
+
+use crate::nesting::{Nested, TooDeep};
+use crate::vars::Variables;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArithError(String);
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, ArithError> {
+    Err(ArithError(message.into()))
+}
+
+impl From<TooDeep> for ArithError {
+    fn from(too_deep: TooDeep) -> Self {
+        ArithError(format!("expression {too_deep}"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+/// Longest first, so `<<=` isn't lexed as `<` `<=`.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", "=", ",",
+];
+
+/// A number as written: decimal, hex with `0x`, octal with `0o`, binary with
+/// `0b`, or `base#digits` in any base from 2 to 64 as in bash.
+fn parse_number(literal: &str) -> Option<i64> {
+    let prefixed = |lower: &str, upper: &str| {
+        literal
+            .strip_prefix(lower)
+            .or_else(|| literal.strip_prefix(upper))
+    };
+    let (base, digits) = if let Some((base, digits)) = literal.split_once('#') {
+        let base = base.parse().ok().filter(|base| (2..=64).contains(base))?;
+        (base, digits)
+    } else if let Some(digits) = prefixed("0x", "0X") {
+        (16, digits)
+    } else if let Some(digits) = prefixed("0o", "0O") {
+        (8, digits)
+    } else if let Some(digits) = prefixed("0b", "0B") {
+        (2, digits)
+    } else {
+        (10, literal)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    digits.chars().try_fold(0i64, |value, c| {
+        value.checked_mul(base)?.checked_add(digit_value(c, base)?)
+    })
+}
+
+/// What a digit is worth in `base`. Past base 36, as in bash, capitals
+/// follow the small letters, then `@` and `_`; up to it, case doesn't matter.
+fn digit_value(c: char, base: i64) -> Option<i64> {
+    let value = match c {
+        '0'..='9' => c as i64 - '0' as i64,
+        'a'..='z' => c as i64 - 'a' as i64 + 10,
+        'A'..='Z' if base <= 36 => c as i64 - 'A' as i64 + 10,
+        'A'..='Z' => c as i64 - 'A' as i64 + 36,
+        '@' => 62,
+        '_' => 63,
+        _ => return None,
+    };
+    (value < base).then_some(value)
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ArithError> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let mut end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            if let Some(digits) = rest[end..].strip_prefix('#') {
+                end += 1 + digits
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '@' || c == '_'))
+                    .unwrap_or(digits.len());
+            }
+            let literal = &rest[..end];
+            match parse_number(literal) {
+                Some(value) => tokens.push(Token::Number(value)),
+                None => return error(format!("{literal}: invalid number")),
+            }
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' {
+                Token::LParen
+            } else {
+                Token::RParen
+            });
+            rest = &rest[1..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return error(format!("{rest}: syntax error: invalid arithmetic operator"));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Name(String),
+    Unary(&'static str, Box<Expr>),
+    /// `++x`, `x--` and friends: the operator, the variable, and whether the
+    /// old value is the result
+    Step(&'static str, String, bool),
+    /// Left-associative operators, applied in turn to the first operand and
+    /// each one after it, so a long sum is evaluated in a loop
+    Chain(Box<Expr>, Vec<(&'static str, Expr)>),
+    /// `**`: the base and the exponent
+    Power(Box<Expr>, Box<Expr>),
+    Assign(&'static str, String, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+/// Binding power of each binary operator; higher binds tighter.
+fn binary_precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 4,
+        "&&" => 5,
+        "|" => 6,
+        "^" => 7,
+        "&" => 8,
+        "==" | "!=" => 9,
+        "<" | ">" | "<=" | ">=" => 10,
+        "<<" | ">>" => 11,
+        "+" | "-" => 12,
+        "*" | "/" | "%" => 13,
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    /// Count a level of nesting while `parse` runs. Only what recurses for
+    /// as long as the input does counts, as chains of operators are read in
+    /// a loop.
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Expr, ArithError>,
+    ) -> Result<Expr, ArithError> {
+        let _nested = Nested::enter()?;
+        parse(self)
+    }
+
+    /// A whole expression: assignments separated by commas.
+    fn parse_expr(&mut self) -> Result<Expr, ArithError> {
+        let first = self.parse_assignment()?;
+        let mut rest = Vec::new();
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            rest.push((",", self.parse_assignment()?));
+        }
+        Ok(chain(first, rest))
+    }
+
+    /// Operators binding at least as tightly as `min_precedence`. Each call
+    /// further down binds tighter, so this only recurses as deep as there
+    /// are precedences.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ArithError> {
+        let first = self.parse_power()?;
+        let mut rest = Vec::new();
+        while let Some(op) = self.peek_op() {
+            let Some(precedence) = binary_precedence(op) else {
+                break;
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            rest.push((op, self.parse_binary(precedence + 1)?));
+        }
+        Ok(chain(first, rest))
+    }
+
+    /// `**`, which binds tightest of the binary operators and is
+    /// right-associative.
+    fn parse_power(&mut self) -> Result<Expr, ArithError> {
+        let base = self.parse_unary()?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let exponent = self.nested(Self::parse_power)?;
+        Ok(Expr::Power(Box::new(base), Box::new(exponent)))
+    }
+
+    /// Assignments and the conditional operator, which bind loosest of all
+    /// apart from the comma.
+    fn parse_assignment(&mut self) -> Result<Expr, ArithError> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if op.ends_with('=') && !matches!(*op, "==" | "!=" | "<=" | ">=") {
+                let (name, op) = (name.clone(), *op);
+                self.pos += 2;
+                let value = self.nested(Self::parse_assignment)?;
+                return Ok(Expr::Assign(op, name, Box::new(value)));
+            }
+        }
+
+        let condition = self.parse_binary(4)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.nested(Self::parse_assignment)?;
+        if self.next() != Some(Token::Op(":")) {
+            return error("syntax error: expected `:'");
+        }
+        let otherwise = self.nested(Self::parse_assignment)?;
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ArithError> {
+        match self.next() {
+            Some(Token::Op(op @ ("++" | "--"))) => match self.next() {
+                Some(Token::Name(name)) => Ok(Expr::Step(op, name, false)),
+                _ => error(format!("syntax error: `{op}' needs a variable")),
+            },
+            Some(Token::Op(op @ ("+" | "-" | "!" | "~"))) => {
+                Ok(Expr::Unary(op, Box::new(self.nested(Self::parse_unary)?)))
+            }
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) => match self.peek_op() {
+                Some(op @ ("++" | "--")) => {
+                    self.pos += 1;
+                    Ok(Expr::Step(op, name, true))
+                }
+                _ => Ok(Expr::Name(name)),
+            },
+            Some(Token::LParen) => {
+                let inner = self.nested(Self::parse_expr)?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => error("syntax error: missing `)'"),
+                }
+            }
+            Some(token) => error(format!("syntax error: unexpected {token:?}")),
+            None => error("syntax error: operand expected"),
+        }
+    }
+}
+
+/// `first` with `rest` applied to it in turn, or just `first`.
+fn chain(first: Expr, rest: Vec<(&'static str, Expr)>) -> Expr {
+    if rest.is_empty() {
+        first
+    } else {
+        Expr::Chain(Box::new(first), rest)
+    }
+}
+
+fn parse(input: &str) -> Result<Expr, ArithError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let expr = parser.parse_expr()?;
+    if let Some(token) = parser.peek() {
+        return error(format!("syntax error: unexpected {token:?}"));
+    }
+    Ok(expr)
+}
+
+fn apply(op: &str, a: i64, b: i64) -> Result<i64, ArithError> {
+    Ok(match op {
+        "+" => a.wrapping_add(b),
+        "-" => a.wrapping_sub(b),
+        "*" => a.wrapping_mul(b),
+        "/" | "%" if b == 0 => return error("division by 0"),
+        "/" => a.wrapping_div(b),
+        "%" => a.wrapping_rem(b),
+        "**" if b < 0 => return error("exponent less than 0"),
+        "**" => a.wrapping_pow(b.min(u32::MAX as i64) as u32),
+        "<<" => a.wrapping_shl(b as u32),
+        ">>" => a.wrapping_shr(b as u32),
+        "&" => a & b,
+        "|" => a | b,
+        "^" => a ^ b,
+        "<" => (a < b) as i64,
+        ">" => (a > b) as i64,
+        "<=" => (a <= b) as i64,
+        ">=" => (a >= b) as i64,
+        "==" => (a == b) as i64,
+        "!=" => (a != b) as i64,
+        "," => b,
+        _ => return error(format!("{op}: unknown operator")),
+    })
+}
+
+struct Evaluator<'a> {
+    vars: &'a mut Variables,
+}
+
+impl Evaluator<'_> {
+    fn value_of(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = self.vars.get(name).unwrap_or("").trim().to_string();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(n) = value.parse() {
+            return Ok(n);
+        }
+        // So `a=a` doesn't recurse forever
+        let _nested = Nested::enter()?;
+        parse(&value).and_then(|expr| self.eval(&expr))
+    }
+
+    fn assign(&mut self, name: &str, value: i64) -> Result<i64, ArithError> {
+        self.vars
+            .set(name, value.to_string())
+            .map_err(|e| ArithError(e.to_string()))?;
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        let _nested = Nested::enter()?;
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Name(name) => self.value_of(name),
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                Ok(match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    _ => value,
+                })
+            }
+            Expr::Step(op, name, postfix) => {
+                let old = self.value_of(name)?;
+                let new = if *op == "++" {
+                    old.wrapping_add(1)
+                } else {
+                    old.wrapping_sub(1)
+                };
+                self.assign(name, new)?;
+                Ok(if *postfix { old } else { new })
+            }
+            Expr::Chain(first, rest) => {
+                let mut value = self.eval(first)?;
+                for (op, operand) in rest {
+                    value = match *op {
+                        "&&" => (value != 0 && self.eval(operand)? != 0) as i64,
+                        "||" => (value != 0 || self.eval(operand)? != 0) as i64,
+                        _ => apply(op, value, self.eval(operand)?)?,
+                    };
+                }
+                Ok(value)
+            }
+            Expr::Power(base, exponent) => {
+                let base = self.eval(base)?;
+                apply("**", base, self.eval(exponent)?)
+            }
+            Expr::Assign(op, name, value) => {
+                let value = self.eval(value)?;
+                let value = match op.strip_suffix('=') {
+                    Some("") | None => value,
+                    Some(op) => apply(op, self.value_of(name)?, value)?,
+                };
+                self.assign(name, value)
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.eval(condition)? != 0 {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+        }
+    }
+}
+
+/// Evaluate `input`, which may assign to variables along the way.
+pub fn eval(input: &str, vars: &mut Variables) -> Result<i64, ArithError> {
+    if input.trim().is_empty() {
+        return Ok(0);
+    }
+    let expr = parse(input)?;
+    Evaluator { vars }.eval(&expr)
+}
@@ -0,0 +1,78 @@
+use std::io;
+
+use super::vars::quote;
+use super::Builtin;
+use crate::shell::ShellState;
+
+pub struct Alias;
+pub struct Unalias;
+
+impl Builtin for Alias {
+    fn name(&self) -> &'static str {
+        "alias"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[name[=value] ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Define aliases, or show them."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() == 1 {
+            for (name, value) in &shell.aliases {
+                println!("alias {}={}", name, quote(value));
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for arg in &args[1..] {
+            match arg.split_once('=') {
+                Some((name, value)) => {
+                    shell.aliases.insert(name.to_string(), value.to_string());
+                }
+                None => match shell.aliases.get(arg) {
+                    Some(value) => println!("alias {}={}", arg, quote(value)),
+                    None => {
+                        eprintln!("alias: {}: not found", arg);
+                        status = 1;
+                    }
+                },
+            }
+        }
+        Ok(status)
+    }
+}
+
+impl Builtin for Unalias {
+    fn name(&self) -> &'static str {
+        "unalias"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-a] name ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove aliases, or all of them with -a."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.get(1).is_some_and(|arg| arg == "-a") {
+            shell.aliases.clear();
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for name in &args[1..] {
+            if shell.aliases.remove(name).is_none() {
+                eprintln!("unalias: {}: not found", name);
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::io;
+
+use super::Builtin;
+use crate::shell::ShellState;
+
+/// `argparse [options] SPEC... -- ARGS...`: parse a script's arguments
+/// against declarative option specs like `h/help` or `o/output=`. Each option
+/// seen sets `_flag_<short>` and `_flag_<long>`, and what's left becomes the
+/// positional parameters.
+pub struct Argparse;
+
+const SYNOPSIS: &str = "[-n name] [-N min] [-X max] [-s] spec... -- [arg ...]";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Takes {
+    Nothing,
+    Value,
+    OptionalValue,
+    /// A value per use, collected into an array.
+    Values,
+}
+
+struct Spec {
+    short: Option<char>,
+    long: Option<String>,
+    takes: Takes,
+}
+
+impl Spec {
+    /// Parse `s`, `long`, or `s/long`, followed by `=`, `=?` or `=+` if it
+    /// takes a value.
+    fn parse(spec: &str) -> Result<Spec, String> {
+        let (names, takes) = if let Some(names) = spec.strip_suffix("=?") {
+            (names, Takes::OptionalValue)
+        } else if let Some(names) = spec.strip_suffix("=+") {
+            (names, Takes::Values)
+        } else if let Some(names) = spec.strip_suffix('=') {
+            (names, Takes::Value)
+        } else {
+            (spec, Takes::Nothing)
+        };
+
+        let valid = |name: &str| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                && !name.starts_with('-')
+        };
+        let (short, long) = match names.split_once('/') {
+            Some((short, long)) => (Some(short), Some(long)),
+            None if names.chars().count() == 1 => (Some(names), None),
+            None => (None, Some(names)),
+        };
+        if short.is_some_and(|short| short.chars().count() != 1 || !valid(short))
+            || long.is_some_and(|long| !valid(long))
+        {
+            return Err(format!("{spec}: invalid option spec"));
+        }
+
+        Ok(Spec {
+            short: short.and_then(|short| short.chars().next()),
+            long: long.map(str::to_string),
+            takes,
+        })
+    }
+
+    fn variables(&self) -> impl Iterator<Item = String> + '_ {
+        let short = self.short.map(|short| format!("_flag_{short}"));
+        let long = self
+            .long
+            .as_ref()
+            .map(|long| format!("_flag_{}", long.replace('-', "_")));
+        short.into_iter().chain(long)
+    }
+
+    fn usage(&self) -> String {
+        let names = match (self.short, &self.long) {
+            (Some(short), Some(long)) => format!("-{short}|--{long}"),
+            (Some(short), None) => format!("-{short}"),
+            (None, Some(long)) => format!("--{long}"),
+            (None, None) => String::new(),
+        };
+        match self.takes {
+            Takes::Nothing => format!("[{names}]"),
+            Takes::Value | Takes::Values => format!("[{names} VALUE]"),
+            Takes::OptionalValue => format!("[{names}[=VALUE]]"),
+        }
+    }
+}
+
+struct Options {
+    name: String,
+    min: Option<usize>,
+    max: Option<usize>,
+    stop_at_nonoption: bool,
+    specs: Vec<Spec>,
+    args: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        name: "argparse".to_string(),
+        min: None,
+        max: None,
+        stop_at_nonoption: false,
+        specs: Vec::new(),
+        args: Vec::new(),
+    };
+
+    let number = |value: Option<&String>, flag: &str| -> Result<usize, String> {
+        value
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("{flag}: invalid number"))
+    };
+
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" => match args.next() {
+                Some(name) => options.name = name.clone(),
+                None => return Err("-n: option requires an argument".to_string()),
+            },
+            "-N" => options.min = Some(number(args.next(), "-N")?),
+            "-X" => options.max = Some(number(args.next(), "-X")?),
+            "-s" => options.stop_at_nonoption = true,
+            "--" => {
+                options.args = args.cloned().collect();
+                return Ok(options);
+            }
+            flag if flag.starts_with('-') => return Err(format!("{flag}: invalid option")),
+            spec => options.specs.push(Spec::parse(spec)?),
+        }
+    }
+    Err("missing -- before the arguments".to_string())
+}
+
+/// What parsing found: the values of each option used, by spec, and the
+/// arguments left over.
+struct Parsed {
+    found: HashMap<usize, Vec<String>>,
+    rest: Vec<String>,
+}
+
+fn parse_args(options: &Options) -> Result<Parsed, String> {
+    let mut parsed = Parsed {
+        found: HashMap::new(),
+        rest: Vec::new(),
+    };
+    let find = |matches: &dyn Fn(&Spec) -> bool, shown: String| {
+        options
+            .specs
+            .iter()
+            .position(matches)
+            .ok_or_else(|| format!("{shown}: unknown option"))
+    };
+
+    let mut args = options.args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.rest.extend(args.by_ref().cloned());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (long, attached) = match long.split_once('=') {
+                Some((long, value)) => (long, Some(value.to_string())),
+                None => (long, None),
+            };
+            let index = find(
+                &|spec| spec.long.as_deref() == Some(long),
+                format!("--{long}"),
+            )?;
+            let value = match (options.specs[index].takes, attached) {
+                (Takes::Nothing, Some(_)) => {
+                    return Err(format!("--{long}: option doesn't take a value"))
+                }
+                (Takes::Nothing, None) => "1".to_string(),
+                (Takes::OptionalValue, value) => value.unwrap_or_default(),
+                (_, Some(value)) => value,
+                (_, None) => args
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("--{long}: option requires a value"))?,
+            };
+            parsed.found.entry(index).or_default().push(value);
+        } else if let Some(letters) = arg.strip_prefix('-').filter(|letters| !letters.is_empty()) {
+            for (i, letter) in letters.char_indices() {
+                let index = find(&|spec| spec.short == Some(letter), format!("-{letter}"))?;
+                let rest = &letters[i + letter.len_utf8()..];
+                let value = match options.specs[index].takes {
+                    Takes::Nothing => "1".to_string(),
+                    Takes::OptionalValue => rest.to_string(),
+                    _ if !rest.is_empty() => rest.to_string(),
+                    _ => args
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| format!("-{letter}: option requires a value"))?,
+                };
+                let takes_rest = options.specs[index].takes != Takes::Nothing;
+                parsed.found.entry(index).or_default().push(value);
+                if takes_rest {
+                    break;
+                }
+            }
+        } else {
+            parsed.rest.push(arg.clone());
+            if options.stop_at_nonoption {
+                parsed.rest.extend(args.by_ref().cloned());
+                break;
+            }
+        }
+    }
+
+    let count = parsed.rest.len();
+    if let Some(min) = options.min.filter(|&min| count < min) {
+        return Err(format!("expected at least {min} arguments, got {count}"));
+    }
+    if let Some(max) = options.max.filter(|&max| count > max) {
+        return Err(format!("expected at most {max} arguments, got {count}"));
+    }
+    Ok(parsed)
+}
+
+impl Builtin for Argparse {
+    fn name(&self) -> &'static str {
+        "argparse"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Parse a script's options from declarative specs. Each option found sets _flag_ variables named after it, and what's left becomes the positional parameters."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let options = match parse_options(args) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("argparse: {}", e);
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        };
+        let parsed = match parse_args(&options) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let usage: Vec<_> = options.specs.iter().map(Spec::usage).collect();
+                eprintln!("{}: {}", options.name, e);
+                eprintln!("usage: {} {}", options.name, usage.join(" "));
+                return Ok(1);
+            }
+        };
+
+        // Start every flag variable afresh, local to the calling function
+        for spec in &options.specs {
+            for name in spec.variables() {
+                shell.variables.make_local(&name)?;
+                shell.variables.unset(&name)?;
+            }
+        }
+        for (index, values) in parsed.found {
+            let spec = &options.specs[index];
+            for name in spec.variables() {
+                match spec.takes {
+                    Takes::Nothing => shell.variables.set(name, values.len().to_string())?,
+                    Takes::Values => shell.variables.set_array(name, values.clone())?,
+                    _ => shell
+                        .variables
+                        .set(name, values.last().cloned().unwrap_or_default())?,
+                }
+            }
+        }
+        shell.variables.set_positional(parsed.rest);
+        Ok(0)
+    }
+}
@@ -0,0 +1,42 @@
+use std::io;
+
+use super::Builtin;
+use crate::arith;
+use crate::shell::ShellState;
+
+/// `let expr...`, for scripts written before `$(( ))`.
+pub struct Let;
+
+impl Builtin for Let {
+    fn name(&self) -> &'static str {
+        "let"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "expr [expr ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluate each arithmetic expression in turn, as in $(( )), returning 0 if \
+         the last one is nonzero and 1 otherwise."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() < 2 {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        let mut value = 0;
+        for expr in &args[1..] {
+            value = match arith::eval(expr, &mut shell.variables) {
+                Ok(value) => value,
+                Err(e) => {
+                    eprintln!("let: {}: {}", expr, e);
+                    return Ok(1);
+                }
+            };
+        }
+        Ok(if value != 0 { 0 } else { 1 })
+    }
+}
@@ -0,0 +1,58 @@
+use std::io;
+
+use super::Builtin;
+use crate::exec;
+use crate::shell::ShellState;
+
+/// `capture [-a] name command...` runs a command as `$(...)` would, putting
+/// its output in a variable rather than on the command line.
+pub struct Capture;
+
+const SYNOPSIS: &str = "[-a] name command [arg ...]";
+
+impl Builtin for Capture {
+    fn name(&self) -> &'static str {
+        "capture"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a command in a subshell and store its output in a variable, without the \
+         trailing newlines, returning the command's status. With -a, each line of the \
+         output becomes an element of an array."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let (lines, args) = match args.get(1).map(String::as_str) {
+            Some("-a") => (true, &args[2..]),
+            _ => (false, &args[1..]),
+        };
+        let [name, command @ ..] = args else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+        if command.is_empty() {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        let (output, status) = exec::capture_args(shell, command.to_vec())?;
+        let output = String::from_utf8_lossy(&output);
+        let output = output.trim_end_matches('\n');
+        if lines {
+            let lines = output.split('\n').map(String::from);
+            let values = if output.is_empty() {
+                Vec::new()
+            } else {
+                lines.collect()
+            };
+            shell.variables.set_array(name.as_str(), values)?;
+        } else {
+            shell.variables.set(name.as_str(), output)?;
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,221 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::Builtin;
+use crate::direnv;
+use crate::shell::ShellState;
+
+/// How many directories `$cdhist` remembers.
+const CD_HISTORY_LEN: usize = 10;
+
+/// The directory `-N` or `+N` stands for: the Nth entry of `$cdhist` or
+/// `$dirstack` respectively, counting from 0.
+fn indexed_dir(shell: &ShellState, arg: &str) -> Option<Result<PathBuf, String>> {
+    let (array, index) = match arg.split_at_checked(1)? {
+        ("-", index) => ("cdhist", index),
+        ("+", index) => ("dirstack", index),
+        _ => return None,
+    };
+    let index: usize = index.parse().ok()?;
+    let dirs = shell.variables.get_array(array).unwrap_or_default();
+    Some(
+        dirs.get(index)
+            .map(PathBuf::from)
+            .ok_or_else(|| format!("{arg}: no such entry in ${array}")),
+    )
+}
+
+/// Change directory, setting `$PWD` and `$OLDPWD`, remembering where we were
+/// in `$cdhist`, and loading or unloading `.sigsh.env` files. Errors are
+/// reported as coming from `name`.
+fn change_dir(shell: &mut ShellState, name: &str, target: &Path) -> io::Result<i32> {
+    let old = std::env::current_dir().ok();
+    if let Err(e) = std::env::set_current_dir(target) {
+        eprintln!("{}: {}: {}", name, target.display(), e);
+        return Ok(1);
+    }
+
+    if let Some(old) = old {
+        let old = old.to_string_lossy().into_owned();
+        let mut history = shell.variables.get_array("cdhist").unwrap_or_default();
+        history.retain(|dir| *dir != old);
+        history.insert(0, old.clone());
+        history.truncate(CD_HISTORY_LEN);
+        shell.variables.set_array("cdhist", history)?;
+        shell.variables.set("OLDPWD", old)?;
+    }
+    if let Ok(cwd) = std::env::current_dir() {
+        shell
+            .variables
+            .set("PWD", cwd.to_string_lossy().into_owned())?;
+    }
+    direnv::update(shell);
+    Ok(0)
+}
+
+pub struct Cd;
+
+impl Builtin for Cd {
+    fn name(&self) -> &'static str {
+        "cd"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[dir | - | -N | +N]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Change the current directory, to $HOME by default or back to $OLDPWD with -. \
+         -N goes to ${cdhist[N]}, one of the last directories left, and +N to \
+         ${dirstack[N]}, one saved by pushd."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let target = match args.get(1).map(String::as_str) {
+            None => match shell.variables.get("HOME") {
+                Some(home) => PathBuf::from(home),
+                None => {
+                    eprintln!("cd: HOME not set");
+                    return Ok(1);
+                }
+            },
+            Some("-") => match shell.variables.get("OLDPWD") {
+                Some(old) => {
+                    println!("{}", old);
+                    PathBuf::from(old)
+                }
+                None => {
+                    eprintln!("cd: OLDPWD not set");
+                    return Ok(1);
+                }
+            },
+            Some(arg) => match indexed_dir(shell, arg) {
+                Some(Ok(dir)) => {
+                    println!("{}", dir.display());
+                    dir
+                }
+                Some(Err(e)) => {
+                    eprintln!("cd: {}", e);
+                    return Ok(1);
+                }
+                None => PathBuf::from(arg),
+            },
+        };
+
+        change_dir(shell, "cd", &target)
+    }
+}
+
+/// Print the current directory and then `$dirstack`, as `dirs` does after
+/// `pushd` and `popd`.
+fn print_stack(shell: &ShellState) {
+    let mut dirs = vec![shell.variables.get("PWD").unwrap_or_default().to_string()];
+    dirs.extend(shell.variables.get_array("dirstack").unwrap_or_default());
+    println!("{}", dirs.join(" "));
+}
+
+pub struct Pushd;
+
+impl Builtin for Pushd {
+    fn name(&self) -> &'static str {
+        "pushd"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[dir]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Change directory, saving the current one at the top of $dirstack. Without a \
+         dir, swap the current directory with the top of the stack."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut stack = shell.variables.get_array("dirstack").unwrap_or_default();
+        let target = match args.get(1) {
+            Some(dir) => PathBuf::from(dir),
+            None if stack.is_empty() => {
+                eprintln!("pushd: no other directory");
+                return Ok(1);
+            }
+            None => PathBuf::from(stack.remove(0)),
+        };
+
+        let cwd = std::env::current_dir()?;
+        let status = change_dir(shell, "pushd", &target)?;
+        if status != 0 {
+            return Ok(status);
+        }
+        stack.insert(0, cwd.to_string_lossy().into_owned());
+        shell.variables.set_array("dirstack", stack)?;
+        print_stack(shell);
+        Ok(0)
+    }
+}
+
+pub struct Popd;
+
+impl Builtin for Popd {
+    fn name(&self) -> &'static str {
+        "popd"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        ""
+    }
+
+    fn description(&self) -> &'static str {
+        "Change to the directory at the top of $dirstack, taking it off the stack."
+    }
+
+    fn run(&self, shell: &mut ShellState, _args: &[String]) -> io::Result<i32> {
+        let mut stack = shell.variables.get_array("dirstack").unwrap_or_default();
+        if stack.is_empty() {
+            eprintln!("popd: directory stack empty");
+            return Ok(1);
+        }
+
+        let target = PathBuf::from(stack.remove(0));
+        let status = change_dir(shell, "popd", &target)?;
+        if status != 0 {
+            return Ok(status);
+        }
+        shell.variables.set_array("dirstack", stack)?;
+        print_stack(shell);
+        Ok(0)
+    }
+}
+
+pub struct Dirs;
+
+impl Builtin for Dirs {
+    fn name(&self) -> &'static str {
+        "dirs"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-v]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Print the current directory followed by $dirstack. With -v, print just \
+         $dirstack, one a line, numbered for cd +N."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        match args.get(1).map(String::as_str) {
+            None => print_stack(shell),
+            Some("-v") => {
+                let stack = shell.variables.get_array("dirstack").unwrap_or_default();
+                for (index, dir) in stack.iter().enumerate() {
+                    println!("{index}\t{dir}");
+                }
+            }
+            Some(_) => {
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,63 @@
+use std::io::{self, BufRead};
+
+use super::{Builtin, INTERRUPTED};
+use crate::editor;
+use crate::shell::ShellState;
+
+/// `choose [-p prompt] [candidate ...]` lets the user pick one of its
+/// arguments, or of the lines of its input, from a menu they can filter by
+/// typing, and prints the one picked. The menu is drawn on the terminal, so
+/// it works in a pipeline or a command substitution.
+pub struct Choose;
+
+const SYNOPSIS: &str = "[-p prompt] [candidate ...]";
+
+impl Builtin for Choose {
+    fn name(&self) -> &'static str {
+        "choose"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Pick one of the arguments, or of the lines read from standard input \
+         without any, from a menu: type to narrow it down, move with the arrow \
+         keys and press Enter to print the one selected. Fails if nothing was \
+         picked."
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let (prompt, args) = match args.get(1).map(String::as_str) {
+            Some("-p") if args.len() > 2 => (args[2].as_str(), &args[3..]),
+            Some("-p") => {
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+            Some("--") => ("> ", &args[2..]),
+            _ => ("> ", &args[1..]),
+        };
+        let candidates = if args.is_empty() {
+            io::stdin().lock().lines().collect::<io::Result<Vec<_>>>()?
+        } else {
+            args.to_vec()
+        };
+        if candidates.is_empty() {
+            return Ok(1);
+        }
+
+        match editor::choose(&candidates, prompt) {
+            Ok(Some(index)) => {
+                println!("{}", candidates[index]);
+                Ok(0)
+            }
+            Ok(None) => Ok(1),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(INTERRUPTED),
+            Err(e) => {
+                eprintln!("choose: {e}");
+                Ok(1)
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::io;
+use std::path::PathBuf;
+
+use super::Builtin;
+use crate::complete::{self, Candidates, CompletionSpec};
+use crate::shell::ShellState;
+
+pub struct Complete;
+
+/// `compgen [-c] [-f] [-d] [-v] [-W wordlist] [prefix]` prints the
+/// candidates of each kind asked for that start with `prefix`, one to a
+/// line, as completing a word would find them.
+pub struct Compgen;
+
+const COMPGEN_SYNOPSIS: &str = "[-c] [-f] [-d] [-v] [-W wordlist] [prefix]";
+
+const SYNOPSIS: &str = "[-p] [-r] [-W wordlist] [-F function [--source file]] [-o option] name...";
+
+impl Builtin for Complete {
+    fn name(&self) -> &'static str {
+        "complete"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Set how the arguments of commands are completed."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut words = None;
+        let mut function = None;
+        let mut script = None;
+        let mut remove = false;
+        let mut names = Vec::new();
+
+        let mut iter = args[1..].iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-p" => (),
+                "-r" => remove = true,
+                "-W" => words = iter.next(),
+                "-F" => function = iter.next(),
+                "--source" => script = iter.next().map(PathBuf::from),
+                // Options like `nospace` and `default` only tweak how bash
+                // inserts the result; accept them so existing specs load
+                "-o" => {
+                    iter.next();
+                }
+                flag if flag.starts_with('-') => {
+                    eprintln!("complete: {flag}: unsupported option");
+                    eprintln!("{}", self.usage());
+                    return Ok(2);
+                }
+                name => names.push(name.to_string()),
+            }
+        }
+
+        if remove {
+            if names.is_empty() {
+                shell.completions.clear();
+            }
+            for name in &names {
+                shell.completions.remove(name);
+            }
+            return Ok(0);
+        }
+
+        let spec = match (words, function) {
+            (Some(words), None) => {
+                CompletionSpec::Words(words.split_whitespace().map(str::to_string).collect())
+            }
+            (None, Some(function)) => CompletionSpec::BashFunction {
+                function: function.clone(),
+                script,
+            },
+            (None, None) => {
+                // No action given: print the matching specs
+                for (name, spec) in &shell.completions {
+                    if names.is_empty() || names.contains(name) {
+                        println!("{}", spec.describe(name));
+                    }
+                }
+                return Ok(0);
+            }
+            (Some(_), Some(_)) => {
+                eprintln!("complete: -W and -F can't be combined");
+                return Ok(2);
+            }
+        };
+
+        if names.is_empty() {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+        for name in names {
+            shell.completions.insert(name, spec.clone());
+        }
+        Ok(0)
+    }
+}
+
+impl Builtin for Compgen {
+    fn name(&self) -> &'static str {
+        "compgen"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        COMPGEN_SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Print the completion candidates starting with a prefix: commands with \
+         -c, files with -f, directories with -d, variables with -v, or the \
+         words of a list with -W. Fails if there are none."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut kinds = Vec::new();
+        let mut prefix = None;
+
+        let mut iter = args[1..].iter();
+        while let Some(arg) = iter.next() {
+            let kind = match arg.as_str() {
+                "-c" => Candidates::Commands,
+                "-f" => Candidates::Files,
+                "-d" => Candidates::Directories,
+                "-v" => Candidates::Variables,
+                "-W" => match iter.next() {
+                    Some(words) => {
+                        Candidates::Words(words.split_whitespace().map(str::to_string).collect())
+                    }
+                    None => {
+                        eprintln!("compgen: -W: option requires an argument");
+                        return Ok(2);
+                    }
+                },
+                "--" => {
+                    prefix = iter.next();
+                    break;
+                }
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    eprintln!("compgen: {flag}: unsupported option");
+                    eprintln!("{}", self.usage());
+                    return Ok(2);
+                }
+                _ if prefix.is_none() => {
+                    prefix = Some(arg);
+                    continue;
+                }
+                _ => {
+                    eprintln!("{}", self.usage());
+                    return Ok(2);
+                }
+            };
+            kinds.push(kind);
+        }
+        if kinds.is_empty() || iter.next().is_some() {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        let prefix = prefix.map_or("", String::as_str);
+        let mut candidates: Vec<String> = kinds
+            .iter()
+            .flat_map(|kind| complete::generate(shell, kind, prefix))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        for candidate in &candidates {
+            println!("{candidate}");
+        }
+        Ok(i32::from(candidates.is_empty()))
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::Builtin;
+use crate::arith;
+use crate::glob;
+use crate::platform;
+use crate::regex::Regex;
+use crate::shell::ShellState;
+
+/// `[[ expr ]]`. Its args arrive as patterns, with quoted glob characters
+/// escaped, since they aren't globbed, and the operand of `=~` as a regex
+/// (see `expand::expand_conditional`).
+pub struct Conditional;
+
+impl Builtin for Conditional {
+    fn name(&self) -> &'static str {
+        "[["
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[!] expr ]]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Test a condition: -n or -z str for whether a string is empty, -e, -f, -d, \
+         -L, -s or -x file for whether a file exists, is a regular file, a directory, \
+         a symlink, non-empty or executable, str == pattern or str != pattern for \
+         glob matching, str < str or str > str for sorting order, str =~ regex, which sets $MATCH to what matched and $match \
+         to its groups, or -eq, -ne, -lt, -le, -gt or -ge between arithmetic \
+         expressions. ! negates it. Its args are expanded before it runs, so join \
+         tests with && between [[ ]]s rather than inside one."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() < 2 || args[args.len() - 1] != "]]" {
+            eprintln!("[[: missing ]]");
+            return Ok(2);
+        }
+
+        match test(shell, &args[1..args.len() - 1]) {
+            Ok(true) => Ok(0),
+            Ok(false) => Ok(1),
+            Err(e) => {
+                eprintln!("[[: {}", e);
+                Ok(2)
+            }
+        }
+    }
+}
+
+fn test(shell: &mut ShellState, words: &[String]) -> Result<bool, String> {
+    match words {
+        [not, rest @ ..] if not == "!" && !rest.is_empty() => test(shell, rest).map(|b| !b),
+        [word] => Ok(!glob::unescape(word).is_empty()),
+        [op, operand] => unary(op, &glob::unescape(operand)),
+        [lhs, op, rhs] => binary(shell, &glob::unescape(lhs), op, rhs),
+        [] => Err("expected an expression".to_string()),
+        _ => Err(format!("{}: too many arguments", words.join(" "))),
+    }
+}
+
+fn unary(op: &str, operand: &str) -> Result<bool, String> {
+    let path = Path::new(operand);
+    Ok(match op {
+        "-n" => !operand.is_empty(),
+        "-z" => operand.is_empty(),
+        "-e" => path.exists(),
+        "-f" => path.is_file(),
+        "-d" => path.is_dir(),
+        "-L" | "-h" => path.is_symlink(),
+        "-s" => fs::metadata(path).is_ok_and(|meta| meta.len() > 0),
+        "-x" => platform::is_executable(path),
+        _ => return Err(format!("{op}: unknown test")),
+    })
+}
+
+/// `lhs op rhs`, where `rhs` is still a pattern, or for `=~` a regex.
+fn binary(shell: &mut ShellState, lhs: &str, op: &str, rhs: &str) -> Result<bool, String> {
+    Ok(match op {
+        "==" | "=" => glob::matches(rhs, lhs),
+        "!=" => !glob::matches(rhs, lhs),
+        "<" => lhs < glob::unescape(rhs).as_str(),
+        ">" => lhs > glob::unescape(rhs).as_str(),
+        "=~" => regex_match(shell, lhs, rhs)?,
+        "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+            let mut number = |expr: &str| {
+                arith::eval(expr, &mut shell.variables).map_err(|e| format!("{expr}: {e}"))
+            };
+            let (lhs, rhs) = (number(lhs)?, number(&glob::unescape(rhs))?);
+            match op {
+                "-eq" => lhs == rhs,
+                "-ne" => lhs != rhs,
+                "-lt" => lhs < rhs,
+                "-le" => lhs <= rhs,
+                "-gt" => lhs > rhs,
+                _ => lhs >= rhs,
+            }
+        }
+        _ => return Err(format!("{op}: unknown operator")),
+    })
+}
+
+/// `text =~ regex`, setting `$MATCH` to the match and `$match` to its
+/// groups, with "" for any that took no part, or unsetting both if there's
+/// no match.
+fn regex_match(shell: &mut ShellState, text: &str, regex: &str) -> Result<bool, String> {
+    let compiled = Regex::new(regex).map_err(|e| format!("{regex}: {e}"))?;
+    let Some(captures) = compiled.captures(text) else {
+        let _ = shell.variables.unset("MATCH");
+        let _ = shell.variables.unset("match");
+        return Ok(false);
+    };
+
+    let mut captures = captures
+        .into_iter()
+        .map(|range| range.map_or_else(String::new, |range| text[range].to_string()));
+    let whole = captures.next().unwrap_or_default();
+    shell
+        .variables
+        .set("MATCH", whole)
+        .map_err(|e| e.to_string())?;
+    shell
+        .variables
+        .set_array("match", captures.collect())
+        .map_err(|e| e.to_string())?;
+    Ok(true)
+}
@@ -0,0 +1,77 @@
+use std::io;
+
+use super::Builtin;
+use crate::shell::ShellState;
+
+pub struct Exit;
+pub struct Return;
+
+/// The status given as `args[1]`, defaulting to the last command's.
+fn status_arg(shell: &ShellState, args: &[String]) -> Result<i32, String> {
+    match args.get(1) {
+        None => Ok(shell.last_status),
+        Some(arg) => arg
+            .parse::<i32>()
+            .map(|status| status & 0xff)
+            .map_err(|_| format!("{}: {}: numeric argument required", args[0], arg)),
+    }
+}
+
+impl Builtin for Exit {
+    fn name(&self) -> &'static str {
+        "exit"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[status]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Exit the shell, with the last command's status by default. With jobs \
+         stopped, this only warns about them unless it's run twice in a row."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let status = status_arg(shell, args).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            2
+        });
+        if !shell.may_exit() {
+            return Ok(1);
+        }
+        shell.exit = Some(status);
+        Ok(status)
+    }
+}
+
+impl Builtin for Return {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[status]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Return from a function."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if shell.call_stack.is_empty() {
+            eprintln!("return: can only return from a function");
+            return Ok(1);
+        }
+
+        match status_arg(shell, args) {
+            Ok(status) => {
+                shell.returning = true;
+                Ok(status)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                Ok(2)
+            }
+        }
+    }
+}
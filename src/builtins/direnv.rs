@@ -0,0 +1,92 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::Builtin;
+use crate::direnv::{self, FILE_NAME};
+use crate::shell::ShellState;
+
+/// The env file an `allow` or `deny` argument means: the file itself, the
+/// one in a directory, or without one, the one that applies here.
+fn env_file(arg: Option<&String>) -> io::Result<PathBuf> {
+    let file = match arg {
+        Some(path) if Path::new(path).is_dir() => Path::new(path).join(FILE_NAME),
+        Some(path) => PathBuf::from(path),
+        None => direnv::find(&env::current_dir()?).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no {FILE_NAME} here"))
+        })?,
+    };
+    file.canonicalize()
+}
+
+pub struct Allow;
+
+impl Builtin for Allow {
+    fn name(&self) -> &'static str {
+        "allow"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[file | dir]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Trust a .sigsh.env file as it is now, by default the one for the current \
+         directory, so that `set -o direnv` loads it, and load it if it applies here."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let file = match env_file(args.get(1)) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("allow: {}", e);
+                return Ok(1);
+            }
+        };
+        if let Err(e) = shell.direnv.allow(&file) {
+            eprintln!("allow: {}: {}", file.display(), e);
+            return Ok(1);
+        }
+        // Load it again even if it's the one loaded, as it may have changed
+        if shell.direnv.loaded() == Some(&file) {
+            direnv::unload(shell);
+        }
+        direnv::update(shell);
+        Ok(0)
+    }
+}
+
+pub struct Deny;
+
+impl Builtin for Deny {
+    fn name(&self) -> &'static str {
+        "deny"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[file | dir]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stop trusting a .sigsh.env file, by default the one for the current directory, \
+         unloading it if it's loaded and no longer asking about it."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let file = match env_file(args.get(1)) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("deny: {}", e);
+                return Ok(1);
+            }
+        };
+        if let Err(e) = shell.direnv.deny(&file) {
+            eprintln!("deny: {}: {}", file.display(), e);
+            return Ok(1);
+        }
+        if shell.direnv.loaded() == Some(&file) {
+            direnv::unload(shell);
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,102 @@
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::timeout::parse_duration;
+use super::{Builtin, INTERRUPTED};
+use crate::exec;
+use crate::platform::InterruptGuard;
+use crate::shell::ShellState;
+
+/// `every [-n count] interval command...` runs a command over and over, like
+/// `watch`, clearing the screen before each run, until Ctrl-C or the command
+/// is interrupted.
+pub struct Every;
+
+const SYNOPSIS: &str = "[-n count] interval command [arg ...]";
+
+/// How often to check for Ctrl-C between runs.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+impl Builtin for Every {
+    fn name(&self) -> &'static str {
+        "every"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a command over and over, clearing the screen each time, until Ctrl-C."
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut args = &args[1..];
+        let mut count = None;
+        if args.first().is_some_and(|arg| arg == "-n") {
+            match args.get(1).and_then(|count| count.parse::<usize>().ok()) {
+                Some(n) => count = Some(n),
+                None => {
+                    eprintln!("every: -n: invalid count");
+                    return Ok(2);
+                }
+            }
+            args = &args[2..];
+        }
+
+        let Some((interval, command)) = args
+            .split_first()
+            .filter(|(_, command)| !command.is_empty())
+        else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+        let Some(interval) = parse_duration(interval) else {
+            eprintln!("every: {}: invalid interval", interval);
+            return Ok(2);
+        };
+
+        let clear = io::stdout().is_terminal();
+        let guard = InterruptGuard::new();
+        let mut runs = 0;
+
+        loop {
+            if clear {
+                let mut stdout = io::stdout();
+                write!(
+                    stdout,
+                    "\x1b[H\x1b[2JEvery {:?}: {}\n\n",
+                    interval,
+                    command.join(" ")
+                )?;
+                stdout.flush()?;
+            }
+
+            let status = exec::run_args(shell, command.to_vec()).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                1
+            });
+            runs += 1;
+            if status == INTERRUPTED
+                || guard.interrupted()
+                || shell.exit.is_some()
+                || count == Some(runs)
+            {
+                return Ok(status);
+            }
+
+            let next = Instant::now() + interval;
+            while !guard.interrupted() && Instant::now() < next {
+                thread::sleep(POLL_INTERVAL.min(next - Instant::now()));
+            }
+            if guard.interrupted() {
+                return Ok(status);
+            }
+        }
+    }
+}
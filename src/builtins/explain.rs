@@ -0,0 +1,324 @@
+use std::io;
+
+use super::Builtin;
+use crate::builtins;
+use crate::exec::{quote_word, redirect_operator};
+use crate::expand;
+use crate::lexer::{Lexer, Token, WordPart};
+use crate::parser::{AndThen, Arg, Command, Compound, FileRedir, RedirType};
+use crate::platform;
+use crate::shell::ShellState;
+
+/// `explain line` shows how the shell reads a command line, and what running
+/// it would do without running any of it: the tokens, the pipelines they're
+/// parsed into, and for each command its expanded words, what runs them and
+/// where its streams go.
+pub struct Explain;
+
+const SYNOPSIS: &str = "line";
+
+impl Builtin for Explain {
+    fn name(&self) -> &'static str {
+        "explain"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Show how a command line is read and what running it would do, without running \
+         it: its tokens, the pipelines they make up, and each command's words as they'd \
+         expand now, what would run it and where its input and output would go. Words \
+         with a command substitution aren't expanded, since that would run it."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let line = args[1..].join(" ");
+        if line.trim().is_empty() {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        println!("tokens:");
+        for token in Lexer::new(&line) {
+            match token {
+                Ok(token) => {
+                    let (kind, text) = describe_token(&token);
+                    println!("  {kind:<14}{text}");
+                }
+                Err(e) => {
+                    eprintln!("explain: {e}");
+                    return Ok(2);
+                }
+            }
+        }
+
+        let command = match Command::parse_with_aliases(&line, &shell.aliases) {
+            Ok(command) => command,
+            Err(e) => {
+                eprintln!("explain: {e}");
+                return Ok(2);
+            }
+        };
+        let pipelines = split_pipelines(&command);
+
+        println!("parsed:");
+        for (i, (stages, after)) in pipelines.iter().enumerate() {
+            println!("  pipeline {}{}:", i + 1, describe_link(i, *after));
+            for (j, stage) in stages.iter().enumerate() {
+                let before = j.checked_sub(1).map(|prev| stages[prev]);
+                let fed = match before.and_then(|prev| prev.pipe_to.as_ref()) {
+                    Some(pipe) if pipe.pipe_type == RedirType::Both => {
+                        format!(", fed the stdout and stderr of stage {j}")
+                    }
+                    Some(_) => format!(", fed the stdout of stage {j}"),
+                    None => String::new(),
+                };
+                println!("    stage {}: {}{fed}", j + 1, alone(stage));
+            }
+        }
+
+        // Expanding can assign, as `$((i++))` does, so it's all undone after
+        println!("expanded, as things stand now:");
+        let snapshot = shell.snapshot()?;
+        let res = pipelines
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, (stages, _))| explain_pipeline(shell, i, stages));
+        shell.restore(snapshot)?;
+        res.map(|_| 0)
+    }
+}
+
+/// What kind of token this is, and how it was written.
+fn describe_token(token: &Token) -> (&'static str, String) {
+    match token {
+        Token::Word(word) => ("word", quote_word(word)),
+        Token::Glob(pattern) => ("pattern", pattern.clone()),
+        Token::SubShell(command) => ("substitution", format!("$({command})")),
+        Token::Variable(name) => ("variable", format!("${{{name}}}")),
+        Token::Parts(parts) => ("word", parts.iter().map(describe_part).collect()),
+        Token::Parens(inner) => ("parens", format!("({inner})")),
+        Token::Pipe => ("pipe", "|".to_string()),
+        Token::PipeBoth => ("pipe", "|&".to_string()),
+        Token::AndThen => ("separator", ";".to_string()),
+        Token::AndThenIf => ("separator", "&&".to_string()),
+        Token::RedirOut => ("redirect", ">".to_string()),
+        Token::RedirErr => ("redirect", "2>".to_string()),
+        Token::RedirBoth => ("redirect", "&>".to_string()),
+        Token::AppendOut => ("redirect", ">>".to_string()),
+        Token::AppendErr => ("redirect", "2>>".to_string()),
+        Token::AppendBoth => ("redirect", "&>>".to_string()),
+        Token::ClobberOut => ("redirect", ">|".to_string()),
+        Token::ClobberErr => ("redirect", "2>|".to_string()),
+        Token::RedirIn => ("redirect", "<".to_string()),
+        Token::FdIn(fd) => ("redirect", format!("{fd}<")),
+        Token::FdOut(fd) => ("redirect", format!("{fd}>")),
+        Token::FdAppend(fd) => ("redirect", format!("{fd}>>")),
+        Token::FdClose(fd) => ("redirect", format!("{fd}<&-")),
+    }
+}
+
+fn describe_part(part: &WordPart) -> String {
+    match part {
+        WordPart::Literal(text) if text.is_empty() => String::new(),
+        WordPart::Literal(text) => quote_word(text),
+        WordPart::Pattern(pattern) => pattern.clone(),
+        WordPart::Variable(name) => format!("${{{name}}}"),
+        WordPart::SubShell(command) => format!("$({command})"),
+        WordPart::Arith(expr) => format!("$(({expr}))"),
+        WordPart::Quoted(parts) => {
+            let inner: String = parts.iter().map(describe_part).collect();
+            format!("\"{inner}\"")
+        }
+    }
+}
+
+/// The pipelines chained in `command`, each with the link to the one
+/// before it.
+fn split_pipelines(command: &Command) -> Vec<(Vec<&Command>, Option<&AndThen>)> {
+    let mut pipelines = Vec::new();
+    let mut next = Some((command, None));
+    while let Some((first, after)) = next {
+        let mut stages = vec![first];
+        while let Some(pipe) = &stages[stages.len() - 1].pipe_to {
+            stages.push(&pipe.target);
+        }
+        // What comes after a pipeline hangs off its last command
+        let link = stages[stages.len() - 1].and_then.as_ref();
+        next = link.map(|link| (&*link.target, Some(link)));
+        pipelines.push((stages, after));
+    }
+    pipelines
+}
+
+/// When pipeline `i` runs, after the one before it.
+fn describe_link(i: usize, link: Option<&AndThen>) -> String {
+    match link {
+        Some(link) if link.conditional => format!(", if pipeline {i} succeeds"),
+        Some(_) => format!(", after pipeline {i}"),
+        None => String::new(),
+    }
+}
+
+/// A stage of a pipeline as it was written, without what it's piped into or
+/// chained with.
+fn alone(stage: &Command) -> String {
+    let stage = Command {
+        pipe_to: None,
+        and_then: None,
+        ..stage.clone()
+    };
+    stage.to_string()
+}
+
+fn explain_pipeline(shell: &mut ShellState, i: usize, stages: &[&Command]) -> io::Result<()> {
+    println!("  pipeline {}:", i + 1);
+    for (j, stage) in stages.iter().enumerate() {
+        match stage.compound.as_deref() {
+            Some(compound) => println!("    stage {}: {}", j + 1, describe_compound(compound)),
+            None => explain_simple(shell, j, stage)?,
+        }
+
+        // Pipes first, then the redirections on top of them, in order
+        let mut streams: [Option<String>; 3] = [None, None, None];
+        if j > 0 {
+            streams[0] = Some(format!("pipe from stage {j}"));
+        }
+        if let Some(pipe) = &stage.pipe_to {
+            let to = format!("pipe to stage {}", j + 2);
+            if pipe.pipe_type == RedirType::Both {
+                streams[2] = Some(to.clone());
+            }
+            streams[1] = Some(to);
+        }
+        let mut others = Vec::new();
+        for redirect in &stage.redirect_to {
+            let target = describe_target(shell, redirect);
+            match redirect.redirect_type {
+                RedirType::Stdin => streams[0] = Some(target),
+                RedirType::Stdout => streams[1] = Some(target),
+                RedirType::Stderr => streams[2] = Some(target),
+                RedirType::Both => {
+                    streams[1] = Some(target.clone());
+                    streams[2] = Some(target);
+                }
+                RedirType::FdIn(fd) | RedirType::FdOut(fd) | RedirType::FdClose(fd) => {
+                    others.push(format!("fd {fd}: {target}"))
+                }
+            }
+        }
+        for (name, stream) in ["stdin", "stdout", "stderr"].iter().zip(streams) {
+            if let Some(stream) = stream {
+                println!("      {name}: {stream}");
+            }
+        }
+        for other in others {
+            println!("      {other}");
+        }
+    }
+    Ok(())
+}
+
+fn describe_compound(compound: &Compound) -> String {
+    match compound {
+        Compound::Group(_) => "a group, running its commands in this shell".to_string(),
+        Compound::Subshell(_) => {
+            "a subshell, running its commands in a copy of the shell".to_string()
+        }
+        Compound::FunctionDef { name, .. } => format!("defines the function {name}"),
+        Compound::Spawn { name, .. } => {
+            format!("starts the pipeline {name} in the background")
+        }
+    }
+}
+
+fn explain_simple(shell: &mut ShellState, j: usize, stage: &Command) -> io::Result<()> {
+    let mut assignments = Vec::new();
+    for assignment in &stage.assignments {
+        let value = match &assignment.value {
+            Arg::Array(words) if !words.iter().any(substitutes) => {
+                let words = expand::expand_args(shell, words)?;
+                let words: Vec<String> = words.iter().map(|word| quote_word(word)).collect();
+                format!("({})", words.join(" "))
+            }
+            value if !substitutes(value) => quote_word(&expand::expand_word(shell, value)?),
+            value => value.to_string(),
+        };
+        assignments.push(format!("{}={value}", assignment.name));
+    }
+
+    if stage.argv.is_empty() {
+        println!("    stage {}: sets {}", j + 1, assignments.join(" "));
+        return Ok(());
+    }
+    if stage.argv.iter().any(substitutes) {
+        let words: Vec<String> = stage.argv.iter().map(Arg::to_string).collect();
+        println!(
+            "    stage {}: not expanded, as a command substitution would run",
+            j + 1
+        );
+        println!("      args: {}", words.join(" "));
+        return Ok(());
+    }
+
+    let args = match stage.argv.first() {
+        Some(Arg::Glob(word)) if word == "[[" => expand::expand_conditional(shell, &stage.argv)?,
+        _ => expand::expand_args(shell, &stage.argv)?,
+    };
+    let Some(name) = args.first() else {
+        println!(
+            "    stage {}: nothing, as its words expand to nothing",
+            j + 1
+        );
+        return Ok(());
+    };
+    println!("    stage {}: {}", j + 1, describe_runner(shell, name));
+    let args: Vec<String> = args.iter().map(|arg| quote_word(arg)).collect();
+    println!("      args: {}", args.join(" "));
+    if !assignments.is_empty() {
+        println!("      with: {}", assignments.join(" "));
+    }
+    Ok(())
+}
+
+/// What would run a command called `name`.
+fn describe_runner(shell: &ShellState, name: &str) -> String {
+    if shell.functions.contains_key(name) {
+        format!("function {name}")
+    } else if builtins::find(name).is_some() {
+        format!("builtin {name}")
+    } else if let Some(path) = platform::find_executable(name) {
+        format!("program {}", path.display())
+    } else {
+        format!("{name}, which isn't found")
+    }
+}
+
+/// Where a redirection points a stream.
+fn describe_target(shell: &ShellState, redirect: &FileRedir) -> String {
+    let path = quote_word(&redirect.target.to_string_lossy());
+    let writes = !matches!(
+        redirect.redirect_type,
+        RedirType::Stdin | RedirType::FdIn(_)
+    );
+    match redirect.redirect_type {
+        RedirType::FdClose(_) => "closed".to_string(),
+        _ if !writes => format!("{path}, read"),
+        _ if redirect.append => format!("{path}, appended to"),
+        _ if shell.options.noclobber && !redirect.clobber => {
+            format!("{path}, created but not overwritten (noclobber)")
+        }
+        _ => format!("{path}, truncated ({})", redirect_operator(redirect)),
+    }
+}
+
+/// Whether expanding `arg` would run a command substitution.
+fn substitutes(arg: &Arg) -> bool {
+    match arg {
+        Arg::Subshell(_) => true,
+        Arg::Array(args) | Arg::Quoted(args) | Arg::Concat(args) => args.iter().any(substitutes),
+        Arg::Word(_) | Arg::Glob(_) | Arg::Variable(_) | Arg::Arith(_) => false,
+    }
+}
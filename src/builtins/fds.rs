@@ -0,0 +1,61 @@
+use std::io;
+
+use super::Builtin;
+use crate::platform;
+use crate::shell::ShellState;
+
+pub struct Exec;
+pub struct Fdlist;
+
+impl Builtin for Exec {
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[command [args ...]]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a program in place of the shell. Without one, make the redirections \
+         after exec last, as in exec 3< file, which opens descriptor 3 for builtins \
+         like mapfile -u 3 until exec 3<&- closes it. See them with fdlist."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        // On its own, it's handled before its redirections are undone
+        if args.len() < 2 {
+            return Ok(0);
+        }
+
+        let env = shell.environment();
+        let e = platform::replace_process(&args[1..], &env);
+        eprintln!("exec: {}", e);
+        Ok(if e.kind() == io::ErrorKind::NotFound {
+            127
+        } else {
+            126
+        })
+    }
+}
+
+impl Builtin for Fdlist {
+    fn name(&self) -> &'static str {
+        "fdlist"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        ""
+    }
+
+    fn description(&self) -> &'static str {
+        "List the descriptors redirected with exec and the files they're open on."
+    }
+
+    fn run(&self, shell: &mut ShellState, _args: &[String]) -> io::Result<i32> {
+        for (fd, open) in &shell.fds {
+            println!("{}{}\t{}", fd, open.mode, open.target.display());
+        }
+        Ok(0)
+    }
+}
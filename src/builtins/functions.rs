@@ -0,0 +1,74 @@
+use std::io;
+
+use super::Builtin;
+use crate::editor;
+use crate::shell::ShellState;
+use crate::unparse;
+
+/// `functions [-e] [name ...]` lists the functions, shows their definitions
+/// laid out over lines, or with `-e`, opens one in an editor and defines it
+/// again from what's saved.
+pub struct Functions;
+
+impl Builtin for Functions {
+    fn name(&self) -> &'static str {
+        "functions"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-e] [name ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the functions, or show the definitions of the ones named. With -e, \
+         edit a function in the editor fc uses, starting a new one if there's \
+         no such function, and define it again from what's saved."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        match &args[1..] {
+            [] => {
+                for name in shell.functions.keys() {
+                    println!("{name}");
+                }
+                Ok(0)
+            }
+            [flag, name] if flag == "-e" => edit(shell, name),
+            [flag, ..] if flag.starts_with('-') => {
+                eprintln!("{}", self.usage());
+                Ok(2)
+            }
+            names => {
+                let mut status = 0;
+                for name in names {
+                    match definition(shell, name) {
+                        Some(text) => print!("{text}"),
+                        None => {
+                            eprintln!("functions: {name}: no such function");
+                            status = 1;
+                        }
+                    }
+                }
+                Ok(status)
+            }
+        }
+    }
+}
+
+/// How the function `name` would be written out, ending in a newline.
+fn definition(shell: &ShellState, name: &str) -> Option<String> {
+    let function = shell.functions.get(name)?;
+    Some(format!(
+        "{name}() {}\n",
+        unparse::pretty(&function.body, 0).trim()
+    ))
+}
+
+fn edit(shell: &mut ShellState, name: &str) -> io::Result<i32> {
+    let text = definition(shell, name).unwrap_or_else(|| format!("{name}() {{\n}}\n"));
+    let Some(saved) = editor::edit_command(shell, &text, None)? else {
+        eprintln!("functions: {name}: editor failed, left as it was");
+        return Ok(1);
+    };
+    shell.eval(&saved)
+}
@@ -0,0 +1,116 @@
+use std::io::{self, IsTerminal};
+
+use super::{Builtin, BUILTINS};
+use crate::options::STRICT;
+use crate::platform;
+use crate::shell::ShellState;
+
+/// `help [builtin...]` lists the builtins with a line about each, or shows
+/// the usage and description of the ones named.
+pub struct Help;
+
+/// Things other than builtins `help` can explain.
+const TOPICS: &[&str] = &["strict"];
+
+/// How a topic in [`TOPICS`] is used, and what it does.
+fn topic(name: &str) -> Option<(String, String)> {
+    match name {
+        "strict" => Some((
+            "usage: sigsh --strict script [arg ...]".to_string(),
+            format!(
+                "Run a script the way CI should: stopping at the first command that \
+                 fails, even partway along a pipeline or inside a $(...), at the first unset variable and \
+                 at the first glob that matches nothing, with unquoted variables never \
+                 split into words or globbed. This turns on {}.",
+                STRICT.join(", ")
+            ),
+        )),
+        _ => None,
+    }
+}
+
+/// The first sentence of a description, for the summary list.
+fn summary(description: &str) -> &str {
+    match description.find(". ") {
+        Some(end) => &description[..=end],
+        None => description,
+    }
+}
+
+/// Break `text` into lines no wider than `width`, except for words which
+/// are longer by themselves.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+impl Builtin for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[builtin | topic ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the builtins, or show how to use the ones named, or explain a topic \
+         such as strict."
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let width = io::stdout()
+            .is_terminal()
+            .then(platform::terminal_width)
+            .flatten()
+            .unwrap_or(80);
+
+        if args.len() == 1 {
+            let name_width = BUILTINS.iter().map(|b| b.name().len()).max().unwrap_or(0);
+            for builtin in BUILTINS {
+                println!(
+                    "{:<name_width$}  {}",
+                    builtin.name(),
+                    summary(builtin.description())
+                );
+            }
+            println!("\nAlso see help {}.", TOPICS.join(", help "));
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for (i, name) in args[1..].iter().enumerate() {
+            let (usage, description) = if let Some(builtin) = super::find(name) {
+                (builtin.usage(), builtin.description().to_string())
+            } else if let Some(topic) = topic(name) {
+                topic
+            } else {
+                eprintln!("help: {}: no such builtin", name);
+                status = 1;
+                continue;
+            };
+            if i > 0 {
+                println!();
+            }
+            println!("{}", usage);
+            println!();
+            for line in wrap(&description, width.saturating_sub(4)) {
+                println!("    {}", line);
+            }
+        }
+        Ok(status)
+    }
+}
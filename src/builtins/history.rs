@@ -0,0 +1,272 @@
+use std::io;
+use std::path::Path;
+
+use super::Builtin;
+use crate::editor::edit_command;
+use crate::history::{Format, HistoryEntry};
+use crate::shell::ShellState;
+
+pub struct History;
+pub struct Fc;
+
+const SYNOPSIS: &str = "[count] | -c | -d offset | search text | import --from bash|zsh [file]";
+
+/// Print entries numbered the way `history -d` and `fc` count them.
+fn print_entries<'a>(entries: impl Iterator<Item = (usize, &'a HistoryEntry)>) {
+    for (index, entry) in entries {
+        println!("{:>5}  {}", index + 1, entry.command);
+    }
+}
+
+/// Turn a 1-based position, or a negative one counting back from the end,
+/// into an index into `len` entries.
+fn position(arg: &str, len: usize) -> Option<usize> {
+    let n: i64 = arg.parse().ok()?;
+    let index = if n < 0 {
+        len.checked_sub(n.unsigned_abs() as usize)?
+    } else {
+        (n as usize).checked_sub(1)?
+    };
+    (index < len).then_some(index)
+}
+
+impl Builtin for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "List the command history, or the last count entries. -c clears it, -d deletes \
+         an entry (negative offsets count back from the end), search lists the entries \
+         containing some text, and import reads another shell's history."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let entries = shell.history.entries();
+        match (args.get(1).map(String::as_str), args.len()) {
+            (None, _) => print_entries(entries.iter().enumerate()),
+            (Some("import"), _) => return import(shell, &args[2..]),
+            (Some("-c"), 2) => {
+                if let Err(e) = shell.history.clear() {
+                    eprintln!("history: {}", e);
+                    return Ok(1);
+                }
+            }
+            (Some("-d"), 3) => {
+                let Some(index) = position(&args[2], entries.len()) else {
+                    eprintln!("history: {}: position out of range", args[2]);
+                    return Ok(1);
+                };
+                if let Err(e) = shell.history.delete(index) {
+                    eprintln!("history: {}", e);
+                    return Ok(1);
+                }
+            }
+            (Some("search"), 3) => {
+                let mut found = shell
+                    .history
+                    .search(&args[2])
+                    .map(|index| (index, &entries[index]))
+                    .peekable();
+                if found.peek().is_none() {
+                    return Ok(1);
+                }
+                print_entries(found);
+            }
+            (Some(count), 2) if count.parse::<usize>().is_ok() => {
+                let count = count.parse::<usize>().unwrap_or(0);
+                let skip = entries.len().saturating_sub(count);
+                print_entries(entries.iter().enumerate().skip(skip));
+            }
+            _ => {
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        }
+        Ok(0)
+    }
+}
+
+fn import(shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+    let (format, file) = match args {
+        [flag, name, rest @ ..] if flag == "--from" && rest.len() <= 1 => {
+            (Format::from_name(name), rest.first())
+        }
+        _ => (None, None),
+    };
+    let Some(format) = format else {
+        eprintln!("{}", History.usage());
+        return Ok(2);
+    };
+
+    match shell.history.import(format, file.map(Path::new)) {
+        Ok(count) => {
+            println!("imported {count} entries");
+            Ok(0)
+        }
+        Err(e) => {
+            eprintln!("history: {e}");
+            Ok(1)
+        }
+    }
+}
+
+/// Whether `command` runs `fc`, as the newest history entry does when `fc`
+/// was typed at the prompt.
+fn is_fc(command: &str) -> bool {
+    command.split_whitespace().next() == Some("fc")
+}
+
+/// Find the entry `arg` refers to: one at a history position, or else the
+/// newest one starting with it.
+fn find_entry(entries: &[HistoryEntry], arg: &str) -> Option<usize> {
+    position(arg, entries.len()).or_else(|| {
+        entries
+            .iter()
+            .rposition(|entry| entry.command.starts_with(arg))
+    })
+}
+
+impl Builtin for Fc {
+    fn name(&self) -> &'static str {
+        "fc"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "-l [-nr] [first [last]] | [-e editor] [first [last]] | -s [old=new ...] [first]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Edit previous commands and run what's saved. The editor is $FCEDIT, \
+         $VISUAL, $EDITOR or vi unless given with -e, and -e - runs the commands as \
+         they are. With -s, run one again straight away after replacing each old with \
+         new. With -l, list the commands instead, the last 16 by default, without \
+         numbers with -n or newest first with -r. Commands are the last one by \
+         default, or first to last, each either a history position or the newest \
+         command starting with it. What's run takes the place of the fc command in \
+         the history."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut editor = None;
+        let (mut list, mut numbers, mut reverse, mut again) = (false, true, false, false);
+        let mut rest = &args[1..];
+        while let Some(flag) = rest
+            .first()
+            .filter(|arg| arg.len() > 1 && arg.starts_with('-'))
+        {
+            // A negative position isn't a flag
+            if flag[1..].parse::<usize>().is_ok() {
+                break;
+            }
+            match flag.as_str() {
+                "-s" => {
+                    editor = Some("-");
+                    again = true;
+                }
+                "-e" if rest.len() > 1 => {
+                    editor = Some(rest[1].as_str());
+                    rest = &rest[1..];
+                }
+                "--" => {
+                    rest = &rest[1..];
+                    break;
+                }
+                flags if flags[1..].chars().all(|c| "lnr".contains(c)) => {
+                    list = true;
+                    numbers &= !flags.contains('n');
+                    reverse |= flags.contains('r');
+                }
+                _ => {
+                    eprintln!("{}", self.usage());
+                    return Ok(2);
+                }
+            }
+            rest = &rest[1..];
+        }
+        let substitute = editor == Some("-");
+        let (substitutions, range): (Vec<_>, Vec<_>) = rest
+            .iter()
+            .partition(|arg| substitute && arg.contains('=') && !arg.starts_with('='));
+        if range.len() > 2 || (again && range.len() > 1) || (list && editor.is_some()) {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        let entries = shell.history.entries();
+        let typed = entries.last().is_some_and(|entry| is_fc(&entry.command));
+        let entries = &entries[..entries.len() - usize::from(typed)];
+        let default = if list { "-16" } else { "-1" };
+        let first = range.first().map_or(default, |first| first.as_str());
+        let last = range
+            .get(1)
+            .map_or(if list { "-1" } else { first }, |last| last.as_str());
+        // Listing the last 16 is fine with fewer than that
+        let first_index = match find_entry(entries, first) {
+            None if range.is_empty() && !entries.is_empty() => Some(0),
+            found => found,
+        };
+        let (Some(first_index), Some(last_index)) = (first_index, find_entry(entries, last)) else {
+            let missing = if first_index.is_none() { first } else { last };
+            eprintln!("fc: {}: no command found", missing);
+            return Ok(1);
+        };
+        let mut indexes: Vec<usize> = if first_index <= last_index {
+            (first_index..=last_index).collect()
+        } else {
+            (last_index..=first_index).rev().collect()
+        };
+
+        if list {
+            if reverse {
+                indexes.reverse();
+            }
+            for index in indexes {
+                if numbers {
+                    print_entries(std::iter::once((index, &entries[index])));
+                } else {
+                    println!("\t{}", entries[index].command);
+                }
+            }
+            return Ok(0);
+        }
+
+        let commands: Vec<&str> = indexes
+            .iter()
+            .map(|&index| entries[index].command.as_str())
+            .collect();
+        let mut command = commands.join(if substitute { "; " } else { "\n" });
+        for substitution in substitutions {
+            if let Some((old, new)) = substitution.split_once('=') {
+                command = command.replace(old, new);
+            }
+        }
+        if !substitute {
+            command = match edit_command(shell, &command, editor)? {
+                Some(edited) if !edited.is_empty() => edited,
+                Some(_) => return Ok(0),
+                None => return Ok(1),
+            };
+        }
+
+        println!("{}", command);
+        if typed {
+            if let Err(e) = shell.history.replace_last(&command) {
+                eprintln!("history: {}", e);
+            }
+        }
+        shell.eval(&command).or_else(|e| {
+            eprintln!("{}", e);
+            // Syntax errors are 2, like at the prompt
+            Ok(if e.kind() == io::ErrorKind::InvalidInput {
+                2
+            } else {
+                1
+            })
+        })
+    }
+}
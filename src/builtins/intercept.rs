@@ -0,0 +1,83 @@
+use std::io;
+
+use super::Builtin;
+use crate::intercept::{Action, Rule};
+use crate::shell::ShellState;
+
+pub struct Intercept;
+
+impl Builtin for Intercept {
+    fn name(&self) -> &'static str {
+        "intercept"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-w pattern] name handler [args ...] | [-w pattern] -x name | -r name"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run handler with args and then the command's own argv instead of name, \
+         whatever path name is run by, or with -x, refuse to run it. With -w, only \
+         when one of its arguments matches the glob pattern. Refusing wins over \
+         handlers, and inside a handler function, the command it stands in for \
+         runs as usual. -r removes the rules for name, and with no arguments, the \
+         rules are listed."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() == 1 {
+            for rule in shell.intercepts.iter() {
+                println!("{}", rule.to_command());
+            }
+            return Ok(0);
+        }
+
+        let mut pattern = None;
+        let mut deny = false;
+        let mut remove = false;
+        let mut rest = &args[1..];
+        while let Some(flag) = rest.first() {
+            match flag.as_str() {
+                "-w" if rest.len() > 1 => {
+                    pattern = Some(rest[1].clone());
+                    rest = &rest[2..];
+                    continue;
+                }
+                "-x" => deny = true,
+                "-r" => remove = true,
+                "--" => {
+                    rest = &rest[1..];
+                    break;
+                }
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+
+        let rule = match rest {
+            [name] if remove && !deny && pattern.is_none() => {
+                if shell.intercepts.remove(name) {
+                    return Ok(0);
+                }
+                eprintln!("intercept: {}: no rules", name);
+                return Ok(1);
+            }
+            [name] if deny && !remove => Rule {
+                name: name.clone(),
+                pattern,
+                action: Action::Deny,
+            },
+            [name, handler @ ..] if !deny && !remove && !handler.is_empty() => Rule {
+                name: name.clone(),
+                pattern,
+                action: Action::Run(handler.to_vec()),
+            },
+            _ => {
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        };
+        shell.intercepts.add(rule);
+        Ok(0)
+    }
+}
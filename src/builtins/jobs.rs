@@ -0,0 +1,403 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use super::{Builtin, INTERRUPTED};
+use crate::exec;
+use crate::jobs::JobState;
+use crate::platform::{self, InterruptGuard, ProcessInfo, Stdio, WaitStatus};
+use crate::shell::ShellState;
+
+pub struct Jobs;
+pub struct Fg;
+pub struct Bg;
+pub struct Disown;
+pub struct WaitFor;
+pub struct Procs;
+
+/// How often `wait-for` checks on a pipeline, and for Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+impl Builtin for Jobs {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-t]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the jobs started from this shell and their states. With -t, show the \
+         processes in each job as a tree, with what each is doing and the CPU time it's \
+         used, to find the stage a pipeline is stuck on."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let tree = match &args[1..] {
+            [] => false,
+            [flag] if flag == "-t" => true,
+            _ => {
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        };
+        let current = shell.jobs.current().map(|job| job.id);
+        for job in shell.jobs.iter() {
+            println!("{}", job.describe(current == Some(job.id)));
+            if tree {
+                print_tree(&job.processes());
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Print a job's processes under it, each below the one that started it.
+fn print_tree(processes: &[ProcessInfo]) {
+    let started_here = |info: &&ProcessInfo| processes.iter().all(|other| other.pid != info.parent);
+    for info in processes.iter().filter(started_here) {
+        print_branch(processes, info, "", "");
+    }
+}
+
+/// Print `info` and everything under it, with `lead` drawn before its
+/// command and `rest` before those of its descendants.
+fn print_branch(processes: &[ProcessInfo], info: &ProcessInfo, lead: &str, rest: &str) {
+    println!(
+        "    {:>7}  {:<8}  {:>7.2}s  {lead}{}",
+        info.pid,
+        info.state,
+        info.cpu.as_secs_f64(),
+        info.command
+    );
+    let children: Vec<&ProcessInfo> = processes
+        .iter()
+        .filter(|child| child.parent == info.pid)
+        .collect();
+    for (i, child) in children.iter().enumerate() {
+        let (branch, under) = match i + 1 == children.len() {
+            true => ("└─ ", "   "),
+            false => ("├─ ", "│  "),
+        };
+        print_branch(
+            processes,
+            child,
+            &format!("{rest}{branch}"),
+            &format!("{rest}{under}"),
+        );
+    }
+}
+
+impl Builtin for Fg {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[job]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Bring a job to the foreground, resuming it if it's stopped."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if !shell.job_control {
+            eprintln!("fg: no job control");
+            return Ok(1);
+        }
+        let Some(job) = shell.jobs.find(args.get(1).map(String::as_str)) else {
+            eprintln!("fg: no such job");
+            return Ok(1);
+        };
+        let (id, pgid) = (job.id, job.pgid);
+        println!("{}", job.command);
+
+        let status = platform::continue_job(pgid, true)?.unwrap_or(WaitStatus::Unknown);
+        shell.children.changed(pgid, status.into());
+        if let WaitStatus::Stopped(_) = status {
+            shell.jobs.set_state(id, JobState::Stopped);
+            if let Some(job) = shell.jobs.get(id) {
+                eprintln!("\n{}", job.describe(true));
+            }
+        } else if let Some(name) = shell.jobs.remove(id).and_then(|job| job.name) {
+            shell.set_spawn_status(&name, &status.code().to_string());
+        }
+        Ok(status.code())
+    }
+}
+
+impl Builtin for Bg {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[job]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Resume a stopped job in the background."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if !shell.job_control {
+            eprintln!("bg: no job control");
+            return Ok(1);
+        }
+        let Some(job) = shell.jobs.find(args.get(1).map(String::as_str)) else {
+            eprintln!("bg: no such job");
+            return Ok(1);
+        };
+        let (id, pgid) = (job.id, job.pgid);
+        println!("[{}] {} &", id, job.command);
+
+        platform::continue_job(pgid, false)?;
+        shell.jobs.set_state(id, JobState::Running);
+        Ok(0)
+    }
+}
+
+impl Builtin for Disown {
+    fn name(&self) -> &'static str {
+        "disown"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-h] [-a | job ...] | command [args ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Take jobs out of the job table, the current one by default or all of them \
+         with -a, so they're left running when the shell exits. With -h they stay \
+         listed but are never hung up. Given a command instead, start it in the \
+         background the way nohup would: away from the terminal, ignoring hangups, \
+         and with output that would have gone to the terminal appended to nohup.out."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let is_job = |arg: &str| arg.starts_with('%') || arg.parse::<usize>().is_ok();
+        if args
+            .get(1)
+            .is_some_and(|arg| !arg.starts_with('-') && !is_job(arg))
+        {
+            return detach(shell, args[1..].to_vec());
+        }
+
+        let keep = args.get(1).is_some_and(|arg| arg == "-h");
+        let specs = &args[1 + usize::from(keep)..];
+        let mut status = 0;
+        let ids: Vec<usize> = match specs {
+            [all] if all == "-a" => shell.jobs.iter().map(|job| job.id).collect(),
+            specs if specs.iter().all(|spec| is_job(spec)) => {
+                let specs: Vec<Option<&str>> = match specs {
+                    [] => vec![None],
+                    specs => specs.iter().map(|spec| Some(spec.as_str())).collect(),
+                };
+                specs
+                    .into_iter()
+                    .filter_map(|spec| {
+                        let job = shell.jobs.find(spec);
+                        if job.is_none() {
+                            eprintln!("disown: {}: no such job", spec.unwrap_or("%+"));
+                            status = 1;
+                        }
+                        job.map(|job| job.id)
+                    })
+                    .collect()
+            }
+            _ => {
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        };
+
+        for id in ids {
+            if keep {
+                shell.jobs.set_nohup(id);
+                continue;
+            }
+            let Some(job) = shell.jobs.remove(id) else {
+                continue;
+            };
+            // Nothing could ever continue it once it's gone from the table
+            if job.state == JobState::Stopped {
+                if let Err(e) = platform::continue_job(job.pgid, false) {
+                    eprintln!("disown: {}: {}", job.command, e);
+                    status = 1;
+                }
+            }
+        }
+        Ok(status)
+    }
+}
+
+impl Builtin for WaitFor {
+    fn name(&self) -> &'static str {
+        "wait-for"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[name ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Wait for pipelines started in the background with `spawn name { ...; }` to \
+         finish, all of them by default, returning the status of the last. Ctrl-C stops \
+         the waiting but not the pipelines. Each one's status is kept in the SPAWNSTATUS \
+         associative array, which says `running` until it's been collected here or at \
+         the prompt."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let names: Vec<String> = match &args[1..] {
+            [] => shell
+                .jobs
+                .iter()
+                .filter_map(|job| job.name.clone())
+                .collect(),
+            names => names.to_vec(),
+        };
+
+        let guard = InterruptGuard::new();
+        let mut status = 0;
+        for name in names {
+            let Some((id, pid)) = shell.jobs.named(&name).map(|job| (job.id, job.pgid)) else {
+                // It may have finished and been collected already
+                let collected = shell.variables.get_element("SPAWNSTATUS", &name);
+                status = match collected.ok().flatten().and_then(|code| code.parse().ok()) {
+                    Some(code) => code,
+                    None => {
+                        eprintln!("wait-for: {name}: no such pipeline");
+                        127
+                    }
+                };
+                continue;
+            };
+            status = loop {
+                if let Some(status) = shell.jobs.try_wait(id)? {
+                    break status;
+                }
+                if guard.interrupted() {
+                    return Ok(INTERRUPTED);
+                }
+                thread::sleep(POLL_INTERVAL);
+            };
+            shell.children.changed(pid, JobState::Done(status));
+            shell.set_spawn_status(&name, &status.to_string());
+        }
+        Ok(status)
+    }
+}
+
+impl Builtin for Procs {
+    fn name(&self) -> &'static str {
+        "procs"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-r] [-s] [-d]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the processes this shell has started, oldest first, with their process \
+         ID, state, how long they ran or have been running, and the command. -r, -s \
+         and -d show only those running, stopped or done, and may be combined."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let (mut running, mut stopped, mut done) = (false, false, false);
+        for arg in &args[1..] {
+            match arg.as_str() {
+                "-r" => running = true,
+                "-s" => stopped = true,
+                "-d" => done = true,
+                _ => {
+                    eprintln!("{}", self.usage());
+                    return Ok(2);
+                }
+            }
+        }
+        let all = !(running || stopped || done);
+
+        for child in shell.children.iter() {
+            let (shown, state) = match child.state {
+                JobState::Running => (running, "running".to_string()),
+                JobState::Stopped => (stopped, "stopped".to_string()),
+                JobState::Done(0) => (done, "done".to_string()),
+                JobState::Done(code) => (done, format!("exit {code}")),
+            };
+            if all || shown {
+                println!(
+                    "{:>7}  {:<8}  {:>8.2}s  {}",
+                    child.pid,
+                    state,
+                    child.runtime().as_secs_f64(),
+                    child.command
+                );
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Where nohup would put a command's output: `nohup.out` here, or in
+/// `$HOME` if it can't be written here.
+fn open_nohup_out(shell: &ShellState) -> io::Result<(PathBuf, File)> {
+    let open = |path: PathBuf| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map(|file| (path, file))
+    };
+    open(PathBuf::from("nohup.out")).or_else(|e| match shell.variables.get("HOME") {
+        Some(home) => open(PathBuf::from(home).join("nohup.out")),
+        None => Err(e),
+    })
+}
+
+/// Start `args` detached, as `nohup args &` would and then `disown`.
+fn detach(shell: &mut ShellState, args: Vec<String>) -> io::Result<i32> {
+    let mut stdio = Stdio::default();
+    if io::stdin().is_terminal() {
+        let null = if cfg!(windows) { "NUL" } else { "/dev/null" };
+        stdio.stdin = Some(File::open(null)?);
+    }
+    let stdout = io::stdout().is_terminal();
+    let stderr = io::stderr().is_terminal();
+    if stdout || stderr {
+        let (path, file) = match open_nohup_out(shell) {
+            Ok(opened) => opened,
+            Err(e) => {
+                eprintln!("disown: nohup.out: {}", e);
+                return Ok(1);
+            }
+        };
+        eprintln!("disown: appending output to {}", path.display());
+        if stdout {
+            stdio.stdout = Some(file.try_clone()?);
+        }
+        if stderr {
+            stdio.stderr = Some(file);
+        }
+    }
+
+    match exec::start_detached(shell, args, &stdio) {
+        Ok(pid) => {
+            eprintln!("disown: started process {pid}");
+            Ok(0)
+        }
+        Err(e) => {
+            eprintln!("disown: {}", e);
+            Ok(if e.kind() == io::ErrorKind::NotFound {
+                127
+            } else {
+                1
+            })
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use super::Builtin;
+use crate::nesting::Nested;
+use crate::shell::ShellState;
+
+/// `json get|keys|length|type [PATH]`: parse a JSON document from standard
+/// input and print part of it, so scripts can consume API output without `jq`.
+pub struct Json;
+
+const SYNOPSIS: &str = "get|keys|length|type [PATH]";
+
+enum Value {
+    Null,
+    Bool(bool),
+    /// Kept as written, so large or precise numbers survive untouched.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in document order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    fn member(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn element(&self, index: i64) -> Option<&Value> {
+        match self {
+            Value::Array(elements) => {
+                let index = if index < 0 {
+                    elements.len().checked_sub(index.unsigned_abs() as usize)?
+                } else {
+                    index as usize
+                };
+                elements.get(index)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Compact JSON.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => f.write_str(n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, what: &str) -> Result<T, String> {
+        Err(format!("{what} at offset {}", self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.eat(c) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{c}'"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => {
+                for (word, value) in [
+                    ("null", Value::Null),
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false)),
+                ] {
+                    if self.input[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                self.error("unexpected character")
+            }
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    /// Parse an array or object a level further down the stack.
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        let _nested = match Nested::enter() {
+            Ok(nested) => nested,
+            Err(too_deep) => return self.error(&too_deep.to_string()),
+        };
+        parse(self)
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return self.error("expected a key");
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Value::Object(members));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Value::Array(elements));
+        }
+        loop {
+            elements.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Value::Array(elements));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.input.get(self.pos..self.pos + 4).unwrap_or("");
+        match u32::from_str_radix(digits, 16) {
+            Ok(n) if digits.len() == 4 => {
+                self.pos += 4;
+                Ok(n)
+            }
+            _ => self.error("invalid \\u escape"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return self.error("unterminated string");
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let Some(escape) = self.peek() else {
+                        return self.error("unterminated string");
+                    };
+                    self.pos += escape.len_utf8();
+                    match escape {
+                        '"' | '\\' | '/' => s.push(escape),
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{c}'),
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'u' => {
+                            let mut code = self.hex4()?;
+                            // A high surrogate pairs with the low one after it
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.input[self.pos..].starts_with("\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return self.error("invalid escape"),
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        let number = &rest[..len];
+        if number.parse::<f64>().is_err() {
+            return self.error("invalid number");
+        }
+        self.pos = start + len;
+        Ok(Value::Number(number.to_string()))
+    }
+}
+
+fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser { input, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < input.len() {
+        return parser.error("trailing data");
+    }
+    Ok(value)
+}
+
+enum Step {
+    Key(String),
+    Index(i64),
+}
+
+/// Parse a path like `.items[0].name` or `.["odd key"]`; `.` alone is the
+/// whole document.
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+    let invalid = || format!("{path}: invalid path");
+    let mut steps = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            let inner = &after[..end];
+            let step = if inner.starts_with('"') {
+                let mut parser = Parser {
+                    input: inner,
+                    pos: 0,
+                };
+                let key = parser.string()?;
+                if parser.pos != inner.len() {
+                    return Err(invalid());
+                }
+                Step::Key(key)
+            } else {
+                Step::Index(inner.trim().parse().map_err(|_| invalid())?)
+            };
+            steps.push(step);
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end > 0 {
+                steps.push(Step::Key(after[..end].to_string()));
+            }
+            rest = &after[end..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(steps)
+}
+
+fn lookup<'a>(mut value: &'a Value, steps: &[Step]) -> Option<&'a Value> {
+    for step in steps {
+        value = match step {
+            Step::Key(key) => value.member(key)?,
+            Step::Index(index) => value.element(*index)?,
+        };
+    }
+    Some(value)
+}
+
+impl Builtin for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Print part of a JSON document read from standard input."
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let (Some(command), path) = (args.get(1), args.get(2).map_or(".", String::as_str)) else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+        if !matches!(command.as_str(), "get" | "keys" | "length" | "type") || args.len() > 3 {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        let steps = match parse_path(path) {
+            Ok(steps) => steps,
+            Err(e) => {
+                eprintln!("json: {}", e);
+                return Ok(2);
+            }
+        };
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        let document = match parse(&input) {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("json: {}", e);
+                return Ok(2);
+            }
+        };
+        let Some(value) = lookup(&document, &steps) else {
+            return Ok(1);
+        };
+
+        match (command.as_str(), value) {
+            ("get", Value::String(s)) => println!("{}", s),
+            ("get", value) => println!("{}", value),
+            ("keys", Value::Object(members)) => {
+                for (key, _) in members {
+                    println!("{}", key);
+                }
+            }
+            ("keys", Value::Array(elements)) => {
+                for i in 0..elements.len() {
+                    println!("{}", i);
+                }
+            }
+            ("length", Value::Object(members)) => println!("{}", members.len()),
+            ("length", Value::Array(elements)) => println!("{}", elements.len()),
+            ("length", Value::String(s)) => println!("{}", s.chars().count()),
+            ("type", value) => println!("{}", value.type_name()),
+            (_, value) => {
+                eprintln!("json: {}: {} has no {}", path, value.type_name(), command);
+                return Ok(1);
+            }
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use super::Builtin;
+use crate::listing::{self, Entry, Kind};
+use crate::platform;
+use crate::shell::ShellState;
+
+/// `list [-1aFrSt] [--color=when] [path...]`, a small `ls` that works without
+/// coreutils and lays things out just like the completion menu.
+pub struct List;
+
+const SYNOPSIS: &str = "[-1aFrSt] [--color=always|auto|never] [path ...]";
+
+#[derive(Default)]
+struct Options {
+    one_per_line: bool,
+    hidden: bool,
+    classify: bool,
+    reverse: bool,
+    by_size: bool,
+    by_time: bool,
+    color: Option<bool>,
+    paths: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                options.paths.extend(args.by_ref().cloned());
+                break;
+            }
+            "--color" | "--color=always" => options.color = Some(true),
+            "--color=never" => options.color = Some(false),
+            "--color=auto" => options.color = None,
+            flags if flags.starts_with('-') && flags.len() > 1 => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        '1' => options.one_per_line = true,
+                        'a' => options.hidden = true,
+                        'F' => options.classify = true,
+                        'r' => options.reverse = true,
+                        'S' => options.by_size = true,
+                        't' => options.by_time = true,
+                        _ => return Err(format!("-{flag}: invalid option")),
+                    }
+                }
+            }
+            path => options.paths.push(path.to_string()),
+        }
+    }
+    Ok(options)
+}
+
+fn sort(entries: &mut [Entry], options: &Options) {
+    if options.by_size {
+        entries.sort_by(|a, b| {
+            b.size
+                .cmp(&a.size)
+                .then_with(|| listing::by_name(&a.name, &b.name))
+        });
+    } else if options.by_time {
+        entries.sort_by(|a, b| {
+            b.modified
+                .cmp(&a.modified)
+                .then_with(|| listing::by_name(&a.name, &b.name))
+        });
+    }
+    if options.reverse {
+        entries.reverse();
+    }
+}
+
+/// Print `entries` in columns fitting `width`, or one per line without one.
+fn print(
+    out: &mut impl Write,
+    entries: &[Entry],
+    options: &Options,
+    color: bool,
+    width: Option<usize>,
+) -> io::Result<()> {
+    let cells: Vec<_> = entries
+        .iter()
+        .map(|entry| entry.cell(color, options.classify))
+        .collect();
+    let width = width.filter(|_| !options.one_per_line).unwrap_or(0);
+    write!(out, "{}", listing::columns(&cells, width))
+}
+
+impl Builtin for List {
+    fn name(&self) -> &'static str {
+        "list"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "List files in columns, colored by type."
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut options = match parse_options(args) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("list: {}", e);
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        };
+        if options.paths.is_empty() {
+            options.paths.push(".".to_string());
+        }
+
+        // Like `ls`, columns and color are for people, not pipes
+        let terminal = io::stdout().is_terminal();
+        let color = options.color.unwrap_or(terminal);
+        let width = terminal.then(|| platform::terminal_width().unwrap_or(80));
+
+        let mut status = 0;
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for path in &options.paths {
+            let entry = Entry::new(Path::new(path), path.clone());
+            match entry.kind {
+                Kind::Missing => {
+                    eprintln!("list: {}: No such file or directory", path);
+                    status = 1;
+                }
+                Kind::Directory => dirs.push(entry),
+                // A link to a directory lists the directory
+                Kind::Symlink if Path::new(path).is_dir() => dirs.push(entry),
+                _ => files.push(entry),
+            }
+        }
+
+        let mut out = io::stdout().lock();
+        let headers = options.paths.len() > 1;
+        sort(&mut files, &options);
+        if !files.is_empty() {
+            print(&mut out, &files, &options, color, width)?;
+        }
+        for (i, dir) in dirs.iter().enumerate() {
+            let mut entries = match listing::read_dir(Path::new(&dir.name), options.hidden) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("list: {}: {}", dir.name, e);
+                    status = 1;
+                    continue;
+                }
+            };
+            sort(&mut entries, &options);
+            if headers {
+                if i > 0 || !files.is_empty() {
+                    writeln!(out)?;
+                }
+                writeln!(out, "{}:", dir.name)?;
+            }
+            print(&mut out, &entries, &options, color, width)?;
+        }
+        out.flush()?;
+        Ok(status)
+    }
+}
@@ -0,0 +1,61 @@
+use std::io;
+
+use super::Builtin;
+use crate::platform;
+use crate::shell::ShellState;
+
+pub struct Where;
+
+impl Builtin for Where {
+    fn name(&self) -> &'static str {
+        "where"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "name ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Show everything a command name could run, in the order they're tried: an \
+         alias, a function, a builtin, then each program of that name on PATH. The \
+         first one listed is the one that runs."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() < 2 {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        let mut status = 0;
+        for name in &args[1..] {
+            let mut found = false;
+            if let Some(value) = shell.aliases.get(name) {
+                println!("{name}: aliased to {value}");
+                found = true;
+            }
+            if shell.functions.contains_key(name) {
+                println!("{name}: shell function");
+                found = true;
+            }
+            if super::find(name).is_some() {
+                println!("{name}: shell builtin");
+                found = true;
+            }
+            let mut programs: Vec<_> = Vec::new();
+            for program in platform::find_executables(name) {
+                // The same directory can be on PATH more than once
+                if !programs.contains(&program) {
+                    println!("{}", program.display());
+                    programs.push(program);
+                }
+            }
+
+            if !found && programs.is_empty() {
+                eprintln!("where: {name}: not found");
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,157 @@
+use std::io::{self, Read};
+
+use super::Builtin;
+use crate::platform;
+use crate::shell::ShellState;
+
+/// `mapfile`, also known as `readarray`.
+pub struct Mapfile(pub &'static str);
+
+const SYNOPSIS: &str = "[-d delim] [-n count] [-O origin] [-s count] [-t] [-u fd] [array]";
+
+struct Options {
+    delimiter: u8,
+    /// How many lines to read, or 0 for all of them
+    count: usize,
+    origin: Option<usize>,
+    skip: usize,
+    trim: bool,
+    fd: i32,
+    array: String,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        delimiter: b'\n',
+        count: 0,
+        origin: None,
+        skip: 0,
+        trim: false,
+        fd: 0,
+        array: "MAPFILE".to_string(),
+    };
+
+    let number = |value: Option<&String>, flag: &str| -> Result<usize, String> {
+        value
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("{flag}: invalid number"))
+    };
+
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // An empty delimiter means NUL, for `find -print0` output
+            "-d" => match args.next() {
+                Some(delim) => options.delimiter = delim.bytes().next().unwrap_or(0),
+                None => return Err("-d: option requires an argument".to_string()),
+            },
+            "-n" => options.count = number(args.next(), "-n")?,
+            "-O" => options.origin = Some(number(args.next(), "-O")?),
+            "-s" => options.skip = number(args.next(), "-s")?,
+            "-u" => options.fd = number(args.next(), "-u")? as i32,
+            "-t" => options.trim = true,
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("{flag}: invalid option"))
+            }
+            name => options.array = name.to_string(),
+        }
+    }
+    Ok(options)
+}
+
+/// Read the lines we're after. When there's a limit, read a byte at a time so
+/// that whatever's left is still there for the next reader.
+fn read_lines(input: &mut impl Read, options: &Options) -> io::Result<Vec<Vec<u8>>> {
+    let wanted = options.skip + options.count;
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+
+    if options.count == 0 {
+        let mut contents = Vec::new();
+        input.read_to_end(&mut contents)?;
+        for &byte in &contents {
+            line.push(byte);
+            if byte == options.delimiter {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+    } else {
+        let mut byte = [0];
+        while lines.len() < wanted && input.read(&mut byte)? == 1 {
+            line.push(byte[0]);
+            if byte[0] == options.delimiter {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+    }
+    // The last line may not have a delimiter
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    Ok(lines.into_iter().skip(options.skip).collect())
+}
+
+impl Builtin for Mapfile {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Read lines from standard input into an indexed array, MAPFILE by default."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let options = match parse_options(args) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("{}: {}", self.0, e);
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        };
+
+        let lines = match platform::borrow_fd(options.fd)
+            .and_then(|mut input| read_lines(&mut *input, &options))
+        {
+            Ok(lines) => lines,
+            Err(e) => {
+                eprintln!("{}: {}", self.0, e);
+                return Ok(1);
+            }
+        };
+
+        let lines = lines.into_iter().map(|mut line| {
+            if options.trim && line.last() == Some(&options.delimiter) {
+                line.pop();
+            }
+            String::from_utf8_lossy(&line).into_owned()
+        });
+
+        // Without an origin the array starts over; with one, only the
+        // elements the lines land on are replaced
+        let origin = options.origin.unwrap_or(0);
+        let mut values = match options.origin {
+            Some(_) => shell
+                .variables
+                .get_array(&options.array)
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if values.len() < origin {
+            values.resize(origin, String::new());
+        }
+        for (i, line) in lines.enumerate() {
+            match values.get_mut(origin + i) {
+                Some(value) => *value = line,
+                None => values.push(line),
+            }
+        }
+        shell.variables.set_array(options.array, values)?;
+        Ok(0)
+    }
+}
@@ -0,0 +1,320 @@
+use std::io;
+
+use super::Builtin;
+use crate::nesting::Nested;
+use crate::shell::ShellState;
+
+/// `math [-s scale] expression...` evaluates floating-point arithmetic, for
+/// what `$(( ))` can't do since it only knows integers.
+pub struct Math;
+
+const SYNOPSIS: &str = "[-s scale] expression";
+
+/// Digits after the point without `-s`, as in fish.
+const DEFAULT_SCALE: usize = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut prev = ' ';
+            // Digits, a point, and an exponent which may have its own sign
+            while let Some(&(i, c)) = chars.peek() {
+                let exponent_sign = matches!(c, '+' | '-') && matches!(prev, 'e' | 'E');
+                if !(c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E') || exponent_sign) {
+                    break;
+                }
+                prev = c;
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let literal = &input[start..end];
+            let value = literal
+                .parse()
+                .map_err(|_| format!("{literal}: invalid number"))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else {
+            chars.next();
+            tokens.push(match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                // `**` is the same as `^`
+                '*' if chars.next_if(|&(_, c)| c == '*').is_some() => Token::Op('^'),
+                '+' | '-' | '*' | '/' | '%' | '^' => Token::Op(c),
+                c => return Err(format!("{c}: unexpected character")),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent evaluator over the usual precedence: `+ -`, then
+/// `* / %`, then unary signs, then `^`, which is right-associative.
+struct Evaluator {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Evaluator {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            let rhs = self.product()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/' | '%'))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = match op {
+                '*' => value * rhs,
+                _ if rhs == 0.0 => return Err("division by zero".to_string()),
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    /// Counts a level of nesting for each parenthesis, sign and exponent.
+    fn unary(&mut self) -> Result<f64, String> {
+        let _nested = Nested::enter().map_err(|too_deep| format!("expression {too_deep}"))?;
+        self.signed()
+    }
+
+    fn signed(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                Ok(-self.unary()?)
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.atom()?;
+        if self.peek() == Some(&Token::Op('^')) {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            return Ok(base.powf(exponent));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<f64, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::LParen) => {
+                let value = self.sum()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(value),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(Token::Name(name)) if self.peek() == Some(&Token::LParen) => {
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RParen) {
+                    loop {
+                        args.push(self.sum()?);
+                        if self.peek() != Some(&Token::Comma) {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                match self.next() {
+                    Some(Token::RParen) => call(&name, &args),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(Token::Name(name)) => match name.as_str() {
+                "pi" => Ok(std::f64::consts::PI),
+                "e" => Ok(std::f64::consts::E),
+                "tau" => Ok(std::f64::consts::TAU),
+                _ => Err(format!("{name}: unknown constant")),
+            },
+            Some(token) => Err(format!("unexpected {}", describe(&token))),
+            None => Err("missing operand".to_string()),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => format!("'{op}'"),
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::Comma => "','".to_string(),
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+    let one = |f: fn(f64) -> f64| match args {
+        [x] => Ok(f(*x)),
+        _ => Err(format!("{name}: expected 1 argument")),
+    };
+    let two = |f: fn(f64, f64) -> f64| match args {
+        [x, y] => Ok(f(*x, *y)),
+        _ => Err(format!("{name}: expected 2 arguments")),
+    };
+
+    match name {
+        "sqrt" => one(f64::sqrt),
+        "abs" => one(f64::abs),
+        "round" => one(f64::round),
+        "floor" => one(f64::floor),
+        "ceil" => one(f64::ceil),
+        "exp" => one(f64::exp),
+        "ln" => one(f64::ln),
+        "log" | "log10" => one(f64::log10),
+        "log2" => one(f64::log2),
+        "sin" => one(f64::sin),
+        "cos" => one(f64::cos),
+        "tan" => one(f64::tan),
+        "asin" => one(f64::asin),
+        "acos" => one(f64::acos),
+        "atan" => one(f64::atan),
+        "pow" => two(f64::powf),
+        "atan2" => two(f64::atan2),
+        "min" | "max" if args.is_empty() => Err(format!("{name}: expected arguments")),
+        "min" => Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+        "max" => Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+        _ => Err(format!("{name}: unknown function")),
+    }
+}
+
+pub fn eval(input: &str) -> Result<f64, String> {
+    let mut evaluator = Evaluator {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let value = evaluator.sum()?;
+    match evaluator.peek() {
+        Some(token) => Err(format!("unexpected {}", describe(token))),
+        None => Ok(value),
+    }
+}
+
+/// Show `value` with at most `scale` digits after the point, leaving off
+/// trailing zeros.
+pub fn format(value: f64, scale: usize) -> String {
+    let text = format!("{:.*}", scale, value);
+    let text = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &text
+    };
+    // `-0.0000001` rounds to "-0"
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+impl Builtin for Math {
+    fn name(&self) -> &'static str {
+        "math"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluate a floating-point expression."
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut args = &args[1..];
+        let mut scale = DEFAULT_SCALE;
+        if let Some(value) = args.first().and_then(|arg| arg.strip_prefix("-s")) {
+            let value = if value.is_empty() {
+                args = &args[1..];
+                args.first().map_or("", String::as_str)
+            } else {
+                value
+            };
+            match value.parse() {
+                Ok(n) => scale = n,
+                Err(_) => {
+                    eprintln!("math: -s: invalid scale");
+                    return Ok(2);
+                }
+            }
+            args = &args[1..];
+        }
+
+        if args.is_empty() {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+        let expr = args.join(" ");
+
+        match eval(&expr) {
+            Ok(value) if value.is_finite() => {
+                println!("{}", format(value, scale));
+                Ok(0)
+            }
+            Ok(_) => {
+                eprintln!("math: {}: result is not a finite number", expr);
+                Ok(1)
+            }
+            Err(e) => {
+                eprintln!("math: {}: {}", expr, e);
+                Ok(1)
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Commands the shell runs itself rather than spawning a process for,
+//! because they need to see or change the shell's own state.
+
+mod alias;
+mod argparse;
+mod arith;
+mod capture;
+mod cd;
+mod choose;
+mod complete;
+mod conditional;
+mod control;
+mod direnv;
+mod every;
+mod explain;
+mod fds;
+mod functions;
+mod help;
+mod history;
+mod intercept;
+mod jobs;
+mod json;
+mod list;
+mod lookup;
+mod mapfile;
+mod math;
+mod noglob;
+mod printf;
+mod range;
+mod sleep;
+mod source;
+mod string;
+mod timeout;
+mod trap;
+mod vars;
+
+use std::io;
+
+use crate::shell::ShellState;
+
+/// The status of a command killed by SIGINT, which builtins cut short by
+/// Ctrl-C return too.
+const INTERRUPTED: i32 = 128 + 2;
+
+pub trait Builtin: Sync {
+    fn name(&self) -> &'static str;
+
+    /// The arguments it takes, as shown after its name in usage messages.
+    fn synopsis(&self) -> &'static str;
+
+    /// What it does, for `help`.
+    fn description(&self) -> &'static str;
+
+    fn usage(&self) -> String {
+        format!("usage: {} {}", self.name(), self.synopsis())
+            .trim_end()
+            .to_string()
+    }
+
+    /// Whether it can go on writing for a long time, or for ever, so that
+    /// the next stage of a pipeline needs what it writes as it comes.
+    fn streams(&self) -> bool {
+        false
+    }
+
+    /// Run the builtin. `args` is the whole argv, including the builtin's name.
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32>;
+}
+
+static BUILTINS: &[&dyn Builtin] = &[
+    &jobs::Jobs,
+    &jobs::Fg,
+    &jobs::Bg,
+    &jobs::Disown,
+    &jobs::WaitFor,
+    &jobs::Procs,
+    &history::History,
+    &history::Fc,
+    &complete::Complete,
+    &complete::Compgen,
+    &cd::Cd,
+    &cd::Pushd,
+    &cd::Popd,
+    &cd::Dirs,
+    &direnv::Allow,
+    &direnv::Deny,
+    &vars::Export,
+    &vars::Unset,
+    &vars::Set,
+    &vars::Declare("declare"),
+    &vars::Declare("typeset"),
+    &vars::Readonly,
+    &vars::Declare("local"),
+    &alias::Alias,
+    &alias::Unalias,
+    &functions::Functions,
+    &intercept::Intercept,
+    &control::Exit,
+    &control::Return,
+    &mapfile::Mapfile("mapfile"),
+    &mapfile::Mapfile("readarray"),
+    &timeout::Timeout,
+    &noglob::Noglob,
+    &capture::Capture,
+    &trap::Trap,
+    &source::Source("source"),
+    &source::Source("."),
+    &fds::Exec,
+    &fds::Fdlist,
+    &every::Every,
+    &math::Math,
+    &arith::Let,
+    &conditional::Conditional,
+    &printf::Printf,
+    &string::StringBuiltin,
+    &json::Json,
+    &argparse::Argparse,
+    &range::Range,
+    &sleep::Sleep,
+    &list::List,
+    &lookup::Where,
+    &explain::Explain,
+    &choose::Choose,
+    &help::Help,
+];
+
+pub fn find(name: &str) -> Option<&'static dyn Builtin> {
+    BUILTINS
+        .iter()
+        .copied()
+        .find(|builtin| builtin.name() == name)
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|builtin| builtin.name())
+}
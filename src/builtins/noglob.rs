@@ -0,0 +1,36 @@
+use std::io;
+
+use super::Builtin;
+use crate::exec;
+use crate::shell::ShellState;
+
+/// `noglob command [arg ...]` runs a command with its arguments left
+/// unglobbed, as in zsh. The arguments are expanded before a builtin sees
+/// them, so it's the shell that spots `noglob` in front of a command; the
+/// builtin only runs the rest, as when it's reached some other way.
+pub struct Noglob;
+
+const SYNOPSIS: &str = "command [arg ...]";
+
+impl Builtin for Noglob {
+    fn name(&self) -> &'static str {
+        "noglob"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a command without expanding glob patterns in its arguments, so \
+         that patterns for programs like find and scp reach them untouched. \
+         `set -f` does the same for every command."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() < 2 {
+            return Ok(0);
+        }
+        exec::run_args(shell, args[1..].to_vec())
+    }
+}
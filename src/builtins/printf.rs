@@ -0,0 +1,510 @@
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::slice;
+use std::str::Chars;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Builtin;
+use crate::exec::quote_word;
+use crate::platform;
+use crate::shell::{self, ShellState};
+use crate::vars::split_subscript;
+
+/// `printf [-v var] format [args...]` formats its arguments as C's printf
+/// does, reusing the format until they run out. `%b` expands escapes in its
+/// argument, and `%q` quotes it so the shell would read it back unchanged.
+/// `%(fmt)T` formats a time in seconds since the epoch as `strftime` does,
+/// with -1, or no argument, for now and -2 for when the shell started.
+pub struct Printf;
+
+const SYNOPSIS: &str = "[-v var] format [args ...]";
+
+/// Why formatting stopped before the end of the format.
+enum Stop {
+    /// A `\c` escape, which ends all output.
+    Escape,
+    /// A conversion it doesn't know.
+    Invalid(char),
+    /// A `%` at the end with no conversion after it.
+    Missing,
+    /// A `%(` without its `)T`.
+    Unclosed,
+    /// A time `strftime` couldn't format.
+    Time(i64),
+}
+
+/// A conversion's flags, width and precision.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Pad `text` out to the width, with zeros after any sign or `0x` if
+    /// it's `numeric` and that was asked for.
+    fn pad(&self, text: &[u8], numeric: bool, out: &mut Vec<u8>) {
+        let fill = self.width.saturating_sub(text.len());
+        if self.left {
+            out.extend_from_slice(text);
+            out.resize(out.len() + fill, b' ');
+        } else if self.zero && numeric {
+            let mut prefix = usize::from(matches!(text.first(), Some(b'-' | b'+' | b' ')));
+            if text[prefix..].starts_with(b"0x") || text[prefix..].starts_with(b"0X") {
+                prefix += 2;
+            }
+            out.extend_from_slice(&text[..prefix]);
+            out.resize(out.len() + fill, b'0');
+            out.extend_from_slice(&text[prefix..]);
+        } else {
+            out.resize(out.len() + fill, b' ');
+            out.extend_from_slice(text);
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        match (negative, self.plus, self.space) {
+            (true, ..) => "-",
+            (false, true, _) => "+",
+            (false, false, true) => " ",
+            _ => "",
+        }
+    }
+}
+
+/// What's been formatted so far.
+#[derive(Default)]
+struct Output {
+    bytes: Vec<u8>,
+    /// Whether an argument wasn't a valid number.
+    failed: bool,
+}
+
+impl Output {
+    fn integer(&mut self, arg: Option<&String>) -> i64 {
+        let arg = arg.map_or("", |arg| arg.as_str());
+        parse_integer(arg).unwrap_or_else(|| {
+            eprintln!("printf: {arg}: invalid number");
+            self.failed = true;
+            0
+        })
+    }
+
+    fn float(&mut self, arg: Option<&String>) -> f64 {
+        let arg = arg.map_or("", |arg| arg.trim());
+        match arg.parse() {
+            Ok(value) => value,
+            Err(_) => self.integer(Some(&arg.to_string())) as f64,
+        }
+    }
+}
+
+/// An integer argument: decimal, hex with `0x`, octal with `0`, or the
+/// character code of what follows a quote. Nothing at all is 0.
+fn parse_integer(arg: &str) -> Option<i64> {
+    let arg = arg.trim_start();
+    if arg.is_empty() {
+        return Some(0);
+    }
+    if let Some(rest) = arg.strip_prefix(['\'', '"']) {
+        return Some(rest.chars().next().map_or(0, |c| c as i64));
+    }
+    let (negative, digits) = match arg.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, arg.strip_prefix('+').unwrap_or(arg)),
+    };
+    let magnitude = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    // Out of range values wrap, as they do in C
+    let value = magnitude as i64;
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+/// Expand the backslash escape just after the `\` in `chars`. In `%b`
+/// arguments octal escapes start with `\0`, as they do for echo.
+fn escape(chars: &mut Peekable<Chars>, out: &mut Vec<u8>, argument: bool) -> Result<(), Stop> {
+    fn digits(chars: &mut Peekable<Chars>, radix: u32, max: usize, mut value: u32) -> u32 {
+        for _ in 0..max {
+            let Some(digit) = chars.peek().and_then(|c| c.to_digit(radix)) else {
+                break;
+            };
+            value = value * radix + digit;
+            chars.next();
+        }
+        value
+    }
+
+    let Some(c) = chars.next() else {
+        out.push(b'\\');
+        return Ok(());
+    };
+    let byte = match c {
+        'a' => 0x07,
+        'b' => 0x08,
+        'c' => return Err(Stop::Escape),
+        'e' | 'E' => 0x1b,
+        'f' => 0x0c,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0b,
+        '\\' => b'\\',
+        '0' if argument => digits(chars, 8, 3, 0) as u8,
+        '0'..='7' => digits(chars, 8, 2, c.to_digit(8).unwrap_or(0)) as u8,
+        'x' if chars.peek().is_some_and(char::is_ascii_hexdigit) => digits(chars, 16, 2, 0) as u8,
+        'u' | 'U' if chars.peek().is_some_and(char::is_ascii_hexdigit) => {
+            let max = if c == 'u' { 4 } else { 8 };
+            let c = char::from_u32(digits(chars, 16, max, 0)).unwrap_or('\u{fffd}');
+            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            return Ok(());
+        }
+        c => {
+            out.push(b'\\');
+            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            return Ok(());
+        }
+    };
+    out.push(byte);
+    Ok(())
+}
+
+/// `value` as `%e` writes it, with a signed exponent of at least two digits.
+fn exponential(value: f64, precision: usize, alternate: bool) -> String {
+    let formatted = format!("{value:.precision$e}");
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let point = if alternate && precision == 0 { "." } else { "" };
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}{point}e{sign}{:02}", exponent.abs())
+}
+
+/// A non-negative float as `%f`, `%e` or `%g` writes it.
+fn float(spec: &Spec, conversion: char, value: f64) -> String {
+    if !value.is_finite() {
+        return if value.is_nan() { "nan" } else { "inf" }.to_string();
+    }
+    let precision = spec.precision.unwrap_or(6);
+    match conversion.to_ascii_lowercase() {
+        'f' => {
+            let point = if spec.alternate && precision == 0 {
+                "."
+            } else {
+                ""
+            };
+            format!("{value:.precision$}{point}")
+        }
+        'e' => exponential(value, precision, spec.alternate),
+        _ => {
+            // The shorter of %e and %f, going by the exponent, with the
+            // precision counting significant digits
+            let significant = precision.max(1);
+            let shown = exponential(value, significant - 1, false);
+            let exponent: i64 = shown
+                .rsplit_once('e')
+                .map_or(0, |(_, e)| e.parse().unwrap_or(0));
+            let mut formatted = if exponent < -4 || exponent >= significant as i64 {
+                shown
+            } else {
+                let decimals = (significant as i64 - 1 - exponent) as usize;
+                format!("{value:.decimals$}")
+            };
+            if !spec.alternate {
+                let (number, exponent) = match formatted.find('e') {
+                    Some(i) => formatted.split_at(i),
+                    None => (formatted.as_str(), ""),
+                };
+                if number.contains('.') {
+                    let number = number.trim_end_matches('0').trim_end_matches('.');
+                    formatted = format!("{number}{exponent}");
+                }
+            }
+            formatted
+        }
+    }
+}
+
+/// Go through the format once, taking arguments for its conversions.
+fn format_once(format: &str, args: &mut slice::Iter<String>, out: &mut Output) -> Result<(), Stop> {
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escape(&mut chars, &mut out.bytes, false)?,
+            '%' if chars.next_if_eq(&'%').is_some() => out.bytes.push(b'%'),
+            '%' => convert(&mut chars, args, out)?,
+            c => out
+                .bytes
+                .extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Ok(())
+}
+
+/// Format the argument for the conversion after a `%`.
+fn convert(
+    chars: &mut Peekable<Chars>,
+    args: &mut slice::Iter<String>,
+    out: &mut Output,
+) -> Result<(), Stop> {
+    let mut spec = Spec::default();
+    while let Some(flag) = chars.next_if(|c| "-0+ #".contains(*c)) {
+        match flag {
+            '-' => spec.left = true,
+            '0' => spec.zero = true,
+            '+' => spec.plus = true,
+            ' ' => spec.space = true,
+            _ => spec.alternate = true,
+        }
+    }
+    // A `*` takes the number from the arguments
+    let mut number = |chars: &mut Peekable<Chars>, out: &mut Output| -> Option<i64> {
+        if chars.next_if_eq(&'*').is_some() {
+            return Some(out.integer(args.next()));
+        }
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            digits.push(digit);
+        }
+        digits.parse().ok()
+    };
+    if let Some(width) = number(chars, out) {
+        spec.left |= width < 0;
+        spec.width = width.unsigned_abs() as usize;
+    }
+    if chars.next_if_eq(&'.').is_some() {
+        // A negative precision is as good as none
+        spec.precision = match number(chars, out) {
+            Some(precision) if precision < 0 => None,
+            precision => Some(precision.unwrap_or(0) as usize),
+        };
+    }
+    // Length modifiers mean nothing when every integer is 64 bits
+    while chars.next_if(|c| "hlLjzt".contains(*c)).is_some() {}
+
+    if chars.next_if_eq(&'(').is_some() {
+        return time(chars, args.next(), &spec, out);
+    }
+    let conversion = chars.next().ok_or(Stop::Missing)?;
+    if conversion == '%' {
+        out.bytes.push(b'%');
+        return Ok(());
+    }
+    let arg = args.next();
+    let text = arg.map_or("", |arg| arg.as_str());
+    match conversion {
+        's' | 'q' => {
+            let text = if conversion == 'q' {
+                quote_word(text)
+            } else {
+                text.to_string()
+            };
+            let text = match spec.precision {
+                Some(precision) => text.chars().take(precision).collect(),
+                None => text,
+            };
+            spec.pad(text.as_bytes(), false, &mut out.bytes);
+        }
+        'b' => {
+            let mut expanded = Vec::new();
+            let mut chars = text.chars().peekable();
+            let mut stopped = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if escape(&mut chars, &mut expanded, true).is_err() {
+                            stopped = true;
+                            break;
+                        }
+                    }
+                    c => expanded.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+            if let Some(precision) = spec.precision {
+                expanded.truncate(precision);
+            }
+            spec.pad(&expanded, false, &mut out.bytes);
+            if stopped {
+                return Err(Stop::Escape);
+            }
+        }
+        'c' => {
+            let c = text.chars().next().map(String::from).unwrap_or_default();
+            spec.pad(c.as_bytes(), false, &mut out.bytes);
+        }
+        'd' | 'i' => {
+            let value = out.integer(arg);
+            let digits = value.unsigned_abs().to_string();
+            let digits = pad_digits(digits, spec.precision);
+            let text = format!("{}{digits}", spec.sign(value < 0));
+            spec.pad(text.as_bytes(), spec.precision.is_none(), &mut out.bytes);
+        }
+        'u' | 'o' | 'x' | 'X' => {
+            let value = out.integer(arg) as u64;
+            let digits = match conversion {
+                'u' => value.to_string(),
+                'o' => format!("{value:o}"),
+                'x' => format!("{value:x}"),
+                _ => format!("{value:X}"),
+            };
+            let mut digits = pad_digits(digits, spec.precision);
+            if spec.alternate && value != 0 {
+                match conversion {
+                    'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                    'x' => digits.insert_str(0, "0x"),
+                    'X' => digits.insert_str(0, "0X"),
+                    _ => {}
+                }
+            }
+            spec.pad(digits.as_bytes(), spec.precision.is_none(), &mut out.bytes);
+        }
+        'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+            let value = out.float(arg);
+            let mut text = float(&spec, conversion, value.abs());
+            if conversion.is_ascii_uppercase() {
+                text = text.to_uppercase();
+            }
+            let text = format!(
+                "{}{text}",
+                spec.sign(value.is_sign_negative() && !value.is_nan())
+            );
+            spec.pad(text.as_bytes(), value.is_finite(), &mut out.bytes);
+        }
+        c => return Err(Stop::Invalid(c)),
+    }
+    Ok(())
+}
+
+/// Format the time in `arg` for a `%(fmt)T`, just after its `(`.
+fn time(
+    chars: &mut Peekable<Chars>,
+    arg: Option<&String>,
+    spec: &Spec,
+    out: &mut Output,
+) -> Result<(), Stop> {
+    let mut format: String = chars.by_ref().take_while(|&c| c != ')').collect();
+    if chars.next() != Some('T') {
+        return Err(Stop::Unclosed);
+    }
+    if format.is_empty() {
+        format.push_str("%X");
+    }
+    let seconds = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as i64)
+    };
+    let time = match arg.map(|_| out.integer(arg)) {
+        None | Some(-1) => seconds(SystemTime::now()),
+        Some(-2) => seconds(shell::started()),
+        Some(time) => time,
+    };
+    let text = platform::format_time(&format, time).ok_or(Stop::Time(time))?;
+    let text: String = match spec.precision {
+        Some(precision) => text.chars().take(precision).collect(),
+        None => text,
+    };
+    spec.pad(text.as_bytes(), false, &mut out.bytes);
+    Ok(())
+}
+
+/// Zero-pad `digits` to at least `precision` of them.
+fn pad_digits(digits: String, precision: Option<usize>) -> String {
+    match precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if digits.len() < precision => {
+            format!("{}{digits}", "0".repeat(precision - digits.len()))
+        }
+        _ => digits,
+    }
+}
+
+impl Builtin for Printf {
+    fn name(&self) -> &'static str {
+        "printf"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Format and print the arguments, or assign the result to a variable."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let (variable, args) = match args.get(1).map(String::as_str) {
+            Some("-v") if args.len() > 2 => (Some(&args[2]), &args[3..]),
+            Some("--") => (None, &args[2..]),
+            _ => (None, &args[1..]),
+        };
+        let Some((format, args)) = args.split_first() else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+
+        // The format is reused until the arguments run out, but not if it
+        // didn't take any
+        let mut out = Output::default();
+        let mut args = args.iter();
+        loop {
+            let remaining = args.len();
+            match format_once(format, &mut args, &mut out) {
+                Ok(()) if args.len() > 0 && args.len() < remaining => {}
+                Ok(()) | Err(Stop::Escape) => break,
+                Err(Stop::Invalid(c)) => {
+                    eprintln!("printf: %{c}: invalid conversion");
+                    out.failed = true;
+                    break;
+                }
+                Err(Stop::Missing) => {
+                    eprintln!("printf: %: missing conversion");
+                    out.failed = true;
+                    break;
+                }
+                Err(Stop::Unclosed) => {
+                    eprintln!("printf: %(: missing )T");
+                    out.failed = true;
+                    break;
+                }
+                Err(Stop::Time(time)) => {
+                    eprintln!("printf: {time}: can't format that time");
+                    out.failed = true;
+                    break;
+                }
+            }
+        }
+
+        match variable {
+            Some(name) => {
+                let value = String::from_utf8_lossy(&out.bytes).into_owned();
+                match split_subscript(name) {
+                    Some((name, subscript)) => {
+                        shell.variables.set_element(name, subscript, value)?
+                    }
+                    None => shell.variables.set(name.as_str(), value)?,
+                }
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                match stdout.write_all(&out.bytes).and_then(|()| stdout.flush()) {
+                    // Whoever was reading has had enough
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                    result => result?,
+                }
+            }
+        }
+        Ok(i32::from(out.failed))
+    }
+}
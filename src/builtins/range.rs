@@ -0,0 +1,197 @@
+use std::io::{self, BufWriter, Write};
+
+use super::{Builtin, INTERRUPTED};
+use crate::platform::InterruptGuard;
+use crate::shell::ShellState;
+
+/// `range [-s sep] [-w] [first] last [step]`, like `seq` but in the order of
+/// a `{first..last..step}` brace range. Endpoints may be integers, decimals
+/// or single letters, and counting goes down when `last` is below `first`.
+pub struct Range;
+
+const SYNOPSIS: &str = "[-s separator] [-w] [first] last [step]";
+
+enum Endpoints {
+    Integer(i64, i64),
+    /// With how many decimal places to print.
+    Decimal(f64, f64, usize),
+    Letter(char, char),
+}
+
+fn decimals(number: &str) -> usize {
+    number
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len())
+}
+
+fn letter(arg: &str) -> Option<char> {
+    let mut chars = arg.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_ascii_digit() => Some(c),
+        _ => None,
+    }
+}
+
+impl Endpoints {
+    fn parse(first: &str, last: &str, step: &str) -> Option<Endpoints> {
+        if let (Some(first), Some(last)) = (letter(first), letter(last)) {
+            return Some(Endpoints::Letter(first, last));
+        }
+        if let (Ok(first), Ok(last)) = (first.parse(), last.parse()) {
+            if step.parse::<i64>().is_ok() {
+                return Some(Endpoints::Integer(first, last));
+            }
+        }
+        let places = decimals(first).max(decimals(last)).max(decimals(step));
+        Some(Endpoints::Decimal(
+            first.parse().ok()?,
+            last.parse().ok()?,
+            places,
+        ))
+    }
+
+    /// The values from one end to the other, computed as they're needed so
+    /// huge ranges cost nothing up front.
+    fn values(self, step: f64, width: bool) -> Box<dyn Iterator<Item = String>> {
+        match self {
+            Endpoints::Integer(first, last) => {
+                let step = step as i64;
+                let step = if last < first { -step } else { step };
+                let width = if width {
+                    first.to_string().len().max(last.to_string().len())
+                } else {
+                    0
+                };
+                Box::new(
+                    std::iter::successors(Some(first), move |&n| n.checked_add(step))
+                        .take_while(move |&n| if step > 0 { n <= last } else { n >= last })
+                        .map(move |n| format!("{n:0width$}")),
+                )
+            }
+            Endpoints::Decimal(first, last, places) => {
+                let step = if last < first { -step } else { step };
+                // Allow for rounding error when landing on the last value
+                let slack = step.abs() * 1e-9;
+                let width = if width {
+                    let shown = |n: f64| format!("{n:.places$}").len();
+                    shown(first).max(shown(last))
+                } else {
+                    0
+                };
+                Box::new(
+                    (0u64..)
+                        .map(move |i| first + i as f64 * step)
+                        .take_while(move |&n| {
+                            if step > 0.0 {
+                                n <= last + slack
+                            } else {
+                                n >= last - slack
+                            }
+                        })
+                        .map(move |n| format!("{n:0width$.places$}")),
+                )
+            }
+            Endpoints::Letter(first, last) => {
+                let (first, last, step) = (first as u32, last as u32, step as u32);
+                let codes: Box<dyn Iterator<Item = u32>> = if first <= last {
+                    Box::new((first..=last).step_by(step as usize))
+                } else {
+                    Box::new((last..=first).rev().step_by(step as usize))
+                };
+                Box::new(codes.filter_map(char::from_u32).map(String::from))
+            }
+        }
+    }
+}
+
+impl Builtin for Range {
+    fn name(&self) -> &'static str {
+        "range"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Print a sequence of numbers or letters."
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut separator = "\n".to_string();
+        let mut width = false;
+        let mut args = &args[1..];
+        loop {
+            match args.first().map(String::as_str) {
+                Some("-s") => match args.get(1) {
+                    Some(sep) => {
+                        separator = sep.clone();
+                        args = &args[2..];
+                    }
+                    None => {
+                        eprintln!("range: -s: option requires an argument");
+                        return Ok(2);
+                    }
+                },
+                Some("-w") => {
+                    width = true;
+                    args = &args[1..];
+                }
+                Some("--") => {
+                    args = &args[1..];
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        let (first, last, step) = match args {
+            [last] => ("1", last.as_str(), "1"),
+            [first, last] => (first.as_str(), last.as_str(), "1"),
+            [first, last, step] => (first.as_str(), last.as_str(), step.as_str()),
+            _ => {
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+        };
+        let (Some(endpoints), Some(step_size)) = (
+            Endpoints::parse(first, last, step),
+            step.parse::<f64>().ok().map(f64::abs),
+        ) else {
+            eprintln!("range: {} {} {}: invalid range", first, last, step);
+            return Ok(2);
+        };
+        let fractional = step_size.fract() != 0.0;
+        if step_size == 0.0 || (fractional && matches!(endpoints, Endpoints::Letter(..))) {
+            eprintln!("range: {}: invalid step", step);
+            return Ok(2);
+        }
+
+        let guard = InterruptGuard::new();
+        let mut out = BufWriter::new(io::stdout().lock());
+        let mut values = endpoints.values(step_size, width).peekable();
+        while let Some(value) = values.next() {
+            if guard.interrupted() {
+                return Ok(INTERRUPTED);
+            }
+            let end = if values.peek().is_some() {
+                separator.as_str()
+            } else {
+                "\n"
+            };
+            match write!(out, "{value}{end}") {
+                // Whoever was reading has had enough
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(0),
+                result => result?,
+            }
+        }
+        match out.flush() {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(0),
+            result => result.map(|()| 0),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::timeout::parse_duration;
+use super::{Builtin, INTERRUPTED};
+use crate::platform::InterruptGuard;
+use crate::shell::ShellState;
+
+/// `sleep duration...` waits for the total of its arguments, which may be
+/// fractional and have a unit suffix, without starting a process. Ctrl-C
+/// cuts it short.
+pub struct Sleep;
+
+const SYNOPSIS: &str = "duration[s|m|h|d] ...";
+
+/// How often to check for Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+impl Builtin for Sleep {
+    fn name(&self) -> &'static str {
+        "sleep"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Wait for the total of the durations."
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() < 2 {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        let mut total = Duration::ZERO;
+        for arg in &args[1..] {
+            match parse_duration(arg) {
+                Some(duration) => total = total.saturating_add(duration),
+                None => {
+                    eprintln!("sleep: {}: invalid duration", arg);
+                    return Ok(2);
+                }
+            }
+        }
+
+        let guard = InterruptGuard::new();
+        let deadline = Instant::now().checked_add(total);
+        loop {
+            if guard.interrupted() {
+                return Ok(INTERRUPTED);
+            }
+            let left = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => POLL_INTERVAL,
+            };
+            if left.is_zero() {
+                return Ok(0);
+            }
+            thread::sleep(POLL_INTERVAL.min(left));
+        }
+    }
+}
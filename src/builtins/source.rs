@@ -0,0 +1,40 @@
+use std::io;
+use std::path::Path;
+
+use super::Builtin;
+use crate::shell::ShellState;
+
+/// `source`, also known as `.`.
+pub struct Source(pub &'static str);
+
+impl Builtin for Source {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "file [arg ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run the commands in a file in this shell, one line at a time. Any args are \
+         the positional parameters while it runs."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let Some(file) = args.get(1) else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+
+        let saved = (args.len() > 2).then(|| shell.variables.set_positional(args[2..].to_vec()));
+        let res = shell.source(Path::new(file));
+        if let Some(saved) = saved {
+            shell.variables.set_positional(saved);
+        }
+        res.or_else(|e| {
+            eprintln!("{}: {}: {}", self.0, file, e);
+            Ok(1)
+        })
+    }
+}
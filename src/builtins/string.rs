@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use super::Builtin;
+use crate::glob;
+use crate::shell::ShellState;
+
+/// `string SUBCOMMAND [options] [args...]`, fish's text toolkit. Strings come
+/// from the arguments, or from the lines of standard input if there are none.
+pub struct StringBuiltin;
+
+const SYNOPSIS: &str = "length|sub|split|join|replace|match|upper|lower|trim|pad \
+[options] [string ...]";
+
+/// A subcommand's options and the arguments after them.
+struct Parsed {
+    flags: Vec<char>,
+    values: HashMap<char, String>,
+    args: Vec<String>,
+}
+
+impl Parsed {
+    fn has(&self, flag: char) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    fn number(&self, flag: char) -> Result<Option<i64>, String> {
+        self.values
+            .get(&flag)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("-{flag}: invalid number '{value}'"))
+            })
+            .transpose()
+    }
+}
+
+/// Parse options up to the first argument that isn't one. `flags` are the
+/// letters allowed on their own, `valued` those which take a value.
+fn parse(args: &[String], flags: &str, valued: &str) -> Result<Parsed, String> {
+    let mut parsed = Parsed {
+        flags: Vec::new(),
+        values: HashMap::new(),
+        args: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        let Some(letters) = arg.strip_prefix('-').filter(|letters| !letters.is_empty()) else {
+            parsed.args.push(arg.clone());
+            break;
+        };
+        for (i, letter) in letters.char_indices() {
+            if valued.contains(letter) {
+                // The value is the rest of the arg, or the next one
+                let rest = &letters[i + letter.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next()
+                        .cloned()
+                        .ok_or_else(|| format!("-{letter}: option requires an argument"))?
+                } else {
+                    rest.to_string()
+                };
+                parsed.values.insert(letter, value);
+                break;
+            } else if flags.contains(letter) {
+                parsed.flags.push(letter);
+            } else {
+                return Err(format!("-{letter}: invalid option"));
+            }
+        }
+    }
+    parsed.args.extend(args.cloned());
+    Ok(parsed)
+}
+
+/// The strings to work on: `args`, or failing that, standard input's lines
+/// as they're read.
+fn inputs(args: Vec<String>) -> Box<dyn Iterator<Item = String>> {
+    if !args.is_empty() {
+        return Box::new(args.into_iter());
+    }
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Box::new(std::iter::empty());
+    }
+    Box::new(stdin.lock().lines().map_while(Result::ok))
+}
+
+/// Split off the leading arguments a subcommand needs before its strings.
+fn take_args<const N: usize>(parsed: &mut Parsed, what: &str) -> Result<[String; N], String> {
+    if parsed.args.len() < N {
+        return Err(format!("expected {what}"));
+    }
+    let rest = parsed.args.split_off(N);
+    let taken = std::mem::replace(&mut parsed.args, rest);
+    Ok(taken.try_into().unwrap_or_else(|_| unreachable!()))
+}
+
+/// Turn fish's 1-based, possibly negative, positions into a 0-based index.
+fn position(index: i64, len: usize) -> usize {
+    if index < 0 {
+        len.saturating_sub(index.unsigned_abs() as usize)
+    } else {
+        (index as usize).saturating_sub(1).min(len)
+    }
+}
+
+fn substring(text: &str, start: Option<i64>, length: Option<i64>, end: Option<i64>) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let from = start.map_or(0, |start| position(start, chars.len()));
+    let to = match (length, end) {
+        (Some(length), _) => from.saturating_add(length.max(0) as usize),
+        (None, Some(end)) if end < 0 => position(end, chars.len()) + 1,
+        (None, Some(end)) => end as usize,
+        (None, None) => chars.len(),
+    }
+    .min(chars.len());
+    chars[from.min(to)..to].iter().collect()
+}
+
+fn split(text: &str, separator: &str, max: Option<usize>, from_right: bool) -> Vec<String> {
+    if separator.is_empty() {
+        return text.chars().map(String::from).collect();
+    }
+    let parts: Vec<String> = match (max, from_right) {
+        (Some(max), false) => text.splitn(max + 1, separator).map(String::from).collect(),
+        (Some(max), true) => {
+            let mut parts: Vec<String> =
+                text.rsplitn(max + 1, separator).map(String::from).collect();
+            parts.reverse();
+            parts
+        }
+        (None, _) => text.split(separator).map(String::from).collect(),
+    };
+    parts
+}
+
+fn replace(text: &str, pattern: &str, replacement: &str, all: bool, fold: bool) -> Option<String> {
+    if pattern.is_empty() {
+        return None;
+    }
+    // Find matches in a lower-cased copy when ignoring case; lowering can
+    // change lengths, so only do that where it doesn't
+    let haystack = if fold && text.to_lowercase().len() == text.len() {
+        text.to_lowercase()
+    } else {
+        text.to_string()
+    };
+    let needle = if fold {
+        pattern.to_lowercase()
+    } else {
+        pattern.to_string()
+    };
+
+    let mut out = String::new();
+    let mut last = 0;
+    for (i, _) in haystack.match_indices(&needle) {
+        out.push_str(&text[last..i]);
+        out.push_str(replacement);
+        last = i + needle.len();
+        if !all {
+            break;
+        }
+    }
+    if last == 0 && !haystack.starts_with(&needle) {
+        return None;
+    }
+    out.push_str(&text[last..]);
+    Some(out)
+}
+
+fn trim<'a>(text: &'a str, chars: &str, left: bool, right: bool) -> &'a str {
+    let strip = |c: char| {
+        if chars.is_empty() {
+            c.is_whitespace()
+        } else {
+            chars.contains(c)
+        }
+    };
+    match (left, right) {
+        (true, false) => text.trim_start_matches(strip),
+        (false, true) => text.trim_end_matches(strip),
+        _ => text.trim_matches(strip),
+    }
+}
+
+/// The letters each subcommand takes on their own, and those which take a
+/// value.
+fn options(name: &str) -> Option<(&'static str, &'static str)> {
+    Some(match name {
+        "length" | "join" | "upper" | "lower" => ("q", ""),
+        "sub" => ("q", "sle"),
+        "split" => ("qrn", "m"),
+        "replace" => ("qaif", ""),
+        "match" => ("qiv", ""),
+        "trim" => ("qlr", "c"),
+        "pad" => ("qr", "wc"),
+        _ => return None,
+    })
+}
+
+/// Run a subcommand, handing each line to print to `print` as soon as it's
+/// worked out, unless `-q` is given. Returns whether it did anything, which
+/// becomes the exit status.
+fn subcommand(name: &str, args: &[String], print: &mut dyn FnMut(&str)) -> Result<bool, String> {
+    let (flags, valued) = options(name).ok_or_else(|| format!("{name}: unknown subcommand"))?;
+    let mut parsed = parse(args, flags, valued)?;
+    let quiet = parsed.has('q');
+    let mut emit = |line: &str| {
+        if !quiet {
+            print(line);
+        }
+    };
+
+    let any = match name {
+        "length" => {
+            let mut any = false;
+            for s in inputs(std::mem::take(&mut parsed.args)) {
+                let length = s.chars().count();
+                any |= length > 0;
+                emit(&length.to_string());
+            }
+            any
+        }
+        "sub" => {
+            let (start, length, end) = (
+                parsed.number('s')?,
+                parsed.number('l')?,
+                parsed.number('e')?,
+            );
+            if length.is_some() && end.is_some() {
+                return Err("-l and -e can't be used together".to_string());
+            }
+            let mut any = false;
+            for s in inputs(std::mem::take(&mut parsed.args)) {
+                any = true;
+                emit(&substring(&s, start, length, end));
+            }
+            any
+        }
+        "split" => {
+            let max = parsed.number('m')?.map(|max| max.max(0) as usize);
+            let [separator] = take_args(&mut parsed, "a separator")?;
+            let mut any = false;
+            for s in inputs(std::mem::take(&mut parsed.args)) {
+                let parts = split(&s, &separator, max, parsed.has('r'));
+                any |= parts.len() > 1;
+                for part in parts {
+                    if !parsed.has('n') || !part.is_empty() {
+                        emit(&part);
+                    }
+                }
+            }
+            any
+        }
+        "join" => {
+            let [separator] = take_args(&mut parsed, "a separator")?;
+            let strings: Vec<_> = inputs(std::mem::take(&mut parsed.args)).collect();
+            emit(&strings.join(&separator));
+            strings.len() > 1
+        }
+        "replace" => {
+            let [pattern, replacement] = take_args(&mut parsed, "a pattern and a replacement")?;
+            let (all, fold, filter) = (parsed.has('a'), parsed.has('i'), parsed.has('f'));
+            let mut any = false;
+            for s in inputs(std::mem::take(&mut parsed.args)) {
+                match replace(&s, &pattern, &replacement, all, fold) {
+                    Some(replaced) => {
+                        any = true;
+                        emit(&replaced);
+                    }
+                    None if !filter => emit(&s),
+                    None => {}
+                }
+            }
+            any
+        }
+        "match" => {
+            let [pattern] = take_args(&mut parsed, "a pattern")?;
+            let (fold, invert) = (parsed.has('i'), parsed.has('v'));
+            let pattern = if fold {
+                pattern.to_lowercase()
+            } else {
+                pattern
+            };
+            let mut any = false;
+            for s in inputs(std::mem::take(&mut parsed.args)) {
+                let folded = if fold { s.to_lowercase() } else { s.clone() };
+                if glob::matches(&pattern, &folded) != invert {
+                    any = true;
+                    emit(&s);
+                }
+            }
+            any
+        }
+        "upper" | "lower" => {
+            let mut any = false;
+            for s in inputs(std::mem::take(&mut parsed.args)) {
+                let changed = if name == "upper" {
+                    s.to_uppercase()
+                } else {
+                    s.to_lowercase()
+                };
+                any |= changed != s;
+                emit(&changed);
+            }
+            any
+        }
+        "trim" => {
+            let chars = parsed.values.get(&'c').cloned().unwrap_or_default();
+            let mut any = false;
+            for s in inputs(std::mem::take(&mut parsed.args)) {
+                let trimmed = trim(&s, &chars, parsed.has('l'), parsed.has('r'));
+                any |= trimmed.len() != s.len();
+                emit(trimmed);
+            }
+            any
+        }
+        "pad" => {
+            // Every string has to be seen to find the longest
+            let fill = match parsed.values.get(&'c') {
+                Some(fill) if fill.chars().count() == 1 => fill.chars().next().unwrap_or(' '),
+                Some(_) => return Err("-c: expected a single character".to_string()),
+                None => ' ',
+            };
+            let strings: Vec<_> = inputs(std::mem::take(&mut parsed.args)).collect();
+            let longest = strings.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+            let width = parsed.number('w')?.map_or(longest, |w| w.max(0) as usize);
+            let mut any = false;
+            for s in &strings {
+                let padding: String =
+                    std::iter::repeat_n(fill, width.saturating_sub(s.chars().count())).collect();
+                any |= !padding.is_empty();
+                if parsed.has('r') {
+                    emit(&format!("{s}{padding}"));
+                } else {
+                    emit(&format!("{padding}{s}"));
+                }
+            }
+            any
+        }
+        _ => unreachable!("options() knows every subcommand"),
+    };
+    Ok(any)
+}
+
+impl Builtin for StringBuiltin {
+    fn name(&self) -> &'static str {
+        "string"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Manipulate strings given as arguments or read from standard input."
+    }
+
+    fn run(&self, _shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let Some(name) = args.get(1) else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+
+        let mut stdout = io::stdout().lock();
+        let mut print = |line: &str| {
+            let _ = writeln!(stdout, "{line}");
+        };
+        match subcommand(name, &args[2..], &mut print) {
+            Ok(any) => Ok(if any { 0 } else { 1 }),
+            Err(e) => {
+                eprintln!("string {}: {}", name, e);
+                Ok(2)
+            }
+        }
+    }
+}
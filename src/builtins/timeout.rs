@@ -0,0 +1,137 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Builtin;
+use crate::exec::{self, Started};
+use crate::jobs::JobState;
+use crate::platform::{self, Process};
+use crate::shell::ShellState;
+
+/// `timeout [-k duration] duration command...` runs a command, which may be a
+/// builtin or a function, sending it SIGTERM if it's still going after
+/// `duration`, then SIGKILL if it's still going `-k duration` after that.
+pub struct Timeout;
+
+const SYNOPSIS: &str = "[-k duration] duration command [arg ...]";
+
+/// Exit status when the command ran out of time, as for coreutils' timeout.
+const TIMED_OUT: i32 = 124;
+
+/// How long a command gets to exit after SIGTERM before SIGKILL, without `-k`.
+const DEFAULT_KILL_AFTER: Duration = Duration::from_secs(5);
+
+/// How often to check whether the command is done.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Parse a duration like `5`, `1.5s`, `2m`, `1h` or `1d`.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.find(|c: char| c.is_alphabetic()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let scale = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        _ => return None,
+    };
+    let seconds: f64 = number.parse().ok()?;
+    Duration::try_from_secs_f64(seconds * scale).ok()
+}
+
+/// Wait up to `deadline` for `process` to exit, returning its status if it
+/// did.
+fn wait_until(process: &mut Process, deadline: Instant) -> io::Result<Option<i32>> {
+    loop {
+        if let Some(status) = process.try_wait()? {
+            return Ok(Some(status.code()));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+fn run_with_limit(process: &mut Process, limit: Duration, kill_after: Duration) -> io::Result<i32> {
+    if let Some(status) = wait_until(process, Instant::now() + limit)? {
+        return Ok(status);
+    }
+
+    process.terminate(false)?;
+    if wait_until(process, Instant::now() + kill_after)?.is_some() {
+        return Ok(TIMED_OUT);
+    }
+
+    process.terminate(true)?;
+    Ok(process.wait()?.code())
+}
+
+impl Builtin for Timeout {
+    fn name(&self) -> &'static str {
+        "timeout"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        SYNOPSIS
+    }
+
+    fn description(&self) -> &'static str {
+        "Run a command, killing it if it takes longer than the duration."
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let mut kill_after = DEFAULT_KILL_AFTER;
+        let mut args = args[1..].iter();
+        let mut limit = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-k" => match args.next().and_then(|arg| parse_duration(arg)) {
+                    Some(duration) => kill_after = duration,
+                    None => {
+                        eprintln!("timeout: -k: invalid duration");
+                        return Ok(2);
+                    }
+                },
+                arg => match parse_duration(arg) {
+                    Some(duration) => {
+                        limit = Some(duration);
+                        break;
+                    }
+                    None => {
+                        eprintln!("timeout: {}: invalid duration", arg);
+                        return Ok(2);
+                    }
+                },
+            }
+        }
+
+        let command: Vec<String> = args.cloned().collect();
+        let Some(limit) = limit.filter(|_| !command.is_empty()) else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+
+        let mut process = match exec::start_command(shell, command)? {
+            Started::Process(process) => process,
+            // Nothing to time out where there's no fork
+            Started::Finished(status, _) => return Ok(status),
+        };
+        let status = run_with_limit(&mut process, limit, kill_after);
+        if let Ok(status) = status {
+            shell.children.changed(process.id(), JobState::Done(status));
+        }
+        if shell.job_control {
+            platform::reclaim_terminal()?;
+        }
+        status
+    }
+}
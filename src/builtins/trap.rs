@@ -0,0 +1,89 @@
+use std::io;
+
+use super::Builtin;
+use crate::expand::quote;
+use crate::platform;
+use crate::shell::ShellState;
+
+pub struct Trap;
+
+/// The conditions a trap can be set for. Signals aren't supported yet.
+const CONDITIONS: &[&str] = &["DEBUG", "ERR"];
+
+impl Builtin for Trap {
+    fn name(&self) -> &'static str {
+        "trap"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-l] [-p [condition...]] [[--] action condition...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run action before each command (DEBUG) or after each one that fails (ERR), \
+         with $LINENO saying where. An action of - removes the trap and '' ignores \
+         the condition. -p, or no arguments, lists the traps as commands that set \
+         them again, and -l lists the signals. Subshells keep only ignored \
+         conditions, but list the traps they came from until they set their own."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let args = &args[1..];
+        match args.first().map(String::as_str) {
+            None => return Ok(list(shell, &[])),
+            Some("-p") => return Ok(list(shell, &args[1..])),
+            Some("-l") if args.len() == 1 => {
+                let mut signals = platform::SIGNALS.to_vec();
+                signals.sort();
+                for (number, name) in signals {
+                    println!("{number:2}) SIG{name}");
+                }
+                return Ok(0);
+            }
+            _ => {}
+        }
+
+        let args = args.strip_prefix(&["--".to_string()][..]).unwrap_or(args);
+        let [action, conditions @ ..] = args else {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        };
+        if conditions.is_empty() || (action.starts_with('-') && action != "-") {
+            eprintln!("{}", self.usage());
+            return Ok(2);
+        }
+
+        // Once a subshell sets a trap, it only lists its own
+        shell.parent_traps = None;
+        let mut status = 0;
+        for condition in conditions {
+            if !CONDITIONS.contains(&condition.as_str()) {
+                eprintln!("trap: {condition}: invalid condition");
+                status = 1;
+            } else if action == "-" {
+                shell.traps.remove(condition);
+            } else {
+                shell.traps.insert(condition.clone(), action.clone());
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// Print the traps for `conditions`, or all of them, as `trap` commands.
+fn list(shell: &ShellState, conditions: &[String]) -> i32 {
+    let traps = shell.parent_traps.as_ref().unwrap_or(&shell.traps);
+    let mut status = 0;
+    for condition in conditions {
+        if !CONDITIONS.contains(&condition.as_str()) {
+            eprintln!("trap: {condition}: invalid condition");
+            status = 1;
+        }
+    }
+    for (condition, action) in traps {
+        if conditions.is_empty() || conditions.contains(condition) {
+            println!("trap -- {} {}", quote(action), condition);
+        }
+    }
+    status
+}
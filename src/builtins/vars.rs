@@ -0,0 +1,573 @@
+use std::io;
+
+use super::Builtin;
+use crate::lexer::{Lexer, Token};
+use crate::options::{Options, OPTIONS};
+use crate::shell::ShellState;
+use crate::vars::{Value, Variable};
+
+pub struct Export;
+pub struct Unset;
+pub struct Set;
+/// `declare`, also known as `typeset`, and `local`. Inside a function all of
+/// them make local variables, unless `declare -g` asks for a global one.
+pub struct Declare(pub &'static str);
+pub struct Readonly;
+
+pub use crate::expand::quote;
+
+/// A variable's value as it would be written in an assignment.
+fn quote_value(value: &Value) -> String {
+    match value {
+        Value::Scalar(value) => quote(value),
+        Value::Array(values) => {
+            let quoted: Vec<_> = values.iter().map(|value| quote(value)).collect();
+            format!("({})", quoted.join(" "))
+        }
+        Value::Assoc(values) => {
+            let quoted: Vec<_> = values
+                .iter()
+                .map(|(key, value)| format!("[{}]={}", quote(key), quote(value)))
+                .collect();
+            format!("({})", quoted.join(" "))
+        }
+    }
+}
+
+impl Builtin for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[name[=value] ...] | -f [name ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Mark variables to be passed to the programs the shell runs, or list them. \
+         With -f, mark functions to be passed to the shells it runs, such as scripts."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.get(1).is_some_and(|arg| arg == "-f") {
+            return export_functions(shell, &args[2..]);
+        }
+        if args.len() == 1 {
+            for (name, var) in shell.variables.iter().filter(|(_, var)| var.exported) {
+                println!("export {}={}", name, quote_value(&var.value));
+            }
+            return Ok(0);
+        }
+
+        for arg in &args[1..] {
+            match arg.split_once('=') {
+                Some((name, value)) => {
+                    shell.variables.set(name, value)?;
+                    shell.variables.export(name);
+                }
+                None => shell.variables.export(arg.as_str()),
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// `export -f`: mark functions for export, or list those that are.
+fn export_functions(shell: &mut ShellState, names: &[String]) -> io::Result<i32> {
+    if names.is_empty() {
+        for (name, _) in shell.functions.iter().filter(|(_, f)| f.exported) {
+            println!("export -f {}", name);
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for name in names {
+        match shell.functions.get_mut(name) {
+            Some(function) => function.exported = true,
+            None => {
+                eprintln!("export: {}: not a function", name);
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+impl Builtin for Unset {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-f | -v] name ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove variables, or functions with -f."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        let (functions, names) = match args.get(1).map(String::as_str) {
+            Some("-f") => (true, &args[2..]),
+            Some("-v") => (false, &args[2..]),
+            _ => (false, &args[1..]),
+        };
+
+        let mut status = 0;
+        for name in names {
+            if functions {
+                shell.functions.remove(name);
+            } else if let Err(e) = shell.variables.unset(name) {
+                eprintln!("unset: {}", e);
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
+
+impl Builtin for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-|+eux] [-|+o option] [-- arg ...] | -U [-e] [name [value ...]]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show variables, or set shell options or the positional parameters. set -o \
+         alone lists the options with what each does, and set +o lists them as \
+         commands. With -U, set universal variables, which are shared by every session."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if args.len() == 1 {
+            for (name, var) in shell.variables.iter() {
+                println!("{}={}", name, quote_value(&var.value));
+            }
+            return Ok(0);
+        }
+
+        if args[1] == "-U" {
+            return set_universal(shell, &args[2..]);
+        }
+
+        let mut args = args[1..].iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                let params = args.cloned().collect();
+                shell.variables.set_positional(params);
+                break;
+            }
+
+            let (value, flags) = if let Some(flags) = arg.strip_prefix('-') {
+                (true, flags)
+            } else if let Some(flags) = arg.strip_prefix('+') {
+                (false, flags)
+            } else {
+                eprintln!("set: {}: invalid argument", arg);
+                return Ok(2);
+            };
+
+            if flags == "o" {
+                match args.next() {
+                    Some(name) => {
+                        if shell.options.set(name, value).is_none() {
+                            eprintln!("set: {}: invalid option name", name);
+                            return Ok(2);
+                        }
+                    }
+                    // `set +o` lists them as commands that set them again
+                    None if !value => {
+                        for option in OPTIONS {
+                            let on = shell.options.get(option.name) == Some(true);
+                            println!("set {}o {}", if on { '-' } else { '+' }, option.name);
+                        }
+                    }
+                    None => {
+                        for option in OPTIONS {
+                            let on = shell.options.get(option.name) == Some(true);
+                            let on = if on { "on" } else { "off" };
+                            println!("{:<23} {:<3}  {}", option.name, on, option.description);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            for letter in flags.chars() {
+                let Some(name) = Options::name_for_letter(letter) else {
+                    eprintln!("set: -{}: invalid option", letter);
+                    return Ok(2);
+                };
+                shell.options.set(name, value);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// `set -U` lists universal variables, `set -U NAME value...` sets one and
+/// `set -U -e NAME...` erases them.
+fn set_universal(shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+    let res = match args.first().map(String::as_str) {
+        None => {
+            for (name, value) in shell.universal.iter() {
+                println!("set -U {} {}", name, quote(value));
+            }
+            return Ok(0);
+        }
+        Some("-e") => args[1..].iter().try_for_each(|name| {
+            shell.variables.unset(name)?;
+            let changes = shell.universal.erase(name)?;
+            shell.apply_universal(changes)
+        }),
+        Some(name) if !is_identifier(name) => {
+            eprintln!("set: `{name}': not a valid identifier");
+            return Ok(1);
+        }
+        Some(name) => {
+            let value = args[1..].join(" ");
+            shell
+                .variables
+                .set(name, value.as_str())
+                .map_err(io::Error::from)
+                .and_then(|_| {
+                    shell.variables.export(name);
+                    shell.universal.set(name, &value)
+                })
+                .and_then(|changes| shell.apply_universal(changes))
+        }
+    };
+
+    match res {
+        Ok(()) => Ok(0),
+        Err(e) => {
+            eprintln!("set: {}", e);
+            Ok(1)
+        }
+    }
+}
+
+/// The attributes `declare` was asked to turn on (`Some(true)`) or off
+/// (`Some(false)`).
+#[derive(Default)]
+struct Attributes {
+    array: bool,
+    assoc: bool,
+    integer: Option<bool>,
+    readonly: Option<bool>,
+    lowercase: Option<bool>,
+    uppercase: Option<bool>,
+    export: Option<bool>,
+}
+
+impl Attributes {
+    fn is_empty(&self) -> bool {
+        !self.array
+            && !self.assoc
+            && [
+                self.integer,
+                self.readonly,
+                self.lowercase,
+                self.uppercase,
+                self.export,
+            ]
+            .iter()
+            .all(Option::is_none)
+    }
+
+    /// Whether a listed variable has all the attributes asked for.
+    fn matches(&self, var: &Variable) -> bool {
+        let wanted = |flag: Option<bool>, has: bool| flag != Some(true) || has;
+        (!self.array || matches!(var.value, Value::Array(_)))
+            && (!self.assoc || matches!(var.value, Value::Assoc(_)))
+            && wanted(self.integer, var.attrs.integer)
+            && wanted(self.readonly, var.attrs.readonly)
+            && wanted(self.lowercase, var.attrs.lowercase)
+            && wanted(self.uppercase, var.attrs.uppercase)
+            && wanted(self.export, var.exported)
+    }
+}
+
+/// How `declare -p` shows a variable, which reads back in as the same thing.
+fn declaration(name: &str, var: &Variable) -> String {
+    let mut flags = String::new();
+    let attrs = [
+        (matches!(var.value, Value::Array(_)), 'a'),
+        (matches!(var.value, Value::Assoc(_)), 'A'),
+        (var.attrs.integer, 'i'),
+        (var.attrs.lowercase, 'l'),
+        (var.attrs.readonly, 'r'),
+        (var.attrs.uppercase, 'u'),
+        (var.exported, 'x'),
+    ];
+    for (set, flag) in attrs {
+        if set {
+            flags.push(flag);
+        }
+    }
+    if flags.is_empty() {
+        flags.push('-');
+    }
+    format!("declare -{} {}={}", flags, name, quote_value(&var.value))
+}
+
+/// The words of a `(...)` list as `declare` is given it, already expanded
+/// and quoted.
+fn list_words(list: &str) -> Option<Vec<String>> {
+    let inner = list.strip_prefix('(')?.strip_suffix(')')?;
+    Lexer::new(inner)
+        .map(|token| match token {
+            Ok(Token::Word(word)) => Some(word),
+            Ok(Token::Glob(pattern)) => Some(crate::glob::unescape(&pattern)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+impl Declare {
+    /// Give `name` the attributes asked for, then assign `value` if there is
+    /// one.
+    fn declare(
+        &self,
+        shell: &mut ShellState,
+        attrs: &Attributes,
+        name: &str,
+        value: Option<&str>,
+        local: bool,
+    ) -> Result<(), String> {
+        if !is_identifier(name) {
+            return Err(format!("`{name}': not a valid identifier"));
+        }
+        if local {
+            shell
+                .variables
+                .make_local(name)
+                .map_err(|e| e.to_string())?;
+        }
+        let readonly = shell
+            .variables
+            .var(name)
+            .is_some_and(|var| var.attrs.readonly);
+        let loosening = [
+            attrs.integer,
+            attrs.readonly,
+            attrs.lowercase,
+            attrs.uppercase,
+        ]
+        .contains(&Some(false));
+        if readonly && (value.is_some() || loosening || attrs.array || attrs.assoc) {
+            return Err(format!("{name}: readonly variable"));
+        }
+
+        let var = shell.variables.declare(name);
+        if attrs.assoc {
+            var.value = match &var.value {
+                Value::Scalar(value) if value.is_empty() => Value::Assoc(Default::default()),
+                Value::Scalar(value) => Value::Assoc([("0".to_string(), value.clone())].into()),
+                Value::Array(values) if values.is_empty() => Value::Assoc(Default::default()),
+                Value::Array(_) => {
+                    return Err(format!(
+                        "{name}: cannot convert indexed to associative array"
+                    ))
+                }
+                Value::Assoc(values) => Value::Assoc(values.clone()),
+            };
+        } else if attrs.array {
+            var.value = match &var.value {
+                Value::Scalar(value) if value.is_empty() => Value::Array(Vec::new()),
+                Value::Scalar(value) => Value::Array(vec![value.clone()]),
+                Value::Array(values) => Value::Array(values.clone()),
+                Value::Assoc(_) => {
+                    return Err(format!(
+                        "{name}: cannot convert associative to indexed array"
+                    ))
+                }
+            };
+        }
+
+        if let Some(integer) = attrs.integer {
+            var.attrs.integer = integer;
+        }
+        // Lower and upper case each turn the other off
+        if let Some(lowercase) = attrs.lowercase {
+            var.attrs.lowercase = lowercase;
+            var.attrs.uppercase &= !lowercase;
+        }
+        if let Some(uppercase) = attrs.uppercase {
+            var.attrs.uppercase = uppercase;
+            var.attrs.lowercase &= !uppercase;
+        }
+        if let Some(export) = attrs.export {
+            var.exported = export;
+        }
+        let is_array = !matches!(var.value, Value::Scalar(_));
+
+        if let Some(value) = value {
+            let res = match list_words(value) {
+                Some(words) if is_array || value.starts_with('(') => {
+                    shell.variables.assign_list(name, words)
+                }
+                _ => shell.variables.set(name, value),
+            };
+            res.map_err(|e| e.to_string())?;
+        }
+
+        // Last, so `declare -r NAME=value` can set it first
+        if attrs.readonly == Some(true) {
+            shell.variables.declare(name).attrs.readonly = true;
+        }
+        Ok(())
+    }
+}
+
+impl Builtin for Declare {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn synopsis(&self) -> &'static str {
+        match self.0 {
+            "local" => "[-aAilrux] [name[=value] ...] | -",
+            _ => "[-aAgilrux] [-p] [name[=value] ...]",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self.0 {
+            "local" => "Make variables local to the running function, optionally setting their values and attributes. `local -` restores the shell options when the function returns.",
+            _ => "Set variables' values and attributes, or show them. Inside a function they're local unless -g is given.",
+        }
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        if self.0 == "local" && shell.call_stack.is_empty() {
+            eprintln!("local: can only be used in a function");
+            return Ok(1);
+        }
+
+        let mut attrs = Attributes::default();
+        let mut print = false;
+        let mut global = false;
+
+        let mut args = args[1..].iter().peekable();
+        while let Some(arg) = args.next_if(|arg| arg.len() > 1 && arg.starts_with(['-', '+'])) {
+            if arg == "--" {
+                break;
+            }
+            let on = arg.starts_with('-');
+            for flag in arg[1..].chars() {
+                match flag {
+                    'a' if on => attrs.array = true,
+                    'A' if on => attrs.assoc = true,
+                    'i' => attrs.integer = Some(on),
+                    'r' => attrs.readonly = Some(on),
+                    'l' => attrs.lowercase = Some(on),
+                    'u' => attrs.uppercase = Some(on),
+                    'x' => attrs.export = Some(on),
+                    'p' if on => print = true,
+                    'g' if on && self.0 != "local" => global = true,
+                    _ => {
+                        eprintln!("{}: {}{}: invalid option", self.0, &arg[..1], flag);
+                        eprintln!("{}", self.usage());
+                        return Ok(2);
+                    }
+                }
+            }
+        }
+        let names: Vec<_> = args.collect();
+
+        if names.is_empty() {
+            for (name, var) in shell.variables.iter() {
+                if attrs.matches(var) {
+                    if print || !attrs.is_empty() {
+                        println!("{}", declaration(name, var));
+                    } else {
+                        println!("{}={}", name, quote_value(&var.value));
+                    }
+                }
+            }
+            return Ok(0);
+        }
+
+        let local = !global && !shell.call_stack.is_empty();
+        let mut status = 0;
+        for arg in names {
+            // `local -` makes option changes last only until the function
+            // returns
+            if arg == "-" && local {
+                if let Some(saved @ None) = shell.local_options.last_mut() {
+                    *saved = Some(shell.options.clone());
+                }
+                continue;
+            }
+
+            if print {
+                match shell.variables.var(arg) {
+                    Some(var) => println!("{}", declaration(arg, var)),
+                    None => {
+                        eprintln!("{}: {}: not found", self.0, arg);
+                        status = 1;
+                    }
+                }
+                continue;
+            }
+
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if let Err(e) = self.declare(shell, &attrs, name, value, local) {
+                eprintln!("{}: {}", self.0, e);
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// `readonly NAME...` is `declare -r NAME...`, and with no names lists the
+/// readonly variables the way `declare -p` would.
+impl Builtin for Readonly {
+    fn name(&self) -> &'static str {
+        "readonly"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "[-aAp] [name[=value] ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Make variables read-only, or list those which are."
+    }
+
+    fn run(&self, shell: &mut ShellState, args: &[String]) -> io::Result<i32> {
+        // Readonly variables are always global
+        let mut declare_args = vec!["readonly".to_string(), "-gr".to_string()];
+        let mut args = args[1..].iter().peekable();
+        while let Some(arg) = args.next_if(|arg| arg.len() > 1 && arg.starts_with('-')) {
+            if arg == "--" {
+                break;
+            }
+            if let Some(flag) = arg[1..].chars().find(|flag| !"aAp".contains(*flag)) {
+                eprintln!("readonly: -{}: invalid option", flag);
+                eprintln!("{}", self.usage());
+                return Ok(2);
+            }
+            declare_args.push(arg.clone());
+        }
+        declare_args.push("--".to_string());
+        declare_args.extend(args.cloned());
+
+        Declare("readonly").run(shell, &declare_args)
+    }
+}
@@ -0,0 +1,80 @@
+//! Every process the shell has started this session, for `procs`: what it
+//! was started to run, when, and how it's doing or how it finished.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::jobs::JobState;
+
+/// How many finished processes are remembered, the oldest being forgotten
+/// first.
+const FINISHED_LIMIT: usize = 500;
+
+pub struct Child {
+    pub pid: u32,
+    pub command: String,
+    pub state: JobState,
+    started: Instant,
+    finished: Option<Instant>,
+}
+
+impl Child {
+    /// How long it ran for, or has been running.
+    pub fn runtime(&self) -> Duration {
+        let until = self.finished.unwrap_or_else(Instant::now);
+        until.duration_since(self.started)
+    }
+}
+
+#[derive(Default)]
+pub struct Children {
+    children: VecDeque<Child>,
+}
+
+impl Children {
+    /// Note a process just started to run `command`.
+    pub(crate) fn started(&mut self, pid: u32, command: String) {
+        self.children.push_back(Child {
+            pid,
+            command,
+            state: JobState::Running,
+            started: Instant::now(),
+            finished: None,
+        });
+        let finished = self
+            .children
+            .iter()
+            .filter(|child| matches!(child.state, JobState::Done(_)))
+            .count();
+        if finished > FINISHED_LIMIT {
+            let oldest = self
+                .children
+                .iter()
+                .position(|child| matches!(child.state, JobState::Done(_)));
+            if let Some(index) = oldest {
+                self.children.remove(index);
+            }
+        }
+    }
+
+    /// Note that the process `pid` stopped, carried on or finished.
+    pub(crate) fn changed(&mut self, pid: u32, state: JobState) {
+        // Process IDs are reused, but only once the last one is waited for
+        let Some(child) = self
+            .children
+            .iter_mut()
+            .rev()
+            .find(|child| child.pid == pid && !matches!(child.state, JobState::Done(_)))
+        else {
+            return;
+        };
+        if let JobState::Done(_) = state {
+            child.finished = Some(Instant::now());
+        }
+        child.state = state;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Child> {
+        self.children.iter()
+    }
+}
@@ -0,0 +1,145 @@
+//! Completion through bash, for the many tools that only ship a bash
+//! completion script.
+//!
+//! Rather than reimplementing bash to run those scripts, we hand the line to a
+//! `bash` process with `COMP_WORDS`, `COMP_CWORD`, `COMP_LINE` and
+//! `COMP_POINT` set up the way bash's programmable completion would, call the
+//! completion function, and read `COMPREPLY` back one entry per line.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Run by `bash -c`, with the function, script, command, `COMP_CWORD`,
+/// `COMP_LINE` and `COMP_POINT` as `$1`..`$6` and the words after that.
+const BRIDGE: &str = r#"
+func=$1 script=$2 cmd=$3
+COMP_CWORD=$4 COMP_LINE=$5 COMP_POINT=$6
+shift 6
+COMP_WORDS=("$@")
+COMP_TYPE=9 COMP_KEY=9
+
+for helpers in "$BASH_COMPLETION" \
+    /usr/share/bash-completion/bash_completion \
+    /usr/local/share/bash-completion/bash_completion \
+    /opt/homebrew/share/bash-completion/bash_completion \
+    /etc/bash_completion; do
+    if [ -n "$helpers" ] && [ -r "$helpers" ]; then
+        . "$helpers" >/dev/null 2>&1
+        break
+    fi
+done
+
+if [ -n "$script" ]; then
+    . "$script" >/dev/null 2>&1
+fi
+
+if [ -z "$func" ]; then
+    spec=$(complete -p "$cmd" 2>/dev/null)
+    func=${spec##*-F }
+    func=${func%% *}
+    [ -n "$spec" ] && [ "$func" != "$spec" ] || exit 1
+fi
+
+"$func" "$cmd" "${COMP_WORDS[COMP_CWORD]}" "${COMP_WORDS[COMP_CWORD-1]}" >/dev/null 2>&1
+printf '%s\n' "${COMPREPLY[@]}"
+"#;
+
+/// Where bash-completion looks for per-command scripts, in priority order.
+fn completion_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(dir) = env::var_os("BASH_COMPLETION_USER_DIR") {
+        dirs.push(PathBuf::from(dir).join("completions"));
+    }
+    match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => dirs.push(PathBuf::from(dir).join("bash-completion/completions")),
+        None => {
+            if let Some(home) = env::var_os("HOME") {
+                dirs.push(PathBuf::from(home).join(".local/share/bash-completion/completions"));
+            }
+        }
+    }
+    dirs.extend(
+        [
+            "/usr/local/share/bash-completion/completions",
+            "/usr/share/bash-completion/completions",
+            "/opt/homebrew/share/bash-completion/completions",
+            "/etc/bash_completion.d",
+        ]
+        .map(PathBuf::from),
+    );
+    dirs
+}
+
+/// Find the bash completion script for `command`, named the way
+/// bash-completion's loader expects.
+pub fn find_script(command: &str) -> Option<PathBuf> {
+    let names = [
+        command.to_string(),
+        format!("{command}.bash"),
+        format!("_{command}"),
+    ];
+    completion_dirs()
+        .into_iter()
+        .flat_map(|dir| names.clone().map(|name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// Complete the last of `words` with a bash completion function.
+///
+/// With no `function`, the command's own completion script is found and
+/// sourced, and whichever function it registers is used. Returns `None` if
+/// there's no such script, or bash couldn't be run.
+pub fn complete(
+    function: Option<&str>,
+    script: Option<&Path>,
+    line: &[char],
+    cursor: usize,
+    words: &[(usize, String)],
+) -> Option<Vec<String>> {
+    let command = &words[0].1;
+    let found;
+    let script = match (function, script) {
+        (_, Some(script)) => Some(script),
+        (Some(_), None) => None,
+        (None, None) => {
+            found = find_script(command)?;
+            Some(found.as_path())
+        }
+    };
+
+    let comp_line: String = line.iter().collect();
+    let comp_point = line[..cursor].iter().collect::<String>().len();
+
+    let output = Command::new("bash")
+        .arg("--norc")
+        .arg("--noprofile")
+        .arg("-c")
+        .arg(BRIDGE)
+        .arg("sigsh-complete")
+        .arg(function.unwrap_or(""))
+        .arg(script.map(Path::as_os_str).unwrap_or_default())
+        .arg(command)
+        .arg((words.len() - 1).to_string())
+        .arg(comp_line)
+        .arg(comp_point.to_string())
+        .args(words.iter().map(|(_, word)| word))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            // Scripts using `-o nospace` add their own trailing space
+            .map(|candidate| candidate.trim_end().to_string())
+            .filter(|candidate| !candidate.is_empty())
+            .collect(),
+    )
+}
@@ -0,0 +1,156 @@
+//! The programs on PATH, for completing command names.
+//!
+//! Reading every PATH directory on each Tab is slow with ones as big as
+//! `/usr/bin`, so the names are cached per directory along with its
+//! modification time, which changes whenever an entry is added or removed.
+//! The cache is kept in `$XDG_CACHE_HOME/sigsh/commands` (or under
+//! `~/.cache`), so a new session starts out with it too, and is only loaded
+//! on the first completion.
+//!
+//! The file has a `MODIFIED DIR` line for each directory, with the time in
+//! nanoseconds, followed by its programs on lines starting with a tab.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::platform;
+
+struct Dir {
+    modified: SystemTime,
+    names: Vec<String>,
+}
+
+type Cache = BTreeMap<PathBuf, Dir>;
+
+/// Loaded on first use.
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+fn default_path() -> Option<PathBuf> {
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache.join("sigsh").join("commands"))
+}
+
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+fn parse(contents: &str) -> Cache {
+    let mut cache = Cache::new();
+    let mut current = None;
+    for line in contents.lines() {
+        if let Some(name) = line.strip_prefix('\t') {
+            if let Some(dir) = current.as_ref().and_then(|dir| cache.get_mut(dir)) {
+                dir.names.push(name.to_string());
+            }
+            continue;
+        }
+
+        current = None;
+        let Some((modified, dir)) = line.split_once(' ') else {
+            continue;
+        };
+        let Ok(modified) = modified.parse::<u64>() else {
+            continue;
+        };
+        let dir = PathBuf::from(dir);
+        cache.insert(
+            dir.clone(),
+            Dir {
+                modified: SystemTime::UNIX_EPOCH + Duration::from_nanos(modified),
+                names: Vec::new(),
+            },
+        );
+        current = Some(dir);
+    }
+    cache
+}
+
+fn save(cache: &Cache) -> std::io::Result<()> {
+    let Some(path) = default_path() else {
+        return Ok(());
+    };
+    let mut contents = String::new();
+    for (dir, entry) in cache {
+        // Neither a path nor a name with a newline would read back
+        let Some(dir) = dir.to_str().filter(|dir| !dir.contains('\n')) else {
+            continue;
+        };
+        contents.push_str(&format!("{} {}\n", nanos(entry.modified), dir));
+        for name in &entry.names {
+            if !name.contains('\n') {
+                contents.push_str(&format!("\t{name}\n"));
+            }
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write and rename, so a session reading it never sees half a file
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, &path)
+}
+
+fn scan(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| platform::is_executable(&entry.path()))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+/// The programs on PATH whose names `matches` accepts, rescanning only the
+/// directories that changed since they were cached.
+pub fn matching(matches: impl Fn(&str) -> bool) -> Vec<String> {
+    let Some(path) = env::var_os("PATH") else {
+        return Vec::new();
+    };
+
+    let mut guard = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = guard.get_or_insert_with(|| {
+        default_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| parse(&contents))
+            .unwrap_or_default()
+    });
+
+    let mut changed = false;
+    let mut candidates = Vec::new();
+    for dir in env::split_paths(&path) {
+        let Ok(modified) = fs::metadata(&dir).and_then(|meta| meta.modified()) else {
+            continue;
+        };
+        if cache
+            .get(&dir)
+            .is_none_or(|entry| entry.modified != modified)
+        {
+            let names = scan(&dir);
+            cache.insert(dir.clone(), Dir { modified, names });
+            changed = true;
+        }
+        candidates.extend(
+            cache[&dir]
+                .names
+                .iter()
+                .filter(|name| matches(name))
+                .cloned(),
+        );
+    }
+
+    if changed {
+        let _ = save(cache);
+    }
+    candidates
+}
@@ -0,0 +1,551 @@
+//! Tab completion.
+//!
+//! Commands can have a completion spec registered with the `complete`
+//! builtin, either directly or from a file named after the command in
+//! `$XDG_CONFIG_HOME/sigsh/completions` (or under `~/.config`), which is
+//! sourced the first time the command is completed so that startup doesn't
+//! pay for every command's completions. Commands without a spec fall back
+//! to an existing bash completion script if there is one (see [`bash`]),
+//! and to filenames otherwise.
+
+pub mod bash;
+mod commands;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::builtins;
+use crate::options::{Options, OPTIONS};
+use crate::parser::is_name;
+use crate::shell::ShellState;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompletionSpec {
+    /// `complete -W "words" cmd`
+    Words(Vec<String>),
+    /// `complete -F func cmd`: a bash function, run through bash, optionally
+    /// after sourcing the script that defines it.
+    BashFunction {
+        function: String,
+        script: Option<PathBuf>,
+    },
+}
+
+impl CompletionSpec {
+    /// The `complete` invocation that would recreate this spec.
+    pub fn describe(&self, command: &str) -> String {
+        match self {
+            CompletionSpec::Words(words) => {
+                format!("complete -W '{}' {}", words.join(" "), command)
+            }
+            CompletionSpec::BashFunction { function, script } => match script {
+                Some(script) => format!(
+                    "complete -F {} --source '{}' {}",
+                    function,
+                    script.display(),
+                    command
+                ),
+                None => format!("complete -F {} {}", function, command),
+            },
+        }
+    }
+}
+
+pub type CompletionSpecs = BTreeMap<String, CompletionSpec>;
+
+/// The result of completing the word under the cursor.
+#[derive(Debug, Default)]
+pub struct Completion {
+    /// Where the word being completed starts, as a char index into the line.
+    pub start: usize,
+    /// Replacements for the word. Directories end in `/`.
+    pub candidates: Vec<String>,
+    /// Whether the candidates are paths, so the menu can show their types.
+    pub files: bool,
+    /// What to show beside a candidate in the menu, such as the directory
+    /// `cd -2` goes to.
+    pub descriptions: BTreeMap<String, String>,
+}
+
+/// How candidates are matched against the word being completed, set by the
+/// `completion-*` options so that every kind of completion agrees.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Matcher {
+    ignore_case: bool,
+    /// Whether `-` and `_` are the same
+    map_case: bool,
+}
+
+impl Matcher {
+    pub fn new(options: &Options, word: &str) -> Matcher {
+        let smart = options.completion_smart_case && !word.chars().any(char::is_uppercase);
+        Matcher {
+            ignore_case: options.completion_ignore_case || smart,
+            map_case: options.completion_map_case,
+        }
+    }
+
+    fn fold(&self, c: char) -> char {
+        let c = if self.ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        };
+        if self.map_case && c == '-' {
+            '_'
+        } else {
+            c
+        }
+    }
+
+    /// Whether `candidate` starts with `prefix`, as far as this matcher
+    /// is concerned.
+    pub fn matches(&self, candidate: &str, prefix: &str) -> bool {
+        let mut candidate = candidate.chars();
+        prefix.chars().all(|p| {
+            candidate
+                .next()
+                .is_some_and(|c| self.fold(c) == self.fold(p))
+        })
+    }
+}
+
+/// A command seen while scanning a line, up to where it ends.
+#[derive(Default)]
+struct Scanned {
+    /// Where each finished word starts and ends, and whether it's the target
+    /// of a redirection rather than an argument
+    words: Vec<(usize, usize, bool)>,
+    /// Where the word in progress starts
+    start: Option<usize>,
+    /// Whether the word in progress is a redirection's target
+    target: bool,
+    /// Set after a redirection operator, for the word that follows it
+    redirect: bool,
+}
+
+impl Scanned {
+    fn begin(&mut self, i: usize) {
+        if self.start.is_none() {
+            self.start = Some(i);
+            self.target = std::mem::take(&mut self.redirect);
+        }
+    }
+
+    fn end(&mut self, i: usize) {
+        if let Some(start) = self.start.take() {
+            self.words.push((start, i, self.target));
+        }
+    }
+}
+
+/// The words of the command the cursor is in, found from the line up to it
+/// much as the lexer would, though the line needn't be finished. The last
+/// word is the one being completed, empty if the cursor follows whitespace.
+/// Also says whether that word follows a redirection operator.
+///
+/// Operators like `|`, `&&` and `;` start a new command, and so do `$(` and
+/// `(`, until the `)` which goes back to the command around them.
+fn split_words(line: &[char]) -> (Vec<(usize, String)>, bool) {
+    let mut command = Scanned::default();
+    let mut outer: Vec<(Scanned, Option<char>)> = Vec::new();
+    let mut quote = None;
+
+    let mut i = 0;
+    while i < line.len() {
+        let c = line[i];
+        let next = line.get(i + 1).copied();
+        match quote {
+            Some('\'') => {
+                if c == '\'' {
+                    quote = None;
+                }
+                i += 1;
+                continue;
+            }
+            Some(_) => {
+                if c == '"' {
+                    quote = None;
+                } else if c == '\\' {
+                    i += 1;
+                } else if c == '$' && next == Some('(') {
+                    outer.push((std::mem::take(&mut command), quote.take()));
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            None => {}
+        }
+
+        match c {
+            '\\' => {
+                command.begin(i);
+                i += 1;
+            }
+            '\'' | '"' => {
+                command.begin(i);
+                quote = Some(c);
+            }
+            '$' if next == Some('(') => {
+                command.begin(i);
+                outer.push((std::mem::take(&mut command), None));
+                i += 1;
+            }
+            '(' if command.start.is_none() => {
+                outer.push((std::mem::take(&mut command), None));
+            }
+            ')' => match outer.pop() {
+                Some((around, around_quote)) => (command, quote) = (around, around_quote),
+                None => command.end(i),
+            },
+            '<' | '>' | '&' if c != '&' || next == Some('>') => {
+                // The digits of `2>` or `3<` are part of the operator
+                match command.start {
+                    Some(start) if line[start..i].iter().all(char::is_ascii_digit) => {
+                        command.start = None;
+                    }
+                    _ => command.end(i),
+                }
+                while line
+                    .get(i + 1)
+                    .is_some_and(|c| matches!(c, '<' | '>' | '|' | '&' | '-'))
+                {
+                    i += 1;
+                }
+                command.redirect = true;
+            }
+            '|' | '&' | ';' => command = Scanned::default(),
+            c if c.is_whitespace() => command.end(i),
+            _ => command.begin(i),
+        }
+        i += 1;
+    }
+
+    let last = command.start.unwrap_or(line.len());
+    let redirect = if command.start.is_some() {
+        command.target
+    } else {
+        command.redirect
+    };
+    let text = |start: usize, end: usize| line[start..end].iter().collect::<String>();
+    let mut words: Vec<(usize, String)> = command
+        .words
+        .iter()
+        .filter(|(_, _, target)| !target)
+        .map(|&(start, end, _)| (start, text(start, end)))
+        .collect();
+    words.push((last, text(last, line.len())));
+    (words, redirect)
+}
+
+/// Where completion files named after the commands they're for are kept.
+fn completions_dir() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("sigsh").join("completions"))
+}
+
+/// Source `command`'s completion file, unless it already has a spec or the
+/// file has been looked for before.
+fn load_spec(shell: &mut ShellState, command: &str) {
+    if command.contains('/')
+        || shell.completions.contains_key(command)
+        || !shell.completions_loaded.insert(command.to_string())
+    {
+        return;
+    }
+    let Some(path) = completions_dir()
+        .map(|dir| dir.join(command))
+        .filter(|path| path.is_file())
+    else {
+        return;
+    };
+    if let Err(e) = shell.keeping_status(|shell| shell.source(&path)) {
+        eprintln!("{}: {}", path.display(), e);
+    }
+}
+
+pub fn complete(shell: &mut ShellState, line: &[char], cursor: usize) -> Completion {
+    let (words, redirect) = split_words(&line[..cursor]);
+    let (start, word) = words.last().cloned().unwrap_or_default();
+    let matcher = Matcher::new(&shell.options, &word);
+
+    if words.len() == 2 && words[0].1 == "cd" && word.starts_with(['-', '+']) && !redirect {
+        let descriptions = complete_dir_index(shell, &word);
+        return Completion {
+            start,
+            candidates: descriptions.keys().cloned().collect(),
+            files: false,
+            descriptions,
+        };
+    }
+
+    let after_o = words.len() > 2 && matches!(words[words.len() - 2].1.as_str(), "-o" | "+o");
+    if after_o && words[0].1 == "set" && !redirect {
+        let descriptions = complete_option(matcher, &word);
+        return Completion {
+            start,
+            candidates: descriptions.keys().cloned().collect(),
+            files: false,
+            descriptions,
+        };
+    }
+
+    if let Some(candidates) = complete_assignment(shell, matcher, &words).filter(|_| !redirect) {
+        return Completion {
+            start,
+            candidates,
+            files: false,
+            descriptions: BTreeMap::new(),
+        };
+    }
+
+    // A `${` that hasn't been closed yet, anywhere in the word
+    let braced = word
+        .rfind("${")
+        .filter(|&index| !word[index..].contains('}'));
+    let found = if redirect {
+        None
+    } else if let Some(index) = braced {
+        let (before, name) = (&word[..index], &word[index + 2..]);
+        let names = complete_variable(shell, matcher, name);
+        Some(
+            names
+                .iter()
+                .map(|name| format!("{before}${{{}}}", &name[1..]))
+                .collect(),
+        )
+    } else if let Some(name) = word.strip_prefix('$') {
+        Some(complete_variable(shell, matcher, name))
+    } else if words.len() == 1 && !word.contains('/') {
+        let mut commands = complete_command(matcher, &word);
+        if shell.options.autocd {
+            let dirs = complete_file(matcher, &word).into_iter();
+            commands.extend(dirs.filter(|path| path.ends_with('/')));
+        }
+        Some(commands)
+    } else if words.len() > 1 {
+        load_spec(shell, &words[0].1);
+        complete_argument(shell, matcher, line, cursor, &words)
+    } else {
+        None
+    };
+    let files = found.is_none();
+    let mut candidates = found.unwrap_or_else(|| complete_file(matcher, &word));
+
+    candidates.sort();
+    candidates.dedup();
+    Completion {
+        start,
+        candidates,
+        files,
+        descriptions: BTreeMap::new(),
+    }
+}
+
+/// Complete `cd -N` with the indexes into `$cdhist`, or `cd +N` with those
+/// into `$dirstack`, each described by the directory it goes to.
+fn complete_dir_index(shell: &ShellState, word: &str) -> BTreeMap<String, String> {
+    let (sign, array) = if word.starts_with('-') {
+        ('-', "cdhist")
+    } else {
+        ('+', "dirstack")
+    };
+    let dirs = shell.variables.get_array(array).unwrap_or_default();
+    dirs.into_iter()
+        .enumerate()
+        .map(|(index, dir)| (format!("{sign}{index}"), dir))
+        .filter(|(candidate, _)| candidate.starts_with(word))
+        .collect()
+}
+
+/// Complete a `NAME=value` word in front of a command or after `env`: the
+/// value as a filename, or for lists of directories like `$PATH`, its last
+/// `:`-separated directory. After `env` or other assignments, a word without
+/// an `=` may be either a variable to set or the command.
+fn complete_assignment(
+    shell: &ShellState,
+    matcher: Matcher,
+    words: &[(usize, String)],
+) -> Option<Vec<String>> {
+    let (word, before) = words.split_last()?;
+    let word = &word.1;
+    let is_assignment = |word: &str| word.split_once('=').is_some_and(|(name, _)| is_name(name));
+    let after_env = before.first().is_some_and(|(_, first)| first == "env")
+        && before[1..]
+            .iter()
+            .all(|(_, word)| is_assignment(word) || word.starts_with('-'));
+    if !after_env && !before.iter().all(|(_, word)| is_assignment(word)) {
+        return None;
+    }
+
+    let Some((name, value)) = word.split_once('=').filter(|(name, _)| is_name(name)) else {
+        if before.is_empty() {
+            return None;
+        }
+        let names = shell.variables.iter().map(|(name, _)| name);
+        let names = names.filter(|name| matcher.matches(name, word));
+        let mut candidates: Vec<String> = names.map(|name| format!("{name}=")).collect();
+        candidates.extend(complete_command(matcher, word));
+        return Some(candidates);
+    };
+
+    let candidates = if name.ends_with("PATH") {
+        let (head, last) = match value.rfind(':') {
+            Some(index) => value.split_at(index + 1),
+            None => ("", value),
+        };
+        let dirs = complete_file(matcher, last).into_iter();
+        let dirs = dirs.filter(|path| path.ends_with('/'));
+        dirs.map(|dir| format!("{name}={head}{dir}")).collect()
+    } else {
+        let files = complete_file(matcher, value).into_iter();
+        files.map(|file| format!("{name}={file}")).collect()
+    };
+    Some(candidates)
+}
+
+/// Complete `set -o` with the names of options, each described by what it
+/// does.
+fn complete_option(matcher: Matcher, word: &str) -> BTreeMap<String, String> {
+    OPTIONS
+        .iter()
+        .filter(|option| matcher.matches(option.name, word))
+        .map(|option| (option.name.to_string(), option.description.to_string()))
+        .collect()
+}
+
+/// A kind of candidate for [`generate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Candidates {
+    /// Builtins and programs on `$PATH`
+    Commands,
+    Files,
+    Directories,
+    Variables,
+    Words(Vec<String>),
+}
+
+/// The candidates of a kind starting with `prefix`, as completing would
+/// offer them, for `compgen`. Directories don't end in `/` and variables
+/// don't start with `$`.
+pub fn generate(shell: &ShellState, kind: &Candidates, prefix: &str) -> Vec<String> {
+    let matcher = Matcher::new(&shell.options, prefix);
+    let paths = |dirs_only: bool| {
+        let files = complete_file(matcher, prefix).into_iter();
+        let files = files.filter(move |path| !dirs_only || path.ends_with('/'));
+        files.map(|path| path.strip_suffix('/').unwrap_or(&path).to_string())
+    };
+    match kind {
+        Candidates::Commands => complete_command(matcher, prefix),
+        Candidates::Files => paths(false).collect(),
+        Candidates::Directories => paths(true).collect(),
+        Candidates::Variables => {
+            let names = complete_variable(shell, matcher, prefix).into_iter();
+            names.map(|name| name[1..].to_string()).collect()
+        }
+        Candidates::Words(words) => words
+            .iter()
+            .filter(|word| matcher.matches(word, prefix))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Complete an argument from the command's spec, or return `None` to
+/// complete filenames instead.
+fn complete_argument(
+    shell: &ShellState,
+    matcher: Matcher,
+    line: &[char],
+    cursor: usize,
+    words: &[(usize, String)],
+) -> Option<Vec<String>> {
+    let word = &words[words.len() - 1].1;
+    let command = &words[0].1;
+
+    let candidates = match shell.completions.get(command) {
+        Some(CompletionSpec::Words(list)) => {
+            return Some(
+                list.iter()
+                    .filter(|w| matcher.matches(w, word))
+                    .cloned()
+                    .collect(),
+            );
+        }
+        Some(CompletionSpec::BashFunction { function, script }) => {
+            bash::complete(Some(function), script.as_deref(), line, cursor, words)
+        }
+        None => bash::complete(None, None, line, cursor, words),
+    };
+
+    // Like `-o default`: no answer from the spec means filenames
+    candidates.filter(|candidates| !candidates.is_empty())
+}
+
+fn complete_command(matcher: Matcher, prefix: &str) -> Vec<String> {
+    let mut candidates: Vec<String> = builtins::names()
+        .filter(|name| matcher.matches(name, prefix))
+        .map(str::to_string)
+        .collect();
+
+    candidates.extend(commands::matching(|name| matcher.matches(name, prefix)));
+    candidates
+}
+
+/// Complete `$name` with the names of variables.
+fn complete_variable(shell: &ShellState, matcher: Matcher, prefix: &str) -> Vec<String> {
+    shell
+        .variables
+        .iter()
+        .filter(|(name, _)| matcher.matches(name, prefix))
+        .map(|(name, _)| format!("${name}"))
+        .collect()
+}
+
+fn complete_file(matcher: Matcher, prefix: &str) -> Vec<String> {
+    let (dir, name) = match prefix.rfind('/') {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix),
+    };
+    let read_from = if dir.is_empty() { "." } else { dir };
+    let Ok(entries) = fs::read_dir(read_from) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if !matcher.matches(&file_name, name)
+                || (file_name.starts_with('.') && !name.starts_with('.'))
+            {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            Some(format!("{dir}{file_name}{}", if is_dir { "/" } else { "" }))
+        })
+        .collect()
+}
+
+/// The longest prefix all candidates share.
+pub fn common_prefix(candidates: &[String]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+
+    let mut prefix: Vec<char> = first.chars().collect();
+    for candidate in &candidates[1..] {
+        let shared = prefix
+            .iter()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(shared);
+    }
+    prefix.into_iter().collect()
+}
@@ -0,0 +1,194 @@
+//! A debugger for scripts run with `--debug`, which stops before commands to
+//! show them with their words expanded and take commands at a `(sigdb)`
+//! prompt.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use crate::exec;
+use crate::expand;
+use crate::shell::{Location, ShellState};
+
+const HELP: &str = "\
+s, step          run this command, stopping at the next one
+n, next          run this command, stopping at the next one outside it
+c, continue      run until a breakpoint
+b [line|func]    set a breakpoint, or list them without an argument
+d line|func      delete a breakpoint
+p name           print a variable
+e command        run a shell command, e.g. to set a variable
+w, where         show the functions being run
+q, quit          stop the script
+An empty line repeats the last command.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// Stop before the next command
+    Step,
+    /// Stop before the next command no more than this many calls deep
+    Next(usize),
+    /// Only stop at breakpoints
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Breakpoint {
+    Line(usize),
+    Function(String),
+}
+
+impl Breakpoint {
+    fn parse(arg: &str) -> Breakpoint {
+        match arg.parse() {
+            Ok(line) => Breakpoint::Line(line),
+            Err(_) => Breakpoint::Function(arg.to_string()),
+        }
+    }
+}
+
+/// What to do with the command the debugger stopped at.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Run,
+    /// The user quit, so nothing more should run
+    Quit,
+}
+
+pub struct Debugger {
+    mode: Mode,
+    breakpoints: BTreeSet<Breakpoint>,
+    /// Where the last command ran, so a breakpoint only stops the first
+    /// command on its line
+    last: Location,
+    /// The last command typed, repeated by an empty line
+    repeat: String,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    /// A debugger which stops before the first command.
+    pub fn new() -> Debugger {
+        Debugger {
+            mode: Mode::Step,
+            breakpoints: BTreeSet::new(),
+            last: Location::default(),
+            repeat: String::new(),
+        }
+    }
+
+    fn should_stop(&self, shell: &ShellState) -> bool {
+        let depth = shell.call_stack.len();
+        match self.mode {
+            Mode::Step => return true,
+            Mode::Next(max) if depth <= max => return true,
+            _ => {}
+        }
+        let line = shell.location.line;
+        shell.location != self.last && self.breakpoints.contains(&Breakpoint::Line(line))
+    }
+
+    /// Called with each simple command's expanded words, or for a command
+    /// that only assigns variables, its assignments, before it runs.
+    pub fn before_command(&mut self, shell: &mut ShellState, args: &[String]) -> Action {
+        let stop = self.should_stop(shell);
+        self.last = shell.location.clone();
+        if !stop {
+            return Action::Run;
+        }
+
+        eprintln!("{}: {}", shell.location, args.join(" "));
+        self.prompt(shell)
+    }
+
+    /// Called as a function is entered, to stop at its first command if
+    /// there's a breakpoint on it.
+    pub fn entering_function(&mut self, name: &str) {
+        if self.mode == Mode::Continue
+            && self
+                .breakpoints
+                .contains(&Breakpoint::Function(name.to_string()))
+        {
+            self.mode = Mode::Step;
+        }
+    }
+
+    /// Take commands until one lets the script carry on.
+    fn prompt(&mut self, shell: &mut ShellState) -> Action {
+        let stdin = io::stdin();
+        loop {
+            eprint!("(sigdb) ");
+            let _ = io::stderr().flush();
+
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => return Action::Quit,
+                Ok(_) => {}
+            }
+            let line = match line.trim() {
+                "" => self.repeat.clone(),
+                line => {
+                    self.repeat = line.to_string();
+                    line.to_string()
+                }
+            };
+            let (command, arg) = match line.split_once(' ') {
+                Some((command, arg)) => (command, arg.trim()),
+                None => (line.as_str(), ""),
+            };
+
+            match command {
+                "s" | "step" => {
+                    self.mode = Mode::Step;
+                    return Action::Run;
+                }
+                "n" | "next" => {
+                    self.mode = Mode::Next(shell.call_stack.len());
+                    return Action::Run;
+                }
+                "c" | "continue" => {
+                    self.mode = Mode::Continue;
+                    return Action::Run;
+                }
+                "q" | "quit" => return Action::Quit,
+                "b" | "break" if arg.is_empty() => {
+                    for breakpoint in &self.breakpoints {
+                        match breakpoint {
+                            Breakpoint::Line(line) => eprintln!("line {line}"),
+                            Breakpoint::Function(name) => eprintln!("function {name}"),
+                        }
+                    }
+                }
+                "b" | "break" => {
+                    self.breakpoints.insert(Breakpoint::parse(arg));
+                }
+                "d" | "delete" => {
+                    if !self.breakpoints.remove(&Breakpoint::parse(arg)) {
+                        eprintln!("no breakpoint at {arg}");
+                    }
+                }
+                "p" | "print" => match expand::lookup(shell, arg) {
+                    Some(value) => eprintln!("{arg}={value}"),
+                    None => eprintln!("{arg} is not set"),
+                },
+                "e" | "eval" => {
+                    let status = shell.last_status;
+                    if let Err(e) = shell.eval(arg) {
+                        eprintln!("{e}");
+                    }
+                    shell.last_status = status;
+                }
+                "w" | "where" => {
+                    eprintln!("at {}", shell.location);
+                    exec::print_stack(shell);
+                }
+                "h" | "help" => eprintln!("{HELP}"),
+                _ => eprintln!("unknown command {command:?}, try help"),
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Per-directory environments, like direnv. With `set -o direnv`, changing
+//! into a directory with a `.sigsh.env` in it, or in a directory above it,
+//! runs that file and exports the variables it exports. Leaving the tree
+//! puts them back as they were.
+//!
+//! A file only runs once it's been trusted with `allow`, and only while it's
+//! unchanged since, as told by its SHA-256 hash. What's been allowed or
+//! denied is kept in `$XDG_CONFIG_HOME/sigsh/trusted_env` (or under
+//! `~/.config`), one `allow <hash> <path>` or `deny <path>` per line.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::shell::ShellState;
+use crate::vars::{Value, Variable};
+
+pub const FILE_NAME: &str = ".sigsh.env";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trust {
+    /// Allowed with these contents, by their hash
+    Allowed([u8; 32]),
+    Denied,
+}
+
+/// A file that's been run, and what to put back when it's unloaded.
+#[derive(Debug)]
+struct Loaded {
+    file: PathBuf,
+    /// Each variable it changed and what it was before, if it was set
+    saved: Vec<(String, Option<Variable>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct DirEnv {
+    trust: BTreeMap<PathBuf, Trust>,
+    path: Option<PathBuf>,
+    loaded: Option<Loaded>,
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("sigsh").join("trusted_env"))
+}
+
+/// The SHA-256 hash of `contents`. A file someone else can write to could
+/// otherwise be changed to something with the same hash as what was allowed.
+pub(crate) fn hash(contents: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // Padded with a 1 bit, zeros, and the length in bits
+    let mut message = contents.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((contents.len() as u64).wrapping_mul(8)).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+
+    let mut hash = [0; 32];
+    for (bytes, word) in hash.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    hash
+}
+
+fn to_hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The hash `to_hex` wrote, which is all 32 bytes of it or nothing.
+fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    let mut hash = [0; 32];
+    if hex.len() != 2 * hash.len() || !hex.is_ascii() {
+        return None;
+    }
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+fn parse(contents: &str) -> BTreeMap<PathBuf, Trust> {
+    contents
+        .lines()
+        .filter_map(|line| match line.split_once(' ')? {
+            ("allow", rest) => {
+                let (hash, path) = rest.split_once(' ')?;
+                let hash = from_hex(hash)?;
+                Some((PathBuf::from(path), Trust::Allowed(hash)))
+            }
+            ("deny", path) => Some((PathBuf::from(path), Trust::Denied)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The nearest env file in `dir` or a directory above it.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|file| file.is_file())
+}
+
+impl DirEnv {
+    /// Load the trust database, if there's anywhere to keep one.
+    pub fn load() -> DirEnv {
+        let path = default_path();
+        let trust = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| parse(&contents))
+            .unwrap_or_default();
+        DirEnv {
+            trust,
+            path,
+            loaded: None,
+        }
+    }
+
+    /// The env file that's loaded, if any.
+    pub fn loaded(&self) -> Option<&Path> {
+        self.loaded.as_ref().map(|loaded| loaded.file.as_path())
+    }
+
+    /// Trust `file` with its current contents.
+    pub fn allow(&mut self, file: &Path) -> io::Result<()> {
+        let contents = fs::read(file)?;
+        self.trust
+            .insert(file.to_path_buf(), Trust::Allowed(hash(&contents)));
+        self.save()
+    }
+
+    /// Never load `file`, or ask about it.
+    pub fn deny(&mut self, file: &Path) -> io::Result<()> {
+        self.trust.insert(file.to_path_buf(), Trust::Denied);
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let contents: String = self
+            .trust
+            .iter()
+            .map(|(file, trust)| match trust {
+                Trust::Allowed(hash) => format!("allow {} {}\n", to_hex(hash), file.display()),
+                Trust::Denied => format!("deny {}\n", file.display()),
+            })
+            .collect();
+
+        // Write then rename, so other sessions never read half a file
+        let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// Load or unload env files for the current directory, after a `cd`.
+pub fn update(shell: &mut ShellState) {
+    if let Ok(cwd) = env::current_dir() {
+        enter(shell, &cwd);
+    }
+}
+
+/// Load the env file for `dir`, unloading whichever was loaded before if
+/// it's a different one.
+pub fn enter(shell: &mut ShellState, dir: &Path) {
+    if !shell.options.direnv && shell.direnv.loaded.is_none() {
+        return;
+    }
+    let file = if shell.options.direnv {
+        find(dir)
+    } else {
+        None
+    };
+    if file.as_deref() == shell.direnv.loaded() {
+        return;
+    }
+
+    unload(shell);
+    if let Some(file) = file {
+        if let Err(e) = load(shell, &file) {
+            eprintln!("sigsh: {}: {}", file.display(), e);
+        }
+    }
+}
+
+/// Put back the variables the loaded env file changed.
+pub fn unload(shell: &mut ShellState) {
+    let Some(loaded) = shell.direnv.loaded.take() else {
+        return;
+    };
+    eprintln!("sigsh: unloading {}", loaded.file.display());
+    for (name, var) in loaded.saved.into_iter().rev() {
+        match var {
+            Some(var) => shell.variables.insert(name, var),
+            None => {
+                let _ = shell.variables.unset(&name);
+            }
+        }
+    }
+}
+
+/// Run `file` if it's trusted. Only the variables it exports are kept;
+/// anything else it does, such as defining functions, setting options or
+/// changing directory, is rolled back.
+fn load(shell: &mut ShellState, file: &Path) -> io::Result<()> {
+    // What runs is what was checked, whatever happens to the file meanwhile
+    let contents = fs::read(file)?;
+    match shell.direnv.trust.get(file) {
+        Some(Trust::Allowed(allowed)) if *allowed == hash(&contents) => {}
+        Some(Trust::Denied) => return Ok(()),
+        Some(Trust::Allowed(_)) => {
+            eprintln!(
+                "sigsh: {} has changed since it was allowed; run `allow` to load it",
+                file.display()
+            );
+            return Ok(());
+        }
+        None => {
+            eprintln!(
+                "sigsh: {} is not trusted; run `allow` to load it or `deny` to stop asking",
+                file.display()
+            );
+            return Ok(());
+        }
+    }
+
+    eprintln!("sigsh: loading {}", file.display());
+    let snapshot = shell.snapshot()?;
+    let status = shell.last_status;
+    // So a `cd` in the file doesn't load anything itself
+    shell.options.direnv = false;
+    let res = String::from_utf8(contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        .and_then(|contents| shell.source_contents(file, &contents));
+    let after = std::mem::take(&mut shell.variables);
+    shell.exit = None;
+    shell.returning = false;
+    shell.last_status = status;
+    shell.restore(snapshot)?;
+    res?;
+
+    let exported = |var: Option<&Variable>| match var {
+        Some(
+            var @ Variable {
+                value: Value::Scalar(_),
+                exported: true,
+                ..
+            },
+        ) => Some(var.clone()),
+        _ => None,
+    };
+    let mut saved = Vec::new();
+    let names = after
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .chain(shell.variables.iter().map(|(name, _)| name.to_string()));
+    for name in names.collect::<BTreeSet<_>>() {
+        let before = exported(shell.variables.var(&name));
+        let now = exported(after.var(&name));
+        if before == now {
+            continue;
+        }
+        saved.push((name.clone(), shell.variables.var(&name).cloned()));
+        match now {
+            Some(var) => shell.variables.insert(name, var),
+            None => {
+                let _ = shell.variables.unset(&name);
+            }
+        }
+    }
+
+    shell.direnv.loaded = Some(Loaded {
+        file: file.to_path_buf(),
+        saved,
+    });
+    Ok(())
+}
@@ -0,0 +1,920 @@
+//! A small line editor for interactive input.
+//!
+//! The console is put into raw mode through [`platform::RawMode`] so we see
+//! every keypress, and redrawing is done with plain VT escape sequences.
+//! Each redraw is sent as one synchronized update, which terminals that
+//! know the mode show all at once rather than half drawn, and the rest
+//! ignore.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::complete;
+use crate::expand::quote;
+use crate::history::History;
+use crate::listing;
+use crate::platform;
+use crate::shell::ShellState;
+
+enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    EndOfFile,
+    /// Ctrl-R
+    SearchHistory,
+    /// Ctrl-G
+    Cancel,
+    /// Ctrl-C
+    Interrupt,
+    /// Alt-S, which puts `sudo` in front of the line, or the last command
+    /// if nothing's been typed, or takes it off again
+    ToggleSudo,
+    /// Ctrl-X Ctrl-E, which opens the line in an editor and puts what's
+    /// saved back in its place, to be run with Enter
+    EditCommand,
+    /// The window was resized, or some other signal cut the wait for a key
+    /// short, so the line should be drawn again
+    Resize,
+    Unknown,
+}
+
+pub struct Editor {
+    buffer: Vec<char>,
+    cursor: usize,
+    /// Which history entry is being shown, if we've moved into history.
+    history_index: Option<usize>,
+    /// What was typed before moving into history, restored when moving past
+    /// the newest entry.
+    saved_buffer: Vec<char>,
+    /// What the last redraw wrote, kept so each keypress reuses its memory
+    /// and goes out in a single write.
+    render: String,
+    /// Which row of the prompt and buffer, as they wrap, the terminal's
+    /// cursor was left on, so the next redraw can find where they start.
+    cursor_row: usize,
+    /// Where the closers typed by `autopair` are, until the line is changed
+    /// other than by typing or deleting, so they can be typed over or
+    /// deleted along with their openers.
+    auto_closers: Vec<usize>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor {
+            buffer: Vec::new(),
+            cursor: 0,
+            history_index: None,
+            saved_buffer: Vec::new(),
+            render: String::new(),
+            cursor_row: 0,
+            auto_closers: Vec::new(),
+        }
+    }
+
+    /// Read a single line of input, showing `prompt` before it.
+    ///
+    /// Returns `Ok(None)` when the user signals end of input.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        shell: &mut ShellState,
+    ) -> io::Result<Option<String>> {
+        let mut raw = Some(platform::RawMode::enable()?);
+        let mut resize = Some(platform::ResizeGuard::new());
+        let mut stdin = io::stdin().lock();
+
+        self.buffer.clear();
+        self.cursor = 0;
+        self.history_index = None;
+        self.cursor_row = 0;
+        self.auto_closers.clear();
+        self.redraw(prompt)?;
+
+        loop {
+            let key = match read_key(&mut stdin)? {
+                Key::SearchHistory => {
+                    let key = self.search_history(&shell.history, &mut stdin)?;
+                    self.redraw(prompt)?;
+                    key
+                }
+                key => key,
+            };
+            if !matches!(
+                key,
+                Key::Char(_) | Key::Backspace | Key::Delete | Key::Left | Key::Right
+            ) {
+                self.auto_closers.clear();
+            }
+            match key {
+                Key::Char(c) if shell.options.autopair => self.type_paired(c),
+                Key::Char(c) => self.insert(c),
+                Key::Enter => {
+                    self.leave_line(prompt, "")?;
+                    return Ok(Some(self.buffer.iter().collect()));
+                }
+                Key::Tab => self.complete(prompt, shell)?,
+                Key::Backspace => {
+                    if self.cursor > 0 {
+                        self.cursor -= 1;
+                        // An opener takes the closer typed with it along
+                        if self.auto_closers.contains(&(self.cursor + 1))
+                            && self.buffer.get(self.cursor + 1).copied()
+                                == Some(closer_for(self.buffer[self.cursor]))
+                        {
+                            self.remove(self.cursor + 1);
+                        }
+                        self.remove(self.cursor);
+                    }
+                }
+                Key::Left => self.cursor = self.cursor.saturating_sub(1),
+                Key::Right => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+                Key::Up if !self.line_up() => self.history_up(&shell.history),
+                Key::Down if !self.line_down() => self.history_down(&shell.history),
+                Key::Up | Key::Down => {}
+                Key::Home => self.cursor = 0,
+                Key::End => self.cursor = self.buffer.len(),
+                // Ctrl-D ends input on an empty line, and deletes otherwise
+                Key::EndOfFile if self.buffer.is_empty() => {
+                    self.leave_line(prompt, "")?;
+                    return Ok(None);
+                }
+                Key::Delete | Key::EndOfFile => {
+                    if self.cursor < self.buffer.len() {
+                        self.remove(self.cursor);
+                    }
+                }
+                Key::Interrupt => {
+                    // Leave what was typed with ^C after it, then start over
+                    self.leave_line(prompt, "^C")?;
+                    self.buffer.clear();
+                    self.cursor = 0;
+                    self.history_index = None;
+                }
+                Key::ToggleSudo => self.toggle_sudo(&shell.history),
+                Key::EditCommand => {
+                    self.leave_line(prompt, "")?;
+                    // The editor needs the terminal back as it normally is
+                    drop(raw.take());
+                    drop(resize.take());
+                    let text: String = self.buffer.iter().collect();
+                    match edit_command(shell, &text, None) {
+                        Ok(Some(edited)) => {
+                            self.buffer = edited.chars().collect();
+                            self.cursor = self.buffer.len();
+                        }
+                        Ok(None) => {}
+                        Err(e) => eprintln!("sigsh: {e}"),
+                    }
+                    raw = Some(platform::RawMode::enable()?);
+                    resize = Some(platform::ResizeGuard::new());
+                }
+                Key::SearchHistory | Key::Cancel | Key::Resize => {}
+                Key::Unknown => continue,
+            }
+            self.redraw(prompt)?;
+        }
+    }
+
+    fn insert(&mut self, c: char) {
+        for closer in &mut self.auto_closers {
+            if *closer >= self.cursor {
+                *closer += 1;
+            }
+        }
+        self.buffer.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.auto_closers.retain(|&closer| closer != index);
+        for closer in &mut self.auto_closers {
+            if *closer > index {
+                *closer -= 1;
+            }
+        }
+        self.buffer.remove(index);
+    }
+
+    /// Type `c` for `autopair`: an opener brings its closer along, unless
+    /// it's quoted or escaped or would land in the middle of a word, and a
+    /// closer that was brought along is typed over.
+    fn type_paired(&mut self, c: char) {
+        if self.auto_closers.contains(&self.cursor) && self.buffer.get(self.cursor) == Some(&c) {
+            self.auto_closers.retain(|&closer| closer != self.cursor);
+            self.cursor += 1;
+            return;
+        }
+
+        let nesting = scan(&self.buffer[..self.cursor]);
+        let inside = nesting.open.last().map(|&(_, opener)| opener);
+        let before = self.cursor.checked_sub(1).map(|index| self.buffer[index]);
+        let after = self.buffer.get(self.cursor).copied();
+        let word_ends = after.is_none_or(|after| after.is_whitespace() || ")]}\"'".contains(after));
+        let pair = match c {
+            _ if nesting.escaped || !word_ends => false,
+            // `$(` still nests in double quotes
+            '(' if inside == Some('"') => before == Some('$'),
+            '(' | '[' | '{' => !matches!(inside, Some('\'' | '"')),
+            // A quote in a word, as in don't, or closing one, stays single
+            '\'' | '"' => {
+                !matches!(inside, Some('\'' | '"'))
+                    && before.is_none_or(|before| !before.is_alphanumeric())
+            }
+            _ => false,
+        };
+
+        self.insert(c);
+        if pair {
+            self.insert(closer_for(c));
+            self.cursor -= 1;
+            self.auto_closers.push(self.cursor);
+        }
+    }
+
+    fn complete(&mut self, prompt: &str, shell: &mut ShellState) -> io::Result<()> {
+        let completion = complete::complete(shell, &self.buffer, self.cursor);
+        let candidates = &completion.candidates;
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        let word_len = self.cursor - completion.start;
+        let mut replacement = complete::common_prefix(candidates);
+        // More is bound to follow a directory, or the `=` of an assignment
+        if candidates.len() == 1 && !replacement.ends_with(['/', '=']) {
+            replacement.push(' ');
+        }
+
+        if replacement.chars().count() > word_len || candidates.len() == 1 {
+            self.cursor = completion.start + replacement.chars().count();
+            self.buffer.splice(
+                completion.start..completion.start + word_len,
+                replacement.chars(),
+            );
+        } else {
+            // Nothing more to fill in, so show the choices
+            let cells: Vec<_> = candidates
+                .iter()
+                .map(|candidate| {
+                    if completion.files {
+                        listing::Entry::new(Path::new(candidate), candidate.clone())
+                            .cell(true, false)
+                    } else if let Some(description) = completion.descriptions.get(candidate) {
+                        listing::Cell::plain(&format!("{candidate}  {description}"))
+                    } else {
+                        listing::Cell::plain(candidate)
+                    }
+                })
+                .collect();
+            let width = platform::terminal_width().unwrap_or(80);
+            let cursor = self.cursor;
+            self.cursor = self.buffer.len();
+            self.render_line(prompt, false);
+            let mut update = std::mem::take(&mut self.render);
+            update.push_str("\r\n");
+            update.push_str(&listing::columns(&cells, width).replace('\n', "\r\n"));
+
+            // Then the line again below the choices, all in one go
+            self.cursor = cursor;
+            self.cursor_row = 0;
+            self.render_line(prompt, true);
+            update.push_str(&self.render);
+            synchronized(&mut io::stdout().lock(), &update)?;
+        }
+        Ok(())
+    }
+
+    /// Put `sudo ` in front of the line, or take it off if it's there. An
+    /// empty line gets the last command first, to run it again with sudo.
+    fn toggle_sudo(&mut self, history: &History) {
+        const SUDO: &str = "sudo ";
+        if self.buffer.is_empty() {
+            if let Some(last) = history.entries().last() {
+                self.buffer.extend(last.command.chars());
+                self.cursor = self.buffer.len();
+            }
+        }
+        let len = SUDO.len();
+        if self.buffer.iter().take(len).copied().eq(SUDO.chars()) {
+            self.buffer.drain(..len);
+            self.cursor = self.cursor.saturating_sub(len);
+        } else {
+            self.buffer.splice(0..0, SUDO.chars());
+            self.cursor += len;
+        }
+    }
+
+    /// Where the line the cursor is on starts, and how far into it the
+    /// cursor is.
+    fn line_start(&self) -> (usize, usize) {
+        let start = self.buffer[..self.cursor]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map_or(0, |newline| newline + 1);
+        (start, self.cursor - start)
+    }
+
+    /// Move to the same column of the line above, if there is one.
+    fn line_up(&mut self) -> bool {
+        let (start, column) = self.line_start();
+        if start == 0 {
+            return false;
+        }
+        let above = self.buffer[..start - 1]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map_or(0, |newline| newline + 1);
+        self.cursor = (above + column).min(start - 1);
+        true
+    }
+
+    /// Move to the same column of the line below, if there is one.
+    fn line_down(&mut self) -> bool {
+        let (_, column) = self.line_start();
+        let Some(newline) = self.buffer[self.cursor..].iter().position(|&c| c == '\n') else {
+            return false;
+        };
+        let below = self.cursor + newline + 1;
+        let end = self.buffer[below..]
+            .iter()
+            .position(|&c| c == '\n')
+            .map_or(self.buffer.len(), |newline| below + newline);
+        self.cursor = (below + column).min(end);
+        true
+    }
+
+    /// What Up and Down look for at the start of history entries: what was
+    /// typed before moving into history.
+    fn history_prefix(&self) -> String {
+        match self.history_index {
+            Some(_) => self.saved_buffer.iter().collect(),
+            None => self.buffer.iter().collect(),
+        }
+    }
+
+    /// Show the previous entry starting with what was typed, skipping any
+    /// that are the same as what's shown.
+    fn history_up(&mut self, history: &History) {
+        let prefix = self.history_prefix();
+        let shown: String = self.buffer.iter().collect();
+        let older_than = self.history_index.unwrap_or(history.entries().len());
+        let Some(index) = history
+            .starting_with(&prefix)
+            .rev()
+            .find(|&index| index < older_than && history.entries()[index].command != shown)
+        else {
+            return;
+        };
+
+        if self.history_index.is_none() {
+            self.saved_buffer.clone_from(&self.buffer);
+        }
+        self.show_history(history, index);
+    }
+
+    /// Show the next entry starting with what was typed, or what was typed
+    /// itself after the newest.
+    fn history_down(&mut self, history: &History) {
+        let Some(current) = self.history_index else {
+            return;
+        };
+
+        let prefix = self.history_prefix();
+        let shown: String = self.buffer.iter().collect();
+        let next = history
+            .starting_with(&prefix)
+            .find(|&index| index > current && history.entries()[index].command != shown);
+        match next {
+            Some(index) => self.show_history(history, index),
+            None => {
+                self.history_index = None;
+                std::mem::swap(&mut self.buffer, &mut self.saved_buffer);
+                self.cursor = self.buffer.len();
+            }
+        }
+    }
+
+    fn show_history(&mut self, history: &History, index: usize) {
+        self.history_index = Some(index);
+        self.buffer.clear();
+        self.buffer.extend(history.entries()[index].command.chars());
+        self.cursor = self.buffer.len();
+    }
+
+    /// Search back through history for what's typed, showing the newest
+    /// match, with each Ctrl-R moving on to an older one. Returns the key
+    /// that ended the search, leaving the match to be edited or run, or
+    /// [`Key::Cancel`] after putting back what was there before.
+    fn search_history(&mut self, history: &History, input: &mut impl Read) -> io::Result<Key> {
+        let before = (self.buffer.clone(), self.cursor, self.history_index);
+        let mut query = String::new();
+        let mut failed = false;
+
+        loop {
+            let label = if failed {
+                "failed reverse-i-search"
+            } else {
+                "reverse-i-search"
+            };
+            self.redraw(&format!("({label})`{query}': "))?;
+
+            let older_than = match read_key(input)? {
+                Key::Char(c) => {
+                    query.push(c);
+                    self.history_index.map_or(usize::MAX, |index| index + 1)
+                }
+                Key::Backspace => {
+                    query.pop();
+                    usize::MAX
+                }
+                Key::SearchHistory => self.history_index.unwrap_or(usize::MAX),
+                Key::Resize => continue,
+                Key::Cancel => {
+                    (self.buffer, self.cursor, self.history_index) = before;
+                    return Ok(Key::Cancel);
+                }
+                key => return Ok(key),
+            };
+
+            let found = history
+                .search(&query)
+                .rev()
+                .find(|&index| index < older_than);
+            failed = found.is_none();
+            if let Some(index) = found {
+                if self.history_index.is_none() {
+                    self.saved_buffer.clone_from(&before.0);
+                }
+                self.show_history(history, index);
+                let command = &history.entries()[index].command;
+                if let Some(start) = command.find(query.as_str()) {
+                    self.cursor = command[..start].chars().count();
+                }
+            }
+        }
+    }
+
+    /// Draw the prompt and buffer over what was drawn last, wrapping them at
+    /// the terminal's current width, and put the cursor where it belongs.
+    fn redraw(&mut self, prompt: &str) -> io::Result<()> {
+        self.draw(prompt, true)
+    }
+
+    /// Draw the line, with the bracket or quote matching the one at the
+    /// cursor highlighted, and closers that close nothing in red, if
+    /// `highlight` is set.
+    fn draw(&mut self, prompt: &str, highlight: bool) -> io::Result<()> {
+        self.render_line(prompt, highlight);
+        synchronized(&mut io::stdout().lock(), &self.render)
+    }
+
+    /// Put what [`Editor::draw`] sends to the terminal in `self.render`.
+    fn render_line(&mut self, prompt: &str, highlight: bool) {
+        let width = platform::terminal_width().unwrap_or(80);
+        let prompt_len = prompt.chars().count();
+        let (end_row, end_column) = self.position(prompt_len, self.buffer.len(), width);
+        let (row, column) = self.position(prompt_len, self.cursor, width);
+
+        self.render.clear();
+        if self.cursor_row > 0 {
+            let _ = write!(self.render, "\x1b[{}A", self.cursor_row);
+        }
+        // Lines after the first may be shorter than they were, so clear
+        // everything up front rather than after the end
+        self.render.push_str("\r\x1b[J");
+        self.render.push_str(prompt);
+        if highlight {
+            self.render_highlighted();
+        } else {
+            for &c in &self.buffer {
+                render_char(&mut self.render, c);
+            }
+        }
+        // Ending exactly at the edge leaves the cursor waiting to wrap, so
+        // move it down to where the next character would go
+        if end_row > 0 && end_column == 0 {
+            self.render.push_str("\r\n");
+        }
+
+        let up = end_row - row;
+        if up > 0 {
+            let _ = write!(self.render, "\x1b[{up}A");
+        }
+        self.render.push('\r');
+        if column > 0 {
+            let _ = write!(self.render, "\x1b[{column}C");
+        }
+        self.cursor_row = row;
+    }
+
+    /// The row and column, counting from the prompt's, that the character at
+    /// `index` in the buffer is drawn at, with the lines wrapped at `width`
+    /// and those after a newline starting after [`CONTINUATION`].
+    fn position(&self, prompt_len: usize, index: usize, width: usize) -> (usize, usize) {
+        let (mut row, mut column) = (0, prompt_len);
+        for &c in &self.buffer[..index] {
+            if c == '\n' {
+                // A line that ended at the edge has already moved down
+                if column > 0 || row == 0 {
+                    row += 1;
+                }
+                column = CONTINUATION.len();
+            } else {
+                column += 1;
+            }
+            if column >= width {
+                row += column / width;
+                column %= width;
+            }
+        }
+        // A prompt as wide as the terminal already wraps
+        (row + column / width, column % width)
+    }
+
+    /// Add the buffer to what's being drawn, highlighting the match for the
+    /// bracket or quote under the cursor, or else just before it, as it is
+    /// after typing one.
+    fn render_highlighted(&mut self) {
+        let (pairs, unmatched) = pairs(&self.buffer);
+        let partner = |index: usize| get_pair_match(&pairs, index);
+        let matched = partner(self.cursor).or_else(|| partner(self.cursor.checked_sub(1)?));
+
+        for (index, &c) in self.buffer.iter().enumerate() {
+            if Some(index) == matched {
+                let _ = write!(self.render, "{MATCH}{c}{RESET}");
+            } else if unmatched.contains(&index) {
+                let _ = write!(self.render, "{UNMATCHED}{c}{RESET}");
+            } else {
+                render_char(&mut self.render, c);
+            }
+        }
+    }
+
+    /// Move below the whole line, leaving `mark` at its end, so that what's
+    /// printed next doesn't land in the middle of a line that wrapped.
+    fn leave_line(&mut self, prompt: &str, mark: &str) -> io::Result<()> {
+        self.cursor = self.buffer.len();
+        self.draw(prompt, false)?;
+        print!("{mark}\r\n");
+        self.cursor_row = 0;
+        io::stdout().flush()
+    }
+}
+
+/// Add a character of the buffer to what's being drawn, starting each line
+/// after the first with [`CONTINUATION`].
+fn render_char(render: &mut String, c: char) {
+    if c == '\n' {
+        let _ = write!(render, "\r\n{CONTINUATION}");
+    } else {
+        render.push(c);
+    }
+}
+
+/// Send `update` to the terminal to be shown all at once, by wrapping it in
+/// the synchronized update mode (2026).
+fn synchronized(output: &mut impl Write, update: &str) -> io::Result<()> {
+    write!(output, "{BEGIN_UPDATE}{update}{END_UPDATE}")?;
+    output.flush()
+}
+
+/// How many candidates the `choose` menu shows at once.
+const MENU_ROWS: usize = 10;
+/// How the candidate the `choose` menu has selected is shown
+const SELECTED: &str = "\x1b[7m";
+
+/// Let the user pick one of `candidates` from a menu drawn below `prompt`
+/// on the terminal, whatever the standard streams are, narrowed down by
+/// typing as in fzf: what's typed must appear in order in a candidate, with
+/// case ignored unless it has capitals. Returns the index of the one picked,
+/// or `None` if there was no match or the menu was closed with Ctrl-G or
+/// Ctrl-D, and an `Interrupted` error for Ctrl-C.
+pub fn choose(candidates: &[String], prompt: &str) -> io::Result<Option<usize>> {
+    let (mut input, mut output) = platform::open_terminal()?;
+    let _raw = platform::RawMode::enable_for(&input, &output)?;
+    let _resize = platform::ResizeGuard::new();
+    let width = platform::terminal_width().unwrap_or(80);
+
+    let mut query = String::new();
+    let mut selected = 0;
+    let mut top = 0;
+    let mut render = String::new();
+    let chosen = loop {
+        let shown: Vec<usize> = (0..candidates.len())
+            .filter(|&index| fuzzy_matches(&query, &candidates[index]))
+            .collect();
+        selected = selected.min(shown.len().saturating_sub(1));
+        // Scroll just far enough to keep the selection in view
+        top = top.clamp((selected + 1).saturating_sub(MENU_ROWS), selected);
+
+        render.clear();
+        let _ = write!(render, "\r{prompt}{query}\x1b[K");
+        let rows = shown.iter().enumerate().skip(top).take(MENU_ROWS);
+        for (i, &index) in rows.clone() {
+            let text: String = candidates[index]
+                .chars()
+                .filter(|c| !c.is_control())
+                .take(width.saturating_sub(3))
+                .collect();
+            if i == selected {
+                let _ = write!(render, "\r\n{SELECTED}> {text}{RESET}\x1b[K");
+            } else {
+                let _ = write!(render, "\r\n  {text}\x1b[K");
+            }
+        }
+        render.push_str("\x1b[J");
+        let drawn = rows.count();
+        if drawn > 0 {
+            let _ = write!(render, "\x1b[{drawn}A");
+        }
+        let column = prompt.chars().count() + query.chars().count();
+        render.push('\r');
+        if column > 0 {
+            let _ = write!(render, "\x1b[{column}C");
+        }
+        synchronized(&mut output, &render)?;
+
+        match read_key(&mut input)? {
+            Key::Char(c) => {
+                query.push(c);
+                (selected, top) = (0, 0);
+            }
+            Key::Backspace => {
+                query.pop();
+                (selected, top) = (0, 0);
+            }
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down | Key::Tab => selected += 1,
+            Key::Enter => break Ok(shown.get(selected).copied()),
+            Key::Interrupt => break Err(io::Error::from(io::ErrorKind::Interrupted)),
+            Key::Cancel | Key::EndOfFile => break Ok(None),
+            _ => {}
+        }
+    };
+
+    // Leave the screen as it was before the menu
+    output.write_all(b"\r\x1b[J")?;
+    output.flush()?;
+    chosen
+}
+
+/// Whether the characters of `query` appear in order in `candidate`.
+fn fuzzy_matches(query: &str, candidate: &str) -> bool {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let mut candidate = candidate.chars().map(fold);
+    query.chars().map(fold).all(|q| candidate.any(|c| c == q))
+}
+
+/// What the lines of a command after the first start with, as a reminder
+/// that they're part of it, like the default `$PS2`
+const CONTINUATION: &str = "> ";
+
+const BEGIN_UPDATE: &str = "\x1b[?2026h";
+const END_UPDATE: &str = "\x1b[?2026l";
+
+/// How the partner of the bracket or quote at the cursor is shown
+const MATCH: &str = "\x1b[1;36m";
+/// How a closing bracket that closes nothing is shown
+const UNMATCHED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// How the brackets and quotes in a line nest.
+#[derive(Default)]
+struct Nesting {
+    /// Each pair, by the indexes of its ends
+    pairs: Vec<(usize, usize)>,
+    /// Closers with nothing open to close
+    unmatched: Vec<usize>,
+    /// The openers still waiting to be closed at the end, innermost last
+    open: Vec<(usize, char)>,
+    /// Whether the line ends with a backslash, escaping what comes next
+    escaped: bool,
+}
+
+/// Work out how the brackets and quotes in `line` nest, as the lexer does:
+/// nothing nests in single quotes, only `$(` does in double quotes, and a
+/// backslash escapes what follows it outside single quotes.
+fn scan(line: &[char]) -> Nesting {
+    let mut nesting = Nesting::default();
+
+    let mut chars = line.iter().copied().enumerate().peekable();
+    while let Some((index, c)) = chars.next() {
+        let inside = nesting.open.last().map(|&(_, opener)| opener);
+        match (inside, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => {
+                let (start, _) = nesting.open.pop().unwrap();
+                nesting.pairs.push((start, index));
+            }
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                nesting.escaped = chars.next().is_none();
+            }
+            (Some('"'), '$') if chars.peek().is_some_and(|&(_, next)| next == '(') => {
+                let (start, _) = chars.next().unwrap();
+                nesting.open.push((start, '('));
+            }
+            (Some('"'), _) => {}
+            (_, '\'' | '"' | '(' | '{' | '[') => nesting.open.push((index, c)),
+            (_, ')' | '}' | ']') => {
+                if inside == Some(opener_for(c)) {
+                    let (start, _) = nesting.open.pop().unwrap();
+                    nesting.pairs.push((start, index));
+                } else {
+                    nesting.unmatched.push(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    nesting
+}
+
+/// The bracket or quote that `closer` closes.
+fn opener_for(closer: char) -> char {
+    match closer {
+        ')' => '(',
+        '}' => '{',
+        ']' => '[',
+        quote => quote,
+    }
+}
+
+/// The bracket or quote that closes `opener`.
+fn closer_for(opener: char) -> char {
+    match opener {
+        '(' => ')',
+        '{' => '}',
+        '[' => ']',
+        quote => quote,
+    }
+}
+
+/// Pair up the brackets and quotes in `line`. Returns each pair by the
+/// indexes of its ends, and the closers with nothing open to close.
+/// Openers not closed yet aren't an error, as the line may not be
+/// finished.
+pub fn pairs(line: &[char]) -> (Vec<(usize, usize)>, Vec<usize>) {
+    let nesting = scan(line);
+    (nesting.pairs, nesting.unmatched)
+}
+
+/// The other end of the pair with an end at `index`, if there is one.
+pub fn get_pair_match(pairs: &[(usize, usize)], index: usize) -> Option<usize> {
+    pairs.iter().find_map(|&(start, end)| match index {
+        _ if index == start => Some(end),
+        _ if index == end => Some(start),
+        _ => None,
+    })
+}
+
+/// Open `text` in an editor: `editor` if given, or else `$FCEDIT`,
+/// `$VISUAL`, `$EDITOR` or vi. Returns what was saved as a single line, its
+/// lines joined with `;` unless they open a group or are left unfinished by
+/// a `|` or `&&`, and comments left out, or `None` if the editor failed.
+pub fn edit_command(
+    shell: &mut ShellState,
+    text: &str,
+    editor: Option<&str>,
+) -> io::Result<Option<String>> {
+    let editor = editor
+        .map(str::to_string)
+        .or_else(|| {
+            ["FCEDIT", "VISUAL", "EDITOR"]
+                .iter()
+                .filter_map(|name| shell.variables.get(name))
+                .find(|editor| !editor.is_empty())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "vi".to_string());
+
+    let path = std::env::temp_dir().join(format!("sigsh-edit-{}.sh", std::process::id()));
+    std::fs::write(&path, format!("{text}\n"))?;
+    let status = shell.last_status;
+    let edited = shell.eval(&format!("{} {}", editor, quote(&path.to_string_lossy())));
+    let saved = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    shell.last_status = status;
+    if edited? != 0 {
+        return Ok(None);
+    }
+
+    let saved = saved?;
+    let lines = saved
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.trim_end_matches(';'));
+    let mut joined = String::new();
+    for line in lines {
+        if !joined.is_empty() {
+            let open = ["{", "(", "|", "&&", "||"]
+                .iter()
+                .any(|end| joined.ends_with(end));
+            joined.push_str(if open { " " } else { "; " });
+        }
+        joined.push_str(line);
+    }
+    Ok(Some(joined))
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            // A signal handler ran, which isn't a reason to give up the line
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<Key> {
+    let mut byte = [0u8];
+    let byte = match input.read(&mut byte) {
+        Ok(0) => return Ok(Key::EndOfFile),
+        Ok(_) => byte[0],
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => return Ok(Key::Resize),
+        Err(e) => return Err(e),
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfFile,
+        0x05 => Key::End,
+        0x07 => Key::Cancel,
+        0x12 => Key::SearchHistory,
+        0x18 => match read_byte(input)? {
+            Some(0x05) => Key::EditCommand,
+            _ => Key::Unknown,
+        },
+        0x1b => read_escape(input)?,
+        b if b < 0x20 => Key::Unknown,
+        b => read_utf8(input, b)?,
+    };
+    Ok(key)
+}
+
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    match read_byte(input)? {
+        Some(b'[') => {}
+        Some(b's' | b'S') => return Ok(Key::ToggleSudo),
+        _ => return Ok(Key::Unknown),
+    }
+
+    let key = match read_byte(input)? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(b'3') if read_byte(input)? == Some(b'~') => Key::Delete,
+        _ => Key::Unknown,
+    };
+    Ok(key)
+}
+
+fn read_utf8(input: &mut impl Read, first: u8) -> io::Result<Key> {
+    let len = match first {
+        b if b >= 0xf0 => 4,
+        b if b >= 0xe0 => 3,
+        b if b >= 0xc0 => 2,
+        _ => 1,
+    };
+
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        match read_byte(input)? {
+            Some(b) => bytes.push(b),
+            None => break,
+        }
+    }
+
+    Ok(std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map_or(Key::Unknown, Key::Char))
+}
@@ -0,0 +1,1182 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind as IOErrorKind, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::activity;
+use crate::builtins;
+use crate::debugger::Action;
+use crate::expand;
+use crate::fds;
+use crate::intercept;
+use crate::jobs::JobState;
+use crate::parser::{Arg, Command, Compound, FileRedir, RedirType};
+use crate::platform::{self, Process, ProcessGroup, Stdio, WaitStatus};
+use crate::shell::{Frame, Function, ShellState};
+use crate::stderr::StderrRelay;
+use crate::vars::{self, Value, Variable};
+
+/// Run a command and everything chained after it in the foreground,
+/// returning the exit status of the last one to run.
+pub fn run_command(shell: &mut ShellState, cmd: &Command) -> io::Result<i32> {
+    let mut next = Some(cmd);
+    let mut status = 0;
+
+    while let Some(cmd) = next {
+        run_trap(shell, "DEBUG");
+        let started = shell
+            .profiler
+            .is_some()
+            .then(|| (Instant::now(), shell.location.clone()));
+        let res = run_pipeline(shell, cmd);
+        if let (Some((started, location)), Some(profiler)) = (started, &mut shell.profiler) {
+            profiler.record(location, cmd, started.elapsed());
+        }
+        // An error has already been reported along with where it happened
+        let reported = res.is_err();
+        let statuses = res.unwrap_or_else(|e| vec![report(shell, &e)]);
+        status = if shell.options.pipefail {
+            statuses.iter().rev().find(|&&status| status != 0).copied()
+        } else {
+            statuses.last().copied()
+        }
+        .unwrap_or(0);
+        shell.last_status = status;
+        set_pipestatus(shell, &statuses);
+
+        // What comes after a pipeline hangs off its last command
+        let mut tail = cmd;
+        while let Some(pipe) = &tail.pipe_to {
+            tail = &pipe.target;
+        }
+
+        // Failures on the left of `&&` are expected, so don't count for `set -e`
+        let checked = !tail.and_then.as_ref().is_some_and(|next| next.conditional);
+        if status != 0 && checked {
+            run_trap(shell, "ERR");
+        }
+        if status != 0 && checked && shell.options.errexit && shell.exit.is_none() {
+            // A subshell leaves saying so to the shell it came from
+            if in_script(shell) && !reported && !shell.subshell {
+                eprintln!("{}: exiting with status {status} (set -e)", shell.location);
+                print_stack(shell);
+            }
+            shell.exit = Some(status);
+        }
+        if shell.exit.is_some() || shell.returning {
+            break;
+        }
+
+        // Skip past any `&&` whose left side failed
+        next = None;
+        let mut link = tail.and_then.as_ref();
+        while let Some(and_then) = link {
+            if !and_then.conditional || status == 0 {
+                next = Some(&*and_then.target);
+                break;
+            }
+            let mut skipped = &*and_then.target;
+            while let Some(pipe) = &skipped.pipe_to {
+                skipped = &pipe.target;
+            }
+            link = skipped.and_then.as_ref();
+        }
+    }
+
+    Ok(status)
+}
+
+/// Run the command set with `trap` for `condition`, if there is one, leaving
+/// `$?` as it was. Like bash without `set -E` and `set -T`, traps aren't
+/// run for the commands inside functions.
+fn run_trap(shell: &mut ShellState, condition: &str) {
+    if shell.in_trap || !shell.call_stack.is_empty() {
+        return;
+    }
+    let Some(action) = shell
+        .traps
+        .get(condition)
+        .filter(|action| !action.trim().is_empty())
+        .cloned()
+    else {
+        return;
+    };
+
+    let status = shell.last_status;
+    let pipestatus = shell.variables.get_array("PIPESTATUS");
+    shell.in_trap = true;
+    match Command::parse_with_aliases(&action, &shell.aliases) {
+        Ok(command) => {
+            let _ = run_command(shell, &command);
+        }
+        Err(errs) => eprintln!("trap: {errs}"),
+    }
+    shell.in_trap = false;
+    shell.last_status = status;
+    if let Some(pipestatus) = pipestatus {
+        let _ = shell.variables.set_array("PIPESTATUS", pipestatus);
+    }
+}
+
+/// Set `$PIPESTATUS` to the status of each stage of the pipeline that just
+/// ran, so scripts can tell which one failed.
+pub(crate) fn set_pipestatus(shell: &mut ShellState, statuses: &[i32]) {
+    let statuses = statuses.iter().map(i32::to_string).collect();
+    // It can only fail if made readonly, when it's left as it is
+    let _ = shell.variables.set_array("PIPESTATUS", statuses);
+}
+
+/// Whether errors should say where they happened: only in scripts and
+/// functions, since at the prompt it's obvious.
+fn in_script(shell: &ShellState) -> bool {
+    shell.location.file.is_some() || !shell.call_stack.is_empty()
+}
+
+/// Print the functions being run, innermost first, with where each one was
+/// called from.
+pub(crate) fn print_stack(shell: &ShellState) {
+    for frame in shell.call_stack.iter().rev() {
+        eprintln!("  in {} called at {}", frame.name, frame.called_from);
+    }
+}
+
+/// Print an error that stopped a command from running, with where it
+/// happened when that's in a script or function, and return the status the
+/// command gets for it.
+fn report(shell: &ShellState, e: &io::Error) -> i32 {
+    if in_script(shell) {
+        eprintln!("{}: {}", shell.location, e);
+        print_stack(shell);
+    } else {
+        eprintln!("{}", e);
+    }
+    if e.kind() == IOErrorKind::NotFound {
+        127
+    } else {
+        1
+    }
+}
+
+/// A command with its words expanded, ready to run.
+#[derive(Clone)]
+enum Prepared<'a> {
+    Compound(&'a Compound),
+    Simple {
+        args: Vec<String>,
+        assignments: Vec<(String, Value)>,
+    },
+}
+
+impl Prepared<'_> {
+    /// How the command shows up in the job table.
+    fn describe(&self) -> String {
+        match self {
+            Prepared::Compound(Compound::Group(_)) => "{ ... }".to_string(),
+            Prepared::Compound(Compound::Spawn { name, .. }) => format!("spawn {name}"),
+            Prepared::Compound(_) => "( ... )".to_string(),
+            Prepared::Simple { args, .. } => args.join(" "),
+        }
+    }
+}
+
+fn prepare<'a>(shell: &mut ShellState, cmd: &'a Command) -> io::Result<Prepared<'a>> {
+    if let Some(compound) = &cmd.compound {
+        return Ok(Prepared::Compound(compound));
+    }
+
+    shell.substatus.clear();
+    // `[[` is a builtin, but one whose args aren't split or globbed
+    let mut args = match cmd.argv.first() {
+        Some(Arg::Glob(word)) if word == "[[" => expand::expand_conditional(shell, &cmd.argv)?,
+        // As is the command after `noglob`, which then runs by itself
+        Some(Arg::Word(word)) if word == "noglob" && cmd.argv.len() > 1 => {
+            let noglob = mem::replace(&mut shell.options.noglob, true);
+            let args = expand::expand_args(shell, &cmd.argv[1..]);
+            shell.options.noglob = noglob;
+            args?
+        }
+        _ => expand::expand_args(shell, &cmd.argv)?,
+    };
+    let running = shell.call_stack.iter().map(|frame| frame.name.as_str());
+    if let Some(rule) = shell.intercepts.find(&args, running) {
+        match &rule.action {
+            intercept::Action::Run(handler) => args = handler.iter().cloned().chain(args).collect(),
+            intercept::Action::Deny => {
+                return Err(io::Error::new(
+                    IOErrorKind::PermissionDenied,
+                    format!("{}: refused by an intercept rule", args[0]),
+                ))
+            }
+        }
+    }
+    let mut assignments = Vec::new();
+    for assignment in &cmd.assignments {
+        let value = match &assignment.value {
+            Arg::Array(words) => Value::Array(expand::expand_args(shell, words)?),
+            value => Value::Scalar(expand::expand_word(shell, value)?),
+        };
+        assignments.push((assignment.name.clone(), value));
+    }
+    if !shell.substatus.is_empty() {
+        let statuses = shell.substatus.iter().map(i32::to_string).collect();
+        let _ = shell.variables.set_array("SUBSTATUS", statuses);
+        // With nothing to run, the last one's status is the command's anyway
+        let strict = shell.options.errexit && shell.options.errexit_subst && !args.is_empty();
+        let failed = shell.substatus.iter().find(|&&status| status != 0);
+        if let Some(status) = failed.filter(|_| strict) {
+            return Err(io::Error::other(format!(
+                "{}: not run, as a $(...) failed with status {status}",
+                args[0]
+            )));
+        }
+    }
+
+    if shell.options.xtrace && !args.is_empty() {
+        trace(shell, &args);
+    }
+    if let Some(mut debugger) = shell.debugger.take() {
+        let shown = if args.is_empty() {
+            let assigned = assignments.iter();
+            assigned
+                .map(|(name, value)| format!("{name}={}", value.as_str()))
+                .collect()
+        } else {
+            args.clone()
+        };
+        let action = debugger.before_command(shell, &shown);
+        shell.debugger = Some(debugger);
+        if action == Action::Quit {
+            // Run nothing, and stop there
+            shell.exit = Some(1);
+            return Ok(Prepared::Simple {
+                args: Vec::new(),
+                assignments: Vec::new(),
+            });
+        }
+    }
+    Ok(Prepared::Simple { args, assignments })
+}
+
+/// Open the file a redirection names, for reading or writing as it says.
+pub(crate) fn open_target(shell: &ShellState, redirect: &FileRedir) -> io::Result<File> {
+    let open = || -> io::Result<File> {
+        if matches!(
+            redirect.redirect_type,
+            RedirType::Stdin | RedirType::FdIn(_)
+        ) {
+            File::open(&redirect.target)
+        } else if shell.options.noclobber && !redirect.append && !redirect.clobber {
+            open_without_clobbering(&redirect.target)
+        } else {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(redirect.append)
+                .truncate(!redirect.append)
+                .open(&redirect.target)
+        }
+    };
+    open().map_err(|e| io::Error::new(e.kind(), format!("{}: {}", redirect.target.display(), e)))
+}
+
+/// Open the files a command's redirections name, on top of `stdio`.
+fn open_redirects(
+    shell: &ShellState,
+    redirects: &[FileRedir],
+    stdio: &mut Stdio,
+) -> io::Result<()> {
+    for redirect in redirects {
+        if let RedirType::FdIn(fd) | RedirType::FdOut(fd) | RedirType::FdClose(fd) =
+            redirect.redirect_type
+        {
+            return Err(io::Error::new(
+                IOErrorKind::InvalidInput,
+                format!("{fd}: only exec can redirect descriptors past 2"),
+            ));
+        }
+
+        let file = open_target(shell, redirect)?;
+        match redirect.redirect_type {
+            RedirType::Stdin => stdio.stdin = Some(file),
+            RedirType::Stdout => stdio.stdout = Some(file),
+            RedirType::Stderr => stdio.stderr = Some(file),
+            RedirType::Both => {
+                stdio.stderr = Some(file.try_clone()?);
+                stdio.stdout = Some(file);
+            }
+            RedirType::FdIn(_) | RedirType::FdOut(_) | RedirType::FdClose(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Create `path` for writing, refusing if it's an existing regular file.
+/// Anything else, like `/dev/null`, is opened without truncating it.
+fn open_without_clobbering(path: &Path) -> io::Result<File> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Err(e) if e.kind() == IOErrorKind::AlreadyExists => {
+            if fs::metadata(path)?.is_file() {
+                return Err(io::Error::new(
+                    IOErrorKind::AlreadyExists,
+                    "cannot overwrite existing file",
+                ));
+            }
+            OpenOptions::new().write(true).open(path)
+        }
+        result => result,
+    }
+}
+
+fn first_group(shell: &ShellState) -> ProcessGroup {
+    if shell.job_control {
+        ProcessGroup::Lead
+    } else {
+        ProcessGroup::Inherit
+    }
+}
+
+/// Show `args` for `set -x`, after `$PS4` expanded as if double-quoted, to
+/// the fd in `$SIGSH_XTRACEFD` if it's set or else to stderr.
+fn trace(shell: &mut ShellState, args: &[String]) {
+    let prefix = match shell.variables.get("PS4").map(str::to_string) {
+        Some(ps4) => {
+            let quoted = format!(": \"{}\"", ps4.replace('"', "\\\""));
+            let word = Command::parse(quoted)
+                .ok()
+                .and_then(|cmd| cmd.argv.get(1).cloned());
+            // Nothing run while expanding it is traced, or changes `$?`
+            let xtrace = mem::replace(&mut shell.options.xtrace, false);
+            let substatus = mem::take(&mut shell.substatus);
+            let prefix =
+                shell.keeping_status(|shell| word.map(|word| expand::expand_word(shell, &word)));
+            shell.substatus = substatus;
+            shell.options.xtrace = xtrace;
+            match prefix {
+                Some(Ok(prefix)) => prefix,
+                _ => ps4,
+            }
+        }
+        None => "+ ".to_string(),
+    };
+    let line = args
+        .iter()
+        .map(|arg| quote_word(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let line = format!("{prefix}{line}\n");
+
+    let fd = shell
+        .variables
+        .get("SIGSH_XTRACEFD")
+        .and_then(|fd| fd.parse::<i32>().ok());
+    if let Some(fd) = fd.filter(|&fd| fd != 2) {
+        match platform::borrow_fd(fd).and_then(|mut file| file.write_all(line.as_bytes())) {
+            Ok(()) => return,
+            Err(e) => eprintln!("SIGSH_XTRACEFD: {e}"),
+        }
+    }
+    let _ = io::stderr().write_all(line.as_bytes());
+}
+
+/// Quote `word` for showing a command, unless it reads back fine as it is.
+pub(crate) fn quote_word(word: &str) -> String {
+    let plain = |c: char| c.is_alphanumeric() || "-_./=:,+%@".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        expand::quote(word)
+    }
+}
+
+/// How a redirection is written.
+pub(crate) fn redirect_operator(redirect: &FileRedir) -> String {
+    let operator = match (&redirect.redirect_type, redirect.append, redirect.clobber) {
+        (RedirType::Stdin, ..) => "<",
+        (RedirType::Stdout, true, _) => ">>",
+        (RedirType::Stdout, false, true) => ">|",
+        (RedirType::Stdout, false, false) => ">",
+        (RedirType::Stderr, true, _) => "2>>",
+        (RedirType::Stderr, false, true) => "2>|",
+        (RedirType::Stderr, false, false) => "2>",
+        (RedirType::Both, true, _) => "&>>",
+        (RedirType::Both, false, _) => "&>",
+        (RedirType::FdIn(fd), ..) => return format!("{fd}<"),
+        (RedirType::FdOut(fd), true, _) => return format!("{fd}>>"),
+        (RedirType::FdOut(fd), false, _) => return format!("{fd}>"),
+        (RedirType::FdClose(fd), ..) => return format!("{fd}<&-"),
+    };
+    operator.to_string()
+}
+
+/// For `--dry-run`: print the pipeline with its words expanded instead of
+/// running it. Only what can't do any harm is run: assignments, function
+/// definitions, and groups and subshells on their own, whose commands are
+/// printed as they come up.
+fn dry_run(shell: &mut ShellState, cmd: &Command) -> io::Result<i32> {
+    let alone = cmd.pipe_to.is_none() && cmd.redirect_to.is_empty();
+    if let (Some(compound), true) = (&cmd.compound, alone) {
+        return run_compound(shell, compound);
+    }
+
+    let mut line = Vec::new();
+    let mut stage = Some(cmd);
+    while let Some(cmd) = stage {
+        match prepare(shell, cmd)? {
+            Prepared::Simple { args, assignments } if args.is_empty() && alone => {
+                let prepared = Prepared::Simple { args, assignments };
+                return run_prepared(shell, prepared, Stdio::default());
+            }
+            Prepared::Simple { args, assignments } => {
+                for (name, value) in assignments {
+                    line.push(format!("{name}={}", quote_word(value.as_str())));
+                }
+                line.extend(args.iter().map(|arg| quote_word(arg)));
+            }
+            compound => line.push(compound.describe()),
+        }
+        for redirect in &cmd.redirect_to {
+            let target = match redirect.redirect_type {
+                RedirType::FdClose(_) => String::new(),
+                _ => quote_word(&redirect.target.to_string_lossy()),
+            };
+            line.push(format!("{}{target}", redirect_operator(redirect)));
+        }
+        if let Some(pipe) = &cmd.pipe_to {
+            let operator = match pipe.pipe_type {
+                RedirType::Both => "|&",
+                _ => "|",
+            };
+            line.push(operator.to_string());
+        }
+        stage = cmd.pipe_to.as_ref().map(|pipe| &*pipe.target);
+    }
+    println!("{}", line.join(" "));
+    Ok(0)
+}
+
+/// Run a command and whatever it's piped into, returning the status of
+/// each command in the pipeline.
+fn run_pipeline(shell: &mut ShellState, cmd: &Command) -> io::Result<Vec<i32>> {
+    if shell.dry_run {
+        return dry_run(shell, cmd).map(|status| vec![status]);
+    }
+    // Stderr that isn't redirected goes through this instead
+    let relay = StderrRelay::start(shell)?;
+    let relay_stderr = |stdio: &mut Stdio| -> io::Result<()> {
+        if let (Some(relay), None) = (&relay, &stdio.stderr) {
+            stdio.stderr = Some(relay.writer()?);
+        }
+        Ok(())
+    };
+
+    if cmd.pipe_to.is_none() {
+        let prepared = prepare(shell, cmd)?;
+        // `exec` on its own makes its redirections last
+        if let Prepared::Simple { args, assignments } = &prepared {
+            if args.len() == 1 && args[0] == "exec" && assignments.is_empty() {
+                fds::redirect(shell, &cmd.redirect_to)?;
+                return Ok(vec![0]);
+            }
+        }
+        let mut stdio = Stdio::default();
+        open_redirects(shell, &cmd.redirect_to, &mut stdio)?;
+        relay_stderr(&mut stdio)?;
+        return run_prepared(shell, prepared, stdio).map(|status| vec![status]);
+    }
+
+    let mut stages = vec![cmd];
+    while let Some(pipe) = &stages[stages.len() - 1].pipe_to {
+        stages.push(&pipe.target);
+    }
+
+    // When only builtins run, and none but the last goes on writing for
+    // long, the stages can all run right here. Otherwise they're all forked:
+    // a forked stage would inherit the pipes this shell is still feeding.
+    let in_shell = stages.iter().all(|stage| runs_builtins(shell, stage))
+        && !stages[..stages.len() - 1]
+            .iter()
+            .any(|stage| streams(stage));
+
+    let mut group = first_group(shell);
+    let mut processes = Vec::new();
+    let mut feeders = Vec::new();
+    let mut descriptions = Vec::new();
+    let mut input = None;
+    // The status of each stage, except for processes still to wait for
+    let mut statuses = Vec::new();
+
+    for (i, stage) in stages.iter().enumerate() {
+        let last = i + 1 == stages.len();
+        let mut stdio = Stdio {
+            stdin: input.take(),
+            ..Default::default()
+        };
+        if let Some(pipe) = &stage.pipe_to {
+            let (reader, writer) = platform::pipe()?;
+            if pipe.pipe_type == RedirType::Both {
+                stdio.stderr = Some(writer.try_clone()?);
+            }
+            stdio.stdout = Some(writer);
+            input = Some(reader);
+        }
+
+        // A stage that can't start just gets a failure status, and the next
+        // one sees end-of-file
+        // Builtins run here with their output in memory would pass relayed
+        // stderr down the pipe as if it were `|&`
+        let relayed = last || !in_shell;
+        let started = open_redirects(shell, &stage.redirect_to, &mut stdio)
+            .and_then(|_| match relayed {
+                true => relay_stderr(&mut stdio),
+                false => Ok(()),
+            })
+            .and_then(|_| prepare(shell, stage))
+            .and_then(|prepared| {
+                descriptions.push(prepared.describe());
+                start_stage(shell, prepared, stdio, &mut input, group, last, in_shell)
+            });
+
+        match started {
+            Ok(Started::Process(process)) => {
+                let command = descriptions.last().cloned().unwrap_or_default();
+                shell.children.started(process.id(), command);
+                if group == ProcessGroup::Lead {
+                    group = ProcessGroup::Join(process.id());
+                }
+                processes.push(process);
+                statuses.push(None);
+            }
+            Ok(Started::Finished(code, feeder)) => {
+                feeders.extend(feeder);
+                statuses.push(Some(code));
+            }
+            Err(e) => statuses.push(Some(report(shell, &e))),
+        }
+    }
+
+    let mut waited = wait_job(shell, processes, descriptions.join(" | "))?.into_iter();
+    for feeder in feeders {
+        let _ = feeder.join();
+    }
+    Ok(statuses
+        .into_iter()
+        .map(|status| status.or_else(|| waited.next()).unwrap_or(0))
+        .collect())
+}
+
+pub(crate) enum Started {
+    Process(Process),
+    /// The stage already ran in the shell, maybe leaving a thread feeding its
+    /// output to the next stage.
+    Finished(i32, Option<JoinHandle<()>>),
+}
+
+/// Start one stage of a pipeline. Programs are spawned; anything run by the
+/// shell itself gets a forked copy of the shell, except for the last stage,
+/// which runs right here so that `... | read x` can set variables, and the
+/// stages of a pipeline of builtins when `in_shell` is set, which run here as
+/// subshells with their output handed over from memory.
+/// `downstream` is the read end of the pipe this stage writes to, which a
+/// forked copy closes so it notices when the reader goes away.
+fn start_stage(
+    shell: &mut ShellState,
+    prepared: Prepared,
+    mut stdio: Stdio,
+    downstream: &mut Option<File>,
+    group: ProcessGroup,
+    last: bool,
+    in_shell: bool,
+) -> io::Result<Started> {
+    if let Prepared::Simple { args, assignments } = &prepared {
+        if is_external(shell, args) {
+            let env = command_env(shell, assignments.clone());
+            // Too long a list is left to `run_prepared`, to run in chunks
+            if platform::args_fit(args, &env) {
+                return platform::spawn(args, &env, &stdio, group).map(Started::Process);
+            }
+        }
+    }
+
+    if last {
+        return run_prepared(shell, prepared, stdio).map(|status| Started::Finished(status, None));
+    }
+
+    if !in_shell {
+        let forked = platform::fork_subshell(&stdio, group, || {
+            drop(downstream.take());
+            enter_subshell(shell);
+            let status = run_prepared(shell, prepared.clone(), Stdio::default())
+                .unwrap_or_else(|e| report(shell, &e));
+            shell.exit.unwrap_or(status)
+        })?;
+        if let Some(process) = forked {
+            return Ok(Started::Process(process));
+        }
+    }
+
+    // Otherwise run the stage now, collecting its output in memory so it
+    // can't fill the pipe and block before the next stage is even started.
+    let (mut drain_reader, drain_writer) = platform::pipe()?;
+    let out = stdio.stdout.replace(drain_writer.try_clone()?);
+    if stdio.stderr.is_some() {
+        stdio.stderr = Some(drain_writer.try_clone()?);
+    }
+    drop(drain_writer);
+    let drain = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = drain_reader.read_to_end(&mut output);
+        output
+    });
+
+    let status = run_in_place(shell, |shell| run_prepared(shell, prepared, stdio))?;
+    let output = drain.join().unwrap_or_default();
+    let feeder = out.map(|mut out| {
+        thread::spawn(move || {
+            let _ = out.write_all(&output);
+        })
+    });
+    Ok(Started::Finished(status, feeder))
+}
+
+/// Start `args` as a process of its own, for builtins like `timeout` which
+/// need one to keep an eye on. Builtins and functions run in a forked copy of
+/// the shell, or where there's no fork, run to completion right here.
+pub(crate) fn start_command(shell: &mut ShellState, args: Vec<String>) -> io::Result<Started> {
+    let group = first_group(shell);
+    let command = args.join(" ");
+    if is_external(shell, &args) {
+        let env = command_env(shell, Vec::new());
+        let process = platform::spawn(&args, &env, &Stdio::default(), group)?;
+        shell.children.started(process.id(), command);
+        return Ok(Started::Process(process));
+    }
+
+    let prepared = Prepared::Simple {
+        args,
+        assignments: Vec::new(),
+    };
+    let forked = platform::fork_subshell(&Stdio::default(), group, || {
+        enter_subshell(shell);
+        let status = run_prepared(shell, prepared.clone(), Stdio::default())
+            .unwrap_or_else(|e| report(shell, &e));
+        shell.exit.unwrap_or(status)
+    })?;
+    match forked {
+        Some(process) => {
+            shell.children.started(process.id(), command);
+            Ok(Started::Process(process))
+        }
+        None => run_prepared(shell, prepared, Stdio::default())
+            .map(|status| Started::Finished(status, None)),
+    }
+}
+
+/// Start `args` in a session of its own, away from the terminal and the job
+/// table, for `disown`. Returns its process ID.
+pub(crate) fn start_detached(
+    shell: &mut ShellState,
+    args: Vec<String>,
+    stdio: &Stdio,
+) -> io::Result<u32> {
+    let command = args.join(" ");
+    if is_external(shell, &args) {
+        let env = command_env(shell, Vec::new());
+        let pid = platform::spawn(&args, &env, stdio, ProcessGroup::Detach)?.id();
+        shell.children.started(pid, command);
+        return Ok(pid);
+    }
+
+    let prepared = Prepared::Simple {
+        args,
+        assignments: Vec::new(),
+    };
+    let forked = platform::fork_subshell(stdio, ProcessGroup::Detach, || {
+        enter_subshell(shell);
+        let status =
+            run_prepared(shell, prepared, Stdio::default()).unwrap_or_else(|e| report(shell, &e));
+        shell.exit.unwrap_or(status)
+    })?;
+    let pid = forked.map(|process| process.id()).ok_or_else(|| {
+        io::Error::new(
+            IOErrorKind::Unsupported,
+            "can't run builtins or functions detached here",
+        )
+    })?;
+    shell.children.started(pid, command);
+    Ok(pid)
+}
+
+/// Run `args` in the foreground as a command of its own, for builtins which
+/// run other commands.
+pub(crate) fn run_args(shell: &mut ShellState, args: Vec<String>) -> io::Result<i32> {
+    let prepared = Prepared::Simple {
+        args,
+        assignments: Vec::new(),
+    };
+    run_prepared(shell, prepared, Stdio::default())
+}
+
+/// Set up a forked copy of the shell to run a subshell.
+fn enter_subshell(shell: &mut ShellState) {
+    // The jobs belong to the parent shell, and so does the terminal
+    shell.job_control = false;
+    shell.jobs = Default::default();
+    shell.reset_traps();
+    shell.subshell = true;
+    for fd in mem::take(&mut shell.capturing) {
+        let _ = platform::close_fd(fd);
+    }
+}
+
+/// The environment for a program: exported variables, plus any assignments
+/// in front of the command.
+fn command_env(shell: &ShellState, assignments: Vec<(String, Value)>) -> Vec<(String, String)> {
+    let mut env = shell.environment();
+    for (name, value) in assignments {
+        // Arrays can't go in the environment
+        if let Value::Scalar(value) = value {
+            env.retain(|(existing, _)| *existing != name);
+            env.push((name, value));
+        }
+    }
+    env
+}
+
+/// Run a command in the foreground with its standard streams pointed at
+/// `stdio`.
+fn run_prepared(shell: &mut ShellState, prepared: Prepared, stdio: Stdio) -> io::Result<i32> {
+    let (args, assignments) = match prepared {
+        Prepared::Simple { args, assignments } if is_autocd(shell, &args) => {
+            let cd = builtins::find("cd").expect("cd is a builtin");
+            let _redirect = platform::redirect_std(&stdio)?;
+            let args = ["cd".to_string(), args[0].clone()];
+            return with_assignments(shell, assignments, |shell| cd.run(shell, &args));
+        }
+        Prepared::Simple { args, assignments } if is_external(shell, &args) => {
+            let env = command_env(shell, assignments);
+            if !platform::args_fit(&args, &env) {
+                return run_in_chunks(shell, &args, &env, &stdio);
+            }
+            let process = platform::spawn(&args, &env, &stdio, first_group(shell))?;
+            shell.children.started(process.id(), args.join(" "));
+            let status = wait_job(shell, vec![process], args.join(" "))?;
+            return Ok(status.last().copied().unwrap_or(0));
+        }
+        Prepared::Simple { args, assignments } => (args, assignments),
+        Prepared::Compound(compound) => {
+            let _redirect = platform::redirect_std(&stdio)?;
+            return run_compound(shell, compound);
+        }
+    };
+
+    if args.is_empty() {
+        for (target, value) in assignments {
+            shell.variables.assign(&target, value)?;
+        }
+        return Ok(shell.substatus.last().copied().unwrap_or(0));
+    }
+
+    let _redirect = platform::redirect_std(&stdio)?;
+
+    if let Some(function) = shell.functions.get(&args[0]).cloned() {
+        return with_assignments(shell, assignments, |shell| {
+            call_function(shell, &function, &args)
+        });
+    }
+
+    match builtins::find(&args[0]) {
+        Some(builtin) => with_assignments(shell, assignments, |shell| builtin.run(shell, &args)),
+        None => unreachable!("externals are spawned above"),
+    }
+}
+
+/// Run a program whose argument list is too long to pass at once, if
+/// `chunk-args` is set, as many times as it takes to pass all of it. The
+/// status is that of the last run that failed, and a run killed by a
+/// signal stops the rest.
+fn run_in_chunks(
+    shell: &mut ShellState,
+    args: &[String],
+    env: &[(String, String)],
+    stdio: &Stdio,
+) -> io::Result<i32> {
+    let chunks = shell
+        .options
+        .chunk_args
+        .then(|| platform::split_args(args, env));
+    let Some(chunks) = chunks.flatten() else {
+        return Err(platform::args_too_long(args, env));
+    };
+
+    let mut status = 0;
+    for chunk in chunks {
+        let process = platform::spawn(&chunk, env, stdio, first_group(shell))?;
+        shell.children.started(process.id(), chunk.join(" "));
+        let last = wait_job(shell, vec![process], chunk[0].clone())?;
+        match last.last().copied().unwrap_or(0) {
+            0 => {}
+            signalled if signalled > 128 => return Ok(signalled),
+            failed => status = failed,
+        }
+    }
+    Ok(status)
+}
+
+/// Whether `args` runs a program rather than something in the shell itself.
+fn is_external(shell: &ShellState, args: &[String]) -> bool {
+    args.first()
+        .is_some_and(|name| !shell.functions.contains_key(name) && builtins::find(name).is_none())
+}
+
+/// Whether `args` is just a directory to change into, with `autocd` set and
+/// no command by that name.
+fn is_autocd(shell: &ShellState, args: &[String]) -> bool {
+    shell.options.autocd
+        && args.len() == 1
+        && is_external(shell, args)
+        && platform::find_executable(&args[0]).is_none()
+        && Path::new(&args[0]).is_dir()
+}
+
+/// Run `f` with `assignments` set, putting the variables back afterwards, as
+/// for `FOO=bar builtin`.
+fn with_assignments(
+    shell: &mut ShellState,
+    assignments: Vec<(String, Value)>,
+    f: impl FnOnce(&mut ShellState) -> io::Result<i32>,
+) -> io::Result<i32> {
+    let mut saved: Vec<(String, Option<Variable>)> = Vec::new();
+    let mut res = Ok(());
+    for (target, value) in assignments {
+        let name = vars::split_subscript(&target).map_or(target.as_str(), |(name, _)| name);
+        saved.push((name.to_string(), shell.variables.var(name).cloned()));
+        res = shell.variables.assign(&target, value);
+        if res.is_err() {
+            break;
+        }
+    }
+
+    let res = match res {
+        Ok(()) => f(shell),
+        Err(e) => Err(e.into()),
+    };
+
+    for (name, var) in saved.into_iter().rev() {
+        match var {
+            Some(var) => shell.variables.insert(name, var),
+            None => {
+                let _ = shell.variables.unset(&name);
+            }
+        }
+    }
+    res
+}
+
+/// Run a function, with its body taken to be where it was defined.
+fn call_function(shell: &mut ShellState, function: &Function, args: &[String]) -> io::Result<i32> {
+    let saved = shell.variables.set_positional(args[1..].to_vec());
+    let called_from = std::mem::replace(&mut shell.location, function.location.clone());
+    shell.call_stack.push(Frame {
+        name: args[0].clone(),
+        called_from,
+    });
+    if let Some(debugger) = &mut shell.debugger {
+        debugger.entering_function(&args[0]);
+    }
+    shell.variables.push_scope();
+    shell.local_options.push(None);
+
+    let res = run_command(shell, &function.body);
+
+    if let Some(Some(options)) = shell.local_options.pop() {
+        shell.options = options;
+    }
+    shell.variables.pop_scope();
+    if let Some(frame) = shell.call_stack.pop() {
+        shell.location = frame.called_from;
+    }
+    shell.variables.set_positional(saved);
+    shell.returning = false;
+    res
+}
+
+fn run_compound(shell: &mut ShellState, compound: &Compound) -> io::Result<i32> {
+    match compound {
+        Compound::Group(body) => run_command(shell, body),
+        Compound::Subshell(body) => run_subshell(shell, body),
+        Compound::Spawn { name, body } => spawn(shell, name, body),
+        Compound::FunctionDef { name, body } => {
+            // Defining it again keeps it exported
+            let exported = shell.functions.get(name).is_some_and(|old| old.exported);
+            let function = Function {
+                body: body.clone(),
+                location: shell.location.clone(),
+                exported,
+            };
+            shell.functions.insert(name.clone(), function);
+            Ok(0)
+        }
+    }
+}
+
+/// Start `body` in the background as the pipeline called `name`, for
+/// `wait-for` and `$SPAWNSTATUS`. Where there's no fork it runs to the end
+/// right here instead.
+fn spawn(shell: &mut ShellState, name: &str, body: &Command) -> io::Result<i32> {
+    if shell.jobs.named(name).is_some() {
+        eprintln!("spawn: {name}: already running");
+        return Ok(1);
+    }
+    let group = if shell.job_control {
+        ProcessGroup::Background
+    } else {
+        ProcessGroup::Inherit
+    };
+    let forked = platform::fork_subshell(&Stdio::default(), group, || {
+        enter_subshell(shell);
+        let status = run_command(shell, body).unwrap_or_else(|e| report(shell, &e));
+        shell.exit.unwrap_or(status)
+    })?;
+    let status = match forked {
+        Some(process) => {
+            shell
+                .children
+                .started(process.id(), format!("spawn {name}"));
+            shell.jobs.add_spawned(name, process);
+            "running".to_string()
+        }
+        None => run_subshell(shell, body)?.to_string(),
+    };
+    shell.set_spawn_status(name, &status);
+    Ok(0)
+}
+
+/// Whether `cmd` itself only runs builtins, ignoring whatever's piped or
+/// chained after it.
+fn runs_builtins(shell: &ShellState, cmd: &Command) -> bool {
+    match &cmd.compound {
+        Some(compound) => match &**compound {
+            Compound::Group(body) | Compound::Subshell(body) => is_builtin_only(shell, body),
+            Compound::FunctionDef { .. } | Compound::Spawn { .. } => true,
+        },
+        None => match cmd
+            .argv
+            .iter()
+            .find(|arg| !matches!(arg, Arg::Word(w) if w == "noglob"))
+        {
+            None => true,
+            // Functions may well run other programs, and `exec` changes
+            // the process for good
+            Some(Arg::Word(name)) => {
+                builtins::find(name).is_some()
+                    && name != "exec"
+                    && !shell.functions.contains_key(name)
+            }
+            Some(_) => false,
+        },
+    }
+}
+
+/// Whether `cmd` runs a builtin whose output is wanted as it comes, as
+/// [`Builtin::streams`](builtins::Builtin::streams) says.
+fn streams(cmd: &Command) -> bool {
+    let own = match cmd.compound.as_deref() {
+        Some(Compound::Group(body) | Compound::Subshell(body)) => {
+            let mut stages = vec![body];
+            while let Some(pipe) = &stages[stages.len() - 1].pipe_to {
+                stages.push(&pipe.target);
+            }
+            stages.into_iter().any(streams)
+        }
+        Some(_) => false,
+        None => match cmd
+            .argv
+            .iter()
+            .find(|arg| !matches!(arg, Arg::Word(w) if w == "noglob"))
+        {
+            Some(Arg::Word(name)) => builtins::find(name).is_some_and(|builtin| builtin.streams()),
+            _ => false,
+        },
+    };
+    own || cmd
+        .and_then
+        .as_ref()
+        .is_some_and(|next| streams(&next.target))
+}
+
+/// Whether everything `cmd` runs is a builtin, so that it can run as a
+/// subshell without forking.
+fn is_builtin_only(shell: &ShellState, cmd: &Command) -> bool {
+    // Pipelines need processes on both ends
+    runs_builtins(shell, cmd)
+        && cmd.pipe_to.is_none()
+        && cmd
+            .and_then
+            .as_ref()
+            .is_none_or(|next| is_builtin_only(shell, &next.target))
+}
+
+/// Run `body` in a subshell, so that nothing it does affects this shell.
+///
+/// Bodies which only run builtins are run right here between a snapshot and a
+/// restore of the shell's state. Anything else gets a forked copy of the shell
+/// where that's possible.
+fn run_subshell(shell: &mut ShellState, body: &Command) -> io::Result<i32> {
+    if !is_builtin_only(shell, body) {
+        let forked = platform::fork_subshell(&Stdio::default(), first_group(shell), || {
+            enter_subshell(shell);
+            let status = run_command(shell, body).unwrap_or(1);
+            shell.exit.unwrap_or(status)
+        })?;
+        if let Some(process) = forked {
+            shell.children.started(process.id(), "( ... )".to_string());
+            let status = wait_job(shell, vec![process], "( ... )".to_string())?;
+            return Ok(status.last().copied().unwrap_or(0));
+        }
+    }
+
+    run_in_place(shell, |shell| run_command(shell, body))
+}
+
+/// Run `run` as a subshell right here, between a snapshot and a restore of
+/// the shell's state.
+fn run_in_place(
+    shell: &mut ShellState,
+    run: impl FnOnce(&mut ShellState) -> io::Result<i32>,
+) -> io::Result<i32> {
+    let snapshot = shell.snapshot()?;
+    shell.reset_traps();
+    let subshell = mem::replace(&mut shell.subshell, true);
+    let res = run(shell);
+    shell.subshell = subshell;
+    let exit = shell.exit.take();
+    shell.returning = false;
+    shell.restore(snapshot)?;
+    res.map(|status| exit.unwrap_or(status))
+}
+
+/// Run `run` with the shell's standard output collected in memory, returning
+/// what was written along with the status. `$(...)` and `capture` both go
+/// through here.
+fn capture_output(
+    shell: &mut ShellState,
+    run: impl FnOnce(&mut ShellState) -> io::Result<i32>,
+) -> io::Result<(Vec<u8>, i32)> {
+    let max_bytes = expand::max_bytes(shell);
+    let (reader, writer) = platform::pipe()?;
+    let capturing = platform::fd_of(&reader);
+    shell.capturing.extend(capturing);
+    // Reading stops a byte past the limit, and closing the pipe then stops
+    // whatever's still writing to it
+    let drain = thread::spawn(move || {
+        let mut output = Vec::new();
+        let limit = u64::try_from(max_bytes).unwrap_or(u64::MAX);
+        let _ = reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut output);
+        output
+    });
+
+    let stdio = Stdio {
+        stdout: Some(writer),
+        ..Default::default()
+    };
+    let res = platform::redirect_std(&stdio).and_then(|_redirect| run(shell));
+    shell.capturing.retain(|&fd| Some(fd) != capturing);
+    // The reader only sees end-of-file once every copy of the pipe is closed
+    drop(stdio);
+    let output = drain.join().unwrap_or_default();
+    if output.len() > max_bytes {
+        return Err(expand::too_many_bytes(max_bytes));
+    }
+    Ok((output, res?))
+}
+
+/// Run `body` as a subshell for `$(...)`, returning its output without the
+/// trailing newlines, and its status.
+pub(crate) fn substitute(shell: &mut ShellState, body: &Command) -> io::Result<(String, i32)> {
+    // `$(<file)` has nothing to run, just the file to read
+    if let Some(path) = body.file_read() {
+        return match fs::read(path) {
+            Ok(contents) => {
+                let contents = String::from_utf8_lossy(&contents);
+                Ok((contents.trim_end_matches('\n').to_string(), 0))
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                Ok((String::new(), 1))
+            }
+        };
+    }
+    let (output, status) = capture_output(shell, |shell| run_subshell(shell, body))?;
+    let output = String::from_utf8_lossy(&output);
+    Ok((output.trim_end_matches('\n').to_string(), status))
+}
+
+/// Run `args` as a subshell, returning everything it wrote to standard
+/// output, and its status.
+pub(crate) fn capture_args(
+    shell: &mut ShellState,
+    args: Vec<String>,
+) -> io::Result<(Vec<u8>, i32)> {
+    capture_output(shell, |shell| {
+        run_in_place(shell, |shell| run_args(shell, args))
+    })
+}
+
+/// Say that a foreground command was killed by a signal, unless that was
+/// Ctrl-C or writing to a closed pipe, which other shells don't mention
+/// either.
+fn report_signal(command: &str, signal: i32, core_dumped: bool) {
+    let name = platform::signal_name(signal);
+    if matches!(name, Some("INT" | "PIPE")) {
+        return;
+    }
+    let name = name.map_or_else(|| format!("signal {signal}"), |name| format!("SIG{name}"));
+    let core = if core_dumped { " (core dumped)" } else { "" };
+    eprintln!("{command}: terminated by {name}{core}");
+}
+
+/// Wait for the processes of a foreground job, adding it to the job table if
+/// it stops. Returns the status of each process.
+fn wait_job(
+    shell: &mut ShellState,
+    processes: Vec<Process>,
+    command: String,
+) -> io::Result<Vec<i32>> {
+    let Some(pgid) = processes.first().map(Process::id) else {
+        return Ok(Vec::new());
+    };
+
+    let indicator = activity::Indicator::start(shell, pgid);
+    let mut codes = Vec::new();
+    let mut stopped = false;
+    for mut process in processes {
+        let status = process.wait()?;
+        if let WaitStatus::TermSignal(signal, core_dumped) = status {
+            report_signal(&command, signal, core_dumped);
+        }
+        stopped |= matches!(status, WaitStatus::Stopped(_));
+        codes.push(status.code());
+        shell.children.changed(process.id(), status.into());
+        if let Some(usage) = process.usage() {
+            shell.usage.get_or_insert_default().add(usage);
+        }
+    }
+    drop(indicator);
+
+    if shell.job_control {
+        platform::reclaim_terminal()?;
+    }
+
+    if stopped {
+        let id = shell.jobs.add(pgid, command, JobState::Stopped);
+        if let Some(job) = shell.jobs.get(id) {
+            eprintln!("\n{}", job.describe(true));
+        }
+    }
+
+    Ok(codes)
+}
@@ -0,0 +1,629 @@
+//! Turning parsed args into the strings a command is run with: variable
+//! and arithmetic expansion, field splitting and globbing.
+//!
+//! With `set -o safeexpand`, as in zsh, the value of an unquoted variable
+//! is one word, and isn't globbed: `$file` is always the file, even with
+//! spaces or a `*` in its name. Each element of `$@` or an array is still
+//! its own word, and command substitutions are still split.
+
+use std::fs;
+use std::io::{self, Error as IOError, ErrorKind as IOErrorKind};
+use std::mem;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::arith;
+use crate::exec;
+use crate::glob;
+use crate::parser::{Arg, Command};
+use crate::regex;
+use crate::shell::ShellState;
+
+/// Characters unquoted expansions are split on.
+const IFS: &[char] = &[' ', '\t', '\n'];
+
+/// How many words a command's arguments may expand to, unless
+/// `EXPANSION_MAX_WORDS` says otherwise, so that a runaway glob can't
+/// take the shell down with it.
+const DEFAULT_MAX_WORDS: usize = 1 << 20;
+/// How many bytes a command's arguments or an assignment may expand to,
+/// unless `EXPANSION_MAX_BYTES` says otherwise.
+const DEFAULT_MAX_BYTES: usize = 64 << 20;
+
+/// The limit in the variable `name`, where 0 means there's none.
+fn limit(shell: &ShellState, name: &str, default: usize) -> usize {
+    match shell
+        .variables
+        .get(name)
+        .and_then(|value| value.parse().ok())
+    {
+        Some(0) => usize::MAX,
+        Some(limit) => limit,
+        None => default,
+    }
+}
+
+/// Fail if `words` is more than the limits allow.
+fn check_limits(shell: &ShellState, words: &[String]) -> io::Result<()> {
+    let max_words = limit(shell, "EXPANSION_MAX_WORDS", DEFAULT_MAX_WORDS);
+    if words.len() > max_words {
+        return Err(IOError::new(
+            IOErrorKind::InvalidInput,
+            format!("expansion is more than {max_words} words (see EXPANSION_MAX_WORDS)"),
+        ));
+    }
+    let max_bytes = max_bytes(shell);
+    if words.iter().map(String::len).sum::<usize>() > max_bytes {
+        return Err(too_many_bytes(max_bytes));
+    }
+    Ok(())
+}
+
+/// How many bytes an expansion may come to.
+pub(crate) fn max_bytes(shell: &ShellState) -> usize {
+    limit(shell, "EXPANSION_MAX_BYTES", DEFAULT_MAX_BYTES)
+}
+
+pub(crate) fn too_many_bytes(max_bytes: usize) -> IOError {
+    IOError::new(
+        IOErrorKind::InvalidInput,
+        format!("expansion is more than {max_bytes} bytes (see EXPANSION_MAX_BYTES)"),
+    )
+}
+
+/// One field being built up, kept as a pattern with quoted glob characters
+/// escaped until we know whether to glob it.
+#[derive(Default)]
+struct Field {
+    pattern: String,
+    is_glob: bool,
+}
+
+#[derive(Default)]
+struct Fields {
+    done: Vec<Field>,
+    current: Option<Field>,
+}
+
+impl Fields {
+    fn current(&mut self) -> &mut Field {
+        self.current.get_or_insert_with(Field::default)
+    }
+
+    fn end_field(&mut self) {
+        if let Some(field) = self.current.take() {
+            self.done.push(field);
+        }
+    }
+
+    /// Text that stands for itself, glob characters and all.
+    fn push_literal(&mut self, text: &str) {
+        let field = self.current();
+        for c in text.chars() {
+            if glob::is_meta(c) || c == '\\' || c == '(' {
+                field.pattern.push('\\');
+            }
+            field.pattern.push(c);
+        }
+    }
+
+    fn push_pattern(&mut self, pattern: &str) {
+        let field = self.current();
+        field.pattern.push_str(pattern);
+        field.is_glob = true;
+    }
+
+    /// The value of an unquoted variable with `set -o safeexpand`, which is
+    /// neither split nor globbed, but still left out if it's empty.
+    fn push_whole(&mut self, value: &str) {
+        if !value.is_empty() {
+            self.push_literal(value);
+        }
+    }
+
+    /// The result of an unquoted expansion, which is split into fields and
+    /// may contain glob patterns of its own.
+    fn push_split(&mut self, value: &str) {
+        if value.starts_with(IFS) {
+            self.end_field();
+        }
+        let mut words = value.split(IFS).filter(|word| !word.is_empty()).peekable();
+        while let Some(word) = words.next() {
+            let field = self.current();
+            field.is_glob |= word.chars().any(glob::is_meta);
+            field.pattern.push_str(&word.replace('\\', "\\\\"));
+            if words.peek().is_some() {
+                self.end_field();
+            }
+        }
+        if value.ends_with(IFS) {
+            self.end_field();
+        }
+    }
+
+    fn finish(mut self, shell: &ShellState) -> io::Result<Vec<String>> {
+        self.end_field();
+        let sort = shell
+            .variables
+            .get("GLOBSORT")
+            .and_then(glob::Sort::parse)
+            .unwrap_or_default();
+        let mut words = Vec::new();
+        for field in self.done {
+            if field.is_glob && !shell.options.noglob {
+                let cache = shell.options.glob_cache.then_some(&shell.glob_cache);
+                let qualifiers = shell.options.glob_qualifiers;
+                let expanded = glob::expand_with(&field.pattern, qualifiers, sort, cache)
+                    .map_err(|e| IOError::new(IOErrorKind::InvalidInput, e))?;
+                if shell.options.failglob {
+                    // What didn't match comes back as it was
+                    let pattern = glob::unescape(&field.pattern);
+                    if expanded == [pattern.as_str()] && fs::symlink_metadata(&pattern).is_err() {
+                        let message = format!("no match: {pattern}");
+                        return Err(IOError::new(IOErrorKind::InvalidInput, message));
+                    }
+                }
+                words.extend(expanded);
+            } else {
+                words.push(glob::unescape(&field.pattern));
+            }
+        }
+        Ok(words)
+    }
+}
+
+/// Quote `value` so the shell would read it back unchanged.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn unbound(name: &str) -> IOError {
+    IOError::new(IOErrorKind::NotFound, format!("{name}: unbound variable"))
+}
+
+/// The value of a variable or special parameter, if it's set.
+pub fn lookup(shell: &ShellState, name: &str) -> Option<String> {
+    let positional = shell.variables.positional();
+    match name {
+        "?" => Some(shell.last_status.to_string()),
+        "#" => Some(positional.len().to_string()),
+        "$" => Some(std::process::id().to_string()),
+        "0" => Some(env!("CARGO_PKG_NAME").to_string()),
+        "LINENO" => Some(shell.location.line.to_string()),
+        "EPOCHREALTIME" => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            Some(format!("{}.{:06}", now.as_secs(), now.subsec_micros()))
+        }
+        "FUNCNAME" => shell.call_stack.last().map(|frame| frame.name.clone()),
+        "SIGSH_SOURCE" => shell
+            .location
+            .file
+            .as_ref()
+            .map(|file| file.display().to_string()),
+        "@" | "*" => Some(positional.join(" ")),
+        "-" => Some(
+            crate::options::OPTIONS
+                .iter()
+                .filter(|option| shell.options.get(option.name) == Some(true))
+                .filter_map(|option| option.letter)
+                .collect(),
+        ),
+        _ => match name.parse::<usize>() {
+            Ok(n) => positional.get(n - 1).cloned(),
+            Err(_) => shell.variables.get(name).map(str::to_string),
+        },
+    }
+}
+
+/// What a parameter expands to.
+enum Expansion {
+    Value(String),
+    /// `$@` or `${array[@]}`: one field per element, even when quoted
+    Fields(Vec<String>),
+    /// `$*` or `${array[*]}`: the elements joined with spaces when quoted
+    Joined(Vec<String>),
+}
+
+fn bad_substitution(expr: &str) -> IOError {
+    IOError::new(
+        IOErrorKind::InvalidInput,
+        format!("${{{expr}}}: bad substitution"),
+    )
+}
+
+fn is_parameter_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+        Some(c) if c.is_ascii_digit() => chars.all(|c| c.is_ascii_digit()),
+        Some(c) => chars.next().is_none() && "?#@*$!-".contains(c),
+        None => false,
+    }
+}
+
+/// Look up a parameter expression, which is a name as in `$name`, or
+/// whatever was between the braces of `${...}`: `name`, `name[index]`,
+/// `name[@]`, `#name`, `#name[@]` or `!name[@]`, any of which can end in
+/// `@Q` to quote each value so the shell would read it back unchanged.
+/// `name`, `name[@]`, `@` and the rest may be sliced with `:offset` or
+/// `:offset:length`.
+fn expand_parameter(shell: &mut ShellState, expr: &str) -> io::Result<Expansion> {
+    let expansion = match expr.strip_suffix("@Q") {
+        Some(parameter) if !parameter.is_empty() => {
+            let quote_all = |values: Vec<String>| values.iter().map(|v| quote(v)).collect();
+            match lookup_parameter(shell, parameter, true)? {
+                Expansion::Fields(values) => Expansion::Fields(quote_all(values)),
+                Expansion::Joined(values) => Expansion::Joined(quote_all(values)),
+                // Quoted already, if it was set
+                value => value,
+            }
+        }
+        _ => lookup_parameter(shell, expr, false)?,
+    };
+    Ok(expansion)
+}
+
+/// Look up a parameter expression without any transformation, quoting the
+/// value if `quoted` and it's set.
+fn lookup_parameter(shell: &mut ShellState, expr: &str, quoted: bool) -> io::Result<Expansion> {
+    if let Some((parameter, offset, length)) = split_slice(expr) {
+        return slice(shell, parameter, offset, length, quoted);
+    }
+    let (length, rest) = match expr.strip_prefix('#') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, expr),
+    };
+    let (keys, rest) = match rest.strip_prefix('!') {
+        Some(rest) if !length && !rest.is_empty() => (true, rest),
+        _ => (false, rest),
+    };
+    let (name, subscript) = match crate::vars::split_subscript(rest) {
+        Some((name, subscript)) => (name, Some(subscript)),
+        None => (rest, None),
+    };
+    if !is_parameter_name(name) {
+        return Err(bad_substitution(expr));
+    }
+
+    let elements = |shell: &ShellState| -> Vec<String> {
+        if name == "@" || name == "*" {
+            shell.variables.positional().to_vec()
+        } else if name == "FUNCNAME" {
+            let frames = shell.call_stack.iter().rev();
+            frames.map(|frame| frame.name.clone()).collect()
+        } else {
+            shell.variables.get_array(name).unwrap_or_default()
+        }
+    };
+
+    let value = match subscript {
+        Some("@") if keys => return Ok(Expansion::Fields(shell.variables.keys(name))),
+        Some("*") if keys => return Ok(Expansion::Joined(shell.variables.keys(name))),
+        _ if keys => return Err(bad_substitution(expr)),
+        Some("@" | "*") if length => {
+            return Ok(Expansion::Value(elements(shell).len().to_string()));
+        }
+        Some("@") => return Ok(Expansion::Fields(elements(shell))),
+        Some("*") => return Ok(Expansion::Joined(elements(shell))),
+        // Only variables are arrays
+        Some(_) if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+            return Err(bad_substitution(expr));
+        }
+        Some(subscript) => shell.variables.get_element(name, subscript)?,
+        None if length && (name == "@" || name == "*") => {
+            return Ok(Expansion::Value(elements(shell).len().to_string()));
+        }
+        None if name == "@" => return Ok(Expansion::Fields(elements(shell))),
+        None if name == "*" => return Ok(Expansion::Joined(elements(shell))),
+        None => lookup(shell, name),
+    };
+
+    match value {
+        Some(value) if length => Ok(Expansion::Value(value.chars().count().to_string())),
+        Some(value) if quoted => Ok(Expansion::Value(quote(&value))),
+        Some(value) => Ok(Expansion::Value(value)),
+        None if shell.options.nounset => Err(unbound(name)),
+        None if length => Ok(Expansion::Value("0".to_string())),
+        None => Ok(Expansion::Value(String::new())),
+    }
+}
+
+/// Split `parameter:offset` or `parameter:offset:length` into its parts.
+/// As in bash, a negative offset needs a space or parentheses, as in
+/// `${name: -2}`, since `:-` is another operator.
+fn split_slice(expr: &str) -> Option<(&str, &str, Option<&str>)> {
+    let mut depth = 0;
+    let colon = expr.char_indices().find_map(|(i, c)| {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ':' if depth == 0 => return Some(i),
+            _ => {}
+        }
+        None
+    })?;
+    let (parameter, rest) = (&expr[..colon], &expr[colon + 1..]);
+    if parameter.is_empty() || rest.starts_with(['-', '=', '+', '?']) {
+        return None;
+    }
+    Some(match rest.split_once(':') {
+        Some((offset, length)) => (parameter, offset, Some(length)),
+        None => (parameter, rest, None),
+    })
+}
+
+/// A slice of a parameter's value, by characters, or of its elements.
+/// Offsets count back from the end if they're negative, and so does a
+/// negative length, for a value; `$@` and `$*` count `$0` as the first.
+fn slice(
+    shell: &mut ShellState,
+    parameter: &str,
+    offset: &str,
+    length: Option<&str>,
+    quoted: bool,
+) -> io::Result<Expansion> {
+    let expr = match length {
+        Some(length) => format!("{parameter}:{offset}:{length}"),
+        None => format!("{parameter}:{offset}"),
+    };
+    if parameter.starts_with(['#', '!']) && parameter.len() > 1 {
+        return Err(bad_substitution(&expr));
+    }
+    let offset = number(shell, offset)?;
+    let length = length.map(|length| number(shell, length)).transpose()?;
+    let range = |len: usize, elements: bool| -> io::Result<std::ops::Range<usize>> {
+        let len = len as i64;
+        let start = if offset < 0 { len + offset } else { offset };
+        if start < 0 || start > len {
+            return Ok(0..0);
+        }
+        let end = match length {
+            None => len,
+            Some(length) if length >= 0 => len.min(start + length),
+            Some(length) if !elements && len + length >= start => len + length,
+            Some(_) => {
+                return Err(IOError::new(
+                    IOErrorKind::InvalidInput,
+                    format!("${{{expr}}}: substring expression < 0"),
+                ))
+            }
+        };
+        Ok(start as usize..end as usize)
+    };
+
+    let expansion = match parameter {
+        "@" | "*" => {
+            let mut values = vec![lookup(shell, "0").unwrap_or_default()];
+            values.extend_from_slice(shell.variables.positional());
+            match parameter {
+                "@" => Expansion::Fields(values),
+                _ => Expansion::Joined(values),
+            }
+        }
+        _ => lookup_parameter(shell, parameter, false)?,
+    };
+    let quote_if = |value: String| if quoted { quote(&value) } else { value };
+    Ok(match expansion {
+        Expansion::Value(value) => {
+            let chars: Vec<char> = value.chars().collect();
+            Expansion::Value(quote_if(chars[range(chars.len(), false)?].iter().collect()))
+        }
+        Expansion::Fields(values) => Expansion::Fields(values[range(values.len(), true)?].to_vec()),
+        Expansion::Joined(values) => Expansion::Joined(values[range(values.len(), true)?].to_vec()),
+    })
+}
+
+fn arithmetic(shell: &mut ShellState, expr: &str) -> io::Result<String> {
+    number(shell, expr).map(|value| value.to_string())
+}
+
+/// The value of an arithmetic expression.
+fn number(shell: &mut ShellState, expr: &str) -> io::Result<i64> {
+    arith::eval(expr, &mut shell.variables)
+        .map_err(|e| IOError::new(IOErrorKind::InvalidInput, format!("{expr}: {e}")))
+}
+
+/// The words of a `(...)` list, quoted and put back in parentheses, for
+/// builtins like `declare` which take them as one argument.
+fn expand_list(shell: &mut ShellState, words: &[Arg]) -> io::Result<String> {
+    let words: Vec<_> = expand_args(shell, words)?
+        .iter()
+        .map(|word| quote(word))
+        .collect();
+    Ok(format!("({})", words.join(" ")))
+}
+
+/// The output of `$(...)`, leaving `$?` set to its status and adding it to
+/// the command's statuses for `$SUBSTATUS`. In a dry run, its commands are
+/// printed rather than run, and it expands to nothing.
+fn command_substitution(shell: &mut ShellState, body: &Command) -> io::Result<String> {
+    if shell.dry_run {
+        exec::run_command(shell, body)?;
+        return Ok(String::new());
+    }
+    // The commands inside have statuses of their own
+    let outer = mem::take(&mut shell.substatus);
+    let res = exec::substitute(shell, body);
+    shell.substatus = outer;
+    let (output, status) = res?;
+    shell.last_status = status;
+    shell.substatus.push(status);
+    Ok(output)
+}
+
+fn expand_arg(shell: &mut ShellState, arg: &Arg, fields: &mut Fields) -> io::Result<()> {
+    match arg {
+        Arg::Word(word) => fields.push_literal(word),
+        Arg::Glob(pattern) => fields.push_pattern(pattern),
+        Arg::Variable(expr) => {
+            let values = match expand_parameter(shell, expr)? {
+                Expansion::Value(value) => vec![value],
+                Expansion::Fields(values) | Expansion::Joined(values) => values,
+            };
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    fields.end_field();
+                }
+                if shell.options.safeexpand {
+                    fields.push_whole(value);
+                } else {
+                    fields.push_split(value);
+                }
+            }
+        }
+        Arg::Subshell(body) => fields.push_split(&command_substitution(shell, body)?),
+        Arg::Arith(expr) => fields.push_split(&arithmetic(shell, expr)?),
+        Arg::Array(words) => fields.push_literal(&expand_list(shell, words)?),
+        Arg::Quoted(parts) => {
+            // Even `"$EMPTY"` makes a field
+            fields.current();
+            for part in parts {
+                expand_quoted(shell, part, fields)?;
+            }
+        }
+        Arg::Concat(parts) => {
+            for part in parts {
+                expand_arg(shell, part, fields)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn expand_quoted(shell: &mut ShellState, arg: &Arg, fields: &mut Fields) -> io::Result<()> {
+    match arg {
+        Arg::Variable(expr) => match expand_parameter(shell, expr)? {
+            Expansion::Value(value) => fields.push_literal(&value),
+            Expansion::Joined(values) => fields.push_literal(&values.join(" ")),
+            Expansion::Fields(values) => {
+                // Each element is its own field, joined to whatever's either
+                // side of the quotes; with none at all there isn't even an
+                // empty field.
+                if values.is_empty()
+                    && fields
+                        .current
+                        .as_ref()
+                        .is_some_and(|f| f.pattern.is_empty())
+                {
+                    fields.current = None;
+                }
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        fields.end_field();
+                    }
+                    fields.push_literal(value);
+                }
+            }
+        },
+        Arg::Word(word) => fields.push_literal(word),
+        Arg::Glob(pattern) => fields.push_literal(&glob::unescape(pattern)),
+        Arg::Subshell(body) => fields.push_literal(&command_substitution(shell, body)?),
+        Arg::Arith(expr) => fields.push_literal(&arithmetic(shell, expr)?),
+        Arg::Array(words) => fields.push_literal(&expand_list(shell, words)?),
+        Arg::Quoted(parts) | Arg::Concat(parts) => {
+            for part in parts {
+                expand_quoted(shell, part, fields)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expand a command's args into the fields it's run with.
+pub fn expand_args(shell: &mut ShellState, args: &[Arg]) -> io::Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        let mut fields = Fields::default();
+        expand_arg(shell, arg, &mut fields)?;
+        expanded.extend(fields.finish(shell)?);
+        check_limits(shell, &expanded)?;
+    }
+    Ok(expanded)
+}
+
+/// Expand the args of a `[[ ... ]]` conditional, each into a single word
+/// without splitting or globbing. Each is left as a pattern, with quoted glob
+/// characters escaped, so that `==` can tell `*` from `"*"`, except for the
+/// operand of `=~`, which becomes a regex with what was quoted escaped.
+pub fn expand_conditional(shell: &mut ShellState, args: &[Arg]) -> io::Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 && matches!(&args[i - 1], Arg::Word(op) if op == "=~") {
+            expanded.push(expand_regex(shell, arg)?);
+            check_limits(shell, &expanded)?;
+            continue;
+        }
+        let mut fields = Fields::default();
+        expand_pattern(shell, arg, &mut fields)?;
+        fields.end_field();
+        let words = fields.done.into_iter().map(|field| field.pattern);
+        expanded.push(words.collect::<Vec<_>>().join(" "));
+        check_limits(shell, &expanded)?;
+    }
+    Ok(expanded)
+}
+
+/// Expand the operand of `=~`, where unquoted text and expansions are the
+/// regex and what's quoted stands for itself. The lexer keeps such words as
+/// patterns with quoted punctuation escaped.
+fn expand_regex(shell: &mut ShellState, arg: &Arg) -> io::Result<String> {
+    Ok(match arg {
+        Arg::Glob(pattern) => {
+            let mut regex = String::new();
+            let mut chars = pattern.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        let quoted = chars.next().map(String::from).unwrap_or_default();
+                        regex.push_str(&regex::escape(&quoted));
+                    }
+                    c => regex.push(c),
+                }
+            }
+            regex
+        }
+        Arg::Word(word) => word.clone(),
+        Arg::Variable(_) | Arg::Subshell(_) | Arg::Arith(_) => expand_word(shell, arg)?,
+        Arg::Concat(parts) => {
+            let mut regex = String::new();
+            for part in parts {
+                regex.push_str(&expand_regex(shell, part)?);
+            }
+            regex
+        }
+        arg => regex::escape(&expand_word(shell, arg)?),
+    })
+}
+
+/// Like [`expand_quoted`], but leaving unquoted globs and expansions as
+/// patterns.
+fn expand_pattern(shell: &mut ShellState, arg: &Arg, fields: &mut Fields) -> io::Result<()> {
+    match arg {
+        Arg::Glob(pattern) => fields.push_pattern(pattern),
+        Arg::Variable(_) | Arg::Subshell(_) | Arg::Arith(_) => {
+            fields.push_pattern(&expand_word(shell, arg)?);
+        }
+        Arg::Quoted(_) => expand_arg(shell, arg, fields)?,
+        Arg::Concat(parts) => {
+            for part in parts {
+                expand_pattern(shell, part, fields)?;
+            }
+        }
+        arg => expand_quoted(shell, arg, fields)?,
+    }
+    Ok(())
+}
+
+/// Expand an arg into a single string, without splitting or globbing, as for
+/// the value of an assignment.
+pub fn expand_word(shell: &mut ShellState, arg: &Arg) -> io::Result<String> {
+    let mut fields = Fields::default();
+    expand_quoted(shell, arg, &mut fields)?;
+    let word = fields
+        .current
+        .map(|field| glob::unescape(&field.pattern))
+        .unwrap_or_default();
+    check_limits(shell, std::slice::from_ref(&word))?;
+    Ok(word)
+}
@@ -0,0 +1,77 @@
+//! Descriptors redirected for good with `exec`, as in `exec 3< file` or
+//! `exec 2> log`, which the shell keeps track of for `fdlist`. Those past the
+//! standard three are closed on exec, so only the shell's own builtins, like
+//! `mapfile -u 3`, see them.
+
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind as IOErrorKind};
+use std::path::PathBuf;
+
+use crate::exec;
+use crate::parser::{FileRedir, RedirType};
+use crate::platform;
+use crate::shell::ShellState;
+
+/// What a descriptor was pointed at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenFd {
+    /// `<`, `>` or `>>`
+    pub mode: &'static str,
+    pub target: PathBuf,
+}
+
+pub type Fds = BTreeMap<i32, OpenFd>;
+
+/// Apply `redirects` to the shell itself, for `exec` with no command.
+pub(crate) fn redirect(shell: &mut ShellState, redirects: &[FileRedir]) -> io::Result<()> {
+    for redirect in redirects {
+        let fds = match redirect.redirect_type {
+            RedirType::Stdin => vec![0],
+            RedirType::Stdout => vec![1],
+            RedirType::Stderr => vec![2],
+            RedirType::Both => vec![1, 2],
+            RedirType::FdIn(fd) | RedirType::FdOut(fd) => vec![fd as i32],
+            RedirType::FdClose(fd) => {
+                let fd = fd as i32;
+                check_free(shell, fd)?;
+                if shell.fds.remove(&fd).is_some() {
+                    platform::close_fd(fd)?;
+                }
+                continue;
+            }
+        };
+        for &fd in &fds {
+            check_free(shell, fd)?;
+        }
+
+        let file = exec::open_target(shell, redirect)?;
+        let mode = match redirect.redirect_type {
+            RedirType::Stdin | RedirType::FdIn(_) => "<",
+            _ if redirect.append => ">>",
+            _ => ">",
+        };
+        platform::install_fd(file, &fds)?;
+        for fd in fds {
+            shell.fds.insert(
+                fd,
+                OpenFd {
+                    mode,
+                    target: redirect.target.clone(),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to touch a descriptor the shell opened for itself, such as one
+/// holding a file it's reading.
+fn check_free(shell: &ShellState, fd: i32) -> io::Result<()> {
+    if fd > 2 && platform::is_open(fd) && !shell.fds.contains_key(&fd) {
+        return Err(io::Error::new(
+            IOErrorKind::ResourceBusy,
+            format!("{fd}: descriptor in use by the shell"),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,650 @@
+//! Pathname expansion.
+//!
+//! Patterns come out of the lexer with any quoted metacharacters escaped by a
+//! backslash, so `"*".txt` reaches us as `\*.txt` and only matches literally.
+//!
+//! A `**` component matches any number of directories, or as the last
+//! component, everything below. Those trees are walked on a few threads.
+//!
+//! With `set -o glob-qualifiers`, a pattern may end in zsh-style qualifiers
+//! that filter what it matches, as in `*.log(.mtime-7)`:
+//!
+//! - `.`, `/` and `@` for regular files, directories and symlinks, and `*`
+//!   for executable files
+//! - `mtime` or `m`, then `-n`, `+n` or `n` days since the file was modified,
+//!   for less than, more than or exactly that many, or in a unit of `s`,
+//!   `m`, `h`, `d` or `w` given before the sign, as in zsh's `mh-2`, or
+//!   after the number
+//! - `size` or `L`, then `-n`, `+n` or `n` bytes, or in a unit of `k`, `m`
+//!   or `g`, given the same way, which sizes are rounded up to
+//! - `^`, which negates the qualifiers after it
+//! - `on`, `om` or `oL` to sort the matches by name, newest first or smallest
+//!   first, `O` instead of `o` for the other way round, and `n` to sort
+//!   numbers in names by their value
+//!
+//! A file must pass all of them.
+//!
+//! Matches are otherwise sorted as `$GLOBSORT` says, as in bash: `name`, the
+//! default, `natural` (or `numeric`) so `file2` comes before `file10`,
+//! `mtime` or `size`, oldest or smallest first, or with a `-` in front, the
+//! other way round.
+//!
+//! With `set -o glob-cache`, the directories read are kept until the end of
+//! the command line, so a pattern expanded over and over in a loop doesn't
+//! read a big directory each time. A listing is only used while the
+//! directory's modification time is the same as when it was read.
+
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fs::{self, Metadata};
+use std::path::{self as stdpath, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::platform::{self, GLOB_CASE_SENSITIVE};
+
+/// Directories read for pathname expansion, by absolute path.
+#[derive(Default)]
+pub struct DirCache {
+    listings: Mutex<HashMap<PathBuf, (SystemTime, Listing)>>,
+}
+
+type Listing = Arc<Vec<DirEntry>>;
+
+/// A name in a directory, and whether it's a directory itself, not
+/// following symlinks.
+struct DirEntry {
+    name: OsString,
+    dir: bool,
+}
+
+impl DirCache {
+    /// Forget everything read, at the end of a command line.
+    pub fn clear(&self) {
+        self.listings.lock().unwrap().clear();
+    }
+}
+
+/// What's in `dir`, from `cache` if it's there and the directory hasn't
+/// changed since.
+fn list(dir: &Path, cache: Option<&DirCache>) -> Listing {
+    let read = || -> Vec<DirEntry> {
+        let entries = fs::read_dir(dir).into_iter().flatten().flatten();
+        entries
+            .map(|entry| DirEntry {
+                dir: entry.file_type().is_ok_and(|kind| kind.is_dir()),
+                name: entry.file_name(),
+            })
+            .collect()
+    };
+    let Some(cache) = cache else {
+        return Arc::new(read());
+    };
+    let (Ok(key), Ok(modified)) = (
+        stdpath::absolute(dir),
+        fs::metadata(dir).and_then(|meta| meta.modified()),
+    ) else {
+        return Arc::new(read());
+    };
+
+    if let Some((when, entries)) = cache.listings.lock().unwrap().get(&key) {
+        if *when == modified {
+            return Arc::clone(entries);
+        }
+    }
+    let entries = Arc::new(read());
+    let mut listings = cache.listings.lock().unwrap();
+    listings.insert(key, (modified, Arc::clone(&entries)));
+    entries
+}
+
+pub fn is_meta(c: char) -> bool {
+    matches!(c, '*' | '?' | '[')
+}
+
+fn has_meta(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if is_meta(c) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Remove the escaping backslashes the lexer added to a pattern.
+pub fn unescape(pattern: &str) -> String {
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// What matches are sorted by.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Order {
+    #[default]
+    Name,
+    /// By name, but with runs of digits compared by their value
+    Natural,
+    Mtime,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sort {
+    pub order: Order,
+    pub reverse: bool,
+}
+
+impl Sort {
+    /// Parse a `$GLOBSORT` value, like `mtime` or `-size`.
+    pub fn parse(value: &str) -> Option<Sort> {
+        let (reverse, name) = match value.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        let order = match name {
+            "name" => Order::Name,
+            "natural" | "numeric" => Order::Natural,
+            "mtime" => Order::Mtime,
+            "size" => Order::Size,
+            _ => return None,
+        };
+        Some(Sort { order, reverse })
+    }
+
+    /// Sort `words`, which are already sorted by name, so ties stay that
+    /// way.
+    fn apply(self, words: &mut [String]) {
+        match self.order {
+            Order::Name => {}
+            Order::Natural => words.sort_by(|a, b| natural_cmp(a, b)),
+            Order::Mtime => words.sort_by_cached_key(|word| {
+                fs::symlink_metadata(word)
+                    .and_then(|meta| meta.modified())
+                    .ok()
+            }),
+            Order::Size => words
+                .sort_by_cached_key(|word| fs::symlink_metadata(word).map(|meta| meta.len()).ok()),
+        }
+        if self.reverse {
+            words.reverse();
+        }
+    }
+}
+
+/// Compare names with runs of digits compared by their value, so `file2`
+/// comes before `file10`.
+fn natural_cmp(a: &str, b: &str) -> CmpOrdering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (a_len, b_len) = (digits(a), digits(b));
+        if a_len > 0 && b_len > 0 {
+            let (a_num, b_num) = (
+                a[..a_len].trim_start_matches('0'),
+                b[..b_len].trim_start_matches('0'),
+            );
+            let order = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+            if order != CmpOrdering::Equal {
+                return order;
+            }
+            (a, b) = (&a[a_len..], &b[b_len..]);
+            continue;
+        }
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return CmpOrdering::Equal,
+            (None, Some(_)) => return CmpOrdering::Less,
+            (Some(_), None) => return CmpOrdering::Greater,
+            (Some(x), Some(y)) if x != y => return x.cmp(&y),
+            (Some(x), Some(_)) => (a, b) = (&a[x.len_utf8()..], &b[x.len_utf8()..]),
+        }
+    }
+}
+
+/// Expand `pattern` against the filesystem, sorting the matches by `sort`.
+/// If `qualifiers` is set and it ends in `(qualifiers)`, keep only the paths
+/// they accept, sorted as they say.
+///
+/// Like POSIX shells, a pattern that matches nothing expands to itself.
+pub fn expand_with(
+    pattern: &str,
+    qualifiers: bool,
+    sort: Sort,
+    cache: Option<&DirCache>,
+) -> Result<Vec<String>, String> {
+    let Some((base, qualifiers)) = split_qualifiers(pattern).filter(|_| qualifiers) else {
+        return Ok(into_words(pattern, matching_paths(pattern, cache), sort));
+    };
+    let qualifiers = Qualifiers::parse(qualifiers)?;
+    let mut paths = matching_paths(base, cache);
+    paths.retain(|path| qualifiers.accepts(path));
+    Ok(into_words(pattern, paths, qualifiers.sort.unwrap_or(sort)))
+}
+
+/// Split `pattern(qualifiers)` into its two halves.
+fn split_qualifiers(pattern: &str) -> Option<(&str, &str)> {
+    let inner = pattern.strip_suffix(')')?;
+    let open = inner.rfind('(')?;
+    let escapes = inner[..open]
+        .chars()
+        .rev()
+        .take_while(|&c| c == '\\')
+        .count();
+    if open == 0 || escapes % 2 == 1 {
+        return None;
+    }
+    Some((&inner[..open], &inner[open + 1..]))
+}
+
+/// The paths matched, or `pattern` itself if there are none.
+fn into_words(pattern: &str, paths: Vec<PathBuf>, sort: Sort) -> Vec<String> {
+    let mut matches: Vec<String> = paths
+        .into_iter()
+        .filter(|path| !path.as_os_str().is_empty())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+
+    if matches.is_empty() {
+        vec![unescape(pattern)]
+    } else {
+        // `**/**` and the like find the same paths more than once
+        matches.sort();
+        matches.dedup();
+        sort.apply(&mut matches);
+        matches
+    }
+}
+
+fn matching_paths(pattern: &str, cache: Option<&DirCache>) -> Vec<PathBuf> {
+    let (root, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (PathBuf::from("/"), rest),
+        None => (PathBuf::new(), pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').filter(|c| !c.is_empty()).collect();
+    let mut paths = vec![root];
+    for (i, &component) in components.iter().enumerate() {
+        paths = if component == "**" {
+            let last = i + 1 == components.len();
+            let mut below = walk(&paths, last, cache);
+            if !last {
+                below.append(&mut paths);
+            }
+            below
+        } else {
+            paths
+                .iter()
+                .flat_map(|path| expand_component(path, component, cache))
+                .collect()
+        };
+    }
+    paths
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compare {
+    Less,
+    More,
+    Equal,
+}
+
+impl Compare {
+    fn test(self, value: u64, n: u64) -> bool {
+        match self {
+            Compare::Less => value < n,
+            Compare::More => value > n,
+            Compare::Equal => value == n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Qualifier {
+    File,
+    Dir,
+    Symlink,
+    Executable,
+    /// Size, in units of this many bytes
+    Size(Compare, u64, u64),
+    /// Time since last modified, in units of this many seconds
+    Age(Compare, u64, u64),
+}
+
+/// What a pattern's `(...)` filters its matches by, each with whether
+/// it's negated, and how it sorts them.
+#[derive(Debug, PartialEq)]
+struct Qualifiers {
+    tests: Vec<(bool, Qualifier)>,
+    sort: Option<Sort>,
+}
+
+impl Qualifiers {
+    fn parse(text: &str) -> Result<Qualifiers, String> {
+        let mut qualifiers = Vec::new();
+        let mut sort = None;
+        let mut negated = false;
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let long = [("mtime", 'm'), ("size", 'L')]
+                .into_iter()
+                .find_map(|(name, short)| Some((rest.strip_prefix(name)?, short)));
+            let c = match long {
+                Some((after, short)) => {
+                    rest = after;
+                    short
+                }
+                None => {
+                    rest = &rest[c.len_utf8()..];
+                    c
+                }
+            };
+
+            let qualifier = match c {
+                '^' => {
+                    negated = !negated;
+                    continue;
+                }
+                'o' | 'O' => {
+                    // Newest first is `om`, as in zsh
+                    let (order, reverse) = match rest.chars().next() {
+                        Some('n') => (Order::Name, false),
+                        Some('m') => (Order::Mtime, true),
+                        Some('L') => (Order::Size, false),
+                        _ => return Err(format!("{text}: expected n, m or L after {c}")),
+                    };
+                    rest = &rest[1..];
+                    let natural = sort.is_some_and(|sort: Sort| sort.order == Order::Natural);
+                    let order = if order == Order::Name && natural {
+                        Order::Natural
+                    } else {
+                        order
+                    };
+                    sort = Some(Sort {
+                        order,
+                        reverse: reverse != (c == 'O'),
+                    });
+                    continue;
+                }
+                'n' => {
+                    let mut natural = sort.unwrap_or_default();
+                    if natural.order == Order::Name {
+                        natural.order = Order::Natural;
+                    }
+                    sort = Some(natural);
+                    continue;
+                }
+                '.' => Qualifier::File,
+                '/' => Qualifier::Dir,
+                '@' => Qualifier::Symlink,
+                '*' => Qualifier::Executable,
+                'm' => {
+                    let units = [
+                        ('s', 1),
+                        ('m', 60),
+                        ('h', 3600),
+                        ('d', 86400),
+                        ('w', 604800),
+                    ];
+                    let (compare, n, unit) = parse_amount(&mut rest, &units, 86400)
+                        .ok_or_else(|| format!("{text}: expected a number of days after m"))?;
+                    Qualifier::Age(compare, n, unit)
+                }
+                'L' => {
+                    let units = [('k', 1 << 10), ('m', 1 << 20), ('g', 1 << 30)];
+                    let (compare, n, unit) = parse_amount(&mut rest, &units, 1)
+                        .ok_or_else(|| format!("{text}: expected a number of bytes after L"))?;
+                    Qualifier::Size(compare, n, unit)
+                }
+                c => return Err(format!("{text}: unknown glob qualifier {c}")),
+            };
+            qualifiers.push((negated, qualifier));
+        }
+        Ok(Qualifiers {
+            tests: qualifiers,
+            sort,
+        })
+    }
+
+    fn accepts(&self, path: &Path) -> bool {
+        let Ok(meta) = fs::symlink_metadata(path) else {
+            return false;
+        };
+        self.tests
+            .iter()
+            .all(|&(negated, qualifier)| accepts(qualifier, path, &meta) != negated)
+    }
+}
+
+/// `[unit][+-]n[unit]`, taking it off the front of `rest`.
+fn parse_amount(
+    rest: &mut &str,
+    units: &[(char, u64)],
+    default: u64,
+) -> Option<(Compare, u64, u64)> {
+    let unit = |text: &str| {
+        units
+            .iter()
+            .find(|&&(c, _)| text.starts_with(c))
+            .map(|&(_, unit)| unit)
+    };
+    let before = unit(rest);
+    if before.is_some() {
+        *rest = &rest[1..];
+    }
+    let (compare, digits) = match rest.chars().next()? {
+        '-' => (Compare::Less, &rest[1..]),
+        '+' => (Compare::More, &rest[1..]),
+        _ => (Compare::Equal, *rest),
+    };
+    let len = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    let n = digits[..len].parse().ok()?;
+    let after = &digits[len..];
+    let unit = before.or_else(|| unit(after));
+    *rest = if before.is_none() && unit.is_some() {
+        &after[1..]
+    } else {
+        after
+    };
+    Some((compare, n, unit.unwrap_or(default)))
+}
+
+fn accepts(qualifier: Qualifier, path: &Path, meta: &Metadata) -> bool {
+    match qualifier {
+        Qualifier::File => meta.is_file(),
+        Qualifier::Dir => meta.is_dir(),
+        Qualifier::Symlink => meta.file_type().is_symlink(),
+        Qualifier::Executable => meta.is_file() && platform::is_executable(path),
+        Qualifier::Size(compare, n, unit) => compare.test(meta.len().div_ceil(unit), n),
+        Qualifier::Age(compare, n, unit) => {
+            let age = meta
+                .modified()
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .unwrap_or_default();
+            compare.test(age.as_secs() / unit, n)
+        }
+    }
+}
+
+/// The most threads a walk uses.
+const MAX_WALKERS: usize = 8;
+
+/// Everything below `roots` that a `**` matches: the directories, plus the
+/// other files if `files` is set. Hidden entries are skipped and symlinks
+/// aren't followed, so a link can't lead the walk round in circles.
+///
+/// Each thread works through its own queue of directories, taking from the
+/// others once it runs dry, so one deep subtree doesn't leave the rest idle.
+/// The results come out in no particular order.
+fn walk(roots: &[PathBuf], files: bool, cache: Option<&DirCache>) -> Vec<PathBuf> {
+    let walkers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .clamp(1, MAX_WALKERS);
+    let queues: Vec<Mutex<VecDeque<PathBuf>>> =
+        (0..walkers).map(|_| Mutex::new(VecDeque::new())).collect();
+    for (i, root) in roots.iter().enumerate() {
+        queues[i % walkers].lock().unwrap().push_back(root.clone());
+    }
+    // Directories queued or being read; once none are left, the walk is over
+    let pending = AtomicUsize::new(roots.len());
+
+    let walker = |me: usize| {
+        let mut found = Vec::new();
+        while pending.load(Ordering::Acquire) > 0 {
+            let next = queues[me].lock().unwrap().pop_back().or_else(|| {
+                (1..walkers).find_map(|i| queues[(me + i) % walkers].lock().unwrap().pop_front())
+            });
+            let Some(dir) = next else {
+                thread::yield_now();
+                continue;
+            };
+
+            let read_from = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                &dir
+            };
+            for entry in list(read_from, cache).iter() {
+                if entry.name.to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let path = dir.join(&entry.name);
+                if entry.dir {
+                    pending.fetch_add(1, Ordering::AcqRel);
+                    queues[me].lock().unwrap().push_back(path.clone());
+                    found.push(path);
+                } else if files {
+                    found.push(path);
+                }
+            }
+            pending.fetch_sub(1, Ordering::AcqRel);
+        }
+        found
+    };
+
+    thread::scope(|scope| {
+        let helpers: Vec<_> = (1..walkers)
+            .map(|me| scope.spawn(move || walker(me)))
+            .collect();
+        let mut found = walker(0);
+        for helper in helpers {
+            found.extend(helper.join().unwrap_or_default());
+        }
+        found
+    })
+}
+
+fn expand_component(dir: &Path, component: &str, cache: Option<&DirCache>) -> Vec<PathBuf> {
+    if !has_meta(component) {
+        let path = dir.join(unescape(component));
+        return if path.exists() {
+            vec![path]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let read_from = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    list(read_from, cache)
+        .iter()
+        .map(|entry| entry.name.to_string_lossy().into_owned())
+        // Dotfiles only match when the pattern asks for them explicitly
+        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+        .filter(|name| matches(component, name))
+        .map(|name| dir.join(name))
+        .collect()
+}
+
+/// Match a single path component against a glob pattern.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_from(&pattern, &name)
+}
+
+fn chars_eq(a: char, b: char) -> bool {
+    if GLOB_CASE_SENSITIVE {
+        a == b
+    } else {
+        a.to_lowercase().eq(b.to_lowercase())
+    }
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| match_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        Some('[') => match (name.first(), match_bracket(&pattern[1..], name.first())) {
+            (Some(_), Some((true, len))) => match_from(&pattern[1 + len..], &name[1..]),
+            // An unterminated bracket is just a literal '['
+            (Some(&c), None) => c == '[' && match_from(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some('\\') if pattern.len() > 1 => {
+            name.first().is_some_and(|&c| chars_eq(c, pattern[1]))
+                && match_from(&pattern[2..], &name[1..])
+        }
+        Some(&p) => {
+            name.first().is_some_and(|&c| chars_eq(c, p)) && match_from(&pattern[1..], &name[1..])
+        }
+    }
+}
+
+/// Match a bracket expression (everything after the opening `[`).
+///
+/// Returns whether `c` matched and how many pattern chars the expression
+/// used, or `None` if there's no closing `]`.
+fn match_bracket(pattern: &[char], c: Option<&char>) -> Option<(bool, usize)> {
+    let c = *c?;
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        let p = pattern[i];
+        if p == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let in_range = |x: char| (p..=pattern[i + 2]).contains(&x);
+            matched |= if GLOB_CASE_SENSITIVE {
+                in_range(c)
+            } else {
+                c.to_lowercase().chain(c.to_uppercase()).any(in_range)
+            };
+            i += 3;
+        } else {
+            matched |= chars_eq(c, p);
+            i += 1;
+        }
+    }
+    None
+}
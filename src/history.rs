@@ -0,0 +1,558 @@
+//! Command history, kept in memory and appended to a history file as
+//! commands are entered.
+//!
+//! The file holds one entry per line, as `<unix timestamp> <command>`. Like
+//! zsh, newlines inside a command are written as a backslash ending the line.
+//! Unlike zsh, backslashes the command itself ends a line with are doubled,
+//! so that only an odd number of them carries on onto the next line.
+//!
+//! Searching goes through an index of the entries containing each
+//! three-byte sequence, so it stays quick with a very long history.
+//!
+//! Other shells may be appending to the same file. When the whole file has
+//! to be written again, or at exit if the file grew in the meantime, what
+//! they added is merged in by timestamp rather than overwritten, without
+//! repeating what we wrote ourselves.
+//!
+//! Secrets can be kept out of it. A command matching one of the glob
+//! patterns in `$HISTIGNORE`, separated by `:` as in bash, isn't recorded at
+//! all. Each regex in the `$HISTREDACT` array has what its first group
+//! matches, or the whole match if it has no groups, replaced by `***`, so
+//! `HISTREDACT=('TOKEN=(\S+)' '--password[= ](\S+)')` keeps the command but
+//! not the password.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::glob;
+use crate::regex::Regex;
+use crate::vars::Variables;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub command: String,
+    /// Seconds since the epoch, if known. Imported entries may not have one.
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    path: Option<PathBuf>,
+    index: Index,
+    redaction: Redaction,
+    /// How much of the file we know all of, from loading or writing it.
+    synced: u64,
+    /// What we appended after another shell did, so beyond `synced`.
+    unsynced: Vec<HistoryEntry>,
+}
+
+/// What has to be kept out of the history, from `$HISTIGNORE` and
+/// `$HISTREDACT`.
+#[derive(Debug, Default)]
+pub struct Redaction {
+    ignore: Vec<String>,
+    redact: Vec<Regex>,
+}
+
+/// What a secret is recorded as.
+const MASK: &str = "***";
+
+impl Redaction {
+    pub fn from_variables(variables: &Variables) -> Result<Redaction, String> {
+        let ignore = variables
+            .get("HISTIGNORE")
+            .map(|patterns| {
+                let patterns = patterns.split(':').filter(|pattern| !pattern.is_empty());
+                patterns.map(str::to_string).collect()
+            })
+            .unwrap_or_default();
+        let redact = variables
+            .get_array("HISTREDACT")
+            .unwrap_or_default()
+            .iter()
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("HISTREDACT: {pattern}: {e}")))
+            .collect::<Result<_, _>>()?;
+        Ok(Redaction { ignore, redact })
+    }
+
+    /// `command` as it may be recorded, or `None` if it mustn't be.
+    fn apply(&self, command: &str) -> Option<String> {
+        if self
+            .ignore
+            .iter()
+            .any(|pattern| glob::matches(pattern, command))
+        {
+            return None;
+        }
+        let mut command = command.to_string();
+        for regex in &self.redact {
+            command = mask(regex, &command);
+        }
+        Some(command)
+    }
+}
+
+/// Mask every secret `regex` finds in `text`.
+fn mask(regex: &Regex, text: &str) -> String {
+    let mut masked = String::new();
+    let mut rest = text;
+    while let Some(captures) = regex.captures(rest) {
+        let Some(whole) = captures[0].clone().filter(|whole| !whole.is_empty()) else {
+            break;
+        };
+        let secret = captures.get(1).cloned().flatten().unwrap_or(whole.clone());
+        masked.push_str(&rest[..secret.start]);
+        masked.push_str(MASK);
+        masked.push_str(&rest[secret.end..whole.end]);
+        rest = &rest[whole.end..];
+    }
+    masked.push_str(rest);
+    masked
+}
+
+/// The entries containing each three-byte sequence, in ascending order.
+///
+/// An entry listed under a sequence it no longer contains is harmless, since
+/// every candidate is checked against the search text anyway.
+#[derive(Default)]
+struct Index {
+    postings: HashMap<[u8; 3], Vec<u32>>,
+}
+
+impl Index {
+    fn add(&mut self, id: usize, command: &str) {
+        let id = id as u32;
+        for gram in command.as_bytes().windows(3) {
+            let gram = [gram[0], gram[1], gram[2]];
+            let ids = self.postings.entry(gram).or_default();
+            if ids.last() != Some(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    fn rebuild(&mut self, entries: &[HistoryEntry]) {
+        self.postings.clear();
+        for (id, entry) in entries.iter().enumerate() {
+            self.add(id, &entry.command);
+        }
+    }
+
+    /// The fewest entries that could contain `text`, or `None` if it's too
+    /// short to narrow them down.
+    fn candidates(&self, text: &str) -> Option<&[u32]> {
+        text.as_bytes()
+            .windows(3)
+            .map(|gram| self.postings.get(gram).map_or(&[][..], Vec::as_slice))
+            .min_by_key(|ids| ids.len())
+    }
+}
+
+/// Other shells' history files we know how to read.
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Bash,
+    Zsh,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn home_file(name: &str) -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(name))
+}
+
+impl History {
+    /// Load history from `$HISTFILE`, or `~/.sigsh_history` by default.
+    pub fn load() -> History {
+        let path = env::var_os("HISTFILE")
+            .map(PathBuf::from)
+            .or_else(|| home_file(".sigsh_history"));
+        History::open(path)
+    }
+
+    /// Load history from `path`, recording commands there as they're added.
+    pub(crate) fn open(path: Option<PathBuf>) -> History {
+        let contents = path
+            .as_deref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+
+        let mut history = History {
+            entries: parse_file(&contents),
+            path,
+            synced: contents.len() as u64,
+            ..History::default()
+        };
+        history.index.rebuild(&history.entries);
+        history
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// The positions of the entries containing `text`, oldest first.
+    pub fn search<'a>(&'a self, text: &'a str) -> impl DoubleEndedIterator<Item = usize> + 'a {
+        let candidates: Box<dyn DoubleEndedIterator<Item = usize>> =
+            match self.index.candidates(text) {
+                Some(ids) => Box::new(ids.iter().map(|&id| id as usize)),
+                None => Box::new(0..self.entries.len()),
+            };
+        candidates.filter(move |&id| self.entries[id].command.contains(text))
+    }
+
+    /// The positions of the entries starting with `prefix`, oldest first.
+    pub fn starting_with<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl DoubleEndedIterator<Item = usize> + 'a {
+        self.search(prefix)
+            .filter(move |&id| self.entries[id].command.starts_with(prefix))
+    }
+
+    /// Set what's kept out of the commands recorded from now on.
+    pub fn set_redaction(&mut self, redaction: Redaction) {
+        self.redaction = redaction;
+    }
+
+    /// Record a command that was just entered, unless it's to be ignored.
+    pub fn add(&mut self, command: &str) -> io::Result<()> {
+        let Some(command) = self.redaction.apply(command) else {
+            return Ok(());
+        };
+        self.append(vec![HistoryEntry {
+            command,
+            timestamp: Some(now()),
+        }])
+    }
+
+    fn append(&mut self, entries: Vec<HistoryEntry>) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            let before = file.metadata()?.len();
+            let lines: String = entries.iter().map(|e| format_line(e) + "\n").collect();
+            file.write_all(lines.as_bytes())?;
+            if before == self.synced {
+                self.synced += lines.len() as u64;
+            } else {
+                self.unsynced.extend(entries.iter().cloned());
+            }
+        }
+        for entry in entries {
+            self.index.add(self.entries.len(), &entry.command);
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+
+    /// Remove the entry at `index`, rewriting the history file without it.
+    pub fn delete(&mut self, index: usize) -> io::Result<HistoryEntry> {
+        let entry = self.entries.remove(index);
+        self.index.rebuild(&self.entries);
+        self.rewrite()?;
+        Ok(entry)
+    }
+
+    /// Forget every entry, emptying the history file.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.index.rebuild(&self.entries);
+        self.rewrite()
+    }
+
+    /// Replace the newest entry's command, as `fc -s` does with itself, or
+    /// drop the entry if the new command is to be ignored.
+    pub fn replace_last(&mut self, command: &str) -> io::Result<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        let Some(command) = self.redaction.apply(command) else {
+            return self.delete(self.entries.len() - 1).map(drop);
+        };
+        let last = self.entries.len() - 1;
+        self.index.add(last, &command);
+        self.entries[last].command = command;
+        self.rewrite()
+    }
+
+    /// Merge in what other shells have added to the file since we last
+    /// wrote it, at exit, so it ends up in order.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() != self.synced => self.rewrite(),
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the whole history out again, after something other than an
+    /// append, with what other shells added merged in. Write then rename,
+    /// so a crash can't leave half a file.
+    fn rewrite(&mut self) -> io::Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let others = self.others(&path)?;
+        if !others.is_empty() {
+            self.entries = merge(std::mem::take(&mut self.entries), others);
+            self.index.rebuild(&self.entries);
+        }
+
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|entry| format_line(entry) + "\n")
+            .collect();
+        let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+        fs::write(&tmp, &contents)?;
+        fs::rename(&tmp, &path)?;
+        self.synced = contents.len() as u64;
+        self.unsynced.clear();
+        Ok(())
+    }
+
+    /// The entries other shells have put in the file at `path` that we
+    /// don't know about.
+    fn others(&self, path: &Path) -> io::Result<Vec<HistoryEntry>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let synced = self.synced as usize;
+        let rewritten = synced > bytes.len() || (synced > 0 && bytes[synced - 1] != b'\n');
+        let added = &bytes[synced.min(bytes.len())..];
+        if rewritten {
+            // Another shell wrote it all out again, so anything could be new
+            let contents = String::from_utf8_lossy(&bytes);
+            let known: HashSet<_> = self
+                .entries
+                .iter()
+                .map(|entry| (entry.timestamp, entry.command.as_str()))
+                .collect();
+            let mut others = parse_file(&contents);
+            others.retain(|entry| !known.contains(&(entry.timestamp, entry.command.as_str())));
+            return Ok(others);
+        }
+
+        // Past what we know of, everything's theirs apart from our appends
+        let mut ours = self.unsynced.clone();
+        let mut others = parse_file(&String::from_utf8_lossy(added));
+        others.retain(|entry| match ours.iter().position(|ours| ours == entry) {
+            Some(i) => {
+                ours.remove(i);
+                false
+            }
+            None => true,
+        });
+        Ok(others)
+    }
+
+    /// Import another shell's history file, returning how many entries were
+    /// added. Imported entries go after what we already have, oldest first.
+    pub fn import(&mut self, format: Format, path: Option<&Path>) -> io::Result<usize> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => format
+                .default_path()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?,
+        };
+
+        let bytes = fs::read(&path)?;
+
+        let imported = match format {
+            Format::Bash => parse_bash(&String::from_utf8_lossy(&bytes)),
+            Format::Zsh => parse_zsh(&String::from_utf8_lossy(&unmetafy(&bytes))),
+        };
+        let count = imported.len();
+        self.append(imported)?;
+        Ok(count)
+    }
+}
+
+fn parse_file(contents: &str) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+    let mut command = String::new();
+    for line in contents.lines() {
+        let backslashes = line.len() - line.trim_end_matches('\\').len();
+        command.push_str(&line[..line.len() - backslashes.div_ceil(2)]);
+        if backslashes % 2 == 1 {
+            command.push('\n');
+        } else {
+            entries.push(parse_line(&command));
+            command.clear();
+        }
+    }
+    // A file cut off in the middle of an entry
+    if !command.is_empty() {
+        command.pop();
+        entries.push(parse_line(&command));
+    }
+    entries
+}
+
+/// Merge two histories by timestamp, ours first when they're the same.
+/// Entries without one stay where they are in their own history.
+fn merge(ours: Vec<HistoryEntry>, others: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    let mut merged = Vec::with_capacity(ours.len() + others.len());
+    let mut ours = ours.into_iter().peekable();
+    let mut others = others.into_iter().peekable();
+    loop {
+        let theirs_first = match (ours.peek(), others.peek()) {
+            (Some(a), Some(b)) => matches!((a.timestamp, b.timestamp), (Some(a), Some(b)) if b < a),
+            (Some(_), None) => false,
+            (None, Some(_)) => true,
+            (None, None) => break,
+        };
+        let next = if theirs_first {
+            others.next()
+        } else {
+            ours.next()
+        };
+        merged.extend(next);
+    }
+    merged
+}
+
+fn parse_line(line: &str) -> HistoryEntry {
+    match line.split_once(' ') {
+        Some((timestamp, command)) if timestamp.parse::<u64>().is_ok() => HistoryEntry {
+            command: command.to_string(),
+            // Entries without a timestamp are written with 0
+            timestamp: timestamp.parse().ok().filter(|&ts| ts != 0),
+        },
+        _ => HistoryEntry {
+            command: line.to_string(),
+            timestamp: None,
+        },
+    }
+}
+
+fn format_line(entry: &HistoryEntry) -> String {
+    let mut line = entry.timestamp.unwrap_or(0).to_string();
+    line.push(' ');
+    for (i, part) in entry.command.split('\n').enumerate() {
+        if i > 0 {
+            line.push_str("\\\n");
+        }
+        let backslashes = part.len() - part.trim_end_matches('\\').len();
+        line.push_str(part);
+        line.push_str(&"\\".repeat(backslashes));
+    }
+    line
+}
+
+/// Rejoin lines split by a trailing backslash, turning them back into
+/// embedded newlines, as zsh writes them.
+fn join_continuations(contents: &str) -> Vec<String> {
+    let mut joined = Vec::new();
+    let mut lines = contents.lines();
+
+    while let Some(line) = lines.next() {
+        let mut command = line.to_string();
+        while command.ends_with('\\') {
+            command.pop();
+            command.push('\n');
+            match lines.next() {
+                Some(next) => command.push_str(next),
+                None => break,
+            }
+        }
+        joined.push(command);
+    }
+    joined
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "bash" => Some(Format::Bash),
+            "zsh" => Some(Format::Zsh),
+            _ => None,
+        }
+    }
+
+    fn default_path(self) -> Option<PathBuf> {
+        match self {
+            Format::Bash => home_file(".bash_history"),
+            Format::Zsh => home_file(".zsh_history"),
+        }
+    }
+}
+
+/// Bash writes a `#<timestamp>` line before each command when
+/// `HISTTIMEFORMAT` is set, and nothing but the commands otherwise.
+fn parse_bash(contents: &str) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+    let mut timestamp = None;
+
+    for line in contents.lines() {
+        if let Some(ts) = line.strip_prefix('#').and_then(|ts| ts.parse().ok()) {
+            timestamp = Some(ts);
+        } else if !line.trim().is_empty() {
+            entries.push(HistoryEntry {
+                command: line.to_string(),
+                timestamp: timestamp.take(),
+            });
+        }
+    }
+    entries
+}
+
+/// Zsh's extended format is `: <start>:<elapsed>;<command>`, and without
+/// `EXTENDED_HISTORY` it's just the command. Either way, newlines inside a
+/// command are written as a backslash at the end of the line.
+fn parse_zsh(contents: &str) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+
+    for command in join_continuations(contents) {
+        let (timestamp, command) = match command
+            .strip_prefix(": ")
+            .and_then(|rest| rest.split_once(';'))
+        {
+            Some((meta, command)) => (
+                meta.split(':').next().and_then(|ts| ts.trim().parse().ok()),
+                command.to_string(),
+            ),
+            None => (None, command),
+        };
+
+        if !command.trim().is_empty() {
+            entries.push(HistoryEntry { command, timestamp });
+        }
+    }
+    entries
+}
+
+/// Zsh "metafies" bytes it treats specially by writing 0x83 followed by the
+/// byte xor 0x20. Undo that before decoding as UTF-8.
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    const META: u8 = 0x83;
+
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&b) = iter.next() {
+        if b == META {
+            if let Some(&next) = iter.next() {
+                out.push(next ^ 0x20);
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
@@ -0,0 +1,126 @@
+//! Rules over each command's final argv, after expansion, that hand a
+//! command to a handler instead or refuse to run it at all, such as sending
+//! `rm` to a function that moves files to the trash. Unlike an alias or a
+//! function named after the command, a rule catches `\rm`, `/bin/rm` and
+//! `$cmd` alike. They're set up with the `intercept` builtin.
+
+use std::path::Path;
+
+use crate::expand::quote;
+use crate::glob;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Run this instead, with the whole original argv after it
+    Run(Vec<String>),
+    /// Refuse to run the command
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// The name of the command it applies to, from whichever directory
+    pub name: String,
+    /// A glob which one of the arguments must match for it to apply
+    pub pattern: Option<String>,
+    pub action: Action,
+}
+
+impl Rule {
+    fn matches(&self, args: &[String]) -> bool {
+        let Some(command) = args.first() else {
+            return false;
+        };
+        let base = Path::new(command)
+            .file_name()
+            .map(|name| name.to_string_lossy());
+        (*command == self.name || base.is_some_and(|base| base == self.name))
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| args[1..].iter().any(|arg| glob::matches(pattern, arg)))
+    }
+
+    /// The `intercept` command which sets the rule up.
+    pub fn to_command(&self) -> String {
+        let mut words = vec!["intercept".to_string()];
+        if let Some(pattern) = &self.pattern {
+            words.push("-w".to_string());
+            words.push(quote(pattern));
+        }
+        match &self.action {
+            Action::Deny => {
+                words.push("-x".to_string());
+                words.push(shell_word(&self.name));
+            }
+            Action::Run(handler) => {
+                words.push(shell_word(&self.name));
+                words.extend(handler.iter().map(|word| shell_word(word)));
+            }
+        }
+        words.join(" ")
+    }
+}
+
+/// `word`, quoted if it needs to be.
+fn shell_word(word: &str) -> String {
+    let plain = |c: char| c.is_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        quote(word)
+    }
+}
+
+/// The rules, tried in the order they were added, except that any rule
+/// refusing to run a command wins over those with handlers for it.
+#[derive(Debug, Clone, Default)]
+pub struct Intercepts(Vec<Rule>);
+
+impl Intercepts {
+    /// Add a rule, replacing any for the same command and pattern.
+    pub fn add(&mut self, rule: Rule) {
+        match self
+            .0
+            .iter_mut()
+            .find(|old| old.name == rule.name && old.pattern == rule.pattern)
+        {
+            Some(old) => *old = rule,
+            None => self.0.push(rule),
+        }
+    }
+
+    /// Remove every rule for `name`, returning whether there were any.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.0.len();
+        self.0.retain(|rule| rule.name != name);
+        self.0.len() != before
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        self.0.iter()
+    }
+
+    /// The first rule that applies to `args`. A rule doesn't apply inside
+    /// its own handler, listed in `running`, so a handler function can run
+    /// the command it stands in for.
+    pub fn find<'a>(
+        &self,
+        args: &[String],
+        running: impl Iterator<Item = &'a str>,
+    ) -> Option<&Rule> {
+        let running: Vec<&str> = running.collect();
+        let mut matching = self.0.iter().filter(|rule| {
+            let inside = match &rule.action {
+                Action::Run(handler) => running.contains(&handler[0].as_str()),
+                Action::Deny => false,
+            };
+            !inside && rule.matches(args)
+        });
+        let first = matching.next()?;
+        let deny = std::iter::once(first)
+            .chain(matching)
+            .find(|rule| rule.action == Action::Deny);
+        Some(deny.unwrap_or(first))
+    }
+}
@@ -0,0 +1,189 @@
+//! The job table: every process group the shell has stopped or put in the
+//! background, and hasn't yet reported as finished.
+
+use std::io;
+
+use crate::platform::{self, Process, ProcessInfo, WaitStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+impl From<WaitStatus> for JobState {
+    fn from(status: WaitStatus) -> JobState {
+        match status {
+            WaitStatus::Stopped(_) => JobState::Stopped,
+            WaitStatus::Continued => JobState::Running,
+            status => JobState::Done(status.code()),
+        }
+    }
+}
+
+pub struct Job {
+    pub id: usize,
+    pub pgid: u32,
+    pub command: String,
+    pub state: JobState,
+    /// Left running when the shell exits with `huponexit`, after `disown -h`
+    pub nohup: bool,
+    /// The name a pipeline was started under with `spawn`
+    pub name: Option<String>,
+    /// What `wait-for` waits for, for a spawned pipeline
+    process: Option<Process>,
+}
+
+#[derive(Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+}
+
+impl JobTable {
+    pub fn add(&mut self, pgid: u32, command: String, state: JobState) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pgid,
+            command,
+            state,
+            nohup: false,
+            name: None,
+            process: None,
+        });
+        id
+    }
+
+    /// Add a pipeline started in the background with `spawn`.
+    pub(crate) fn add_spawned(&mut self, name: &str, process: Process) -> usize {
+        let id = self.add(process.id(), format!("spawn {name}"), JobState::Running);
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.name = Some(name.to_string());
+            job.process = Some(process);
+        }
+        id
+    }
+
+    /// The job `spawn` started as `name`, if it hasn't finished.
+    pub fn named(&self, name: &str) -> Option<&Job> {
+        self.jobs
+            .iter()
+            .find(|job| job.name.as_deref() == Some(name))
+    }
+
+    /// The status of a spawned job if it has finished, taking it out of the
+    /// table, without waiting.
+    pub fn try_wait(&mut self, id: usize) -> io::Result<Option<i32>> {
+        let process = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .and_then(|job| job.process.as_mut());
+        let Some(process) = process else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "not a spawned pipeline",
+            ));
+        };
+        let Some(status) = process.try_wait()? else {
+            return Ok(None);
+        };
+        self.remove(id);
+        Ok(Some(status.code()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// The job `%+` refers to: the most recently added one.
+    pub fn current(&self) -> Option<&Job> {
+        self.jobs.last()
+    }
+
+    /// Look up a job from a spec like `%2`, `2`, `%+` or `%-`, or the current
+    /// job if there's no spec at all.
+    pub fn find(&self, spec: Option<&str>) -> Option<&Job> {
+        let Some(spec) = spec else {
+            return self.current();
+        };
+
+        match spec.trim_start_matches('%') {
+            "" | "+" | "%" => self.current(),
+            "-" => self.jobs.iter().rev().nth(1),
+            n => self.get(n.parse().ok()?),
+        }
+    }
+
+    /// Keep the job from being hung up when the shell exits.
+    pub fn set_nohup(&mut self, id: usize) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.nohup = true;
+        }
+    }
+
+    /// Hang up every job that hasn't been marked to keep running, as the
+    /// shell exits.
+    pub fn hang_up(&self) {
+        for job in self.jobs.iter().filter(|job| !job.nohup) {
+            if let Err(e) = platform::hang_up(job.pgid) {
+                eprintln!("{}: {}", job.command, e);
+            }
+        }
+    }
+
+    pub fn set_state(&mut self, id: usize, state: JobState) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.state = state;
+        }
+    }
+
+    /// Update job states from the children that changed state, and drop (and
+    /// report) the jobs which have finished, returning them.
+    pub(crate) fn reap(&mut self, changed: &[(u32, JobState)]) -> Vec<Job> {
+        for &(pid, state) in changed {
+            if let Some(job) = self.jobs.iter_mut().find(|job| job.pgid == pid) {
+                job.state = state;
+            }
+        }
+
+        let current = self.current().map(|job| job.id);
+        let (finished, running): (Vec<Job>, Vec<Job>) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|job| matches!(job.state, JobState::Done(_)));
+        self.jobs = running;
+        for job in &finished {
+            eprintln!("{}", job.describe(current == Some(job.id)));
+        }
+        finished
+    }
+}
+
+impl Job {
+    /// The processes making up the job, and any they've started.
+    pub(crate) fn processes(&self) -> Vec<ProcessInfo> {
+        platform::job_processes(self.pgid)
+    }
+
+    /// The line `jobs` prints for this job, e.g. `[1]+  Stopped  vim`.
+    pub fn describe(&self, is_current: bool) -> String {
+        let marker = if is_current { '+' } else { ' ' };
+        let state = match self.state {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped => "Stopped".to_string(),
+            JobState::Done(0) => "Done".to_string(),
+            JobState::Done(code) => format!("Exit {code}"),
+        };
+        format!("[{}]{}  {:<8}  {}", self.id, marker, state, self.command)
+    }
+}
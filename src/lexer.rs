@@ -1,33 +1,126 @@
 use std::iter::Peekable;
 use std::str::Chars;
 
+use crate::glob;
 use crate::parser::ParseError;
 
-#[derive(Debug)]
-struct UnmatchedDelimiterError;
+/// One piece of a word which needs to be told apart from its neighbours when
+/// the word is expanded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Text taken as-is
+    Literal(String),
+    /// Unquoted text containing glob characters, with any quoted ones escaped
+    Pattern(String),
+    Variable(String),
+    SubShell(String),
+    /// The expression inside `$(( ))`
+    Arith(String),
+    /// Expansions from inside double quotes
+    Quoted(Vec<WordPart>),
+}
+
+/// Accumulates the literal text of a word between expansions.
+#[derive(Default)]
+struct LiteralRun {
+    text: String,
+    /// The same text with quoted glob characters escaped, in case it turns out
+    /// to be a pattern
+    pattern: String,
+    is_glob: bool,
+    /// Whether any of the text was quoted, so `""` still makes a word
+    quoted: bool,
+    /// Whether this is the regex after `=~`, where quoted punctuation is
+    /// escaped too and the word kept as a pattern, so that quoted parts can
+    /// be matched literally
+    regex: bool,
+}
+
+impl LiteralRun {
+    fn push(&mut self, c: char) {
+        self.is_glob |= glob::is_meta(c);
+        self.text.push(c);
+        self.pattern.push(c);
+    }
+
+    fn push_quoted(&mut self, c: char) {
+        self.text.push(c);
+        // `(` too, so it can't start glob qualifiers
+        if glob::is_meta(c) || c == '\\' || c == '(' || (self.regex && !c.is_alphanumeric()) {
+            self.pattern.push('\\');
+        }
+        self.pattern.push(c);
+    }
+
+    fn flush_into(&mut self, parts: &mut Vec<WordPart>) {
+        let regex = self.regex;
+        let run = std::mem::replace(
+            self,
+            LiteralRun {
+                regex,
+                ..Default::default()
+            },
+        );
+        if run.is_glob || (regex && !run.pattern.is_empty()) {
+            parts.push(WordPart::Pattern(run.pattern));
+        } else if !run.text.is_empty() || run.quoted {
+            parts.push(WordPart::Literal(run.text));
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Token {
     Word(String),
+    Glob(String),
     SubShell(String),
     Variable(String),
+    /// A word made of several parts, like `"$HOME"/bin` or `a$b`
+    Parts(Vec<WordPart>),
+    /// A bare `( ... )`
+    Parens(String),
     Pipe,
     PipeBoth,
     RedirOut,
     RedirErr,
     RedirBoth,
+    /// `>>`, `2>>` and `&>>`
+    AppendOut,
+    AppendErr,
+    AppendBoth,
+    /// `>|` and `2>|`, which overwrite a file even with `noclobber` set
+    ClobberOut,
+    ClobberErr,
+    RedirIn,
+    /// `N<`, `N>` and `N>>` on a descriptor from 3 to 9
+    FdIn(u32),
+    FdOut(u32),
+    FdAppend(u32),
+    /// `N<&-` or `N>&-`, closing a descriptor from 3 to 9
+    FdClose(u32),
     AndThen,
     AndThenIf,
 }
 
 pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
+    /// Whether the next token starts a command, where `[[` begins a
+    /// conditional
+    command_start: bool,
+    /// Whether we're inside `[[ ... ]]`, where `(`, `)`, `|`, `<` and `>`
+    /// are just part of words, as they are in regexes and comparisons
+    conditional: bool,
+    /// Whether the next word is the regex after `=~` in a conditional
+    regex: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Lexer {
             chars: input.chars().peekable(),
+            command_start: true,
+            conditional: false,
+            regex: false,
         }
     }
 
@@ -36,58 +129,225 @@ impl<'a> Lexer<'a> {
     }
 
     fn lex_word(&mut self) -> Result<Token, ParseError> {
-        let mut word = String::new();
-        let mut in_single_quotes = false;
-        let mut in_double_quotes = false;
+        let mut parts = Vec::new();
+        let mut run = LiteralRun {
+            regex: self.regex,
+            ..Default::default()
+        };
+        let mut consumed = false;
 
         while let Some(&c) = self.chars.peek() {
-            if in_single_quotes {
-                self.chars.next();
-                if c == '\'' {
-                    in_single_quotes = false;
-                } else {
-                    word.push(c);
+            // A `(...)` straight after a glob is its qualifiers, not a subshell
+            if c == '(' && run.is_glob && !self.conditional {
+                if let Some(qualifiers) = self.lex_qualifiers() {
+                    qualifiers.chars().for_each(|c| run.push(c));
+                    continue;
                 }
-            } else if in_double_quotes {
-                self.chars.next();
-                if c == '"' {
-                    in_double_quotes = false;
-                } else {
-                    word.push(c);
+            }
+
+            // Redirections like "2>" are only recognised at the start of a
+            // token, so a digit never ends a word
+            let special = if self.conditional {
+                matches!(c, ';' | '&')
+            } else {
+                matches!(c, '|' | ';' | '<' | '>' | '&' | '(' | ')')
+            };
+            if c.is_whitespace() || special {
+                break;
+            }
+
+            consumed = true;
+            self.chars.next();
+            match c {
+                '\'' => {
+                    run.quoted = true;
+                    loop {
+                        match self.chars.next() {
+                            Some('\'') => break,
+                            Some(c) => run.push_quoted(c),
+                            None => return Err(ParseError::UnterminatedStringLiteral),
+                        }
+                    }
                 }
-            } else if c.is_whitespace() || c == '|' {
+                '"' => self.lex_double_quoted(&mut run, &mut parts)?,
+                '\\' => {
+                    if let Some(c) = self.chars.next() {
+                        run.push_quoted(c);
+                    }
+                }
+                '$' => match self.lex_dollar()? {
+                    Some(part) => {
+                        run.flush_into(&mut parts);
+                        parts.push(part);
+                    }
+                    None => run.push(c),
+                },
+                c => run.push(c),
+            }
+        }
+        run.flush_into(&mut parts);
+
+        if !consumed {
+            return Err(ParseError::NotFound);
+        }
+
+        if parts.len() != 1 {
+            return Ok(Token::Parts(parts));
+        }
+        let token = match parts.remove(0) {
+            WordPart::Literal(word) => Token::Word(word),
+            WordPart::Pattern(pattern) => Token::Glob(pattern),
+            WordPart::Variable(name) => Token::Variable(name),
+            WordPart::SubShell(inner) => Token::SubShell(inner),
+            part => Token::Parts(vec![part]),
+        };
+        Ok(token)
+    }
+
+    /// Lex glob qualifiers like `(.mtime-7)`, if that's what's next: a `(`
+    /// and `)` with nothing between them a shell would give a meaning to.
+    fn lex_qualifiers(&mut self) -> Option<String> {
+        let mut ahead = self.chars.clone();
+        let mut qualifiers = String::from(ahead.next()?);
+        loop {
+            let c = ahead.next()?;
+            if c.is_whitespace() || "(;&|<>'\"$`\\".contains(c) {
+                return None;
+            }
+            qualifiers.push(c);
+            if c == ')' {
                 break;
-            } else if c == '\'' {
-                self.chars.next();
-                in_single_quotes = true;
-            } else if c == '"' {
+            }
+        }
+        self.chars = ahead;
+        Some(qualifiers)
+    }
+
+    /// Lex the inside of a double-quoted string, after the opening quote.
+    /// Plain text joins the surrounding literal run; expansions become
+    /// [`WordPart::Quoted`] so they escape word splitting and globbing.
+    fn lex_double_quoted(
+        &mut self,
+        run: &mut LiteralRun,
+        parts: &mut Vec<WordPart>,
+    ) -> Result<(), ParseError> {
+        run.quoted = true;
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(()),
+                Some('\\') => match self.chars.next() {
+                    Some(c) if matches!(c, '$' | '`' | '"' | '\\') => run.push_quoted(c),
+                    Some(c) => {
+                        run.push_quoted('\\');
+                        run.push_quoted(c);
+                    }
+                    None => return Err(ParseError::UnterminatedStringLiteral),
+                },
+                Some('$') => match self.lex_dollar()? {
+                    Some(part) => {
+                        run.flush_into(parts);
+                        parts.push(WordPart::Quoted(vec![part]));
+                    }
+                    None => run.push_quoted('$'),
+                },
+                Some(c) => run.push_quoted(c),
+                None => return Err(ParseError::UnterminatedStringLiteral),
+            }
+        }
+    }
+
+    /*
+     * name -  A  word  consisting  only  of alphanumeric characters and underscores,
+     *         and beginning with an alphabetic character or an  underscore.  Also
+     *         referred to as an identifier
+     */
+
+    /// Lex whatever follows a `$`. Returns `None` if it isn't an expansion, in
+    /// which case the `$` is just a character.
+    fn lex_dollar(&mut self) -> Result<Option<WordPart>, ParseError> {
+        let Some(&c) = self.chars.peek() else {
+            return Ok(None);
+        };
+
+        let part = match c {
+            '(' if self.chars.clone().nth(1) == Some('(') => WordPart::Arith(self.lex_arith()?),
+            '(' => WordPart::SubShell(self.subshell_inner(true)?),
+            '{' => WordPart::Variable(self.lex_braced_parameter()?),
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = self.chars.next_if(|&c| c.is_alphanumeric() || c == '_') {
+                    name.push(c);
+                }
+                WordPart::Variable(name)
+            }
+            c if c.is_ascii_digit() => {
                 self.chars.next();
-                in_double_quotes = true;
-            } else if c == ';' || c == '>' || c == '&' {
-                break;
-            } else if c.is_ascii_digit()
-                && self
+                // Positional parameters are a single digit, so `$1abc` is
+                // more likely a typo than `${1}abc`
+                if self
                     .chars
-                    .clone()
-                    .next_if(|&next_c| next_c == '>')
-                    .is_some()
-            {
-                // Only break on digits if they're followed by '>', like "2>"
-                break;
-            } else {
+                    .peek()
+                    .is_some_and(|&c| c.is_alphanumeric() || c == '_')
+                {
+                    return Err(ParseError::InvalidVariable);
+                }
+                WordPart::Variable(c.to_string())
+            }
+            '?' | '#' | '@' | '*' | '$' | '!' | '-' => {
                 self.chars.next();
-                word.push(c);
+                WordPart::Variable(c.to_string())
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(part))
+    }
+
+    /// Lex a `${...}` expansion, returning what's between the braces, which
+    /// is checked and taken apart when it's expanded.
+    fn lex_braced_parameter(&mut self) -> Result<String, ParseError> {
+        self.chars.next();
+
+        let mut inner = String::new();
+        loop {
+            match self.chars.next() {
+                Some('}') => break,
+                Some(c) => inner.push(c),
+                None => return Err(ParseError::UnmatchedBrace),
             }
         }
 
-        if in_single_quotes || in_double_quotes {
-            return Err(ParseError::UnterminatedStringLiteral);
+        if inner.is_empty() {
+            return Err(ParseError::InvalidVariable);
         }
+        Ok(inner)
+    }
 
-        if !word.is_empty() {
-            Ok(Token::Word(word))
-        } else {
-            Err(ParseError::NotFound)
+    /// Lex a `$(( ))` expansion after the `$`, returning the expression
+    /// between the double parentheses.
+    fn lex_arith(&mut self) -> Result<String, ParseError> {
+        self.chars.next();
+        self.chars.next();
+
+        let mut expr = String::new();
+        let mut depth = 0;
+        loop {
+            match self.chars.next() {
+                Some(')') if depth == 0 => {
+                    if self.chars.next_if_eq(&')').is_some() {
+                        return Ok(expr);
+                    }
+                    return Err(ParseError::UnmatchedDelimiterError);
+                }
+                Some(c) => {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    expr.push(c);
+                }
+                None => return Err(ParseError::UnclosedParen),
+            }
         }
     }
 
@@ -111,9 +371,9 @@ impl<'a> Lexer<'a> {
                 }
             } else if c == ';' {
                 self.chars.next();
-                return Some(Token::AndThen);
+                Some(Token::AndThen)
             } else {
-                return None;
+                None
             }
         } else {
             None
@@ -121,9 +381,17 @@ impl<'a> Lexer<'a> {
     }
 
     fn lex_redirection(&mut self) -> Option<Token> {
+        if let Some(token) = self.lex_fd_redirection() {
+            return Some(token);
+        }
+
         let mut iter = self.chars.clone();
         let mut redir = String::new();
 
+        if self.chars.next_if_eq(&'<').is_some() {
+            return Some(Token::RedirIn);
+        }
+
         if let Some(&c) = iter.peek() {
             if c == '1' || c == '2' || c == '&' {
                 redir.push(c);
@@ -137,7 +405,7 @@ impl<'a> Lexer<'a> {
                 iter.next();
 
                 if let Some(&next_c) = iter.peek() {
-                    if next_c == '>' {
+                    if next_c == '>' || next_c == '|' {
                         redir.push(next_c);
                         iter.next();
                     }
@@ -149,27 +417,39 @@ impl<'a> Lexer<'a> {
             return None;
         }
 
-        match redir.as_str() {
-            ">" | "1>" | ">>" | "1>>" => {
-                for _ in 0..redir.len() {
-                    self.chars.next();
-                }
-                Some(Token::RedirOut)
-            }
-            "2>" | "2>>" => {
-                for _ in 0..redir.len() {
-                    self.chars.next();
-                }
-                Some(Token::RedirErr)
-            }
-            "&>" | "&>>" => {
-                for _ in 0..redir.len() {
-                    self.chars.next();
-                }
-                Some(Token::RedirBoth)
-            }
-            _ => None,
+        let token = match redir.as_str() {
+            ">" | "1>" => Token::RedirOut,
+            ">>" | "1>>" => Token::AppendOut,
+            "2>" => Token::RedirErr,
+            "2>>" => Token::AppendErr,
+            ">|" | "1>|" => Token::ClobberOut,
+            "2>|" => Token::ClobberErr,
+            "&>" => Token::RedirBoth,
+            "&>>" => Token::AppendBoth,
+            _ => return None,
+        };
+        for _ in 0..redir.len() {
+            self.chars.next();
         }
+        Some(token)
+    }
+
+    /// Redirections of the descriptors past the standard three, which only
+    /// go up to 9 as in other shells.
+    fn lex_fd_redirection(&mut self) -> Option<Token> {
+        let mut iter = self.chars.clone();
+        let fd = iter.next()?.to_digit(10).filter(|fd| *fd > 2)?;
+        let (token, len) = match (iter.next()?, iter.next(), iter.next()) {
+            ('<' | '>', Some('&'), Some('-')) => (Token::FdClose(fd), 4),
+            ('>', Some('>'), _) => (Token::FdAppend(fd), 3),
+            ('<', ..) => (Token::FdIn(fd), 2),
+            ('>', ..) => (Token::FdOut(fd), 2),
+            _ => return None,
+        };
+        for _ in 0..len {
+            self.chars.next();
+        }
+        Some(token)
     }
 
     fn lex_pipe(&mut self) -> Option<Token> {
@@ -191,126 +471,118 @@ impl<'a> Lexer<'a> {
         None
     }
 
-    // add a custom error type maybe
-    fn subshell_inner(&mut self) -> Result<String, ParseError> {
-        let mut inner_string = String::new();
-        let mut open_parens = 1;
-        let mut matched = false;
+    /// Lex a `( ... )` or the `(...)` of `$(...)`, returning what's between
+    /// the parentheses. Quoted parentheses and those in nested substitutions
+    /// don't count and comments are dropped. With `commands`, newlines
+    /// separate commands as `;` would; otherwise, as for `arr=(...)`, they're
+    /// left for the parser to sort out.
+    fn subshell_inner(&mut self, commands: bool) -> Result<String, ParseError> {
+        let mut inner = String::new();
+        let mut depth = 0;
 
         // We don't want that first '('
         self.chars.next();
 
-        while let Some(&c) = self.chars.peek() {
+        loop {
+            let Some(c) = self.chars.next() else {
+                return Err(ParseError::UnclosedParen);
+            };
             match c {
-                ')' => {
-                    if open_parens == 0 {
-                        break;
-                    } else if open_parens == 1 {
-                        self.chars.next();
-                        matched = true;
-                        break;
-                    } else {
-                        open_parens -= 1;
+                ')' if depth == 0 => return Ok(inner),
+                ')' => depth -= 1,
+                '(' => depth += 1,
+                '\\' => {
+                    inner.push(c);
+                    match self.chars.next() {
+                        Some(escaped) => inner.push(escaped),
+                        None => return Err(ParseError::UnclosedParen),
                     }
+                    continue;
                 }
-                '(' => {
-                    open_parens += 1;
+                '\'' => {
+                    inner.push(c);
+                    self.copy_until_quote(&mut inner, '\'')?;
+                    continue;
                 }
-                _ => {}
-            }
-            self.chars.next();
-            inner_string.push(c);
-        }
-        if matched {
-            Ok(inner_string)
-        } else {
-            Err(ParseError::UnmatchedDelimiterError)
-        }
-    }
-
-    fn lex_subshell(&mut self) -> Result<Token, ParseError> {
-        let mut iter = self.chars.clone();
-
-        if let Some(c) = iter.next() {
-            if c == '$' {
-                if let Some(&next_c) = iter.peek() {
-                    if next_c == '(' {
-                        self.chars.next();
-                        let inner_string = self.subshell_inner()?;
-                        return Ok(Token::SubShell(inner_string));
-                    }
+                '"' => {
+                    inner.push(c);
+                    self.copy_until_quote(&mut inner, '"')?;
+                    continue;
+                }
+                '#' if inner.is_empty() || inner.ends_with(char::is_whitespace) => {
+                    while self.chars.next_if(|&c| c != '\n').is_some() {}
+                    continue;
+                }
+                '\n' if commands => {
+                    let before = inner.trim_end();
+                    let joined = before.is_empty() || before.ends_with([';', '|', '&', '(', '{']);
+                    inner.truncate(before.len());
+                    inner.push_str(if joined { " " } else { "; " });
+                    continue;
                 }
-            } else if c == '(' {
-                let inner_string = self.subshell_inner()?;
-                return Ok(Token::SubShell(inner_string));
+                _ => {}
             }
+            inner.push(c);
         }
-        Err(ParseError::NotFound)
     }
 
-    /*
-     * name -  A  word  consisting  only  of alphanumeric characters and underscores,
-     *         and beginning with an alphabetic character or an  underscore.  Also
-     *         referred to as an identifier
-     */
-
-    fn lex_variable(&mut self) -> Result<Token, ParseError> {
-        if let Some(&c) = self.chars.peek() {
-            if c == '$' {
-                self.chars.next();
-
-                if !self
-                    .chars
-                    .peek()
-                    .is_some_and(|&ch| ch.is_alphabetic() || ch == '_')
-                {
-                    return Err(ParseError::InvalidVariable);
-                }
-
-                let variable_name: String = self
-                    .chars
-                    .clone()
-                    .take_while(|&ch| ch.is_alphanumeric() || ch == '_')
-                    .collect();
-
-                for _ in 0..variable_name.len() {
-                    self.chars.next();
+    /// Copy everything up to and including the closing `quote` into `inner`.
+    /// Inside double quotes, backslashes escape and `$(...)` nests.
+    fn copy_until_quote(&mut self, inner: &mut String, quote: char) -> Result<(), ParseError> {
+        loop {
+            let Some(c) = self.chars.next() else {
+                return Err(ParseError::UnclosedParen);
+            };
+            inner.push(c);
+            match c {
+                c if c == quote => return Ok(()),
+                '\\' if quote == '"' => match self.chars.next() {
+                    Some(escaped) => inner.push(escaped),
+                    None => return Err(ParseError::UnclosedParen),
+                },
+                '$' if quote == '"' && self.chars.peek() == Some(&'(') => {
+                    let nested = self.subshell_inner(true)?;
+                    inner.push('(');
+                    inner.push_str(&nested);
+                    inner.push(')');
                 }
-                return Ok(Token::Variable(variable_name));
+                _ => {}
             }
         }
-        Err(ParseError::NotFound)
     }
-}
-
-impl Iterator for Lexer<'_> {
-    type Item = Result<Token, ParseError>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    fn next_token(&mut self) -> Option<Result<Token, ParseError>> {
         self.skip_whitespace();
 
-        if let Some(token) = self.lex_redirection() {
-            return Some(Ok(token));
-        }
+        if !self.conditional {
+            if let Some(token) = self.lex_redirection() {
+                return Some(Ok(token));
+            }
 
-        if let Some(token) = self.lex_pipe() {
-            return Some(Ok(token));
+            if let Some(token) = self.lex_pipe() {
+                return Some(Ok(token));
+            }
         }
 
         if let Some(token) = self.lex_and_then() {
             return Some(Ok(token));
         }
-
-        match self.lex_subshell() {
-            Ok(token) => return Some(Ok(token)),
-            Err(ParseError::NotFound) => (),
-            Err(e) => return Some(Err(e)),
+        // Anything else would stop the lexing here, dropping the rest
+        if self.chars.next_if_eq(&'&').is_some() {
+            return Some(Err(ParseError::Background));
         }
 
-        match self.lex_variable() {
-            Ok(token) => return Some(Ok(token)),
-            Err(ParseError::NotFound) => (),
-            Err(e) => return Some(Err(e)),
+        if !self.conditional {
+            match self.lex_parens() {
+                Ok(token) => return Some(Ok(token)),
+                Err(ParseError::NotFound) => (),
+                Err(e) => return Some(Err(e)),
+            }
+
+            // A stray `)` has nothing to close
+            if self.chars.next_if_eq(&')').is_some() {
+                return Some(Err(ParseError::UnmatchedDelimiterError));
+            }
         }
 
         match self.lex_word() {
@@ -319,4 +591,44 @@ impl Iterator for Lexer<'_> {
             Err(e) => Some(Err(e)),
         }
     }
+
+    /// A bare `( ... )`, which runs its contents in a subshell.
+    fn lex_parens(&mut self) -> Result<Token, ParseError> {
+        if self.chars.peek() == Some(&'(') {
+            Ok(Token::Parens(self.subshell_inner(false)?))
+        } else {
+            Err(ParseError::NotFound)
+        }
+    }
+}
+
+/// The commands in a `( ... )` lexed without `commands`, with its newlines
+/// made into separators.
+pub fn separate_lines(inner: &str) -> String {
+    let wrapped = format!("({inner})");
+    Lexer::new(&wrapped)
+        .subshell_inner(true)
+        .unwrap_or_else(|_| inner.to_string())
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token();
+        if let Some(Ok(token)) = &token {
+            let separator = matches!(
+                token,
+                Token::Pipe | Token::PipeBoth | Token::AndThen | Token::AndThenIf
+            );
+            self.regex = self.conditional && matches!(token, Token::Word(w) if w == "=~");
+            if self.conditional {
+                self.conditional = !separator && !matches!(token, Token::Word(w) if w == "]]");
+            } else if self.command_start {
+                self.conditional = matches!(token, Token::Glob(w) if w == "[[");
+            }
+            self.command_start = separator;
+        }
+        token
+    }
 }
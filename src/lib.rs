@@ -0,0 +1,42 @@
+//! An interactive shell, usable as a library by programs that want to embed
+//! one: build a [`shell::ShellState`] and feed it lines with
+//! [`shell::ShellState::eval`].
+
+mod activity;
+mod arith;
+mod builtins;
+mod children;
+mod complete;
+pub mod debugger;
+pub mod direnv;
+mod editor;
+mod exec;
+mod expand;
+pub mod fds;
+mod glob;
+mod history;
+pub mod intercept;
+mod jobs;
+mod lexer;
+mod listing;
+mod nesting;
+mod notify;
+pub mod options;
+pub mod parser;
+mod platform;
+pub mod plugin;
+pub mod profiler;
+pub mod prompt;
+mod regex;
+pub mod repl;
+#[cfg(unix)]
+mod safe_wrappers;
+pub mod shell;
+mod stderr;
+pub mod universal;
+mod unparse;
+pub mod vars;
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod tests;
@@ -0,0 +1,201 @@
+//! Listing files in columns, as `ls` does, shared by the `list` builtin and
+//! the completion menu so the two look alike.
+//!
+//! Names are colored by file type using the `di`, `ln`, `ex`, `fi`, `pi`,
+//! `so`, `bd` and `cd` entries of `LS_COLORS`, falling back to the usual
+//! `ls` colors.
+
+use std::cmp::Ordering;
+use std::env;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::platform;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Directory,
+    Symlink,
+    Executable,
+    File,
+    Fifo,
+    Socket,
+    Device,
+    Missing,
+}
+
+impl Kind {
+    fn of(path: &Path, meta: Option<&Metadata>) -> Kind {
+        let Some(meta) = meta else {
+            return Kind::Missing;
+        };
+        let file_type = meta.file_type();
+        if file_type.is_symlink() {
+            Kind::Symlink
+        } else if file_type.is_dir() {
+            Kind::Directory
+        } else if file_type.is_file() {
+            if platform::is_executable(path) {
+                Kind::Executable
+            } else {
+                Kind::File
+            }
+        } else {
+            special_kind(&file_type)
+        }
+    }
+
+    /// The `LS_COLORS` key for this kind, and the color used without one.
+    fn color_key(self) -> (&'static str, Option<&'static str>) {
+        match self {
+            Kind::Directory => ("di", Some("01;34")),
+            Kind::Symlink => ("ln", Some("01;36")),
+            Kind::Executable => ("ex", Some("01;32")),
+            Kind::Fifo => ("pi", Some("33")),
+            Kind::Socket => ("so", Some("01;35")),
+            Kind::Device => ("bd", Some("01;33")),
+            Kind::File | Kind::Missing => ("fi", None),
+        }
+    }
+
+    /// The marker `-F` puts after a name.
+    fn suffix(self) -> &'static str {
+        match self {
+            Kind::Directory => "/",
+            Kind::Symlink => "@",
+            Kind::Executable => "*",
+            Kind::Fifo => "|",
+            Kind::Socket => "=",
+            _ => "",
+        }
+    }
+}
+
+#[cfg(unix)]
+fn special_kind(file_type: &fs::FileType) -> Kind {
+    use std::os::unix::fs::FileTypeExt;
+    if file_type.is_fifo() {
+        Kind::Fifo
+    } else if file_type.is_socket() {
+        Kind::Socket
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        Kind::Device
+    } else {
+        Kind::File
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_file_type: &fs::FileType) -> Kind {
+    Kind::File
+}
+
+/// The color for `kind` from `LS_COLORS`, or the default.
+fn color(kind: Kind) -> Option<String> {
+    let (key, default) = kind.color_key();
+    let from_env = env::var("LS_COLORS").ok().and_then(|colors| {
+        colors.split(':').find_map(|entry| {
+            let (name, value) = entry.split_once('=')?;
+            (name == key).then(|| value.to_string())
+        })
+    });
+    from_env.or(default.map(str::to_string))
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// What to show, which needn't be the file's whole path.
+    pub name: String,
+    pub kind: Kind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Entry {
+    /// Look up the file at `path`, to be shown as `name`. Symlinks are
+    /// described rather than followed.
+    pub fn new(path: &Path, name: String) -> Entry {
+        let meta = fs::symlink_metadata(path).ok();
+        Entry {
+            name,
+            kind: Kind::of(path, meta.as_ref()),
+            size: meta.as_ref().map_or(0, Metadata::len),
+            modified: meta.and_then(|meta| meta.modified().ok()),
+        }
+    }
+
+    /// How to show this entry, maybe colored and with a type marker.
+    pub fn cell(&self, colored: bool, classify: bool) -> Cell {
+        let suffix = if classify { self.kind.suffix() } else { "" };
+        let width = self.name.chars().count() + suffix.len();
+        let text = match colored.then(|| color(self.kind)).flatten() {
+            Some(color) => format!("\x1b[{}m{}\x1b[0m{}", color, self.name, suffix),
+            None => format!("{}{}", self.name, suffix),
+        };
+        Cell { text, width }
+    }
+}
+
+/// The entries of a directory, sorted by name. Hidden files are left out
+/// unless `hidden` is set.
+pub fn read_dir(dir: &Path, hidden: bool) -> io::Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            Entry::new(
+                &entry.path(),
+                entry.file_name().to_string_lossy().into_owned(),
+            )
+        })
+        .filter(|entry| hidden || !entry.name.starts_with('.'))
+        .collect();
+    entries.sort_by(|a, b| by_name(&a.name, &b.name));
+    Ok(entries)
+}
+
+/// Sort names alphabetically regardless of case, as most `ls`es do.
+pub fn by_name(a: &str, b: &str) -> Ordering {
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| a.cmp(b))
+}
+
+/// Some text to lay out, and how many columns it takes up on screen, which
+/// escape sequences don't count towards.
+pub struct Cell {
+    text: String,
+    width: usize,
+}
+
+impl Cell {
+    pub fn plain(text: &str) -> Cell {
+        Cell {
+            text: text.to_string(),
+            width: text.chars().count(),
+        }
+    }
+}
+
+/// Lay out `cells` in as many columns as fit in `width`, filling each column
+/// top to bottom.
+pub fn columns(cells: &[Cell], width: usize) -> String {
+    let column_width = cells.iter().map(|cell| cell.width).max().unwrap_or(0) + 2;
+    let columns = (width / column_width).max(1);
+    let rows = cells.len().div_ceil(columns);
+
+    let mut out = String::new();
+    for row in 0..rows {
+        let mut line = String::new();
+        let mut pending = 0;
+        for cell in (0..columns).filter_map(|column| cells.get(column * rows + row)) {
+            line.extend(std::iter::repeat_n(' ', pending));
+            line.push_str(&cell.text);
+            pending = column_width - cell.width;
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
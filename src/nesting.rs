@@ -0,0 +1,45 @@
+//! The one limit on how deeply input may nest, shared by everything that
+//! parses or evaluates it a level further down the stack for each level: the
+//! command parser, arithmetic, regexes and the `math` and `json` builtins.
+//! With no limit, input nested deeply enough would overflow the stack.
+
+use std::cell::Cell;
+use std::fmt;
+
+/// How many levels may be nested, one inside the next, on one thread.
+pub const MAX_NESTING: usize = 128;
+
+thread_local! {
+    /// How many levels are being parsed or evaluated, one inside the next.
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The error for input nested more deeply than [`MAX_NESTING`] allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooDeep;
+
+impl fmt::Display for TooDeep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "nested too deeply")
+    }
+}
+
+/// Counts one level of nesting for as long as it's alive.
+pub(crate) struct Nested(());
+
+impl Nested {
+    pub(crate) fn enter() -> Result<Nested, TooDeep> {
+        let depth = NESTING.get();
+        if depth >= MAX_NESTING {
+            return Err(TooDeep);
+        }
+        NESTING.set(depth + 1);
+        Ok(Nested(()))
+    }
+}
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        NESTING.set(NESTING.get() - 1);
+    }
+}
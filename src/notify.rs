@@ -0,0 +1,145 @@
+//! Timing the commands typed at the prompt, and with `set -o notify-long`,
+//! saying when one that took a while finishes, for whoever went to look
+//! at something else in the meantime.
+//!
+//! How long the last command took is in `$CMD_DURATION`, in milliseconds,
+//! for `precmd` and the prompt. A command is long if it took at least
+//! `$NOTIFY_SECONDS`, 10 by default. If `$NOTIFY_COMMAND` is set it's run
+//! with the message as its argument, as in `NOTIFY_COMMAND=notify-send`;
+//! otherwise the terminal gets a bell and an OSC 777 notification, which
+//! terminals that don't know it ignore.
+//!
+//! What the programs it ran used is in `$CMD_USER_TIME` and
+//! `$CMD_SYSTEM_TIME`, in milliseconds of CPU time, and `$CMD_MAX_RSS`, the
+//! most memory any of them had at once in kilobytes, where the platform
+//! says. With `set -o report-time`, as with zsh's `REPORTTIME`, they're
+//! printed after a command that took at least `$REPORTTIME` seconds of CPU
+//! time, 10 by default.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+
+use crate::expand::quote;
+use crate::platform::ResourceUsage;
+use crate::shell::ShellState;
+
+const DEFAULT_SECONDS: f64 = 10.0;
+
+/// A threshold in seconds from the variable `name`.
+fn threshold(shell: &ShellState, name: &str) -> Duration {
+    let seconds = shell
+        .variables
+        .get(name)
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .unwrap_or(DEFAULT_SECONDS);
+    Duration::from_secs_f64(seconds)
+}
+
+/// A duration as someone would say it: `4.2s`, `3m 20s` or `1h 5m`.
+fn describe(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match seconds {
+        0..60 => format!("{:.1}s", elapsed.as_secs_f64()),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Record how long `command` took and what it used, and say so if that
+/// was enough to be worth reporting. `$?` is left as the command left it.
+pub fn command_finished(shell: &mut ShellState, command: &str, elapsed: Duration) {
+    record(shell, elapsed);
+    // Only the first line, with nothing that could end an escape sequence
+    let command: String = command
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+
+    if let Some(usage) = shell.usage {
+        let cpu = usage.user + usage.system;
+        if shell.options.report_time && cpu >= threshold(shell, "REPORTTIME") {
+            report(&command, usage, elapsed);
+        }
+    }
+    if shell.options.notify_long && elapsed >= threshold(shell, "NOTIFY_SECONDS") {
+        notify(shell, &command, elapsed);
+    }
+}
+
+/// Set the variables saying how long the last command took and what it
+/// used, unsetting those the platform can't say.
+fn record(shell: &mut ShellState, elapsed: Duration) {
+    let usage = shell.usage;
+    let values = [
+        ("CMD_DURATION", Some(elapsed.as_millis())),
+        ("CMD_USER_TIME", usage.map(|usage| usage.user.as_millis())),
+        (
+            "CMD_SYSTEM_TIME",
+            usage.map(|usage| usage.system.as_millis()),
+        ),
+        ("CMD_MAX_RSS", usage.map(|usage| usage.max_rss.into())),
+    ];
+    for (name, value) in values {
+        let result = match value {
+            Some(value) => shell.variables.set(name, value.to_string()),
+            None => shell.variables.unset(name).map(drop),
+        };
+        if let Err(e) = result {
+            eprintln!("{name}: {e}");
+        }
+    }
+}
+
+/// Print what a command used, much as zsh's default `TIMEFMT` does.
+fn report(command: &str, usage: ResourceUsage, elapsed: Duration) {
+    let cpu = (usage.user + usage.system).as_secs_f64();
+    let total = elapsed.as_secs_f64();
+    let percent = if total > 0.0 {
+        cpu / total * 100.0
+    } else {
+        0.0
+    };
+    eprintln!(
+        "{command}  {:.2}s user {:.2}s system {percent:.0}% cpu {total:.3} total, {} KB max RSS",
+        usage.user.as_secs_f64(),
+        usage.system.as_secs_f64(),
+        usage.max_rss,
+    );
+}
+
+/// Say that a long command is done, through `$NOTIFY_COMMAND` or the
+/// terminal.
+fn notify(shell: &mut ShellState, command: &str, elapsed: Duration) {
+    let message = match shell.last_status {
+        0 => format!("{command} finished after {}", describe(elapsed)),
+        status => format!(
+            "{command} failed with status {status} after {}",
+            describe(elapsed)
+        ),
+    };
+
+    match shell
+        .variables
+        .get("NOTIFY_COMMAND")
+        .filter(|hook| !hook.trim().is_empty())
+    {
+        Some(hook) => {
+            let hook = format!("{hook} {}", quote(&message));
+            if let Err(e) = shell.keeping_status(|shell| shell.eval(&hook)) {
+                eprintln!("NOTIFY_COMMAND: {}", e);
+            }
+        }
+        None => {
+            let mut stdout = io::stdout();
+            if stdout.is_terminal() {
+                let title = env!("CARGO_PKG_NAME");
+                let _ = write!(stdout, "\x07\x1b]777;notify;{title};{message}\x07");
+                let _ = stdout.flush();
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Options toggled with `set -o name` or `set -x`.
+
+/// An option's long name, its single-letter flag if it has one, and what
+/// it does in a line, for `set -o` and completion.
+pub struct OptionInfo {
+    pub name: &'static str,
+    pub letter: Option<char>,
+    pub description: &'static str,
+}
+
+pub static OPTIONS: &[OptionInfo] = &[
+    OptionInfo {
+        name: "activity",
+        letter: None,
+        description: "Show whether a quiet foreground command is busy or stuck",
+    },
+    OptionInfo {
+        name: "autocd",
+        letter: None,
+        description: "Change into a directory typed as a command",
+    },
+    OptionInfo {
+        name: "autopair",
+        letter: None,
+        description: "Type closing brackets and quotes along with opening ones",
+    },
+    OptionInfo {
+        name: "chunk-args",
+        letter: None,
+        description: "Run a program several times over too long an argument list",
+    },
+    OptionInfo {
+        name: "color-stderr",
+        letter: None,
+        description: "Color what commands write to stderr with $STDERR_COLOR",
+    },
+    OptionInfo {
+        name: "completion-ignore-case",
+        letter: None,
+        description: "Complete without regard to case",
+    },
+    OptionInfo {
+        name: "completion-map-case",
+        letter: None,
+        description: "Complete with - and _ treated as the same",
+    },
+    OptionInfo {
+        name: "completion-smart-case",
+        letter: None,
+        description: "Complete without regard to case unless there are capitals",
+    },
+    OptionInfo {
+        name: "direnv",
+        letter: None,
+        description: "Load .sigsh.env files on cd",
+    },
+    OptionInfo {
+        name: "errexit",
+        letter: Some('e'),
+        description: "Exit as soon as a command fails",
+    },
+    OptionInfo {
+        name: "errexit-subst",
+        letter: None,
+        description: "With errexit, don't run a command whose $(...) failed",
+    },
+    OptionInfo {
+        name: "failglob",
+        letter: None,
+        description: "Treat a glob pattern that matches nothing as an error",
+    },
+    OptionInfo {
+        name: "glob-cache",
+        letter: None,
+        description: "Keep directories read for globbing for the rest of the line",
+    },
+    OptionInfo {
+        name: "glob-qualifiers",
+        letter: None,
+        description: "Filter glob matches by a (...) after the pattern",
+    },
+    OptionInfo {
+        name: "huponexit",
+        letter: None,
+        description: "Hang up the remaining jobs when an interactive shell exits",
+    },
+    OptionInfo {
+        name: "ignoreeof",
+        letter: None,
+        description: "Only exit on Ctrl-D pressed $IGNOREEOF times running",
+    },
+    OptionInfo {
+        name: "noclobber",
+        letter: Some('C'),
+        description: "Refuse to overwrite existing files with >",
+    },
+    OptionInfo {
+        name: "noglob",
+        letter: Some('f'),
+        description: "Leave glob patterns unexpanded",
+    },
+    OptionInfo {
+        name: "notify-long",
+        letter: None,
+        description: "Notify when a command takes longer than $NOTIFY_SECONDS",
+    },
+    OptionInfo {
+        name: "nounset",
+        letter: Some('u'),
+        description: "Treat expanding an unset variable as an error",
+    },
+    OptionInfo {
+        name: "pipefail",
+        letter: None,
+        description: "Give a pipeline the status of its last stage to fail",
+    },
+    OptionInfo {
+        name: "report-time",
+        letter: None,
+        description: "Report what a command used past $REPORTTIME seconds of CPU",
+    },
+    OptionInfo {
+        name: "safeexpand",
+        letter: None,
+        description: "Leave unquoted variables unsplit and unglobbed",
+    },
+    OptionInfo {
+        name: "xtrace",
+        letter: Some('x'),
+        description: "Print each command before running it",
+    },
+];
+
+/// The options `sigsh --strict` turns on, for scripts that should stop at
+/// the first thing that goes wrong rather than carry on regardless.
+pub static STRICT: &[&str] = &[
+    "errexit",
+    "errexit-subst",
+    "failglob",
+    "nounset",
+    "pipefail",
+    "safeexpand",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Show whether a foreground command that's gone quiet is using the
+    /// CPU, waiting or stopped
+    pub activity: bool,
+    /// Change into a directory named as a command when there's no command
+    /// by that name, as zsh does
+    pub autocd: bool,
+    /// Type the closing bracket or quote along with the opening one at the
+    /// prompt
+    pub autopair: bool,
+    /// Run a program several times over an argument list too long to pass
+    /// at once, as xargs does
+    pub chunk_args: bool,
+    /// Show what commands write to stderr in `$STDERR_COLOR` when it's the
+    /// terminal
+    pub color_stderr: bool,
+    /// Complete without regard to case
+    pub completion_ignore_case: bool,
+    /// Complete with `-` and `_` treated as the same
+    pub completion_map_case: bool,
+    /// Complete without regard to case unless what's typed has capitals
+    pub completion_smart_case: bool,
+    /// Load `.sigsh.env` files on `cd`
+    pub direnv: bool,
+    /// Exit as soon as a command fails
+    pub errexit: bool,
+    /// With `errexit`, don't run a command one of whose `$(...)`s failed,
+    /// failing instead, so that the script stops there
+    pub errexit_subst: bool,
+    /// Treat a glob pattern that matches nothing as an error, rather than
+    /// passing it on as it is
+    pub failglob: bool,
+    /// Keep the directories read for pathname expansion for the rest of the
+    /// command line, until they change
+    pub glob_cache: bool,
+    /// Filter glob matches by a `(...)` after the pattern, as in zsh
+    pub glob_qualifiers: bool,
+    /// Hang up the jobs still in the job table when an interactive shell
+    /// exits
+    pub huponexit: bool,
+    /// Don't exit on Ctrl-D at the prompt until it's pressed `$IGNOREEOF`
+    /// times running
+    pub ignoreeof: bool,
+    /// Refuse to overwrite existing files with `>`
+    pub noclobber: bool,
+    /// Leave glob patterns as they are rather than expanding them
+    pub noglob: bool,
+    /// Ring the bell or send a notification when a command run at the
+    /// prompt takes longer than `$NOTIFY_SECONDS`
+    pub notify_long: bool,
+    /// Treat expanding an unset variable as an error
+    pub nounset: bool,
+    /// Give a pipeline the status of the last stage to fail, not just of
+    /// its last stage
+    pub pipefail: bool,
+    /// Print what a command run at the prompt used if it took more than
+    /// `$REPORTTIME` seconds of CPU time
+    pub report_time: bool,
+    /// Leave the values of unquoted variables whole, neither split into
+    /// words nor globbed, as zsh does
+    pub safeexpand: bool,
+    /// Print each command before running it
+    pub xtrace: bool,
+}
+
+/// The field of an [`Options`] an option's name refers to, borrowed with
+/// `&` or `&mut` as given, so the names are listed once for both.
+macro_rules! field {
+    ($name:expr, $($borrow:tt)+) => {
+        match $name {
+            "activity" => Some($($borrow)+.activity),
+            "autocd" => Some($($borrow)+.autocd),
+            "autopair" => Some($($borrow)+.autopair),
+            "chunk-args" => Some($($borrow)+.chunk_args),
+            "color-stderr" => Some($($borrow)+.color_stderr),
+            "completion-ignore-case" => Some($($borrow)+.completion_ignore_case),
+            "completion-map-case" => Some($($borrow)+.completion_map_case),
+            "completion-smart-case" => Some($($borrow)+.completion_smart_case),
+            "direnv" => Some($($borrow)+.direnv),
+            "errexit" => Some($($borrow)+.errexit),
+            "errexit-subst" => Some($($borrow)+.errexit_subst),
+            "failglob" => Some($($borrow)+.failglob),
+            "glob-cache" => Some($($borrow)+.glob_cache),
+            "glob-qualifiers" => Some($($borrow)+.glob_qualifiers),
+            "huponexit" => Some($($borrow)+.huponexit),
+            "ignoreeof" => Some($($borrow)+.ignoreeof),
+            "noclobber" => Some($($borrow)+.noclobber),
+            "noglob" => Some($($borrow)+.noglob),
+            "notify-long" => Some($($borrow)+.notify_long),
+            "nounset" => Some($($borrow)+.nounset),
+            "pipefail" => Some($($borrow)+.pipefail),
+            "report-time" => Some($($borrow)+.report_time),
+            "safeexpand" => Some($($borrow)+.safeexpand),
+            "xtrace" => Some($($borrow)+.xtrace),
+            _ => None,
+        }
+    };
+}
+
+impl Options {
+    fn field(&mut self, name: &str) -> Option<&mut bool> {
+        field!(name, &mut self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        field!(name, &self).copied()
+    }
+
+    /// Set an option by name, returning `None` if there's no such option.
+    pub fn set(&mut self, name: &str, value: bool) -> Option<()> {
+        *self.field(name)? = value;
+        Some(())
+    }
+
+    pub fn name_for_letter(letter: char) -> Option<&'static str> {
+        OPTIONS
+            .iter()
+            .find(|option| option.letter == Some(letter))
+            .map(|option| option.name)
+    }
+
+    /// Turn on every option in [`STRICT`].
+    pub fn set_strict(&mut self) {
+        for name in STRICT {
+            self.set(name, true);
+        }
+    }
+}
@@ -1,26 +1,82 @@
-use core::panic;
-use std::io::Result as IOResult;
-use std::path::PathBuf;
-use std::{hint::unreachable_unchecked, iter::Peekable};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
 
-use crate::lexer::{Lexer, Token};
+use crate::lexer::{self, Lexer, Token, WordPart};
+use crate::nesting::{Nested, TooDeep};
 
 #[derive(Debug)]
 pub enum ParseError {
     Empty,
     MissingFileName,
     UnmatchedDelimiterError,
+    /// A `(`, `$(` or `$((` the input ends inside, which more lines might
+    /// close
+    UnclosedParen,
     InvalidVariable,
     UnterminatedStringLiteral,
     NonRedirTypeToken,
+    /// A `{` without its `}`
+    UnmatchedBrace,
+    /// `name()` not followed by a compound command
+    MissingFunctionBody,
+    /// A word like `}` where a command can't end, or can't start
+    UnexpectedWord(String),
+    /// Commands nested inside each other more deeply than [`MAX_NESTING`]
+    /// allows
+    TooDeep,
+    /// A `&` on its own, which would run a job in the background
+    Background,
     NotFound,
 }
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty command"),
+            ParseError::MissingFileName => write!(f, "missing file name after redirection"),
+            ParseError::UnmatchedDelimiterError => write!(f, "unmatched parenthesis"),
+            ParseError::UnclosedParen => write!(f, "missing ')'"),
+            ParseError::InvalidVariable => write!(f, "invalid variable name"),
+            ParseError::UnterminatedStringLiteral => write!(f, "unterminated string literal"),
+            ParseError::NonRedirTypeToken => write!(f, "expected a redirection"),
+            ParseError::UnmatchedBrace => write!(f, "missing '}}'"),
+            ParseError::MissingFunctionBody => write!(f, "missing function body"),
+            ParseError::UnexpectedWord(word) => write!(f, "unexpected '{word}'"),
+            ParseError::TooDeep => write!(f, "commands {TooDeep}"),
+            ParseError::Background => write!(f, "background jobs aren't supported"),
+            ParseError::NotFound => write!(f, "expected a command"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParseErrors {
     errors: Vec<ParseError>,
 }
 
+impl ParseErrors {
+    /// Whether the input only failed to parse because it stopped short, so
+    /// that it might once the next line is added.
+    pub fn is_incomplete(&self) -> bool {
+        self.errors
+            .iter()
+            .any(|e| matches!(e, ParseError::UnclosedParen))
+    }
+}
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.errors.first() {
+            Some(first) => write!(f, "parse error: {first}"),
+            None => write!(f, "parse error: {}", ParseError::Empty),
+        }
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
 impl IntoIterator for ParseErrors {
     type Item = ParseError;
     type IntoIter = std::vec::IntoIter<Self::Item>;
@@ -35,157 +91,584 @@ impl<'a> IntoIterator for &'a ParseErrors {
     type IntoIter = std::slice::Iter<'a, ParseError>;
 
     fn into_iter(self) -> Self::IntoIter {
-        (&self.errors).into_iter()
+        self.errors.iter()
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Arg {
     Word(String),
+    Glob(String),
     Variable(String),
     Subshell(Command),
+    /// `$(( expression ))`
+    Arith(String),
+    /// The words of a `(...)` list assigned to an array, as in `arr=(a b c)`
+    Array(Vec<Arg>),
+    /// Expansions inside double quotes, which aren't split or globbed
+    Quoted(Vec<Arg>),
+    /// Several args making up a single word, like `"$HOME"/bin`
+    Concat(Vec<Arg>),
 }
 
+pub type Aliases = BTreeMap<String, String>;
+
 #[derive(Debug)]
-pub struct Parser<I: Iterator<Item = Result<Token, ParseError>>> {
+pub struct Parser<'a, I: Iterator<Item = Result<Token, ParseError>>> {
     tokens: Peekable<I>,
+    /// Tokens to hand out before the rest of the input: alias expansions, and
+    /// words put back after looking ahead.
+    pending: VecDeque<Result<Token, ParseError>>,
+    aliases: Option<&'a Aliases>,
+    /// Aliases already expanded in the current command, so `alias ls='ls -F'`
+    /// and `alias a='b; a'` don't recurse forever.
+    expanded_aliases: HashSet<String>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Command {
     pub argv: Vec<Arg>,
     pub pipe_to: Option<PipeTo>,
     pub redirect_to: Vec<FileRedir>,
     pub and_then: Option<AndThen>,
+    /// `NAME=value` words before the command name
+    pub assignments: Vec<Assignment>,
+    /// Set instead of `argv` for compound commands like `{ ...; }`
+    pub compound: Option<Box<Compound>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Arg,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compound {
+    /// `{ list; }`
+    Group(Command),
+    /// `( list )`
+    Subshell(Command),
+    /// `name() compound-command`
+    FunctionDef { name: String, body: Command },
+    /// `spawn name compound-command`, run in the background
+    Spawn { name: String, body: Command },
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PipeTo {
     pub pipe_type: RedirType,
     pub target: Box<Command>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AndThen {
     pub conditional: bool,
     pub target: Box<Command>,
 }
 
-#[derive(Debug, PartialEq)]
+/// Move the commands chained after `command` onto `rest`.
+fn take_chained(command: &mut Command, rest: &mut Vec<Command>) {
+    rest.extend(
+        command
+            .pipe_to
+            .as_mut()
+            .map(|pipe| *std::mem::take(&mut pipe.target)),
+    );
+    rest.extend(
+        command
+            .and_then
+            .as_mut()
+            .map(|next| *std::mem::take(&mut next.target)),
+    );
+}
+
+/// Take apart what's chained after `command` one at a time, since dropping it
+/// as it's nested would go a level down the stack for each command.
+fn unlink_chain(command: &mut Command) {
+    let mut rest = Vec::new();
+    take_chained(command, &mut rest);
+    while let Some(mut command) = rest.pop() {
+        take_chained(&mut command, &mut rest);
+    }
+}
+
+impl Drop for PipeTo {
+    fn drop(&mut self) {
+        unlink_chain(&mut self.target);
+    }
+}
+
+impl Drop for AndThen {
+    fn drop(&mut self) {
+        unlink_chain(&mut self.target);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum RedirType {
     Stdout,
     Stderr,
     Both,
+    Stdin,
+    /// Descriptors past the standard three, which only `exec` can redirect
+    FdIn(u32),
+    FdOut(u32),
+    FdClose(u32),
 }
 
-#[derive(Debug)]
-pub struct NonRedirTypeToken {}
 impl TryFrom<Token> for RedirType {
     type Error = ParseError;
     fn try_from(val: Token) -> Result<Self, Self::Error> {
-        use Token as T;
         use RedirType as R;
+        use Token as T;
 
         match val {
-            T::RedirOut | T::Pipe => Ok(R::Stdout),
-            T::RedirBoth | T::PipeBoth => Ok(R::Both),
-            T::RedirErr => Ok(R::Stderr),
-            _ => Err(ParseError::NonRedirTypeToken)
+            T::RedirOut | T::AppendOut | T::ClobberOut | T::Pipe => Ok(R::Stdout),
+            T::RedirBoth | T::AppendBoth | T::PipeBoth => Ok(R::Both),
+            T::RedirErr | T::AppendErr | T::ClobberErr => Ok(R::Stderr),
+            T::RedirIn => Ok(R::Stdin),
+            T::FdIn(fd) => Ok(R::FdIn(fd)),
+            T::FdOut(fd) | T::FdAppend(fd) => Ok(R::FdOut(fd)),
+            T::FdClose(fd) => Ok(R::FdClose(fd)),
+            _ => Err(ParseError::NonRedirTypeToken),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FileRedir {
     pub redirect_type: RedirType,
     pub target: PathBuf,
+    /// Whether output is added to the end of the file instead of replacing it
+    pub append: bool,
+    /// Whether the file is overwritten even with `noclobber` set, as `>|` does
+    pub clobber: bool,
+}
+
+/// How deeply groups, subshells and substitutions may be nested. Each level is
+/// parsed a level further down the stack.
+pub use crate::nesting::MAX_NESTING;
+
+impl From<TooDeep> for ParseErrors {
+    fn from(_: TooDeep) -> Self {
+        ParseErrors {
+            errors: vec![ParseError::TooDeep],
+        }
+    }
+}
+
+pub(crate) fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Builtins which take assignments as arguments, including `(...)` lists.
+const DECLARATION_BUILTINS: &[&str] = &["declare", "typeset", "local", "readonly", "export"];
+
+/// Split `NAME=value` or `NAME[subscript]=value` into its target and value.
+fn split_assignment(word: &str) -> Option<(&str, &str)> {
+    let (target, value) = word.split_once('=')?;
+    let name = match crate::vars::split_subscript(target) {
+        Some((name, _)) => name,
+        None => target,
+    };
+    is_name(name).then_some((target, value))
+}
+
+/// Parse the inside of a `(...)` list into its words.
+fn parse_list(inner: &str) -> Result<Vec<Arg>, ParseErrors> {
+    let mut words = Vec::new();
+    for token in Lexer::new(inner) {
+        let token = token.map_err(|e| ParseErrors { errors: vec![e] })?;
+        words.push(match token {
+            Token::Word(word) => Arg::Word(word),
+            // `[key]=value` is a subscript, not a pattern
+            Token::Glob(pattern) if pattern.starts_with('[') && pattern.contains("]=") => {
+                Arg::Word(crate::glob::unescape(&pattern))
+            }
+            Token::Glob(pattern) => Arg::Glob(pattern),
+            Token::Variable(name) => Arg::Variable(name),
+            Token::SubShell(inner) => Arg::Subshell(Command::parse_substitution(&inner)?),
+            Token::Parts(parts) => Arg::Concat(
+                parts
+                    .into_iter()
+                    .map(part_to_arg)
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => {
+                return Err(ParseErrors {
+                    errors: vec![ParseError::UnexpectedWord(inner.to_string())],
+                })
+            }
+        });
+    }
+    Ok(words)
+}
+
+fn part_to_arg(part: WordPart) -> Result<Arg, ParseErrors> {
+    Ok(match part {
+        WordPart::Literal(text) => Arg::Word(text),
+        WordPart::Pattern(pattern) => Arg::Glob(pattern),
+        WordPart::Variable(name) => Arg::Variable(name),
+        WordPart::SubShell(inner) => Arg::Subshell(Command::parse_substitution(&inner)?),
+        WordPart::Arith(expr) => Arg::Arith(expr),
+        WordPart::Quoted(parts) => Arg::Quoted(
+            parts
+                .into_iter()
+                .map(part_to_arg)
+                .collect::<Result<_, _>>()?,
+        ),
+    })
+}
+
+/// Turn a multi-part word like `FOO=$HOME/bin` into an assignment, if it is
+/// one.
+fn parts_to_assignment(parts: &[WordPart]) -> Option<(String, Vec<WordPart>)> {
+    let (name, first) = match parts.first()? {
+        WordPart::Literal(text) => split_assignment(text)?,
+        // Assignment values aren't globbed
+        WordPart::Pattern(pattern) => split_assignment(pattern)?,
+        _ => return None,
+    };
+
+    let mut value = Vec::new();
+    if !first.is_empty() {
+        value.push(WordPart::Literal(crate::glob::unescape(first)));
+    }
+    value.extend(parts[1..].iter().map(|part| match part {
+        WordPart::Pattern(pattern) => WordPart::Literal(crate::glob::unescape(pattern)),
+        part => part.clone(),
+    }));
+    Some((name.to_string(), value))
 }
 
-impl<I: Iterator<Item = Result<Token, ParseError>>> Parser<I> {
+impl<'a, I: Iterator<Item = Result<Token, ParseError>>> Parser<'a, I> {
     pub fn new(tokens: I) -> Self {
         Parser {
             tokens: tokens.peekable(),
+            pending: VecDeque::new(),
+            aliases: None,
+            expanded_aliases: HashSet::new(),
         }
     }
 
-    fn parse_command(&mut self) -> Result<Command, ParseErrors> {
+    pub fn with_aliases(tokens: I, aliases: &'a Aliases) -> Self {
+        Parser {
+            aliases: Some(aliases),
+            ..Parser::new(tokens)
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token, ParseError>> {
+        self.pending.pop_front().or_else(|| self.tokens.next())
+    }
+
+    fn push_back(&mut self, token: Token) {
+        self.pending.push_front(Ok(token));
+    }
+
+    /// If `word` is an alias that hasn't been expanded yet in this command,
+    /// queue up its tokens in place of the word.
+    fn expand_alias(&mut self, word: &str) -> bool {
+        let Some(value) = self.aliases.and_then(|aliases| aliases.get(word)) else {
+            return false;
+        };
+        if !self.expanded_aliases.insert(word.to_string()) {
+            return false;
+        }
+
+        let tokens: Vec<_> = Lexer::new(value).collect();
+        for token in tokens.into_iter().rev() {
+            self.pending.push_front(token);
+        }
+        true
+    }
+
+    /// Parse one command and everything chained after it, stopping at the end
+    /// of input or at any of `terminators` in command position (which is left
+    /// for the caller). Returns `None` if there was no command at all.
+    fn parse_chain(&mut self, terminators: &[&str]) -> Result<Option<Command>, ParseErrors> {
+        let _nested = Nested::enter()?;
         let mut errors = Vec::new();
-        let mut argv = Vec::new();
-        let mut pipe_to = None;
-        let mut redirect_to = Vec::new();
-        let mut and_then = None;
 
-        while let Some(token_res) = self.tokens.next() {
+        // The commands are read in a loop rather than one level down the stack
+        // for each `;`, `&&` or `|`, however long the line
+        let mut links = Vec::new();
+        loop {
+            let (command, separator) = self.parse_link(terminators, &mut errors);
+            let more = separator.is_some();
+            links.push((command, separator));
+            if !more {
+                break;
+            }
+        }
+
+        // Then joined up from the back, each hanging off the one before it
+        let mut next = None;
+        for (mut command, separator) in links.into_iter().rev() {
+            match separator {
+                Some(pipe @ (Token::Pipe | Token::PipeBoth)) => match next {
+                    Some(target) => {
+                        command.pipe_to = Some(PipeTo {
+                            pipe_type: if matches!(pipe, Token::PipeBoth) {
+                                RedirType::Both
+                            } else {
+                                RedirType::Stdout
+                            },
+                            target: Box::new(target),
+                        })
+                    }
+                    None => errors.push(ParseError::NotFound),
+                },
+                Some(Token::AndThenIf) => match next {
+                    Some(target) => {
+                        command.and_then = Some(AndThen {
+                            target: Box::new(target),
+                            conditional: true,
+                        })
+                    }
+                    None => errors.push(ParseError::NotFound),
+                },
+                // A trailing `;` is fine, so there may be no command
+                Some(_) => {
+                    command.and_then = next.map(|target| AndThen {
+                        target: Box::new(target),
+                        conditional: false,
+                    })
+                }
+                None => {}
+            }
+
+            let is_empty = command.argv.is_empty()
+                && command.compound.is_none()
+                && command.assignments.is_empty();
+            next = if !is_empty {
+                Some(command)
+            } else if command.redirect_to.is_empty() {
+                None
+            } else {
+                errors.push(ParseError::NotFound);
+                None
+            };
+        }
+
+        if errors.is_empty() {
+            Ok(next)
+        } else {
+            Err(ParseErrors { errors })
+        }
+    }
+
+    /// Parse a single command, up to the `;`, `&&` or `|` after it, which is
+    /// returned along with it.
+    fn parse_link(
+        &mut self,
+        terminators: &[&str],
+        errors: &mut Vec<ParseError>,
+    ) -> (Command, Option<Token>) {
+        let mut command = Command::default();
+        // An alias's text can hold more than one command, so it stays
+        // expanded until all of that text has been read
+        if self.pending.is_empty() {
+            self.expanded_aliases.clear();
+        }
+
+        while let Some(token_res) = self.next_token() {
+            let at_command_start = command.argv.is_empty() && command.compound.is_none();
+
             match token_res {
                 Ok(tok) => match tok {
-                    Token::Word(word) => argv.push(Arg::Word(word)),
-                    tok if matches!(tok, Token::RedirOut | Token::RedirErr | Token::RedirBoth) => {
-                        let redir_type = tok.try_into().unwrap();
-                        if let Some(Ok(Token::Word(path))) = self.tokens.next() {
-                            redirect_to.push(FileRedir {
-                                redirect_type: redir_type,
-                                target: PathBuf::from(path),
-                            });
-                        } else {
-                            errors.push(ParseError::MissingFileName);
+                    Token::Word(word)
+                        if at_command_start && terminators.contains(&word.as_str()) =>
+                    {
+                        self.push_back(Token::Word(word));
+                        break;
+                    }
+                    Token::Word(word) if at_command_start && self.expand_alias(&word) => (),
+                    Token::Word(word) if at_command_start && word == "{" => {
+                        match self.parse_group() {
+                            Ok(body) => command.compound = Some(Box::new(Compound::Group(body))),
+                            Err(errs) => errors.extend(errs),
                         }
                     }
-                    pipe_token if matches!(pipe_token, Token::Pipe | Token::PipeBoth) => {
-                        let pipe_type: RedirType = pipe_token.try_into().unwrap();
-
-                        match self.parse_command() {
-                            Ok(next_command) => {
-                                pipe_to = Some(PipeTo {
-                                    pipe_type,
-                                    target: Box::new(next_command),
-                                });
-                            }
-                            Err(errs) => {
-                                errors.extend(errs);
+                    Token::Word(word) if at_command_start && word == "}" => {
+                        errors.push(ParseError::UnexpectedWord(word));
+                    }
+                    Token::Word(word)
+                        if at_command_start
+                            && command.assignments.is_empty()
+                            && is_name(&word)
+                            && matches!(self.peek_token(), Some(Ok(Token::Parens(inner))) if inner.trim().is_empty()) =>
+                    {
+                        self.next_token();
+                        match self.parse_function_body() {
+                            Ok(body) => {
+                                command.compound =
+                                    Some(Box::new(Compound::FunctionDef { name: word, body }))
                             }
+                            Err(errs) => errors.extend(errs),
                         }
-                        break;
                     }
-                    Token::AndThen => {
-                        match self.parse_command() {
-                            Ok(next_command) => {
-                                and_then = Some(AndThen {
-                                    target: Box::new(next_command),
-                                    conditional: false,
-                                });
-                            }
-                            Err(errs) => {
-                                errors.extend(errs);
-                            }
+                    Token::Word(word)
+                        if at_command_start
+                            && word == "spawn"
+                            && matches!(self.peek_token(), Some(Ok(Token::Word(name))) if is_name(name)) =>
+                    {
+                        match self.parse_spawn() {
+                            Some(Ok(compound)) => command.compound = Some(Box::new(compound)),
+                            Some(Err(errs)) => errors.extend(errs),
+                            // Without a body it's just a command called `spawn`
+                            None => command.argv.push(Arg::Word(word)),
                         }
-                        break;
                     }
-                    Token::AndThenIf => {
-                        match self.parse_command() {
-                            Ok(next_command) => {
-                                and_then = Some(AndThen {
-                                    target: Box::new(next_command),
-                                    conditional: true,
-                                });
-                            }
-                            Err(errs) => {
-                                errors.extend(errs);
+                    Token::Word(word)
+                        if at_command_start
+                            && word.ends_with('=')
+                            && split_assignment(&word).is_some()
+                            && matches!(self.peek_token(), Some(Ok(Token::Parens(_)))) =>
+                    {
+                        let Some(Ok(Token::Parens(inner))) = self.next_token() else {
+                            continue;
+                        };
+                        let name = word.strip_suffix('=').unwrap_or(&word);
+                        match parse_list(&inner) {
+                            Ok(words) => command.assignments.push(Assignment {
+                                name: name.to_string(),
+                                value: Arg::Array(words),
+                            }),
+                            Err(errs) => errors.extend(errs),
+                        }
+                    }
+                    Token::Word(word) if at_command_start && split_assignment(&word).is_some() => {
+                        if let Some((name, value)) = split_assignment(&word) {
+                            command.assignments.push(Assignment {
+                                name: name.to_string(),
+                                value: Arg::Word(value.to_string()),
+                            });
+                        }
+                    }
+                    // Assignment values aren't globbed, and `arr[1]=x` looks
+                    // like a pattern
+                    Token::Glob(pattern)
+                        if at_command_start
+                            && split_assignment(&crate::glob::unescape(&pattern)).is_some() =>
+                    {
+                        let word = crate::glob::unescape(&pattern);
+                        if let Some((name, value)) = split_assignment(&word) {
+                            command.assignments.push(Assignment {
+                                name: name.to_string(),
+                                value: Arg::Word(value.to_string()),
+                            });
+                        }
+                    }
+                    Token::Parts(parts)
+                        if at_command_start && parts_to_assignment(&parts).is_some() =>
+                    {
+                        if let Some((name, value)) = parts_to_assignment(&parts) {
+                            match value
+                                .into_iter()
+                                .map(part_to_arg)
+                                .collect::<Result<Vec<_>, _>>()
+                            {
+                                Ok(value) => command.assignments.push(Assignment {
+                                    name,
+                                    value: Arg::Concat(value),
+                                }),
+                                Err(errs) => errors.extend(errs),
                             }
                         }
-                        break;
                     }
-                    Token::SubShell(command) => {
-                        match Command::parse(command) {
-                            Ok(command) => argv.push(Arg::Subshell(command)),
+                    Token::Word(word) => command.argv.push(Arg::Word(word)),
+                    Token::Glob(pattern) => command.argv.push(Arg::Glob(pattern)),
+                    Token::Parts(parts) => {
+                        match parts
+                            .into_iter()
+                            .map(part_to_arg)
+                            .collect::<Result<Vec<_>, _>>()
+                        {
+                            Ok(args) => command.argv.push(Arg::Concat(args)),
                             Err(errs) => errors.extend(errs),
                         }
                     }
-                    Token::Variable(s) => {
-                        argv.push(Arg::Variable(s));
+                    Token::Parens(inner) if at_command_start && command.assignments.is_empty() => {
+                        match Command::parse(lexer::separate_lines(&inner)) {
+                            Ok(body) => command.compound = Some(Box::new(Compound::Subshell(body))),
+                            Err(errs) => errors.extend(errs),
+                        }
                     }
-                    _ => {
-                        // TODO: Re-evaluate this!
-                        unsafe { unreachable_unchecked() }
+                    // `declare arr=(a b c)`
+                    Token::Parens(inner)
+                        if matches!(command.argv.first(), Some(Arg::Word(builtin)) if DECLARATION_BUILTINS.contains(&builtin.as_str()))
+                            && matches!(command.argv.last(), Some(Arg::Word(word)) if word.ends_with('=') && split_assignment(word).is_some()) =>
+                    {
+                        match parse_list(&inner) {
+                            Ok(words) => {
+                                let target = command.argv.pop().unwrap_or(Arg::Word(String::new()));
+                                command
+                                    .argv
+                                    .push(Arg::Concat(vec![target, Arg::Array(words)]));
+                            }
+                            Err(errs) => errors.extend(errs),
+                        }
+                    }
+                    Token::Parens(_) => errors.push(ParseError::UnmatchedDelimiterError),
+                    tok @ (Token::RedirOut
+                    | Token::RedirErr
+                    | Token::RedirBoth
+                    | Token::AppendOut
+                    | Token::AppendErr
+                    | Token::AppendBoth
+                    | Token::ClobberOut
+                    | Token::ClobberErr
+                    | Token::RedirIn
+                    | Token::FdIn(_)
+                    | Token::FdOut(_)
+                    | Token::FdAppend(_)) => {
+                        let append = matches!(
+                            tok,
+                            Token::AppendOut
+                                | Token::AppendErr
+                                | Token::AppendBoth
+                                | Token::FdAppend(_)
+                        );
+                        let clobber = matches!(tok, Token::ClobberOut | Token::ClobberErr);
+                        let redir_type = match tok.try_into() {
+                            Ok(redir_type) => redir_type,
+                            Err(e) => {
+                                errors.push(e);
+                                continue;
+                            }
+                        };
+                        if let Some(Ok(Token::Word(path))) = self.next_token() {
+                            command.redirect_to.push(FileRedir {
+                                redirect_type: redir_type,
+                                target: PathBuf::from(path),
+                                append,
+                                clobber,
+                            });
+                        } else {
+                            errors.push(ParseError::MissingFileName);
+                        }
+                    }
+                    Token::FdClose(fd) => command.redirect_to.push(FileRedir {
+                        redirect_type: RedirType::FdClose(fd),
+                        target: PathBuf::new(),
+                        append: false,
+                        clobber: false,
+                    }),
+                    separator @ (Token::Pipe
+                    | Token::PipeBoth
+                    | Token::AndThen
+                    | Token::AndThenIf) => return (command, Some(separator)),
+                    Token::SubShell(command_str) => match Command::parse_substitution(&command_str)
+                    {
+                        Ok(subshell) => command.argv.push(Arg::Subshell(subshell)),
+                        Err(errs) => errors.extend(errs),
+                    },
+                    Token::Variable(s) => {
+                        command.argv.push(Arg::Variable(s));
                     }
                 },
                 Err(e) => {
@@ -194,26 +677,83 @@ impl<I: Iterator<Item = Result<Token, ParseError>>> Parser<I> {
             }
         }
 
-        if !errors.is_empty() || argv.is_empty() {
-            Err(ParseErrors { errors })
-        } else {
-            Ok(Command {
-                argv,
-                pipe_to,
-                and_then,
-                redirect_to,
-            })
+        (command, None)
+    }
+
+    fn peek_token(&mut self) -> Option<&Result<Token, ParseError>> {
+        if self.pending.is_empty() {
+            let token = self.tokens.next()?;
+            self.pending.push_back(token);
         }
+        self.pending.front()
     }
 
-    fn parse_redir_type(&mut self) -> RedirType {
-        match self.tokens.next() {
-            Some(Ok(Token::RedirOut)) => RedirType::Stdout,
-            Some(Ok(Token::RedirErr)) => RedirType::Stderr,
-            Some(Ok(Token::RedirBoth)) => RedirType::Both,
-            _ => panic!("peek is Token::Redir, but matched none of Redir Variants"),
+    /// Parse the rest of a `{ ...; }` group, after the `{`.
+    fn parse_group(&mut self) -> Result<Command, ParseErrors> {
+        let body = self.parse_chain(&["}"])?;
+        match self.next_token() {
+            Some(Ok(Token::Word(word))) if word == "}" => body.ok_or(ParseErrors {
+                errors: vec![ParseError::NotFound],
+            }),
+            _ => Err(ParseErrors {
+                errors: vec![ParseError::UnmatchedBrace],
+            }),
         }
     }
+
+    /// Parse the compound command making up a function's body, after `name()`.
+    fn parse_function_body(&mut self) -> Result<Command, ParseErrors> {
+        let compound = match self.next_token() {
+            Some(Ok(Token::Word(word))) if word == "{" => Compound::Group(self.parse_group()?),
+            Some(Ok(Token::Parens(inner))) => {
+                Compound::Subshell(Command::parse(lexer::separate_lines(&inner))?)
+            }
+            _ => {
+                return Err(ParseErrors {
+                    errors: vec![ParseError::MissingFunctionBody],
+                })
+            }
+        };
+        Ok(Command {
+            compound: Some(Box::new(compound)),
+            ..Default::default()
+        })
+    }
+
+    /// Parse the rest of `spawn name { ...; }`, after the `spawn`, or put the
+    /// name back if there's no compound command after it.
+    fn parse_spawn(&mut self) -> Option<Result<Compound, ParseErrors>> {
+        let Some(Ok(Token::Word(name))) = self.next_token() else {
+            return None;
+        };
+        match self.peek_token() {
+            Some(Ok(Token::Word(word))) if word == "{" => {}
+            Some(Ok(Token::Parens(_))) => {}
+            _ => {
+                self.push_back(Token::Word(name));
+                return None;
+            }
+        }
+        Some(
+            self.parse_function_body()
+                .map(|body| Compound::Spawn { name, body }),
+        )
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseErrors> {
+        let command = self.parse_chain(&[])?;
+
+        // Anything left over is a terminator nothing was waiting for
+        if let Some(Ok(Token::Word(word))) = self.next_token() {
+            return Err(ParseErrors {
+                errors: vec![ParseError::UnexpectedWord(word)],
+            });
+        }
+
+        command.ok_or(ParseErrors {
+            errors: vec![ParseError::Empty],
+        })
+    }
 }
 
 impl Command {
@@ -223,16 +763,49 @@ impl Command {
         parser.parse_command()
     }
 
-    pub fn args(&self) -> IOResult<Vec<String>> {
-        use crate::safe_wrappers::getenv;
-        let mut args = Vec::new();
-        for arg in &self.argv {
-            match arg {
-                Arg::Word(w) => args.push(w.clone()),
-                Arg::Variable(var_id) => args.push(getenv(var_id)?),
-                Arg::Subshell(_cmd) => todo!(),
+    /// Whether `input` leaves a `$(` or `(` open, so that the next line
+    /// should be added to it.
+    pub fn is_incomplete(input: &str) -> bool {
+        Command::parse(input).is_err_and(|errors| errors.is_incomplete())
+    }
+
+    /// Parse the inside of `$(...)`, where a lone `<file`, which is no
+    /// command anywhere else, stands for the file's contents.
+    pub fn parse_substitution(input: &str) -> Result<Self, ParseErrors> {
+        let trimmed = input.trim_start();
+        if trimmed.starts_with('<') {
+            if let Ok(mut command) = Command::parse(format!(": {trimmed}")) {
+                command.argv.clear();
+                if command.file_read().is_some() {
+                    return Ok(command);
+                }
+            }
+        }
+        Command::parse(input)
+    }
+
+    /// The file a `$(<file)` reads.
+    pub fn file_read(&self) -> Option<&Path> {
+        let simple = self.argv.is_empty()
+            && self.assignments.is_empty()
+            && self.compound.is_none()
+            && self.pipe_to.is_none()
+            && self.and_then.is_none();
+        match self.redirect_to.as_slice() {
+            [redirect] if simple && redirect.redirect_type == RedirType::Stdin => {
+                Some(&redirect.target)
             }
+            _ => None,
         }
-        Ok(args)
+    }
+
+    /// Parse `input`, expanding any aliases in command position.
+    pub fn parse_with_aliases(
+        input: impl AsRef<str>,
+        aliases: &Aliases,
+    ) -> Result<Self, ParseErrors> {
+        let lexer = Lexer::new(input.as_ref());
+        let mut parser = Parser::with_aliases(lexer, aliases);
+        parser.parse_command()
     }
 }
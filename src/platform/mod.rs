@@ -0,0 +1,327 @@
+//! Everything the shell needs from the host OS that `std` doesn't already
+//! paper over lives behind this module, so the rest of the shell can stay
+//! free of `cfg` attributes.
+//!
+//! Each backend provides:
+//! - `spawn`, which starts an external command as a `Process`, which can be
+//!   waited for, polled with `try_wait` or ended with `terminate`, and once
+//!   it's finished may have the [`ResourceUsage`] it ran up
+//! - `fork_subshell`, which runs a closure in a forked copy of the shell, or
+//!   returns `None` if the platform can't fork
+//! - `pipe`, and `redirect_std`, a guard pointing the shell's own standard
+//!   streams somewhere else while a builtin runs
+//! - `borrow_fd`, for builtins that read from a numbered file descriptor,
+//!   and `clone_stderr`, for writing to the shell's stderr wherever fd 2
+//!   is pointed
+//! - `is_open`, `install_fd` and `close_fd`, for `exec` redirections which
+//!   last, and `close_inherited_on_exec`
+//! - `fd_of`, the number of a file's descriptor, where files have one
+//! - `replace_process`, which runs a program in place of the shell
+//! - `spawn_limit`, the [`SpawnLimit`] a failure to start a program ran
+//!   into, if it was one, for [`spawn_error`]
+//! - `arg_limits`, how much can be passed to a program in all and in one
+//!   argument, where that's limited, in bytes
+//! - `init_job_control`, `continue_job`, `hang_up`, `reclaim_terminal` and
+//!   `reap_children`, which fail or do nothing where there's no job control
+//! - `job_processes`, the [`ProcessInfo`] of every process in a job and
+//!   everything they started, or nothing where that can't be found out
+//! - `executable_extensions` and `is_executable`, used by [`find_executable`]
+//! - `GLOB_CASE_SENSITIVE`, the filesystem's case rules for pathname expansion
+//! - `RawMode`, a guard that puts the console into raw mode until dropped,
+//!   with Ctrl-C read as a key, either on the shell's standard input or on
+//!   the console opened by `open_terminal`, for menus that work however the
+//!   standard streams are redirected
+//! - `InterruptGuard`, a guard that notes Ctrl-C sent to the shell until
+//!   dropped
+//! - `ResizeGuard`, a guard that makes a read from the console fail with
+//!   `Interrupted` when the window is resized, until dropped
+//! - `terminal_width`, the console's width in columns if it can be found
+//! - `format_time`, a time in seconds since the epoch formatted in local
+//!   time as C's `strftime` would, where the platform has one
+//! - `SIGNALS`, the number and name of each signal, for `trap -l` and for
+//!   saying what killed a command
+
+use std::env;
+use std::fs::File;
+use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub(crate) use unix::*;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub(crate) use windows::*;
+
+/// Where a command's standard streams go. `None` leaves a stream as the
+/// shell's own.
+#[derive(Default)]
+pub(crate) struct Stdio {
+    pub stdin: Option<File>,
+    pub stdout: Option<File>,
+    pub stderr: Option<File>,
+}
+
+/// Which process group a new process goes in, where there are any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProcessGroup {
+    /// Stay in the shell's group, as when there's no job control
+    Inherit,
+    /// Start a new group in the foreground, as the first process of a job
+    Lead,
+    /// Join the group of a job's first process
+    Join(u32),
+    /// Start a new group that's left in the background, as for a pipeline
+    /// started with `spawn`
+    Background,
+    /// Start a session of its own, away from the terminal and ignoring the
+    /// hangup when it goes, as for a command that's been disowned
+    Detach,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum WaitStatus {
+    Exited(i32),
+    /// Killed by the signal, and whether it dumped core
+    TermSignal(i32, bool),
+    Stopped(i32),
+    Continued,
+    Unknown,
+}
+
+impl WaitStatus {
+    /// The value this status should have as `$?`.
+    pub fn code(&self) -> i32 {
+        match self {
+            WaitStatus::Exited(code) => *code,
+            WaitStatus::TermSignal(sig, _) | WaitStatus::Stopped(sig) => 128 + sig,
+            WaitStatus::Continued => 0,
+            WaitStatus::Unknown => 1,
+        }
+    }
+}
+
+/// The name of signal `number` without its `SIG`, as in [`SIGNALS`].
+pub(crate) fn signal_name(number: i32) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|(signal, _)| *signal == number)
+        .map(|(_, name)| *name)
+}
+
+/// What a finished process and the children it waited for used.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ResourceUsage {
+    pub user: Duration,
+    pub system: Duration,
+    /// The most memory in use at once, in kilobytes
+    pub max_rss: u64,
+}
+
+impl ResourceUsage {
+    /// Add on what another process used, which ran alongside this one as
+    /// far as the memory goes.
+    pub fn add(&mut self, other: ResourceUsage) {
+        self.user += other.user;
+        self.system += other.system;
+        self.max_rss = self.max_rss.max(other.max_rss);
+    }
+}
+
+/// A process belonging to a job, as `jobs -t` shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ProcessInfo {
+    pub pid: u32,
+    pub parent: u32,
+    /// What it's doing: `running`, `sleeping`, `blocked` on IO, `stopped`
+    /// or `zombie`
+    pub state: &'static str,
+    /// The CPU time it's used so far
+    pub cpu: Duration,
+    /// How many bytes it's written so far, to anything, where that can be
+    /// found out
+    pub written: u64,
+    pub command: String,
+}
+
+/// A limit that starting a program ran into, and where it's set.
+pub(crate) enum SpawnLimit {
+    /// Too many files open, with how many are and the limit on them
+    OpenFiles { open: usize, limit: Option<u64> },
+    /// Out of memory, with the limit on the address space in bytes
+    Memory { limit: Option<u64> },
+    /// The arguments and environment together are too big, or one argument
+    /// is
+    ArgSize,
+}
+
+/// What a string takes up among what's passed to a program: itself, the NUL
+/// after it and the pointer to it.
+fn passed_size(len: usize) -> u64 {
+    (len + 1 + size_of::<usize>()) as u64
+}
+
+/// Room to leave under the limit on what's passed to a program, as xargs
+/// does, for what's passed along with it such as the program's path.
+const ARG_HEADROOM: u64 = 2048;
+
+/// How much can be passed to a program, in all and in one argument.
+fn usable_arg_limits() -> (Option<u64>, Option<u64>) {
+    let (limit, single) = arg_limits();
+    (
+        limit.map(|limit| limit.saturating_sub(ARG_HEADROOM)),
+        single,
+    )
+}
+
+fn env_size(env: &[(String, String)]) -> u64 {
+    env.iter()
+        .map(|(name, value)| passed_size(name.len() + 1 + value.len()))
+        .sum()
+}
+
+/// Whether `args` can be passed to a program along with `env`.
+pub(crate) fn args_fit(args: &[String], env: &[(String, String)]) -> bool {
+    let (limit, single) = usable_arg_limits();
+    let total: u64 = args.iter().map(|arg| passed_size(arg.len())).sum::<u64>() + env_size(env);
+    limit.is_none_or(|limit| total <= limit)
+        && single.is_none_or(|single| args.iter().all(|arg| (arg.len() as u64) < single))
+}
+
+/// Split `args` into lists that each fit along with `env`, as xargs would:
+/// each starts with the program and any options before the other
+/// arguments, followed by as many of the rest as fit. Returns `None` if
+/// that can't be done, as when one argument is too long on its own.
+pub(crate) fn split_args(args: &[String], env: &[(String, String)]) -> Option<Vec<Vec<String>>> {
+    let (limit, single) = usable_arg_limits();
+    let mut options = 0;
+    for arg in &args[1..] {
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        options += 1;
+        if arg == "--" {
+            break;
+        }
+    }
+    let (fixed, rest) = args.split_at(1 + options);
+    if rest.is_empty()
+        || single.is_some_and(|single| rest.iter().any(|arg| arg.len() as u64 >= single))
+    {
+        return None;
+    }
+
+    let base: u64 = fixed.iter().map(|arg| passed_size(arg.len())).sum::<u64>() + env_size(env);
+    let room = limit.unwrap_or(u64::MAX);
+    let mut chunks = Vec::new();
+    let mut chunk = fixed.to_vec();
+    let mut used = base;
+    for arg in rest {
+        let size = passed_size(arg.len());
+        if base + size > room {
+            return None;
+        }
+        if used + size > room {
+            chunks.push(std::mem::replace(&mut chunk, fixed.to_vec()));
+            used = base;
+        }
+        chunk.push(arg.clone());
+        used += size;
+    }
+    chunks.push(chunk);
+    Some(chunks)
+}
+
+/// What to say about `args` not fitting along with `env`.
+fn describe_arg_size(args: &[String], env: &[(String, String)]) -> String {
+    let (limit, single) = arg_limits();
+    let longest = args.iter().map(String::len).max().unwrap_or(0);
+    match single {
+        Some(single) if longest as u64 >= single => format!(
+            "argument list too long: one argument is {longest} bytes, and the limit for each \
+             is {single}; pass it in a file or through a pipe instead"
+        ),
+        _ => format!(
+            "argument list too long: {} arguments take {} bytes and the environment {} more, \
+             against a limit of {} (getconf ARG_MAX); pass the arguments in batches, as xargs \
+             does, or set -o chunk-args to have that done",
+            args.len(),
+            args.iter().map(|arg| passed_size(arg.len())).sum::<u64>(),
+            env_size(env),
+            limit.map_or("unlimited".to_string(), |limit| limit.to_string())
+        ),
+    }
+}
+
+/// The error for `args` being too much to pass along with `env`, found out
+/// before trying.
+pub(crate) fn args_too_long(args: &[String], env: &[(String, String)]) -> IOError {
+    IOError::new(
+        IOErrorKind::ArgumentListTooLong,
+        format!("{}: {}", args[0], describe_arg_size(args, env)),
+    )
+}
+
+/// The error to report when `args` couldn't be started because of `e`,
+/// naming the program. Running out of descriptors or memory, or passing it
+/// too much, say what the limit is and what might help rather than just
+/// giving the errno.
+pub(crate) fn spawn_error(args: &[String], env: &[(String, String)], e: IOError) -> IOError {
+    let detail = match spawn_limit(&e) {
+        None => e.to_string(),
+        Some(SpawnLimit::OpenFiles { open, limit }) => format!(
+            "too many open files: {open} are open and the limit is {} (ulimit -n); close \
+             some, such as those opened with exec, or raise the limit",
+            limit.map_or("unlimited".to_string(), |limit| limit.to_string())
+        ),
+        Some(SpawnLimit::Memory { limit: Some(limit) }) => format!(
+            "out of memory: the address space is limited to {} KiB (ulimit -v); raise the \
+             limit or free up some memory",
+            limit / 1024
+        ),
+        Some(SpawnLimit::Memory { limit: None }) => {
+            "out of memory, with no limit on the address space (ulimit -v), so the system \
+             itself is short of it; free up some memory"
+                .to_string()
+        }
+        Some(SpawnLimit::ArgSize) => describe_arg_size(args, env),
+    };
+    IOError::new(e.kind(), format!("{}: {detail}", args[0]))
+}
+
+/// Resolve `name` the way the OS would when asked to run it: names containing
+/// a path separator are taken as-is, everything else is searched for in `PATH`.
+pub(crate) fn find_executable(name: &str) -> Option<PathBuf> {
+    find_executables(name).next()
+}
+
+/// Every program `name` could resolve to, in the order [`find_executable`]
+/// tries them, so the first is the one that runs.
+pub(crate) fn find_executables(name: &str) -> impl Iterator<Item = PathBuf> {
+    let has_separator = name.contains('/') || name.contains(std::path::MAIN_SEPARATOR);
+
+    let dirs: Vec<PathBuf> = if has_separator {
+        vec![PathBuf::new()]
+    } else {
+        env::var_os("PATH")
+            .map(|path| env::split_paths(&path).collect())
+            .unwrap_or_default()
+    };
+
+    let extensions = executable_extensions();
+    let candidates: Vec<PathBuf> = dirs
+        .iter()
+        .flat_map(|dir| {
+            extensions
+                .iter()
+                .map(move |ext| dir.join(format!("{name}{ext}")))
+        })
+        .collect();
+    candidates
+        .into_iter()
+        .filter(|candidate| is_executable(candidate))
+}
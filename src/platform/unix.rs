@@ -0,0 +1,764 @@
+use libc::pid_t;
+use std::fs::{self, File};
+use std::io::{self, Error as IOError, ErrorKind as IOErrorKind, Result as IOResult, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use super::{spawn_error, ProcessGroup, ProcessInfo, ResourceUsage, SpawnLimit, Stdio, WaitStatus};
+use crate::safe_wrappers::{
+    self, close, dup2, exec, fd_is_open, fork, getpgrp, getpid, kill, killpg, resource_limits,
+    restore_signal_action, set_cloexec, set_interrupting_handler, set_signal_handler, setpgid,
+    setsid, sysconf, tcgetattr, tcgetpgrp, tcsetattr, tcsetpgrp, waitpid, ForkReturn, SpawnOptions,
+    WaitReturn,
+};
+
+pub(crate) const GLOB_CASE_SENSITIVE: bool = true;
+
+pub(crate) fn executable_extensions() -> Vec<String> {
+    vec![String::new()]
+}
+
+pub(crate) fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+/// Signals an interactive shell ignores so that only its foreground job
+/// receives them. Children have to put them back before `exec`, since ignored
+/// dispositions survive it.
+const JOB_CONTROL_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+/// The signals there are, by number and name without the `SIG`.
+pub(crate) const SIGNALS: &[(i32, &str)] = &[
+    (libc::SIGHUP, "HUP"),
+    (libc::SIGINT, "INT"),
+    (libc::SIGQUIT, "QUIT"),
+    (libc::SIGILL, "ILL"),
+    (libc::SIGTRAP, "TRAP"),
+    (libc::SIGABRT, "ABRT"),
+    (libc::SIGBUS, "BUS"),
+    (libc::SIGFPE, "FPE"),
+    (libc::SIGKILL, "KILL"),
+    (libc::SIGUSR1, "USR1"),
+    (libc::SIGSEGV, "SEGV"),
+    (libc::SIGUSR2, "USR2"),
+    (libc::SIGPIPE, "PIPE"),
+    (libc::SIGALRM, "ALRM"),
+    (libc::SIGTERM, "TERM"),
+    (libc::SIGCHLD, "CHLD"),
+    (libc::SIGCONT, "CONT"),
+    (libc::SIGSTOP, "STOP"),
+    (libc::SIGTSTP, "TSTP"),
+    (libc::SIGTTIN, "TTIN"),
+    (libc::SIGTTOU, "TTOU"),
+    (libc::SIGURG, "URG"),
+    (libc::SIGXCPU, "XCPU"),
+    (libc::SIGXFSZ, "XFSZ"),
+    (libc::SIGVTALRM, "VTALRM"),
+    (libc::SIGPROF, "PROF"),
+    (libc::SIGWINCH, "WINCH"),
+    (libc::SIGIO, "IO"),
+    (libc::SIGSYS, "SYS"),
+];
+
+/// The terminal modes the shell started with, restored whenever a job hands
+/// the terminal back in whatever state it left it.
+static SHELL_TMODES: OnceLock<libc::termios> = OnceLock::new();
+
+fn terminal_fd() -> RawFd {
+    io::stdin().as_raw_fd()
+}
+
+/// Put the shell in its own process group in the foreground of the terminal,
+/// so that it can hand the terminal to jobs and take it back.
+pub(crate) fn init_job_control() -> IOResult<()> {
+    let fd = terminal_fd();
+
+    // If we were started in the background, wait until we're brought forward
+    // instead of fighting the parent shell for the terminal.
+    while tcgetpgrp(fd)? != getpgrp() {
+        killpg(getpgrp(), libc::SIGTTIN)?;
+    }
+
+    for signal in JOB_CONTROL_SIGNALS {
+        set_signal_handler(signal, libc::SIG_IGN);
+    }
+
+    let pid = getpid();
+    match setpgid(pid, pid) {
+        // Already a session leader, which means we already lead our group
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) && getpgrp() == pid => (),
+        res => res?,
+    }
+    tcsetpgrp(fd, pid)?;
+
+    let _ = SHELL_TMODES.set(tcgetattr(fd)?);
+    Ok(())
+}
+
+pub(crate) struct Process {
+    pid: pid_t,
+    /// Whether the process leads its own group, so that signals should go to
+    /// the whole group
+    leader: bool,
+    usage: Option<ResourceUsage>,
+}
+
+impl Process {
+    pub fn id(&self) -> u32 {
+        self.pid as u32
+    }
+
+    /// Wait for the process to exit or stop. The terminal stays with the
+    /// process's group until [`reclaim_terminal`].
+    pub fn wait(&mut self) -> IOResult<WaitStatus> {
+        let res = wait_for(self.pid)?;
+        Ok(self.record(res))
+    }
+
+    /// The process's status if it has exited, without waiting.
+    pub fn try_wait(&mut self) -> IOResult<Option<WaitStatus>> {
+        loop {
+            match waitpid(self.pid, libc::WNOHANG) {
+                Err(e) if e.kind() == IOErrorKind::Interrupted => continue,
+                res => return res.map(|res| res.map(|res| self.record(Some(res)))),
+            }
+        }
+    }
+
+    fn record(&mut self, res: Option<WaitReturn>) -> WaitStatus {
+        let Some(res) = res else {
+            return WaitStatus::Unknown;
+        };
+        self.usage = self.usage.or(res.usage);
+        res.status
+    }
+
+    /// What the process used, once it's finished.
+    pub fn usage(&self) -> Option<ResourceUsage> {
+        self.usage
+    }
+
+    /// Ask the process to exit with SIGTERM, or with `force`, make it with
+    /// SIGKILL. It's continued too, in case it was stopped.
+    pub fn terminate(&mut self, force: bool) -> IOResult<()> {
+        let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+        let send = if self.leader { killpg } else { kill };
+        send(self.pid, signal)?;
+        send(self.pid, libc::SIGCONT)
+    }
+}
+
+fn wait_for(pid: pid_t) -> IOResult<Option<WaitReturn>> {
+    loop {
+        match waitpid(pid, libc::WUNTRACED) {
+            // A signal arriving mid-wait is routine on macOS and the BSDs
+            Err(e) if e.kind() == IOErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+/// Take the terminal back from a foreground job, in whatever modes the shell
+/// had it.
+pub(crate) fn reclaim_terminal() -> IOResult<()> {
+    let fd = terminal_fd();
+    tcsetpgrp(fd, getpgrp())?;
+    if let Some(tmodes) = SHELL_TMODES.get() {
+        tcsetattr(fd, tmodes)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn pipe() -> IOResult<(File, File)> {
+    let (reader, writer) = io::pipe()?;
+    Ok((
+        File::from(OwnedFd::from(reader)),
+        File::from(OwnedFd::from(writer)),
+    ))
+}
+
+/// In a freshly forked child, join the right process group, taking the
+/// terminal if we lead it, and stop ignoring the job control signals.
+fn enter_group(group: ProcessGroup) {
+    let pid = getpid();
+    // Both sides set the process group, since either may run first. The
+    // terminal has to be ours before `exec` so the program doesn't get
+    // SIGTTOU/SIGTTIN on its first access.
+    match group {
+        ProcessGroup::Inherit => return,
+        ProcessGroup::Lead => {
+            let _ = setpgid(pid, pid);
+            let _ = tcsetpgrp(terminal_fd(), pid);
+        }
+        ProcessGroup::Join(pgid) => {
+            let _ = setpgid(pid, pgid as pid_t);
+        }
+        ProcessGroup::Background => {
+            let _ = setpgid(pid, pid);
+        }
+        ProcessGroup::Detach => {
+            let _ = setsid();
+            set_signal_handler(libc::SIGHUP, libc::SIG_IGN);
+        }
+    }
+    for signal in JOB_CONTROL_SIGNALS {
+        set_signal_handler(signal, libc::SIG_DFL);
+    }
+}
+
+/// Rust ignores SIGPIPE for us, and an ignored signal stays ignored across
+/// `exec`, but anything writing to a pipe should die quietly once the reader
+/// goes away.
+fn reset_sigpipe() {
+    set_signal_handler(libc::SIGPIPE, libc::SIG_DFL);
+}
+
+/// The parent's half of [`enter_group`].
+fn place_in_group(pid: pid_t, group: ProcessGroup) -> IOResult<()> {
+    let pgid = match group {
+        // Only the child itself can start a session
+        ProcessGroup::Inherit | ProcessGroup::Detach => return Ok(()),
+        ProcessGroup::Lead | ProcessGroup::Background => pid,
+        ProcessGroup::Join(pgid) => pgid as pid_t,
+    };
+    // EACCES means the child already exec'd, having set its group itself;
+    // Linux and the BSDs all report it this way.
+    match setpgid(pid, pgid) {
+        Err(e) if e.raw_os_error() == Some(libc::EACCES) => Ok(()),
+        res => res,
+    }
+}
+
+/// In a forked child, move the files in `stdio` onto fds 0, 1 and 2.
+fn install_stdio(stdio: &Stdio) -> IOResult<()> {
+    let streams = [&stdio.stdin, &stdio.stdout, &stdio.stderr];
+    for (fd, file) in streams.into_iter().enumerate() {
+        if let Some(file) = file {
+            dup2(file.as_raw_fd(), fd as RawFd)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn spawn(
+    args: &[String],
+    env: &[(String, String)],
+    stdio: &Stdio,
+    group: ProcessGroup,
+) -> IOResult<Process> {
+    let Some(path) = super::find_executable(&args[0]) else {
+        return Err(IOError::new(
+            IOErrorKind::NotFound,
+            format!("{}: command not found", args[0]),
+        ));
+    };
+
+    // Leading a job means taking the terminal between joining the group and
+    // `exec`, which `posix_spawn` has no portable way to do. If it fails, the
+    // forked child reports why and exits 127 as usual.
+    if !matches!(group, ProcessGroup::Lead | ProcessGroup::Detach) {
+        if let Ok(pid) = spawn_without_fork(&path, args, env, stdio, group) {
+            return Ok(Process {
+                pid,
+                leader: group == ProcessGroup::Background,
+                usage: None,
+            });
+        }
+    }
+
+    match fork().map_err(|e| spawn_error(args, env, e))? {
+        ForkReturn::Child => {
+            enter_group(group);
+            reset_sigpipe();
+
+            let res = install_stdio(stdio).and_then(|_| exec(&path, args, env));
+            if let Err(e) = res {
+                eprintln!("{}", spawn_error(args, env, e));
+            }
+            // Never return into the parent's REPL from the child
+            unsafe { libc::_exit(127) }
+        }
+        ForkReturn::Parent(pid) => {
+            place_in_group(pid, group)?;
+            Ok(Process {
+                pid,
+                leader: matches!(group, ProcessGroup::Lead | ProcessGroup::Background),
+                usage: None,
+            })
+        }
+    }
+}
+
+/// Start a process that doesn't take the terminal with `posix_spawn`, setting
+/// it up the way [`enter_group`], [`reset_sigpipe`] and [`install_stdio`]
+/// would after a fork.
+fn spawn_without_fork(
+    path: &Path,
+    args: &[String],
+    env: &[(String, String)],
+    stdio: &Stdio,
+    group: ProcessGroup,
+) -> IOResult<pid_t> {
+    let streams = [&stdio.stdin, &stdio.stdout, &stdio.stderr];
+    let dup2 = streams
+        .into_iter()
+        .enumerate()
+        .filter_map(|(fd, file)| Some((file.as_ref()?.as_raw_fd(), fd as RawFd)))
+        .collect::<Vec<_>>();
+
+    let mut default_signals = vec![libc::SIGPIPE];
+    let pgroup = match group {
+        ProcessGroup::Inherit => None,
+        ProcessGroup::Lead | ProcessGroup::Detach | ProcessGroup::Background => Some(0),
+        ProcessGroup::Join(pgid) => Some(pgid as pid_t),
+    };
+    if pgroup.is_some() {
+        default_signals.extend(JOB_CONTROL_SIGNALS);
+    }
+
+    let options = SpawnOptions {
+        dup2: &dup2,
+        pgroup,
+        default_signals: &default_signals,
+    };
+    safe_wrappers::spawn(path, args, env, &options)
+}
+
+/// Run `args` in place of the shell, as `exec` does. Only returns, with the
+/// reason why, if that can't be done.
+pub(crate) fn replace_process(args: &[String], env: &[(String, String)]) -> IOError {
+    let Some(path) = super::find_executable(&args[0]) else {
+        return IOError::new(
+            IOErrorKind::NotFound,
+            format!("{}: command not found", args[0]),
+        );
+    };
+
+    let _ = io::stdout().flush();
+    // The program mustn't inherit the signals the shell ignores
+    let saved: Vec<_> = JOB_CONTROL_SIGNALS
+        .into_iter()
+        .chain([libc::SIGPIPE])
+        .map(|signal| (signal, set_signal_handler(signal, libc::SIG_DFL)))
+        .collect();
+    let e = match exec(&path, args, env) {
+        Ok(()) => unreachable!("execve only returns on failure"),
+        Err(e) => e,
+    };
+    for (signal, handler) in saved {
+        set_signal_handler(signal, handler);
+    }
+    spawn_error(args, env, e)
+}
+
+pub(crate) fn arg_limits() -> (Option<u64>, Option<u64>) {
+    let limit = sysconf(libc::_SC_ARG_MAX);
+    // Linux also limits each string, to 32 pages
+    let single = if cfg!(target_os = "linux") {
+        sysconf(libc::_SC_PAGESIZE).map(|page| page * 32)
+    } else {
+        None
+    };
+    (limit, single)
+}
+
+pub(crate) fn spawn_limit(e: &IOError) -> Option<SpawnLimit> {
+    let (files, memory) = resource_limits().unwrap_or_default();
+    match e.raw_os_error()? {
+        libc::EMFILE => {
+            // Counted without opening anything, as there's nothing left to
+            // open with
+            let highest = files.unwrap_or(1024).min(1 << 16) as RawFd;
+            let open = (0..highest).filter(|&fd| fd_is_open(fd)).count();
+            Some(SpawnLimit::OpenFiles { open, limit: files })
+        }
+        libc::ENOMEM => Some(SpawnLimit::Memory { limit: memory }),
+        libc::E2BIG => Some(SpawnLimit::ArgSize),
+        _ => None,
+    }
+}
+
+/// Run `body` in a forked copy of the shell, exiting with the status it
+/// returns. Always forks here; other platforms may return `None`, leaving the
+/// caller to run it in-process.
+pub(crate) fn fork_subshell(
+    stdio: &Stdio,
+    group: ProcessGroup,
+    body: impl FnOnce() -> i32,
+) -> IOResult<Option<Process>> {
+    match fork()? {
+        ForkReturn::Child => {
+            enter_group(group);
+            reset_sigpipe();
+
+            let status = match install_stdio(stdio) {
+                Ok(()) => body(),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            };
+            let _ = io::stdout().flush();
+            unsafe { libc::_exit(status) }
+        }
+        ForkReturn::Parent(pid) => {
+            place_in_group(pid, group)?;
+            Ok(Some(Process {
+                pid,
+                leader: matches!(group, ProcessGroup::Lead | ProcessGroup::Background),
+                usage: None,
+            }))
+        }
+    }
+}
+
+/// Points the shell's own fds 0, 1 and 2 at the files in a [`Stdio`] until
+/// dropped, for builtins run with redirections.
+pub(crate) struct StdRedirect {
+    saved: Vec<(RawFd, OwnedFd)>,
+}
+
+pub(crate) fn redirect_std(stdio: &Stdio) -> IOResult<StdRedirect> {
+    let _ = io::stdout().flush();
+
+    let mut guard = StdRedirect { saved: Vec::new() };
+    let streams = [&stdio.stdin, &stdio.stdout, &stdio.stderr];
+    for (fd, file) in streams.into_iter().enumerate() {
+        if let Some(file) = file {
+            let fd = fd as RawFd;
+            let saved = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?;
+            guard.saved.push((fd, saved));
+            dup2(file.as_raw_fd(), fd)?;
+        }
+    }
+    Ok(guard)
+}
+
+impl Drop for StdRedirect {
+    fn drop(&mut self) {
+        // What a closed pipe wouldn't take would otherwise stay buffered
+        // and come out on the restored stdout, so it goes to /dev/null
+        let redirected = self.saved.iter().any(|(fd, _)| *fd == 1);
+        if io::stdout().flush().is_err() && redirected {
+            if let Ok(null) = File::options().write(true).open("/dev/null") {
+                let _ = dup2(null.as_raw_fd(), 1);
+                let _ = io::stdout().flush();
+            }
+        }
+        for (fd, saved) in self.saved.drain(..).rev() {
+            let _ = dup2(saved.as_raw_fd(), fd);
+        }
+    }
+}
+
+/// Whether the shell has `fd` open.
+pub(crate) fn is_open(fd: i32) -> bool {
+    fd_is_open(fd)
+}
+
+/// Put `file` on each of `fds` for good, as `exec 3< file` does. Past the
+/// standard three they're closed on exec, so programs the shell runs don't
+/// inherit them.
+pub(crate) fn install_fd(file: File, fds: &[i32]) -> IOResult<()> {
+    let _ = io::stdout().flush();
+    for &fd in fds {
+        // Opening it may well have taken the lowest free descriptor, which
+        // is the one asked for
+        if fd != file.as_raw_fd() {
+            dup2(file.as_raw_fd(), fd)?;
+        }
+        set_cloexec(fd, fd > 2)?;
+    }
+    if fds.contains(&file.as_raw_fd()) {
+        let _ = OwnedFd::from(file).into_raw_fd();
+    }
+    Ok(())
+}
+
+pub(crate) fn close_fd(fd: i32) -> IOResult<()> {
+    close(fd)
+}
+
+pub(crate) fn fd_of(file: &File) -> Option<i32> {
+    Some(file.as_raw_fd())
+}
+
+/// Mark whatever the shell was started with open past the standard three to
+/// be closed on exec, so that it doesn't leak into every program it runs.
+pub(crate) fn close_inherited_on_exec() {
+    // Listing the directory opens a descriptor of its own, which is closed
+    // on exec already
+    let Ok(entries) = std::fs::read_dir("/dev/fd") else {
+        return;
+    };
+    let fds: Vec<RawFd> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    for fd in fds.into_iter().filter(|fd| *fd > 2) {
+        let _ = set_cloexec(fd, true);
+    }
+}
+
+/// A file for one of the shell's open fds, which mustn't be closed when
+/// dropped.
+pub(crate) fn borrow_fd(fd: i32) -> IOResult<ManuallyDrop<File>> {
+    if !fd_is_open(fd) {
+        return Err(IOError::new(
+            IOErrorKind::InvalidInput,
+            format!("{fd}: invalid file descriptor"),
+        ));
+    }
+    Ok(ManuallyDrop::new(unsafe { File::from_raw_fd(fd) }))
+}
+
+/// A file for wherever the shell's stderr goes now, which stays put if fd 2
+/// is pointed somewhere else. It's closed on exec.
+pub(crate) fn clone_stderr() -> IOResult<File> {
+    let fd = unsafe { BorrowedFd::borrow_raw(2) }.try_clone_to_owned()?;
+    Ok(File::from(fd))
+}
+
+/// Send SIGCONT to a stopped job, waiting for it if it's being brought to the
+/// foreground.
+pub(crate) fn continue_job(pgid: u32, foreground: bool) -> IOResult<Option<WaitStatus>> {
+    let pgid = pgid as pid_t;
+
+    if foreground {
+        tcsetpgrp(terminal_fd(), pgid)?;
+    }
+    killpg(pgid, libc::SIGCONT)?;
+
+    if foreground {
+        let status = wait_for(pgid);
+        reclaim_terminal()?;
+        status.map(|res| Some(res.map_or(WaitStatus::Unknown, WaitStatus::from)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Send SIGHUP to a job, and SIGCONT so that it sees it even if it's
+/// stopped, as when the shell exits with `huponexit` set.
+pub(crate) fn hang_up(pgid: u32) -> IOResult<()> {
+    killpg(pgid as pid_t, libc::SIGHUP)?;
+    killpg(pgid as pid_t, libc::SIGCONT)
+}
+
+/// Collect the status changes of any children without blocking.
+pub(crate) fn reap_children() -> Vec<(u32, WaitStatus)> {
+    let mut changed = Vec::new();
+    while let Ok(Some(res)) = waitpid(-1, libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED) {
+        changed.push((res.pid as u32, res.status));
+    }
+    changed
+}
+
+/// Every process in the group `pgid`, or the process `pgid` without job
+/// control, and everything they started even if it's left the group, from
+/// `/proc`. Without `/proc` there's nothing.
+pub(crate) fn job_processes(pgid: u32) -> Vec<ProcessInfo> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let ticks = sysconf(libc::_SC_CLK_TCK).unwrap_or(100).max(1);
+    let all: Vec<(u32, ProcessInfo)> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| read_stat(pid, ticks))
+        .collect();
+
+    let mut found: Vec<ProcessInfo> = all
+        .iter()
+        .filter(|(group, info)| *group == pgid || info.pid == pgid)
+        .map(|(_, info)| info.clone())
+        .collect();
+    // Descendants, however deep, until a pass finds no more
+    loop {
+        let more: Vec<ProcessInfo> = all
+            .iter()
+            .map(|(_, info)| info)
+            .filter(|info| found.iter().all(|known| known.pid != info.pid))
+            .filter(|info| found.iter().any(|known| known.pid == info.parent))
+            .cloned()
+            .collect();
+        if more.is_empty() {
+            break;
+        }
+        found.extend(more);
+    }
+    found.sort_by_key(|info| info.pid);
+    found
+}
+
+/// A process's group and what `jobs -t` shows of it, from
+/// `/proc/<pid>/stat` and its command line.
+fn read_stat(pid: u32, ticks: u64) -> Option<(u32, ProcessInfo)> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The name is in parentheses, and may itself hold spaces or parentheses
+    let (name, rest) = stat.split_once('(')?.1.rsplit_once(')')?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let state = match *fields.first()? {
+        "R" => "running",
+        "D" => "blocked",
+        "T" | "t" => "stopped",
+        "Z" => "zombie",
+        _ => "sleeping",
+    };
+    let parent = fields.get(1)?.parse().ok()?;
+    let group = fields.get(2)?.parse().ok()?;
+    let used: u64 = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+
+    let cmdline = fs::read(format!("/proc/{pid}/cmdline")).unwrap_or_default();
+    let args: Vec<String> = cmdline
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    let command = match args.is_empty() {
+        true => format!("[{name}]"),
+        false => args.join(" "),
+    };
+
+    let io = fs::read_to_string(format!("/proc/{pid}/io")).unwrap_or_default();
+    let written = io
+        .lines()
+        .find_map(|line| line.strip_prefix("wchar:"))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0);
+
+    let info = ProcessInfo {
+        pid,
+        parent,
+        state,
+        cpu: Duration::from_secs_f64(used as f64 / ticks as f64),
+        written,
+        command,
+    };
+    Some((group, info))
+}
+
+pub(crate) fn format_time(format: &str, time: i64) -> Option<String> {
+    safe_wrappers::strftime(format, time)
+}
+
+pub(crate) fn terminal_width() -> Option<usize> {
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+    let res = unsafe { libc::ioctl(io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &raw mut size) };
+    (res == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// Puts the terminal into non-canonical, no-echo mode until dropped. Ctrl-C,
+/// Ctrl-Z and Ctrl-\\ come through as keys rather than signals, so the editor
+/// deals with them itself.
+pub(crate) struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> IOResult<Self> {
+        Self::enable_on(terminal_fd())
+    }
+
+    /// Raw mode on a terminal from [`open_terminal`], which must outlive it.
+    pub fn enable_for(input: &File, _output: &File) -> IOResult<Self> {
+        Self::enable_on(input.as_raw_fd())
+    }
+
+    fn enable_on(fd: RawFd) -> IOResult<Self> {
+        let original = tcgetattr(fd)?;
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        tcsetattr(fd, &raw)?;
+
+        Ok(RawMode { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tcsetattr(self.fd, &self.original);
+    }
+}
+
+/// The controlling terminal, to read keys from and draw on, as input and
+/// output.
+pub(crate) fn open_terminal() -> IOResult<(File, File)> {
+    let tty = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    Ok((tty.try_clone()?, tty))
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn note_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catches SIGINT sent to the shell itself until dropped, so a builtin that
+/// runs until told to stop can notice Ctrl-C.
+pub(crate) struct InterruptGuard {
+    previous: libc::sighandler_t,
+}
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let handler = note_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        InterruptGuard {
+            previous: set_signal_handler(libc::SIGINT, handler),
+        }
+    }
+
+    pub fn interrupted(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        set_signal_handler(libc::SIGINT, self.previous);
+    }
+}
+
+extern "C" fn note_resize(_signal: libc::c_int) {}
+
+/// Catches SIGWINCH until dropped, so that the editor's wait for a key is cut
+/// short when the window is resized and it can redraw to fit.
+pub(crate) struct ResizeGuard {
+    previous: Option<libc::sigaction>,
+}
+
+impl ResizeGuard {
+    pub fn new() -> Self {
+        let handler = note_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        ResizeGuard {
+            previous: set_interrupting_handler(libc::SIGWINCH, handler).ok(),
+        }
+    }
+}
+
+impl Drop for ResizeGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = &self.previous {
+            let _ = restore_signal_action(libc::SIGWINCH, previous);
+        }
+    }
+}
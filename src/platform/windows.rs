@@ -0,0 +1,438 @@
+use std::env;
+use std::ffi::c_void;
+use std::fs::File;
+use std::io::{self, Error as IOError, ErrorKind as IOErrorKind, Result as IOResult, Write};
+use std::mem::ManuallyDrop;
+use std::os::windows::io::{AsHandle, AsRawHandle, FromRawHandle, OwnedHandle};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{ProcessGroup, ProcessInfo, ResourceUsage, SpawnLimit, Stdio, WaitStatus};
+
+type Handle = *mut c_void;
+
+const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+
+const CTRL_C_EVENT: u32 = 0;
+
+/// The signals the C runtime knows, by number and name without the `SIG`.
+pub(crate) const SIGNALS: &[(i32, &str)] = &[
+    (2, "INT"),
+    (4, "ILL"),
+    (8, "FPE"),
+    (11, "SEGV"),
+    (15, "TERM"),
+    (21, "BREAK"),
+    (22, "ABRT"),
+];
+
+const ENABLE_PROCESSED_INPUT: u32 = 0x0001;
+const ENABLE_LINE_INPUT: u32 = 0x0002;
+const ENABLE_ECHO_INPUT: u32 = 0x0004;
+const ENABLE_VIRTUAL_TERMINAL_INPUT: u32 = 0x0200;
+const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+#[repr(C)]
+#[derive(Default)]
+struct Coord {
+    x: i16,
+    y: i16,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct SmallRect {
+    left: i16,
+    top: i16,
+    right: i16,
+    bottom: i16,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ConsoleScreenBufferInfo {
+    size: Coord,
+    cursor_position: Coord,
+    attributes: u16,
+    window: SmallRect,
+    maximum_window_size: Coord,
+}
+
+#[link(name = "kernel32")]
+unsafe extern "system" {
+    fn GetStdHandle(std_handle: u32) -> Handle;
+    fn SetStdHandle(std_handle: u32, handle: Handle) -> i32;
+    fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+    fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+    fn GetConsoleScreenBufferInfo(console: Handle, info: *mut ConsoleScreenBufferInfo) -> i32;
+    fn SetConsoleCtrlHandler(
+        handler: Option<unsafe extern "system" fn(u32) -> i32>,
+        add: i32,
+    ) -> i32;
+}
+
+/// NTFS and FAT are case-insensitive, so `*.TXT` should match `notes.txt`.
+pub(crate) const GLOB_CASE_SENSITIVE: bool = false;
+
+pub(crate) fn executable_extensions() -> Vec<String> {
+    let pathext = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+
+    // An explicit extension (`python.exe`) is tried before any appended ones
+    std::iter::once(String::new())
+        .chain(
+            pathext
+                .split(';')
+                .filter(|ext| !ext.is_empty())
+                .map(str::to_string),
+        )
+        .collect()
+}
+
+pub(crate) fn is_executable(path: &Path) -> bool {
+    path.metadata().is_ok_and(|meta| meta.is_file())
+}
+
+/// Windows has no process groups or terminal ownership to hand around, so the
+/// shell runs without job control there.
+pub(crate) fn init_job_control() -> IOResult<()> {
+    Err(IOError::new(
+        IOErrorKind::Unsupported,
+        "job control is not supported on Windows",
+    ))
+}
+
+pub(crate) struct Process {
+    child: process::Child,
+}
+
+impl Process {
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn wait(&mut self) -> IOResult<WaitStatus> {
+        let status = self.child.wait()?;
+        Ok(WaitStatus::Exited(status.code().unwrap_or(1)))
+    }
+
+    pub fn try_wait(&mut self) -> IOResult<Option<WaitStatus>> {
+        let status = self.child.try_wait()?;
+        Ok(status.map(|status| WaitStatus::Exited(status.code().unwrap_or(1))))
+    }
+
+    /// What the process used isn't tracked here.
+    pub fn usage(&self) -> Option<ResourceUsage> {
+        None
+    }
+
+    /// There are no signals to ask nicely with, so this always kills.
+    pub fn terminate(&mut self, _force: bool) -> IOResult<()> {
+        self.child.kill()
+    }
+}
+
+fn to_stdio(file: &Option<File>) -> IOResult<process::Stdio> {
+    match file {
+        Some(file) => Ok(process::Stdio::from(file.try_clone()?)),
+        None => Ok(process::Stdio::inherit()),
+    }
+}
+
+pub(crate) fn spawn(
+    args: &[String],
+    env: &[(String, String)],
+    stdio: &Stdio,
+    _group: ProcessGroup,
+) -> IOResult<Process> {
+    let program = super::find_executable(&args[0]).ok_or_else(|| {
+        IOError::new(
+            IOErrorKind::NotFound,
+            format!("{}: command not found", args[0]),
+        )
+    })?;
+
+    let child = process::Command::new(program)
+        .args(&args[1..])
+        .env_clear()
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(to_stdio(&stdio.stdin)?)
+        .stdout(to_stdio(&stdio.stdout)?)
+        .stderr(to_stdio(&stdio.stderr)?)
+        .spawn()?;
+
+    Ok(Process { child })
+}
+
+/// There's no `exec` here, so the program runs as a child and the shell
+/// exits with its status once it's done.
+pub(crate) fn replace_process(args: &[String], env: &[(String, String)]) -> IOError {
+    match spawn(args, env, &Stdio::default(), ProcessGroup::Inherit).and_then(|mut p| p.wait()) {
+        Ok(status) => process::exit(status.code()),
+        Err(e) => e,
+    }
+}
+
+/// A command line can be at most 32767 characters, all told.
+pub(crate) fn arg_limits() -> (Option<u64>, Option<u64>) {
+    (Some(32767), None)
+}
+
+/// Failures to start a program here come as Windows error codes, none of
+/// which are told apart yet.
+pub(crate) fn spawn_limit(_e: &IOError) -> Option<SpawnLimit> {
+    None
+}
+
+/// There's no `fork` here, so subshells always run in-process.
+pub(crate) fn fork_subshell(
+    _stdio: &Stdio,
+    _group: ProcessGroup,
+    _body: impl FnOnce() -> i32,
+) -> IOResult<Option<Process>> {
+    Ok(None)
+}
+
+pub(crate) fn reclaim_terminal() -> IOResult<()> {
+    Ok(())
+}
+
+pub(crate) fn pipe() -> IOResult<(File, File)> {
+    let (reader, writer) = io::pipe()?;
+    Ok((
+        File::from(OwnedHandle::from(reader)),
+        File::from(OwnedHandle::from(writer)),
+    ))
+}
+
+/// Points the shell's standard handles at the files in a [`Stdio`] until
+/// dropped, for builtins run with redirections.
+pub(crate) struct StdRedirect {
+    saved: Vec<(u32, Handle)>,
+}
+
+pub(crate) fn redirect_std(stdio: &Stdio) -> IOResult<StdRedirect> {
+    let _ = io::stdout().flush();
+
+    let mut guard = StdRedirect { saved: Vec::new() };
+    let streams = [
+        (STD_INPUT_HANDLE, &stdio.stdin),
+        (STD_OUTPUT_HANDLE, &stdio.stdout),
+        (STD_ERROR_HANDLE, &stdio.stderr),
+    ];
+    for (which, file) in streams {
+        if let Some(file) = file {
+            guard.saved.push((which, unsafe { GetStdHandle(which) }));
+            if unsafe { SetStdHandle(which, file.as_raw_handle()) } == 0 {
+                return Err(IOError::last_os_error());
+            }
+        }
+    }
+    Ok(guard)
+}
+
+impl Drop for StdRedirect {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        for (which, handle) in self.saved.drain(..).rev() {
+            unsafe { SetStdHandle(which, handle) };
+        }
+    }
+}
+
+pub(crate) fn is_open(fd: i32) -> bool {
+    (0..=2).contains(&fd)
+}
+
+fn no_fds(fd: i32) -> IOError {
+    IOError::new(
+        IOErrorKind::Unsupported,
+        format!("{fd}: redirecting descriptors is not supported on Windows"),
+    )
+}
+
+pub(crate) fn install_fd(_file: File, fds: &[i32]) -> IOResult<()> {
+    Err(no_fds(fds[0]))
+}
+
+pub(crate) fn close_fd(fd: i32) -> IOResult<()> {
+    Err(no_fds(fd))
+}
+
+/// Files are handles rather than numbered descriptors here.
+pub(crate) fn fd_of(_file: &File) -> Option<i32> {
+    None
+}
+
+/// Handles aren't inherited unless asked for, so there's nothing to do.
+pub(crate) fn close_inherited_on_exec() {}
+
+/// Only standard input has a file descriptor number here.
+pub(crate) fn borrow_fd(fd: i32) -> IOResult<ManuallyDrop<File>> {
+    if fd != 0 {
+        return Err(IOError::new(
+            IOErrorKind::Unsupported,
+            format!("{fd}: only file descriptor 0 is supported on Windows"),
+        ));
+    }
+    let handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
+    Ok(ManuallyDrop::new(unsafe { File::from_raw_handle(handle) }))
+}
+
+/// A file for wherever the shell's stderr goes now, which stays put if the
+/// standard handle is pointed somewhere else.
+pub(crate) fn clone_stderr() -> IOResult<File> {
+    let handle = io::stderr().as_handle().try_clone_to_owned()?;
+    Ok(File::from(handle))
+}
+
+pub(crate) fn continue_job(_pgid: u32, _foreground: bool) -> IOResult<Option<WaitStatus>> {
+    init_job_control().map(|_| None)
+}
+
+pub(crate) fn hang_up(_pgid: u32) -> IOResult<()> {
+    init_job_control()
+}
+
+pub(crate) fn reap_children() -> Vec<(u32, WaitStatus)> {
+    Vec::new()
+}
+
+pub(crate) fn job_processes(_pgid: u32) -> Vec<ProcessInfo> {
+    Vec::new()
+}
+
+pub(crate) fn format_time(_format: &str, _time: i64) -> Option<String> {
+    None
+}
+
+pub(crate) fn terminal_width() -> Option<usize> {
+    let mut info = ConsoleScreenBufferInfo::default();
+    let output = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
+    if unsafe { GetConsoleScreenBufferInfo(output, &raw mut info) } == 0 {
+        return None;
+    }
+    Some((info.window.right - info.window.left + 1) as usize)
+}
+
+fn console_mode(handle: Handle) -> IOResult<u32> {
+    let mut mode = 0;
+    if unsafe { GetConsoleMode(handle, &raw mut mode) } == 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(mode)
+    }
+}
+
+fn set_console_mode(handle: Handle, mode: u32) -> IOResult<()> {
+    if unsafe { SetConsoleMode(handle, mode) } == 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Turns off line buffering, echo and Ctrl-C handling on the console, and
+/// switches both directions to VT sequences so the editor can use the same
+/// escape codes as on a UNIX terminal.
+pub(crate) struct RawMode {
+    input: Handle,
+    output: Handle,
+    original_input: u32,
+    original_output: u32,
+}
+
+impl RawMode {
+    pub fn enable() -> IOResult<Self> {
+        let input = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
+        let output = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
+        Self::enable_on(input, output)
+    }
+
+    /// Raw mode on a console from [`open_terminal`], which must outlive it.
+    pub fn enable_for(input: &File, output: &File) -> IOResult<Self> {
+        Self::enable_on(input.as_raw_handle(), output.as_raw_handle())
+    }
+
+    fn enable_on(input: Handle, output: Handle) -> IOResult<Self> {
+        let original_input = console_mode(input)?;
+        let original_output = console_mode(output)?;
+
+        set_console_mode(
+            input,
+            (original_input & !(ENABLE_PROCESSED_INPUT | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT))
+                | ENABLE_VIRTUAL_TERMINAL_INPUT,
+        )?;
+        set_console_mode(output, original_output | ENABLE_VIRTUAL_TERMINAL_PROCESSING)?;
+
+        Ok(RawMode {
+            input,
+            output,
+            original_input,
+            original_output,
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = set_console_mode(self.input, self.original_input);
+        let _ = set_console_mode(self.output, self.original_output);
+    }
+}
+
+/// The console, to read keys from and draw on, as input and output.
+pub(crate) fn open_terminal() -> IOResult<(File, File)> {
+    let open = |name: &str| {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(name)
+    };
+    Ok((open("CONIN$")?, open("CONOUT$")?))
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "system" fn note_interrupt(event: u32) -> i32 {
+    if event == CTRL_C_EVENT {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        1
+    } else {
+        0
+    }
+}
+
+/// Catches Ctrl-C on the console until dropped, so a builtin that runs until
+/// told to stop can notice it.
+pub(crate) struct InterruptGuard;
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        unsafe { SetConsoleCtrlHandler(Some(note_interrupt), 1) };
+        InterruptGuard
+    }
+
+    pub fn interrupted(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        unsafe { SetConsoleCtrlHandler(Some(note_interrupt), 0) };
+    }
+}
+
+/// The console doesn't tell a blocked read about resizes, so there's nothing
+/// to catch; the editor fits the new width at the next keypress.
+pub(crate) struct ResizeGuard;
+
+impl ResizeGuard {
+    pub fn new() -> Self {
+        ResizeGuard
+    }
+}
@@ -0,0 +1,48 @@
+//! Plugins, which see each command typed at the prompt after it's parsed and
+//! before it runs, and may rewrite it or stop it from running: the place for
+//! things like running commands in tmux, offering to retry with sudo, or
+//! checking commands against a site policy.
+//!
+//! Plugins are compiled in by programs embedding the shell, which register
+//! them with [`ShellState::add_plugin`] or pass them to
+//! [`crate::repl::run_with_plugins`]. Commands from scripts, functions and
+//! hooks don't go through them.
+
+use crate::parser::Command;
+use crate::shell::ShellState;
+
+pub trait Plugin {
+    /// The name errors from the plugin are reported under.
+    fn name(&self) -> &str;
+
+    /// Look at `command`, and maybe change it, before it runs. An error
+    /// stops it from running, and is reported as the plugin's.
+    fn transform(&mut self, shell: &ShellState, command: &mut Command) -> Result<(), String>;
+}
+
+impl ShellState {
+    /// Add a plugin, to run after those already added.
+    pub fn add_plugin(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin);
+    }
+
+    /// The names of the plugins, in the order they run.
+    pub fn plugin_names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|plugin| plugin.name())
+    }
+}
+
+/// Pass `command` through each plugin in turn, stopping at the first error,
+/// which is given with the plugin's name.
+pub(crate) fn transform(shell: &mut ShellState, command: &mut Command) -> Result<(), String> {
+    let mut plugins = std::mem::take(&mut shell.plugins);
+    let res = plugins.iter_mut().try_for_each(|plugin| {
+        plugin
+            .transform(shell, command)
+            .map_err(|e| format!("{}: {}", plugin.name(), e))
+    });
+    // Keep any a plugin somehow added meanwhile
+    plugins.append(&mut shell.plugins);
+    shell.plugins = plugins;
+    res
+}
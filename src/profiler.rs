@@ -0,0 +1,78 @@
+//! Timing for scripts run with `--profile`: how often each command ran and
+//! how long it took, reported slowest first when the script is done.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::parser::{Arg, Command, Compound};
+use crate::shell::Location;
+
+#[derive(Debug, Default)]
+struct Timing {
+    calls: usize,
+    total: Duration,
+}
+
+/// The time spent in each command, keyed by where it is and what it runs.
+/// A command's time includes everything it ran, such as the commands in a
+/// function it called or a subshell it started.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    timings: HashMap<(Location, String), Timing>,
+}
+
+/// What a pipeline runs, for the report: the command name of each stage.
+fn describe(cmd: &Command) -> String {
+    let mut names = Vec::new();
+    let mut stage = Some(cmd);
+    while let Some(cmd) = stage {
+        names.push(match (cmd.compound.as_deref(), cmd.argv.first()) {
+            (Some(Compound::Group(_)), _) => "{ ... }".to_string(),
+            (Some(Compound::Subshell(_)), _) => "( ... )".to_string(),
+            (Some(Compound::FunctionDef { name, .. }), _) => format!("{name}()"),
+            (Some(Compound::Spawn { name, .. }), _) => format!("spawn {name}"),
+            (None, Some(Arg::Word(name))) => name.clone(),
+            (None, Some(_)) => "...".to_string(),
+            (None, None) => match cmd.assignments.first() {
+                Some(assignment) => format!("{}=", assignment.name),
+                None => String::new(),
+            },
+        });
+        stage = cmd.pipe_to.as_ref().map(|pipe| &*pipe.target);
+    }
+    names.join(" | ")
+}
+
+impl Profiler {
+    /// Count one run of the pipeline `cmd` at `location`, which took `elapsed`.
+    pub fn record(&mut self, location: Location, cmd: &Command, elapsed: Duration) {
+        let timing = self.timings.entry((location, describe(cmd))).or_default();
+        timing.calls += 1;
+        timing.total += elapsed;
+    }
+
+    /// A table of the commands that ran, the most time first.
+    pub fn report(&self) -> String {
+        let mut rows: Vec<_> = self.timings.iter().collect();
+        rows.sort_by(|(a_key, a), (b_key, b)| b.total.cmp(&a.total).then(a_key.cmp(b_key)));
+
+        let mut report = format!(
+            "{:>12} {:>8} {:>12}  {:<20} command\n",
+            "total ms", "calls", "ms per call", "location"
+        );
+        for ((location, command), timing) in rows {
+            let total = timing.total.as_secs_f64() * 1000.0;
+            let _ = writeln!(
+                report,
+                "{:>12.3} {:>8} {:>12.3}  {:<20} {}",
+                total,
+                timing.calls,
+                total / timing.calls as f64,
+                location.to_string(),
+                command
+            );
+        }
+        report
+    }
+}
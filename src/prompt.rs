@@ -0,0 +1,131 @@
+//! The prompt, made from `$PS1` with these escapes, or `> ` if it's unset:
+//!
+//! - `\u`: the user's name
+//! - `\w`: the current directory, with `$HOME` shortened to `~`
+//! - `\W`: the last part of the current directory
+//! - `\?`: the status of the last command
+//! - `\V`: the environments the shell is running inside, such as a Python
+//!   virtualenv, a conda environment or a nix-shell, each as `(name) `
+//! - `\\`: a backslash
+
+use std::path::Path;
+
+use crate::shell::ShellState;
+
+const DEFAULT: &str = "> ";
+
+/// Something like a virtualenv that a shell is started inside of, which
+/// it's easy to forget about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    pub kind: &'static str,
+    pub name: String,
+}
+
+/// The environments the shell is inside, worked out from the variables
+/// their activation scripts set.
+pub fn environments(shell: &ShellState) -> Vec<Environment> {
+    let var = |name| shell.variables.get(name).filter(|value| !value.is_empty());
+    let mut found = Vec::new();
+
+    if let Some(venv) = var("VIRTUAL_ENV") {
+        // Newer virtualenvs say what they'd like shown, sometimes already
+        // in parentheses
+        let name = var("VIRTUAL_ENV_PROMPT")
+            .map(|prompt| prompt.trim().trim_start_matches('(').trim_end_matches(')'))
+            .filter(|prompt| !prompt.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| {
+                Path::new(venv)
+                    .file_name()
+                    .map_or(venv.to_string(), |name| name.to_string_lossy().into_owned())
+            });
+        found.push(Environment {
+            kind: "virtualenv",
+            name,
+        });
+    }
+    if let Some(name) = var("CONDA_DEFAULT_ENV") {
+        found.push(Environment {
+            kind: "conda",
+            name: name.to_string(),
+        });
+    }
+    if var("IN_NIX_SHELL").is_some() {
+        found.push(Environment {
+            kind: "nix-shell",
+            name: "nix-shell".to_string(),
+        });
+    }
+    found
+}
+
+/// The prompt to show before reading a line.
+pub fn render(shell: &ShellState) -> String {
+    let Some(ps1) = shell.variables.get("PS1") else {
+        return DEFAULT.to_string();
+    };
+
+    let mut prompt = String::new();
+    let mut chars = ps1.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => {
+                let user = shell
+                    .variables
+                    .get("USER")
+                    .or_else(|| shell.variables.get("USERNAME"));
+                prompt.push_str(user.unwrap_or_default());
+            }
+            Some('w') => prompt.push_str(&cwd(shell, false)),
+            Some('W') => prompt.push_str(&cwd(shell, true)),
+            Some('?') => prompt.push_str(&shell.last_status.to_string()),
+            Some('V') => {
+                for env in environments(shell) {
+                    prompt.push_str(&format!("({}) ", env.name));
+                }
+            }
+            Some('\\') => prompt.push('\\'),
+            // Anything else is left as it was
+            Some(other) => {
+                prompt.push('\\');
+                prompt.push(other);
+            }
+            None => prompt.push('\\'),
+        }
+    }
+    prompt
+}
+
+/// `$PWD`, either with `$HOME` at the start shortened to `~` or cut down
+/// to its last part.
+fn cwd(shell: &ShellState, last: bool) -> String {
+    let pwd = shell
+        .variables
+        .get("PWD")
+        .map(str::to_string)
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    let home = shell.variables.get("HOME").filter(|home| !home.is_empty());
+
+    if home.is_some_and(|home| pwd == home) {
+        return "~".to_string();
+    }
+    if last {
+        return Path::new(&pwd)
+            .file_name()
+            .map_or(pwd.clone(), |name| name.to_string_lossy().into_owned());
+    }
+    match home.and_then(|home| pwd.strip_prefix(home)) {
+        Some(rest) if rest.starts_with('/') => format!("~{rest}"),
+        _ => pwd,
+    }
+}
@@ -0,0 +1,520 @@
+//! Regular expressions, for `[[ string =~ regex ]]`. They're matched here
+//! rather than with the platform's `regcomp` so they work the same on every
+//! platform.
+//!
+//! The syntax is POSIX's extended one: `.`, bracket expressions with ranges
+//! and `[:class:]`es, `^` and `$`, groups, `|`, and the `*`, `+`, `?` and
+//! `{n,m}` repetitions, plus `\d`, `\w` and `\s` and their capitals for the
+//! opposite. Alternatives are tried left to right as in Perl, rather than
+//! for the longest match as POSIX has it. Matching backtracks but never
+//! tries the same state twice, so it takes time in proportion to the length
+//! of the regex times the length of the string.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::nesting::{Nested, TooDeep};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError(String);
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<TooDeep> for RegexError {
+    fn from(too_deep: TooDeep) -> Self {
+        RegexError(format!("regex {too_deep}"))
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, RegexError> {
+    Err(RegexError(message.into()))
+}
+
+/// `text` as a regex that matches just that.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\.^$|()[]{}*+?".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The most `{n,m}` may repeat something, as POSIX's `RE_DUP_MAX`.
+const MAX_REPEAT: usize = 255;
+/// How many instructions a compiled regex may have, so nested repetitions
+/// can't eat all the memory there is.
+const MAX_PROGRAM: usize = 10_000;
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Named(fn(char) -> bool),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+impl Class {
+    fn named(class: fn(char) -> bool, negated: bool) -> Class {
+        Class {
+            negated,
+            items: vec![ClassItem::Named(class)],
+        }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        let matched = self.items.iter().any(|item| match *item {
+            ClassItem::Range(from, to) => (from..=to).contains(&c),
+            ClassItem::Named(class) => class(c),
+        });
+        matched != self.negated
+    }
+}
+
+/// What `[:name:]` in a bracket expression matches.
+fn named_class(name: &str) -> Option<fn(char) -> bool> {
+    let class: fn(char) -> bool = match name {
+        "alnum" => char::is_alphanumeric,
+        "alpha" => char::is_alphabetic,
+        "blank" => |c| c == ' ' || c == '\t',
+        "cntrl" => char::is_control,
+        "digit" => |c| c.is_ascii_digit(),
+        "graph" => |c| !c.is_whitespace() && !c.is_control(),
+        "lower" => char::is_lowercase,
+        "print" => |c| !c.is_control(),
+        "punct" => |c| c.is_ascii_punctuation(),
+        "space" => char::is_whitespace,
+        "upper" => char::is_uppercase,
+        "xdigit" => |c| c.is_ascii_hexdigit(),
+        _ => return None,
+    };
+    Some(class)
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Group(usize, Box<Node>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn alternation(&mut self) -> Result<Node, RegexError> {
+        let mut alternatives = vec![self.concatenation()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.concatenation()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            Node::Alt(alternatives)
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while !matches!(self.peek(), None | Some('|') | Some(')')) {
+            let atom = self.atom()?;
+            nodes.push(self.repetitions(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, RegexError> {
+        let node = match self.bump() {
+            Some('(') => {
+                let nested = Nested::enter()?;
+                self.groups += 1;
+                let index = self.groups;
+                let node = self.alternation()?;
+                drop(nested);
+                if self.bump() != Some(')') {
+                    return error("unmatched (");
+                }
+                Node::Group(index, Box::new(node))
+            }
+            Some('.') => Node::Any,
+            Some('^') => Node::Start,
+            Some('$') => Node::End,
+            Some('[') => self.bracket()?,
+            Some('\\') => match self.bump() {
+                None => return error("trailing backslash"),
+                Some('d') => Node::Class(Class::named(|c| c.is_ascii_digit(), false)),
+                Some('D') => Node::Class(Class::named(|c| c.is_ascii_digit(), true)),
+                Some('w') => Node::Class(Class::named(is_word, false)),
+                Some('W') => Node::Class(Class::named(is_word, true)),
+                Some('s') => Node::Class(Class::named(char::is_whitespace, false)),
+                Some('S') => Node::Class(Class::named(char::is_whitespace, true)),
+                Some(c) => Node::Char(c),
+            },
+            Some(c @ ('*' | '+' | '?')) => return error(format!("nothing for {c} to repeat")),
+            Some(c) => Node::Char(c),
+            None => unreachable!("concatenation stops at the end"),
+        };
+        Ok(node)
+    }
+
+    /// A bracket expression, after its `[`.
+    fn bracket(&mut self) -> Result<Node, RegexError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(c) = self.bump() else {
+                return error("unmatched [");
+            };
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            if c == '[' && self.peek() == Some(':') {
+                let start = self.pos + 1;
+                let Some(end) = (start..self.chars.len().saturating_sub(1))
+                    .find(|&i| self.chars[i] == ':' && self.chars[i + 1] == ']')
+                else {
+                    return error("unmatched [:");
+                };
+                let name: String = self.chars[start..end].iter().collect();
+                let Some(class) = named_class(&name) else {
+                    return error(format!("unknown class [:{name}:]"));
+                };
+                items.push(ClassItem::Named(class));
+                self.pos = end + 2;
+            } else if self.peek() == Some('-')
+                && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']')
+            {
+                let to = self.chars[self.pos + 1];
+                self.pos += 2;
+                if to < c {
+                    return error(format!("invalid range {c}-{to}"));
+                }
+                items.push(ClassItem::Range(c, to));
+            } else {
+                items.push(ClassItem::Range(c, c));
+            }
+        }
+        Ok(Node::Class(Class { negated, items }))
+    }
+
+    /// Each repetition puts `node` a level further down, to be compiled a
+    /// level further down the stack, so each counts as a level of nesting.
+    fn repetitions(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        let mut levels = Vec::new();
+        loop {
+            let (min, max) = match self.peek() {
+                Some('{') => match self.bounds()? {
+                    Some(bounds) => bounds,
+                    // Not a repetition, so a literal `{`
+                    None => return Ok(node),
+                },
+                Some(c @ ('*' | '+' | '?')) => {
+                    self.pos += 1;
+                    match c {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    }
+                }
+                _ => return Ok(node),
+            };
+            levels.push(Nested::enter()?);
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+            };
+        }
+    }
+
+    /// `{n}`, `{n,}` or `{n,m}`, consumed only if it's one of those.
+    fn bounds(&mut self) -> Result<Option<(usize, Option<usize>)>, RegexError> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let Some(end) = rest.find('}') else {
+            return Ok(None);
+        };
+        let number = |s: &str| -> Option<usize> {
+            if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            Some(s.parse().unwrap_or(usize::MAX))
+        };
+        let bounds = match rest[..end].split_once(',') {
+            None => number(&rest[..end]).map(|n| (n, Some(n))),
+            Some((min, "")) => number(min).map(|min| (min, None)),
+            Some((min, max)) => number(min)
+                .zip(number(max))
+                .map(|(min, max)| (min, Some(max))),
+        };
+        let Some((min, max)) = bounds else {
+            return Ok(None);
+        };
+
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return error(format!("can't repeat more than {MAX_REPEAT} times"));
+        }
+        if max.is_some_and(|max| max < min) {
+            return error(format!("invalid repetition {{{}}}", &rest[..end]));
+        }
+        self.pos += 1 + rest[..end].chars().count() + 1;
+        Ok(Some((min, max)))
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// Record the position in this capture slot
+    Save(usize),
+    /// Carry on at the first, and if that fails, at the second
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), RegexError> {
+    if program.len() > MAX_PROGRAM {
+        return error("regex is too big");
+    }
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Group(index, node) => {
+            program.push(Inst::Save(2 * index));
+            compile(node, program)?;
+            program.push(Inst::Save(2 * index + 1));
+        }
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alt(alternatives) => {
+            let (last, rest) = alternatives
+                .split_last()
+                .expect("at least two alternatives");
+            let mut jumps = Vec::new();
+            for alternative in rest {
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(alternative, program)?;
+                jumps.push(program.len());
+                program.push(Inst::Jmp(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            compile(last, program)?;
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jmp(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+    groups: usize,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.peek().is_some() {
+            return error("unmatched )");
+        }
+
+        let mut program = vec![Inst::Save(0)];
+        compile(&node, &mut program)?;
+        program.push(Inst::Save(1));
+        program.push(Inst::Match);
+        Ok(Regex {
+            program,
+            groups: parser.groups,
+        })
+    }
+
+    /// Find the leftmost match in `text`, returning the byte range of the
+    /// whole match and then of each group, or `None` for groups that took no
+    /// part in it.
+    pub fn captures(&self, text: &str) -> Option<Vec<Option<Range<usize>>>> {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let offset = |pos: usize| chars.get(pos).map_or(text.len(), |&(i, _)| i);
+        // States that have been tried and failed, whichever position the
+        // match started from
+        let mut visited = vec![0u64; (self.program.len() * (chars.len() + 1)).div_ceil(64)];
+        for start in 0..=chars.len() {
+            let mut slots = vec![None; 2 * (self.groups + 1)];
+            if self.run(&chars, start, &mut visited, &mut slots) {
+                let captures = slots
+                    .chunks(2)
+                    .map(|pair| match *pair {
+                        [Some(from), Some(to)] => Some(offset(from)..offset(to)),
+                        _ => None,
+                    })
+                    .collect();
+                return Some(captures);
+            }
+        }
+        None
+    }
+
+    fn run(
+        &self,
+        chars: &[(usize, char)],
+        start: usize,
+        visited: &mut [u64],
+        slots: &mut [Option<usize>],
+    ) -> bool {
+        enum Job {
+            Try(usize, usize),
+            Restore(usize, Option<usize>),
+        }
+
+        let width = chars.len() + 1;
+        let mut stack = vec![Job::Try(0, start)];
+        while let Some(job) = stack.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::Try(pc, pos) => (pc, pos),
+                Job::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            loop {
+                let state = pc * width + pos;
+                if visited[state / 64] & (1 << (state % 64)) != 0 {
+                    break;
+                }
+                visited[state / 64] |= 1 << (state % 64);
+
+                let c = chars.get(pos).map(|&(_, c)| c);
+                let matched = match &self.program[pc] {
+                    Inst::Char(want) => c == Some(*want),
+                    Inst::Any => c.is_some(),
+                    Inst::Class(class) => c.is_some_and(|c| class.matches(c)),
+                    Inst::Start => {
+                        if pos != 0 {
+                            break;
+                        }
+                        pc += 1;
+                        continue;
+                    }
+                    Inst::End => {
+                        if pos != chars.len() {
+                            break;
+                        }
+                        pc += 1;
+                        continue;
+                    }
+                    Inst::Save(slot) => {
+                        stack.push(Job::Restore(*slot, slots[*slot]));
+                        slots[*slot] = Some(pos);
+                        pc += 1;
+                        continue;
+                    }
+                    Inst::Split(first, second) => {
+                        stack.push(Job::Try(*second, pos));
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Jmp(to) => {
+                        pc = *to;
+                        continue;
+                    }
+                    Inst::Match => return true,
+                };
+                if !matched {
+                    break;
+                }
+                pc += 1;
+                pos += 1;
+            }
+        }
+        false
+    }
+}
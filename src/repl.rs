@@ -0,0 +1,251 @@
+use std::env;
+use std::io::{self, ErrorKind as IOErrorKind, IsTerminal, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::debugger::Debugger;
+use crate::direnv::DirEnv;
+use crate::editor::Editor;
+use crate::history::{History, Redaction};
+use crate::notify;
+use crate::parser::Command;
+use crate::platform;
+use crate::plugin::Plugin;
+use crate::profiler::Profiler;
+use crate::prompt;
+use crate::shell::ShellState;
+use crate::universal::UniversalVars;
+
+const USAGE: &str = "usage: sigsh [--debug] [--profile] [--dry-run] [--strict] [script [arg ...]]";
+
+/// How to run a script given on the command line.
+#[derive(Default)]
+struct ScriptOptions {
+    debug: bool,
+    profile: bool,
+    dry_run: bool,
+    /// Run with the options in [`STRICT`](crate::options::STRICT) on
+    strict: bool,
+}
+
+/// Run the script named on the command line, or with none, read and run
+/// commands from stdin until EOF or `exit`. Returns the status to exit with.
+pub fn run() -> i32 {
+    run_with_plugins(Vec::new())
+}
+
+/// Run as [`run`] does, with `plugins` seeing each command typed at the
+/// prompt.
+pub fn run_with_plugins(plugins: Vec<Box<dyn Plugin>>) -> i32 {
+    let mut args = env::args().skip(1).peekable();
+    let mut options = ScriptOptions::default();
+    while let Some(flag) = args.next_if(|arg| arg.starts_with('-')) {
+        match flag.as_str() {
+            "--debug" => options.debug = true,
+            "--profile" => options.profile = true,
+            "--dry-run" => options.dry_run = true,
+            "--strict" => options.strict = true,
+            _ => {
+                eprintln!("sigsh: {flag}: unknown option\n{USAGE}");
+                return 2;
+            }
+        }
+    }
+    match args.next() {
+        Some(script) => run_script(Path::new(&script), args.collect(), options),
+        None if options.debug || options.profile || options.dry_run || options.strict => {
+            eprintln!("sigsh: no script to run\n{USAGE}");
+            2
+        }
+        None => interact(plugins),
+    }
+}
+
+/// Set the universal variables as exported variables. `load` already read
+/// the file, so this only has to copy the values in.
+fn copy_universal(shell: &mut ShellState) {
+    for (name, value) in shell.universal.iter() {
+        let _ = shell.variables.set(name, value);
+        shell.variables.export(name);
+    }
+}
+
+/// Run a script with `args` as its positional parameters.
+fn run_script(script: &Path, args: Vec<String>, options: ScriptOptions) -> i32 {
+    let mut shell = ShellState {
+        universal: UniversalVars::load(),
+        debugger: options.debug.then(Debugger::new),
+        profiler: options.profile.then(Profiler::default),
+        dry_run: options.dry_run,
+        ..ShellState::new()
+    };
+    copy_universal(&mut shell);
+    shell.variables.set_positional(args);
+    if options.strict {
+        shell.options.set_strict();
+    }
+
+    let status = match shell.source(script) {
+        Ok(status) => shell.exit.unwrap_or(status),
+        Err(e) => {
+            eprintln!("sigsh: {}: {}", script.display(), e);
+            return 127;
+        }
+    };
+    if let Some(profiler) = &shell.profiler {
+        eprint!("{}", profiler.report());
+    }
+    status
+}
+
+/// How many Ctrl-Ds in a row it takes to leave with `ignoreeof` set:
+/// `$IGNOREEOF`, or 10 if that isn't a number, as in bash.
+fn ignore_eof_limit(shell: &ShellState) -> usize {
+    shell
+        .variables
+        .get("IGNOREEOF")
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(10)
+}
+
+/// Read a line after showing `prompt`, with the line editor if stdin is a
+/// terminal. `None` means EOF.
+fn read_line(
+    editor: &mut Editor,
+    shell: &mut ShellState,
+    prompt: &str,
+) -> io::Result<Option<String>> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return editor.read_line(prompt, shell);
+    }
+    print!("{prompt}");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    stdin
+        .read_line(&mut input)
+        .map(|read| (read > 0).then_some(input))
+}
+
+/// Read and run commands from stdin until EOF or `exit`, returning the status
+/// to exit with.
+fn interact(plugins: Vec<Box<dyn Plugin>>) -> i32 {
+    // Input REPL
+    let stdin = io::stdin();
+    let mut editor = Editor::new();
+    let mut shell = ShellState {
+        history: History::load(),
+        universal: UniversalVars::load(),
+        direnv: DirEnv::load(),
+        ..ShellState::new()
+    };
+    for plugin in plugins {
+        shell.add_plugin(plugin);
+    }
+
+    if stdin.is_terminal() {
+        // Without job control we still work, we just can't stop or resume jobs
+        shell.job_control = platform::init_job_control().is_ok();
+        platform::close_inherited_on_exec();
+    }
+
+    copy_universal(&mut shell);
+    // Ctrl-Ds in a row, for `ignoreeof`
+    let mut eofs = 0;
+
+    let status = loop {
+        shell.reap_jobs();
+        shell.run_prompt_hooks();
+        if let Some(status) = shell.exit {
+            break status;
+        }
+
+        let prompt = prompt::render(&shell);
+        let input = read_line(&mut editor, &mut shell, &prompt);
+
+        let mut input = match input {
+            Ok(Some(input)) => input,
+            Ok(None) if stdin.is_terminal() => {
+                eofs += 1;
+                if shell.options.ignoreeof && eofs < ignore_eof_limit(&shell) {
+                    eprintln!("Use \"exit\" to leave the shell.");
+                    continue;
+                }
+                if !shell.may_exit() {
+                    continue;
+                }
+                break shell.last_status;
+            }
+            Ok(None) => break shell.last_status,
+            Err(e) => {
+                eprintln!("{}", e);
+                break shell.last_status;
+            }
+        };
+        // A `$(` or `(` left open carries on onto the next line
+        while Command::is_incomplete(&input) {
+            let prompt = shell.variables.get("PS2").unwrap_or("> ").to_string();
+            match read_line(&mut editor, &mut shell, &prompt) {
+                Ok(Some(more)) => {
+                    input.truncate(input.trim_end_matches('\n').len());
+                    input.push('\n');
+                    input.push_str(&more);
+                }
+                _ => break,
+            }
+        }
+        let input = input.trim();
+        eofs = 0;
+
+        shell.location.line += 1;
+        if input.is_empty() {
+            continue;
+        }
+        // A rule that can't be used might be the one hiding a secret
+        match Redaction::from_variables(&shell.variables) {
+            Ok(redaction) => {
+                shell.history.set_redaction(redaction);
+                if let Err(e) = shell.history.add(input) {
+                    eprintln!("history: {}", e);
+                }
+            }
+            Err(e) => eprintln!("history: {}; not saved", e),
+        }
+
+        // A warning about stopped jobs only holds for the next command
+        let warned = shell.warned_stopped;
+        let jobs = shell.jobs.iter().count();
+        let started = Instant::now();
+        shell.usage = None;
+        shell.glob_cache.clear();
+        if let Err(e) = shell.eval_interactive(input) {
+            eprintln!("{}", e);
+            // Syntax errors are 2, like other shells
+            shell.last_status = if e.kind() == IOErrorKind::InvalidInput {
+                2
+            } else {
+                1
+            };
+        }
+        if warned {
+            shell.warned_stopped = false;
+        }
+        // A command stopped with Ctrl-Z hasn't finished yet
+        if shell.jobs.iter().count() <= jobs {
+            notify::command_finished(&mut shell, input, started.elapsed());
+        }
+
+        if let Some(status) = shell.exit {
+            break status;
+        }
+    };
+
+    if let Err(e) = shell.history.save() {
+        eprintln!("history: {}", e);
+    }
+    if shell.options.huponexit {
+        shell.jobs.hang_up();
+    }
+    status
+}
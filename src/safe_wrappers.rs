@@ -1,54 +1,93 @@
-use libc::{c_char, pid_t};
+use libc::{c_int, pid_t};
 use std::{
-    ffi::{CStr, CString, OsString},
+    ffi::CString,
     io::{Error as IOError, ErrorKind as IOErrorKind, Result as IOResult},
-    os::unix::ffi::OsStringExt,
+    os::fd::RawFd,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    time::Duration,
 };
 
-unsafe extern "C" {
-    static environ: *const *const c_char;
-}
+use crate::platform::{ResourceUsage, WaitStatus};
 
 pub enum ForkReturn {
     Parent(pid_t),
     Child,
 }
 
-pub(crate) fn fork() -> ForkReturn {
+pub(crate) fn fork() -> IOResult<ForkReturn> {
     let res = unsafe { libc::fork() };
 
     // TODO: Use `assert!` (or `assert_eq!`) here to make sure we only have one thread
 
     if res < 0 {
-        panic!("fork failed")
+        Err(IOError::last_os_error())
+    } else if res == 0 {
+        Ok(ForkReturn::Child)
     } else {
-        if res == 0 {
-            ForkReturn::Child
-        } else {
-            ForkReturn::Parent(res)
-        }
+        Ok(ForkReturn::Parent(res))
     }
 }
 
-pub(crate) fn exec<S: AsRef<str>>(pathname: &S, argv: &[S]) -> IOResult<()> {
-    let pathname = CString::new(pathname.as_ref()).map_err(|_| {
-        IOError::new(
-            IOErrorKind::InvalidInput,
-            "BAD: pathname str had a null byte.",
-        )
-    })?;
+fn to_cstrings<S: AsRef<str>>(strings: impl IntoIterator<Item = S>) -> Vec<CString> {
+    strings
+        .into_iter()
+        .filter_map(|s| CString::new(s.as_ref()).ok())
+        .collect()
+}
+
+/// A program to run, as the C strings `execve` and `posix_spawn` want.
+struct ExecArgs {
+    pathname: CString,
+    argv: Vec<CString>,
+    env: Vec<CString>,
+}
 
-    // Store our CStrings
-    let argv = argv
+impl ExecArgs {
+    fn new<S: AsRef<str>>(pathname: &Path, argv: &[S], env: &[(S, S)]) -> IOResult<ExecArgs> {
+        let pathname = CString::new(pathname.as_os_str().as_bytes()).map_err(|_| {
+            IOError::new(
+                IOErrorKind::InvalidInput,
+                "BAD: pathname str had a null byte.",
+            )
+        })?;
+        Ok(ExecArgs {
+            pathname,
+            argv: to_cstrings(argv),
+            env: to_cstrings(
+                env.iter()
+                    .map(|(name, value)| format!("{}={}", name.as_ref(), value.as_ref())),
+            ),
+        })
+    }
+}
+
+/// Null-terminated pointers to `strings`, which have to outlive them.
+fn pointers(strings: &[CString]) -> Vec<*mut libc::c_char> {
+    strings
         .iter()
-        .map(|arg| CString::new(arg.as_ref()))
-        .filter_map(|res| res.ok())
-        .collect::<Vec<_>>();
+        .map(|s| s.as_ptr().cast_mut())
+        .chain(std::iter::once(std::ptr::null_mut()))
+        .collect()
+}
 
-    let mut argv_ptrs = argv.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
-    argv_ptrs.push(std::ptr::null());
+/// Replace the process with `pathname`, which has to be a path rather than a
+/// bare command name, with `env` as its entire environment.
+pub(crate) fn exec<S: AsRef<str>>(pathname: &Path, argv: &[S], env: &[(S, S)]) -> IOResult<()> {
+    let args = ExecArgs::new(pathname, argv, env)?;
+    let argv_ptrs = pointers(&args.argv);
+    let env_ptrs = pointers(&args.env);
 
-    if unsafe { libc::execvpe(pathname.as_ptr(), argv_ptrs.as_ptr(), environ) } < 0 {
+    // `execve` rather than `execvpe`, which doesn't exist on macOS; we've
+    // already searched PATH ourselves anyway.
+    if unsafe {
+        libc::execve(
+            args.pathname.as_ptr(),
+            argv_ptrs.as_ptr().cast(),
+            env_ptrs.as_ptr().cast(),
+        )
+    } < 0
+    {
         Err(IOError::last_os_error())
     } else {
         unsafe {
@@ -57,17 +96,102 @@ pub(crate) fn exec<S: AsRef<str>>(pathname: &S, argv: &[S]) -> IOResult<()> {
     }
 }
 
-pub(crate) struct WaitReturn {
-    pid: pid_t,
-    status: WaitStatus,
+/// What a child started by [`spawn`] does before running its program.
+pub(crate) struct SpawnOptions<'a> {
+    /// `(from, to)` pairs to `dup2`, in order
+    pub dup2: &'a [(RawFd, RawFd)],
+    /// The process group to join, where 0 starts a new one led by the child
+    pub pgroup: Option<pid_t>,
+    /// Signals to put back to their default dispositions
+    pub default_signals: &'a [c_int],
+}
+
+/// Frees a `posix_spawn_file_actions_t` when dropped.
+struct FileActions(libc::posix_spawn_file_actions_t);
+
+impl Drop for FileActions {
+    fn drop(&mut self) {
+        unsafe { libc::posix_spawn_file_actions_destroy(&raw mut self.0) };
+    }
+}
+
+/// Frees a `posix_spawnattr_t` when dropped.
+struct SpawnAttr(libc::posix_spawnattr_t);
+
+impl Drop for SpawnAttr {
+    fn drop(&mut self) {
+        unsafe { libc::posix_spawnattr_destroy(&raw mut self.0) };
+    }
+}
+
+/// `posix_spawn` calls return an error number rather than setting `errno`.
+fn check_spawn(res: c_int) -> IOResult<()> {
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(IOError::from_raw_os_error(res))
+    }
+}
+
+/// Start `pathname` as a new process with `posix_spawn`, which skips copying
+/// the shell's address space the way `fork` then `exec` does. Failing to
+/// `exec` is reported here rather than in the child.
+pub(crate) fn spawn<S: AsRef<str>>(
+    pathname: &Path,
+    argv: &[S],
+    env: &[(S, S)],
+    options: &SpawnOptions,
+) -> IOResult<pid_t> {
+    let args = ExecArgs::new(pathname, argv, env)?;
+    let argv_ptrs = pointers(&args.argv);
+    let env_ptrs = pointers(&args.env);
+
+    let mut actions = FileActions(unsafe { std::mem::zeroed() });
+    check_spawn(unsafe { libc::posix_spawn_file_actions_init(&raw mut actions.0) })?;
+    for &(from, to) in options.dup2 {
+        check_spawn(unsafe {
+            libc::posix_spawn_file_actions_adddup2(&raw mut actions.0, from, to)
+        })?;
+    }
+
+    let mut attr = SpawnAttr(unsafe { std::mem::zeroed() });
+    check_spawn(unsafe { libc::posix_spawnattr_init(&raw mut attr.0) })?;
+    let mut flags = libc::POSIX_SPAWN_SETSIGDEF | libc::POSIX_SPAWN_SETSIGMASK;
+    if let Some(pgroup) = options.pgroup {
+        flags |= libc::POSIX_SPAWN_SETPGROUP;
+        check_spawn(unsafe { libc::posix_spawnattr_setpgroup(&raw mut attr.0, pgroup) })?;
+    }
+    check_spawn(unsafe { libc::posix_spawnattr_setflags(&raw mut attr.0, flags as _) })?;
+
+    let mut signals = unsafe { std::mem::zeroed::<libc::sigset_t>() };
+    unsafe { libc::sigemptyset(&raw mut signals) };
+    check_spawn(unsafe { libc::posix_spawnattr_setsigmask(&raw mut attr.0, &raw const signals) })?;
+    for &signal in options.default_signals {
+        unsafe { libc::sigaddset(&raw mut signals, signal) };
+    }
+    check_spawn(unsafe {
+        libc::posix_spawnattr_setsigdefault(&raw mut attr.0, &raw const signals)
+    })?;
+
+    let mut pid = 0;
+    check_spawn(unsafe {
+        libc::posix_spawn(
+            &raw mut pid,
+            args.pathname.as_ptr(),
+            &raw const actions.0,
+            &raw const attr.0,
+            argv_ptrs.as_ptr(),
+            env_ptrs.as_ptr(),
+        )
+    })?;
+    Ok(pid)
 }
 
-pub(crate) enum WaitStatus {
-    Exited(i32),
-    TermSignal(i32),
-    Stopped(i32),
-    Continued,
-    Unknown,
+pub(crate) struct WaitReturn {
+    pub pid: pid_t,
+    pub status: WaitStatus,
+    /// What the child used, if it's finished
+    pub usage: Option<ResourceUsage>,
 }
 
 impl From<WaitReturn> for WaitStatus {
@@ -76,23 +200,34 @@ impl From<WaitReturn> for WaitStatus {
     }
 }
 
-pub(crate) fn wait() -> IOResult<WaitReturn> {
+fn duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+}
+
+/// Returns `Ok(None)` if `WNOHANG` was passed and no child has changed state.
+pub(crate) fn waitpid(pid: pid_t, options: c_int) -> IOResult<Option<WaitReturn>> {
+    use libc::{
+        WCOREDUMP, WEXITSTATUS, WIFCONTINUED, WIFEXITED, WIFSIGNALED, WIFSTOPPED, WSTOPSIG,
+        WTERMSIG,
+    };
     use WaitStatus as WS;
-    use libc::{WIFEXITED, WEXITSTATUS, WIFSIGNALED, WTERMSIG, WIFSTOPPED, WSTOPSIG, WIFCONTINUED};
 
     let mut stat_code = 0i32;
+    let mut rusage = unsafe { std::mem::zeroed::<libc::rusage>() };
 
-    let res = unsafe { libc::wait(&raw mut stat_code) };
+    let res = unsafe { libc::wait4(pid, &raw mut stat_code, options, &raw mut rusage) };
 
     if res < 0 {
         Err(IOError::last_os_error())
+    } else if res == 0 {
+        Ok(None)
     } else {
         let pid = res;
 
         let status = if WIFEXITED(stat_code) {
             WS::Exited(WEXITSTATUS(stat_code))
         } else if WIFSIGNALED(stat_code) {
-            WS::TermSignal(WTERMSIG(stat_code))
+            WS::TermSignal(WTERMSIG(stat_code), WCOREDUMP(stat_code))
         } else if WIFSTOPPED(stat_code) {
             WS::Stopped(WSTOPSIG(stat_code))
         } else if WIFCONTINUED(stat_code) {
@@ -101,39 +236,217 @@ pub(crate) fn wait() -> IOResult<WaitReturn> {
             WS::Unknown
         };
 
-        Ok(WaitReturn { pid, status })
+        // macOS counts bytes where everything else counts kilobytes
+        let max_rss = rusage.ru_maxrss as u64;
+        let max_rss = if cfg!(target_os = "macos") {
+            max_rss / 1024
+        } else {
+            max_rss
+        };
+        let usage = matches!(status, WS::Exited(_) | WS::TermSignal(..)).then(|| ResourceUsage {
+            user: duration(rusage.ru_utime),
+            system: duration(rusage.ru_stime),
+            max_rss,
+        });
+
+        Ok(Some(WaitReturn { pid, status, usage }))
     }
 }
 
-pub(crate) fn getenv(var_id: impl AsRef<str>) -> IOResult<String> {
-    let c_var_id = CString::new(var_id.as_ref()).map_err(|_| {
-        IOError::new(
-            IOErrorKind::InvalidInput,
-            format!(
-                "Variable identifier {} had internal null byte",
-                var_id.as_ref()
-            ),
-        )
-    })?;
+pub(crate) fn tcgetattr(fd: RawFd) -> IOResult<libc::termios> {
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
 
-    let getenv_ret = unsafe { libc::getenv(c_var_id.as_ptr()) };
+    if unsafe { libc::tcgetattr(fd, &raw mut termios) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(termios)
+    }
+}
 
-    if getenv_ret.is_null() {
-        Err(IOError::new(
-            IOErrorKind::NotFound,
-            format!("{} not found in environment", var_id.as_ref()),
-        ))
+pub(crate) fn tcsetattr(fd: RawFd, termios: &libc::termios) -> IOResult<()> {
+    if unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, termios) } < 0 {
+        Err(IOError::last_os_error())
     } else {
-        let bytes = unsafe { CStr::from_ptr(getenv_ret).to_bytes().to_vec() };
+        Ok(())
+    }
+}
 
-        Ok(OsString::from_vec(bytes).into_string().map_err(|_| {
-            IOError::new(
-                IOErrorKind::InvalidData,
-                format!(
-                    "Value of {} contains bytes which are not valid UTF-8",
-                    var_id.as_ref()
-                ),
+pub(crate) fn getpid() -> pid_t {
+    unsafe { libc::getpid() }
+}
+
+pub(crate) fn getpgrp() -> pid_t {
+    unsafe { libc::getpgrp() }
+}
+
+pub(crate) fn setpgid(pid: pid_t, pgid: pid_t) -> IOResult<()> {
+    if unsafe { libc::setpgid(pid, pgid) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn setsid() -> IOResult<pid_t> {
+    match unsafe { libc::setsid() } {
+        -1 => Err(IOError::last_os_error()),
+        sid => Ok(sid),
+    }
+}
+
+pub(crate) fn tcgetpgrp(fd: RawFd) -> IOResult<pid_t> {
+    let res = unsafe { libc::tcgetpgrp(fd) };
+    if res < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+pub(crate) fn tcsetpgrp(fd: RawFd, pgid: pid_t) -> IOResult<()> {
+    if unsafe { libc::tcsetpgrp(fd, pgid) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn killpg(pgid: pid_t, signal: c_int) -> IOResult<()> {
+    if unsafe { libc::killpg(pgid, signal) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn kill(pid: pid_t, signal: c_int) -> IOResult<()> {
+    if unsafe { libc::kill(pid, signal) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the handler that was there before.
+pub(crate) fn set_signal_handler(signal: c_int, handler: libc::sighandler_t) -> libc::sighandler_t {
+    unsafe { libc::signal(signal, handler) }
+}
+
+/// Install `handler` without `SA_RESTART`, so that a blocking call the signal
+/// arrives during fails with `EINTR` rather than carrying on. Returns the
+/// action that was there before, for [`restore_signal_action`].
+pub(crate) fn set_interrupting_handler(
+    signal: c_int,
+    handler: libc::sighandler_t,
+) -> IOResult<libc::sigaction> {
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    action.sa_sigaction = handler;
+    unsafe { libc::sigemptyset(&raw mut action.sa_mask) };
+    let mut previous = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    if unsafe { libc::sigaction(signal, &raw const action, &raw mut previous) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(previous)
+    }
+}
+
+pub(crate) fn restore_signal_action(signal: c_int, action: &libc::sigaction) -> IOResult<()> {
+    if unsafe { libc::sigaction(signal, action, std::ptr::null_mut()) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn dup2(fd: RawFd, fd2: RawFd) -> IOResult<()> {
+    if unsafe { libc::dup2(fd, fd2) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn close(fd: RawFd) -> IOResult<()> {
+    if unsafe { libc::close(fd) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn set_cloexec(fd: RawFd, cloexec: bool) -> IOResult<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 {
+        return Err(IOError::last_os_error());
+    }
+    let flags = if cloexec {
+        flags | libc::FD_CLOEXEC
+    } else {
+        flags & !libc::FD_CLOEXEC
+    };
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } < 0 {
+        Err(IOError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// The soft limits on how many files can be open and how big the address
+/// space can get, each `None` if there's no limit.
+pub(crate) fn resource_limits() -> IOResult<(Option<u64>, Option<u64>)> {
+    let mut files = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let mut memory = files;
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &raw mut files) } < 0
+        || unsafe { libc::getrlimit(libc::RLIMIT_AS, &raw mut memory) } < 0
+    {
+        return Err(IOError::last_os_error());
+    }
+    let soft =
+        |limit: libc::rlimit| (limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur);
+    Ok((soft(files), soft(memory)))
+}
+
+/// A system configuration value, or `None` if it's unlimited or unknown.
+pub(crate) fn sysconf(name: c_int) -> Option<u64> {
+    let value = unsafe { libc::sysconf(name) };
+    (value > 0).then_some(value as u64)
+}
+
+pub(crate) fn fd_is_open(fd: RawFd) -> bool {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    flags >= 0
+}
+
+/// `time`, in seconds since the epoch, formatted in local time by
+/// `strftime`, or `None` if it's out of range or the format won't go.
+pub(crate) fn strftime(format: &str, time: i64) -> Option<String> {
+    // With something always written, nothing written means no room
+    let format = CString::new(format!("{format} ")).ok()?;
+    let time = libc::time_t::try_from(time).ok()?;
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    if unsafe { libc::localtime_r(&raw const time, &raw mut tm) }.is_null() {
+        return None;
+    }
+
+    let mut size = 256;
+    while size <= 1 << 16 {
+        let mut buf = vec![0u8; size];
+        let written = unsafe {
+            libc::strftime(
+                buf.as_mut_ptr().cast(),
+                size,
+                format.as_ptr(),
+                &raw const tm,
             )
-        })?)
+        };
+        if written > 0 {
+            buf.truncate(written - 1);
+            return Some(String::from_utf8_lossy(&buf).into_owned());
+        }
+        size *= 4;
     }
+    None
 }
@@ -0,0 +1,505 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Error as IOError, ErrorKind as IOErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use crate::children::Children;
+use crate::complete::CompletionSpecs;
+use crate::debugger::Debugger;
+use crate::direnv::DirEnv;
+use crate::exec;
+use crate::fds::Fds;
+use crate::glob::DirCache;
+use crate::history::History;
+use crate::intercept::Intercepts;
+use crate::jobs::{JobState, JobTable};
+use crate::options::Options;
+use crate::parser::{is_name, Aliases, Command, Compound};
+use crate::platform::{self, ResourceUsage};
+use crate::plugin::{self, Plugin};
+use crate::profiler::Profiler;
+use crate::universal::{Changes, UniversalVars};
+use crate::vars::{Value, Variable, Variables};
+
+/// When the shell started, for `printf '%(...)T' -2`.
+static STARTED: OnceLock<SystemTime> = OnceLock::new();
+
+pub(crate) fn started() -> SystemTime {
+    *STARTED.get_or_init(SystemTime::now)
+}
+
+/// A function's body, and where it was defined.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub body: Command,
+    pub location: Location,
+    /// Passed on to the shells it runs, set with `export -f`
+    pub exported: bool,
+}
+
+/// Exported functions go in the environment as `SIGSH_FUNCTION_name`,
+/// holding just the body. Taking in nothing but a `{ ...; }` or `( ... )`
+/// body means a shell only ever defines a function from one, and never
+/// runs anything else smuggled in with it.
+const FUNCTION_PREFIX: &str = "SIGSH_FUNCTION_";
+
+pub type Functions = BTreeMap<String, Function>;
+
+/// A running function: its name, and where it was called from.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    pub called_from: Location,
+}
+
+/// Everything the shell carries from one command to the next.
+#[derive(Default)]
+pub struct ShellState {
+    pub jobs: JobTable,
+    /// Every process started this session, for `procs`
+    pub(crate) children: Children,
+    pub history: History,
+    pub completions: CompletionSpecs,
+    /// The commands whose completion file has been looked for, so each is
+    /// only loaded once
+    pub(crate) completions_loaded: BTreeSet<String>,
+    pub variables: Variables,
+    pub options: Options,
+    pub aliases: Aliases,
+    pub functions: Functions,
+    /// Rules for running commands through handlers, or not at all
+    pub intercepts: Intercepts,
+    /// Variables shared with every other session, mirrored into `variables`
+    pub universal: UniversalVars,
+    /// Which `.sigsh.env` files are trusted, and the one that's loaded
+    pub direnv: DirEnv,
+    /// The descriptors redirected for good with `exec`
+    pub fds: Fds,
+    /// Whether we own a terminal and can move jobs in and out of its foreground.
+    pub job_control: bool,
+    pub last_status: i32,
+    /// Set by `exit` (or a failure under `set -e`) with the status to exit with
+    pub exit: Option<i32>,
+    /// Set when leaving the shell was refused because of stopped jobs, so
+    /// that trying again straight away goes ahead
+    pub(crate) warned_stopped: bool,
+    /// Set by `return` to unwind out of the running function
+    pub(crate) returning: bool,
+    /// The functions being run, outermost first
+    pub(crate) call_stack: Vec<Frame>,
+    /// For each running function, the options to put back when it returns if
+    /// it ran `local -`
+    pub(crate) local_options: Vec<Option<Options>>,
+    /// What the programs waited for since the last command at the prompt
+    /// used, if the platform says
+    pub(crate) usage: Option<ResourceUsage>,
+    /// The statuses of the `$(...)`s expanded for the command being run so
+    /// far, in order
+    pub(crate) substatus: Vec<i32>,
+    /// The read ends of the pipes `$(...)` and `capture` are collecting
+    /// output from, which a forked copy of the shell closes so that the
+    /// writer notices when the reading stops
+    pub(crate) capturing: Vec<i32>,
+    /// The directories read for pathname expansion on this command line,
+    /// with `set -o glob-cache`
+    pub(crate) glob_cache: DirCache,
+    /// Where the line being run was read from, for `$LINENO` and errors
+    pub location: Location,
+    /// The commands set with `trap` for `ERR` and `DEBUG`
+    pub(crate) traps: BTreeMap<String, String>,
+    /// In a subshell which hasn't set a trap of its own yet, the traps of the
+    /// shell it came from, which `trap` still lists as POSIX asks, so that
+    /// `saved=$(trap)` works
+    pub(crate) parent_traps: Option<BTreeMap<String, String>>,
+    /// Set in a subshell, forked or not
+    pub(crate) subshell: bool,
+    /// Set while a trap runs, so it doesn't set itself off
+    pub(crate) in_trap: bool,
+    /// Set when running a script with `--debug`
+    pub debugger: Option<Debugger>,
+    /// Set when running a script with `--profile`
+    pub profiler: Option<Profiler>,
+    /// Set when running a script with `--dry-run`, to print commands rather
+    /// than run them
+    pub dry_run: bool,
+    /// What sees each command typed at the prompt before it runs
+    pub(crate) plugins: Vec<Box<dyn Plugin>>,
+}
+
+/// A line of input: which file it's in, if it isn't being typed at the
+/// prompt, and its line number.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file.display(), self.line),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
+/// The lines of a script worth running, each with its line number. Lines
+/// ending in a backslash are joined to the next, as are those leaving a
+/// `$(` or `(` open, and blank lines and comments are left out.
+fn script_lines(contents: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut numbered = contents.lines().enumerate();
+    while let Some((index, line)) = numbered.next() {
+        let mut joined = line.to_string();
+        loop {
+            let separator = if joined.ends_with('\\') {
+                joined.pop();
+                ""
+            } else if !joined.trim_start().starts_with('#') && Command::is_incomplete(&joined) {
+                "\n"
+            } else {
+                break;
+            };
+            match numbered.next() {
+                Some((_, next)) => {
+                    joined.push_str(separator);
+                    joined.push_str(next);
+                }
+                None => break,
+            }
+        }
+        let trimmed = joined.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            lines.push((index + 1, trimmed.to_string()));
+        }
+    }
+    lines
+}
+
+/// The parts of a [`ShellState`] a command can change and a subshell has to
+/// leave untouched: variables, options, aliases, functions, traps and the
+/// working directory.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    variables: Variables,
+    options: Options,
+    aliases: Aliases,
+    functions: Functions,
+    intercepts: Intercepts,
+    traps: BTreeMap<String, String>,
+    parent_traps: Option<BTreeMap<String, String>>,
+    fds: Fds,
+    cwd: PathBuf,
+}
+
+impl ShellState {
+    /// A shell starting out with the process's environment as its variables.
+    pub fn new() -> Self {
+        started();
+        let mut shell = ShellState {
+            variables: Variables::from_env(),
+            ..Default::default()
+        };
+        shell.import_functions();
+        shell
+    }
+
+    /// Define the functions exported by the shell that ran this one, taking
+    /// them out of the variables. Anything that isn't just a function body
+    /// is left alone, as an ordinary variable.
+    fn import_functions(&mut self) {
+        let found: Vec<(String, String)> = self
+            .variables
+            .iter()
+            .filter_map(|(name, var)| {
+                let name = name.strip_prefix(FUNCTION_PREFIX)?;
+                Some((name.to_string(), var.value.as_str().to_string()))
+            })
+            .collect();
+
+        for (name, source) in found {
+            let Ok(body) = Command::parse(&source) else {
+                continue;
+            };
+            let just_body = Command {
+                compound: None,
+                ..body.clone()
+            } == Command::default()
+                && matches!(
+                    body.compound.as_deref(),
+                    Some(Compound::Group(_) | Compound::Subshell(_))
+                );
+            if !just_body || !is_name(&name) {
+                continue;
+            }
+            let _ = self.variables.unset(&format!("{FUNCTION_PREFIX}{name}"));
+            self.functions.insert(
+                name,
+                Function {
+                    body,
+                    location: Location::default(),
+                    exported: true,
+                },
+            );
+        }
+    }
+
+    /// The environment for the programs the shell runs: the exported
+    /// variables, and the exported functions for any shells among them.
+    pub fn environment(&self) -> Vec<(String, String)> {
+        let mut env = self.variables.exported();
+        env.extend(
+            self.functions
+                .iter()
+                .filter(|(_, function)| function.exported)
+                .map(|(name, function)| {
+                    (
+                        format!("{FUNCTION_PREFIX}{name}"),
+                        function.body.to_string(),
+                    )
+                }),
+        );
+        env
+    }
+
+    pub fn snapshot(&self) -> io::Result<Snapshot> {
+        Ok(Snapshot {
+            variables: self.variables.clone(),
+            options: self.options.clone(),
+            aliases: self.aliases.clone(),
+            functions: self.functions.clone(),
+            intercepts: self.intercepts.clone(),
+            traps: self.traps.clone(),
+            parent_traps: self.parent_traps.clone(),
+            fds: self.fds.clone(),
+            cwd: std::env::current_dir()?,
+        })
+    }
+
+    /// Reset the traps for a subshell. Conditions that were ignored stay
+    /// ignored, and the rest are back to doing nothing.
+    pub(crate) fn reset_traps(&mut self) {
+        let parent = self.traps.clone();
+        self.traps.retain(|_, action| action.is_empty());
+        self.parent_traps = Some(parent);
+    }
+
+    /// Put back everything captured by [`ShellState::snapshot`], closing any
+    /// descriptors `exec` opened since.
+    ///
+    /// The rest of the state is restored even if the old working directory
+    /// has gone away since, in which case the error is returned.
+    pub fn restore(&mut self, snapshot: Snapshot) -> io::Result<()> {
+        self.variables = snapshot.variables;
+        self.options = snapshot.options;
+        self.aliases = snapshot.aliases;
+        self.functions = snapshot.functions;
+        self.intercepts = snapshot.intercepts;
+        self.traps = snapshot.traps;
+        self.parent_traps = snapshot.parent_traps;
+        for (&fd, _) in self
+            .fds
+            .iter()
+            .filter(|(fd, _)| !snapshot.fds.contains_key(fd))
+        {
+            let _ = platform::close_fd(fd);
+        }
+        self.fds = snapshot.fds;
+        std::env::set_current_dir(snapshot.cwd)
+    }
+
+    /// Run what's due before each prompt: the `precmd` function if there is
+    /// one, then each command in `PROMPT_COMMAND`, which may be an array as
+    /// tools that add their own hooks expect. `$?` and `$PIPESTATUS` are
+    /// left as they were.
+    pub fn run_prompt_hooks(&mut self) {
+        self.keeping_status(Self::run_hooks);
+    }
+
+    /// Do something that runs commands without changing `$?` or
+    /// `$PIPESTATUS`.
+    pub(crate) fn keeping_status<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let status = self.last_status;
+        let pipestatus = self.variables.get_array("PIPESTATUS");
+        let result = f(self);
+        self.last_status = status;
+        if let Some(pipestatus) = pipestatus {
+            let _ = self.variables.set_array("PIPESTATUS", pipestatus);
+        }
+        result
+    }
+
+    fn run_hooks(&mut self) {
+        let mut hooks = Vec::new();
+        if self.functions.contains_key("precmd") {
+            hooks.push("precmd".to_string());
+        }
+        hooks.extend(
+            self.variables
+                .get_array("PROMPT_COMMAND")
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|hook| !hook.trim().is_empty()),
+        );
+
+        for hook in hooks {
+            if let Err(e) = self.eval(&hook) {
+                eprintln!("{}", e);
+            }
+            if self.exit.is_some() {
+                break;
+            }
+        }
+    }
+
+    /// Pick up universal variables other sessions have set or erased since we
+    /// last looked.
+    pub fn refresh_universal(&mut self) -> io::Result<()> {
+        let changes = self.universal.refresh()?;
+        self.apply_universal(changes)
+    }
+
+    /// Mirror what other sessions did to the universal variables into ours.
+    pub(crate) fn apply_universal(&mut self, (set, erased): Changes) -> io::Result<()> {
+        for (name, value) in set {
+            self.variables.set(name.as_str(), value)?;
+            self.variables.export(name);
+        }
+        for name in erased {
+            self.variables.unset(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Parse and run a line of input, returning its exit status.
+    pub fn eval(&mut self, input: &str) -> io::Result<i32> {
+        if let Err(e) = self.refresh_universal() {
+            eprintln!("universal variables: {}", e);
+        }
+        let command = Command::parse_with_aliases(input, &self.aliases)
+            .map_err(|errs| IOError::new(IOErrorKind::InvalidInput, errs))?;
+        let status = exec::run_command(self, &command)?;
+        self.last_status = status;
+        Ok(status)
+    }
+
+    /// Parse and run a line typed at the prompt, as [`ShellState::eval`]
+    /// does, but giving the plugins the chance to change it first.
+    pub fn eval_interactive(&mut self, input: &str) -> io::Result<i32> {
+        if let Err(e) = self.refresh_universal() {
+            eprintln!("universal variables: {}", e);
+        }
+        let mut command = Command::parse_with_aliases(input, &self.aliases)
+            .map_err(|errs| IOError::new(IOErrorKind::InvalidInput, errs))?;
+        if let Err(e) = plugin::transform(self, &mut command) {
+            self.last_status = 1;
+            return Err(IOError::new(IOErrorKind::PermissionDenied, e));
+        }
+        let status = exec::run_command(self, &command)?;
+        self.last_status = status;
+        Ok(status)
+    }
+
+    /// Run the file at `path` a line at a time, as `source` does, returning
+    /// the status of the last command. Errors are reported with the line
+    /// they're on.
+    pub fn source(&mut self, path: &Path) -> io::Result<i32> {
+        let contents = fs::read_to_string(path)?;
+        self.source_contents(path, &contents)
+    }
+
+    /// Run `contents` as if it were read from the file at `path`.
+    pub(crate) fn source_contents(&mut self, path: &Path, contents: &str) -> io::Result<i32> {
+        let outer = std::mem::replace(
+            &mut self.location,
+            Location {
+                file: Some(path.to_path_buf()),
+                line: 0,
+            },
+        );
+
+        let mut status = 0;
+        for (line, input) in script_lines(contents) {
+            self.location.line = line;
+            self.glob_cache.clear();
+            status = self.eval(&input).unwrap_or_else(|e| {
+                eprintln!("{}: {}", self.location, e);
+                // Syntax errors are 2, like other shells
+                if e.kind() == IOErrorKind::InvalidInput {
+                    2
+                } else {
+                    1
+                }
+            });
+            self.last_status = status;
+            if self.exit.is_some() {
+                break;
+            }
+        }
+
+        self.location = outer;
+        Ok(status)
+    }
+
+    /// Whether to go ahead with leaving the shell. Like other shells, the
+    /// first try while there are stopped jobs only warns about them.
+    pub fn may_exit(&mut self) -> bool {
+        let stopped = self.jobs.iter().any(|job| job.state == JobState::Stopped);
+        if stopped && !self.warned_stopped {
+            eprintln!("There are stopped jobs.");
+            self.warned_stopped = true;
+            return false;
+        }
+        true
+    }
+
+    /// Update the job table from children that changed state, reporting the
+    /// jobs that finished and noting how any spawned pipelines went.
+    pub fn reap_jobs(&mut self) {
+        let changed: Vec<(u32, JobState)> = platform::reap_children()
+            .into_iter()
+            .map(|(pid, status)| (pid, status.into()))
+            .collect();
+        for &(pid, state) in &changed {
+            self.children.changed(pid, state);
+        }
+        for job in self.jobs.reap(&changed) {
+            if let (Some(name), JobState::Done(status)) = (&job.name, job.state) {
+                self.set_spawn_status(name, &status.to_string());
+            }
+        }
+    }
+
+    /// Set the element of `$SPAWNSTATUS` for the pipeline `spawn` started as
+    /// `name`: `running`, or the status it finished with.
+    pub(crate) fn set_spawn_status(&mut self, name: &str, status: &str) {
+        const SPAWNSTATUS: &str = "SPAWNSTATUS";
+        if !matches!(
+            self.variables.var(SPAWNSTATUS).map(|var| &var.value),
+            Some(Value::Assoc(_))
+        ) {
+            let statuses = Variable::new(Value::Assoc(BTreeMap::new()));
+            self.variables.insert(SPAWNSTATUS, statuses);
+        }
+        let res = self
+            .variables
+            .set_element(SPAWNSTATUS, name, status.to_string());
+        if let Err(e) = res {
+            eprintln!("{SPAWNSTATUS}: {e}");
+        }
+    }
+
+    /// Run `input` and then roll back any changes it made, as if it had run
+    /// in a subshell, except that no process is forked.
+    pub fn eval_isolated(&mut self, input: &str) -> io::Result<i32> {
+        let snapshot = self.snapshot()?;
+        let res = self.eval(input);
+        let status = self.exit.take();
+        self.returning = false;
+        self.restore(snapshot)?;
+        res.map(|code| status.unwrap_or(code))
+    }
+}
@@ -0,0 +1,108 @@
+//! With `set -o color-stderr`, what commands write to standard error is
+//! passed through the shell on its way to the terminal, so that errors
+//! stand out from the rest of a long build log.
+//!
+//! Each line is shown in the SGR color `$STDERR_COLOR`, `31` (red) unless
+//! it's set, and after `$STDERR_PREFIX` if that's set. Lines are passed on
+//! as soon as they're read, but stdout goes straight to the terminal, so
+//! output written to both at once can come out slightly out of order.
+//! Programs see a pipe rather than the terminal on stderr, and some turn
+//! off their own colors because of it.
+
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crate::platform;
+use crate::shell::ShellState;
+
+const RESET: &[u8] = b"\x1b[0m";
+
+/// How long to wait for the last of a command's stderr once it's finished.
+/// Only a process it left running, or a stopped job, holds it up this long.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Passes what's written to [`StderrRelay::writer`] on to the terminal,
+/// until dropped.
+pub(crate) struct StderrRelay {
+    writer: Option<File>,
+    done: Receiver<()>,
+}
+
+impl StderrRelay {
+    /// Start relaying, if the option is set and stderr is a terminal. Under
+    /// a relay it's a pipe, so commands a function runs aren't relayed twice.
+    pub fn start(shell: &ShellState) -> io::Result<Option<StderrRelay>> {
+        if !shell.options.color_stderr || !io::stderr().is_terminal() {
+            return Ok(None);
+        }
+        let var = |name| shell.variables.get(name).map(str::to_string);
+        let color = match var("STDERR_COLOR") {
+            Some(color) if color.is_empty() => String::new(),
+            color => format!("\x1b[{}m", color.as_deref().unwrap_or("31")),
+        };
+        let prefix = var("STDERR_PREFIX").unwrap_or_default();
+
+        let terminal = platform::clone_stderr()?;
+        let (reader, writer) = platform::pipe()?;
+        let (finished, done) = mpsc::channel();
+        thread::spawn(move || {
+            relay(reader, terminal, color.as_bytes(), prefix.as_bytes());
+            let _ = finished.send(());
+        });
+        Ok(Some(StderrRelay {
+            writer: Some(writer),
+            done,
+        }))
+    }
+
+    /// Somewhere for a command's stderr to go.
+    pub fn writer(&self) -> io::Result<File> {
+        let writer = self.writer.as_ref();
+        writer.expect("only taken when dropped").try_clone()
+    }
+}
+
+impl Drop for StderrRelay {
+    fn drop(&mut self) {
+        drop(self.writer.take());
+        let _ = self.done.recv_timeout(DRAIN_TIMEOUT);
+    }
+}
+
+/// Copy `reader` to `terminal` until every writer has gone, with each line
+/// in `color` and after `prefix`. The color is reset at the end of each read,
+/// so it can't run into anything else written to the terminal.
+fn relay(mut reader: File, mut terminal: File, color: &[u8], prefix: &[u8]) {
+    let reset = if color.is_empty() { &[][..] } else { RESET };
+    let mut buf = [0; 4096];
+    let mut line_start = true;
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => return,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return,
+        };
+        let mut out = Vec::with_capacity(read + 16);
+        for line in buf[..read].split_inclusive(|&b| b == b'\n') {
+            out.extend_from_slice(color);
+            if line_start {
+                out.extend_from_slice(prefix);
+            }
+            let (text, newline) = match line.strip_suffix(b"\n") {
+                Some(text) => (text, &b"\n"[..]),
+                None => (line, &[][..]),
+            };
+            out.extend_from_slice(text);
+            out.extend_from_slice(reset);
+            out.extend_from_slice(newline);
+            line_start = !newline.is_empty();
+        }
+        if terminal.write_all(&out).is_err() {
+            return;
+        }
+    }
+}
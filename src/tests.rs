@@ -4,8 +4,6 @@
 mod tests {
     use std::path::PathBuf;
 
-    
-    
     use crate::parser::*;
 
     fn parse_command(input: &str) -> Option<Command> {
@@ -40,6 +38,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             ]
         );
@@ -76,7 +75,9 @@ mod tests {
             command.redirect_to,
             vec![FileRedir {
                 redirect_type: RedirType::Stdout,
-                target: PathBuf::from("output.txt")
+                target: PathBuf::from("output.txt"),
+                append: false,
+                clobber: false,
             }]
         );
     }
@@ -98,7 +99,9 @@ mod tests {
             command.redirect_to,
             vec![FileRedir {
                 redirect_type: RedirType::Stderr,
-                target: PathBuf::from("error.txt")
+                target: PathBuf::from("error.txt"),
+                append: false,
+                clobber: false,
             }]
         );
     }
@@ -120,11 +123,56 @@ mod tests {
             command.redirect_to,
             vec![FileRedir {
                 redirect_type: RedirType::Both,
-                target: PathBuf::from("output.txt")
+                target: PathBuf::from("output.txt"),
+                append: false,
+                clobber: false,
             }]
         );
     }
 
+    #[test]
+    fn test_noclobber() {
+        use crate::shell::ShellState;
+
+        let command = parse_command("echo hello >| output.txt").expect("Failed to parse command");
+        assert_eq!(
+            command.redirect_to,
+            vec![FileRedir {
+                redirect_type: RedirType::Stdout,
+                target: PathBuf::from("output.txt"),
+                append: false,
+                clobber: true,
+            }]
+        );
+        assert!(command.pipe_to.is_none());
+
+        let root = std::env::temp_dir().join(format!("noclobber-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let file = root.join("f");
+        let file = file.display();
+
+        let mut shell = ShellState::default();
+        shell.eval(&format!("set -C; echo one > {file}")).unwrap();
+        assert_ne!(shell.eval(&format!("echo two > {file}")).unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(root.join("f")).unwrap(), "one\n");
+
+        // Appending, `>|` and files that aren't regular are still allowed
+        shell
+            .eval(&format!(
+                "echo two >| {file}; echo three >> {file}; echo > /dev/null"
+            ))
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("f")).unwrap(),
+            "two\nthree\n"
+        );
+
+        shell.eval(&format!("set +C; echo four > {file}")).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("f")).unwrap(), "four\n");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_pipe() {
         let input = "echo hello | grep world";
@@ -150,6 +198,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             })
         );
@@ -180,6 +229,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             })
         );
@@ -209,6 +259,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 }),
                 conditional: true
             })
@@ -239,6 +290,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 }),
                 conditional: false
             })
@@ -277,11 +329,15 @@ mod tests {
             vec![
                 FileRedir {
                     redirect_type: RedirType::Stdout,
-                    target: PathBuf::from("out.txt")
+                    target: PathBuf::from("out.txt"),
+                    append: false,
+                    clobber: false,
                 },
                 FileRedir {
                     redirect_type: RedirType::Stderr,
-                    target: PathBuf::from("err.txt")
+                    target: PathBuf::from("err.txt"),
+                    append: false,
+                    clobber: false,
                 }
             ]
         );
@@ -351,11 +407,15 @@ mod tests {
             vec![
                 FileRedir {
                     redirect_type: RedirType::Stdout,
-                    target: PathBuf::from("output.txt")
+                    target: PathBuf::from("output.txt"),
+                    append: false,
+                    clobber: false,
                 },
                 FileRedir {
                     redirect_type: RedirType::Stdout,
-                    target: PathBuf::from("another_output.txt")
+                    target: PathBuf::from("another_output.txt"),
+                    append: false,
+                    clobber: false,
                 }
             ]
         );
@@ -378,7 +438,9 @@ mod tests {
             command.redirect_to,
             vec![FileRedir {
                 redirect_type: RedirType::Stdout,
-                target: PathBuf::from("output.txt")
+                target: PathBuf::from("output.txt"),
+                append: false,
+                clobber: false,
             }]
         );
 
@@ -394,6 +456,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             })
         );
@@ -416,7 +479,9 @@ mod tests {
             command.redirect_to,
             vec![FileRedir {
                 redirect_type: RedirType::Stdout,
-                target: PathBuf::from("output.txt")
+                target: PathBuf::from("output.txt"),
+                append: false,
+                clobber: false,
             }]
         );
 
@@ -431,6 +496,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 }),
                 conditional: true
             })
@@ -454,6 +520,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             ]
         );
@@ -473,6 +540,7 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             ]
         );
@@ -481,7 +549,9 @@ mod tests {
             command.redirect_to,
             vec![FileRedir {
                 redirect_type: RedirType::Stdout,
-                target: PathBuf::from("output.txt")
+                target: PathBuf::from("output.txt"),
+                append: false,
+                clobber: false,
             }]
         );
     }
@@ -531,10 +601,12 @@ mod tests {
                             pipe_to: None,
                             redirect_to: Vec::new(),
                             and_then: None,
+                            ..Default::default()
                         })
                     }),
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 }),
                 conditional: true
             })
@@ -570,10 +642,12 @@ mod tests {
                             pipe_to: None,
                             redirect_to: Vec::new(),
                             and_then: None,
+                            ..Default::default()
                         })
                     }),
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             })
         );
@@ -593,8 +667,1831 @@ mod tests {
                     pipe_to: None,
                     redirect_to: Vec::new(),
                     and_then: None,
+                    ..Default::default()
                 })
             ]
         );
     }
+
+    #[test]
+    fn test_malformed_input_is_an_error() {
+        for input in [
+            "\\", "'", "\"$", "$(", "$((1)", "${", "${}", "a=(", "f() x", "{ a", "}", ") (", "> ",
+            "a |", "a &&", "$1x", "\"\\", "|&", "&>", "x=(a) )", "a=$(b", "é$é",
+        ] {
+            // Any of these may or may not parse, but mustn't panic
+            let _ = Command::parse(input);
+        }
+
+        let aliases = Aliases::from([("g".to_string(), "|g|x|".to_string())]);
+        assert!(Command::parse_with_aliases("g", &aliases).is_err());
+        let aliases = Aliases::from([("a".to_string(), "b; a".to_string())]);
+        assert!(Command::parse_with_aliases("a", &aliases).is_ok());
+
+        // Chains are as long as they like, only nesting is limited
+        for chained in [
+            "a;".repeat(10_000),
+            "a|".repeat(10_000) + "a",
+            "a&&".repeat(10_000) + "a",
+        ] {
+            assert!(Command::parse(&chained).is_ok());
+        }
+        let depth = MAX_NESTING - 1;
+        let nested = format!("{}x{}", "$(".repeat(depth), ")".repeat(depth));
+        assert!(Command::parse(&nested).is_ok());
+        let grouped = format!("{}x{}", "{ ".repeat(depth - 1), "; }".repeat(depth - 1));
+        assert!(Command::parse(&grouped).is_ok());
+
+        for input in [
+            format!("{}x{}", "(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING)),
+            format!("{}{}", "$(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING)),
+            "{ ".repeat(10 * MAX_NESTING),
+        ] {
+            let errors = Command::parse(&input).unwrap_err();
+            assert!(errors.into_iter().any(|e| matches!(e, ParseError::TooDeep)));
+        }
+
+        for input in ["echo a & echo b", "sleep 1 &", "a&b", "[[ a & b ]]"] {
+            let errors = Command::parse(input).unwrap_err();
+            assert!(errors
+                .into_iter()
+                .any(|e| matches!(e, ParseError::Background)));
+        }
+        assert!(Command::parse("echo a &> out && echo b").is_ok());
+    }
+
+    #[test]
+    fn test_glob_parsing() {
+        let input = "ls *.rs \"*.txt\" 'a*'b*";
+        let command = parse_command(input).expect("Failed to parse command");
+
+        assert_eq!(
+            command.argv,
+            vec![
+                Arg::Word("ls".to_string()),
+                Arg::Glob("*.rs".to_string()),
+                Arg::Word("*.txt".to_string()),
+                Arg::Glob("a\\*b*".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_glob_matching() {
+        use crate::glob::matches;
+
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rc"));
+        assert!(matches("?ain.rs", "main.rs"));
+        assert!(matches("[a-m]ain.rs", "main.rs"));
+        assert!(!matches("[!m]ain.rs", "main.rs"));
+        assert!(matches("a\\*b", "a*b"));
+        assert!(!matches("a\\*b", "axb"));
+    }
+
+    #[test]
+    fn test_glob_globstar() {
+        use crate::glob::expand_with;
+
+        let root = std::env::temp_dir().join(format!("globstar-{}", std::process::id()));
+        for dir in ["a/b/c", "a/.hidden", "d"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "top.rs",
+            "a/one.rs",
+            "a/b/c/two.rs",
+            "a/.hidden/three.rs",
+            "d/four.txt",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let root_str = root.to_string_lossy().into_owned();
+        let rel = |pattern: &str| -> Vec<String> {
+            expand_with(
+                &format!("{root_str}/{pattern}"),
+                false,
+                Default::default(),
+                None,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|path| path[root_str.len() + 1..].to_string())
+            .collect()
+        };
+
+        assert_eq!(rel("**/*.rs"), ["a/b/c/two.rs", "a/one.rs", "top.rs"]);
+        assert_eq!(rel("a/**/c"), ["a/b/c"]);
+        assert_eq!(rel("**/**/*.txt"), ["d/four.txt"]);
+        assert_eq!(
+            rel("**"),
+            [
+                "a",
+                "a/b",
+                "a/b/c",
+                "a/b/c/two.rs",
+                "a/one.rs",
+                "d",
+                "d/four.txt",
+                "top.rs"
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_glob_qualifiers() {
+        use crate::glob::expand_with;
+        use std::time::{Duration, SystemTime};
+
+        let root = std::env::temp_dir().join(format!("globqual-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("dir.log")).unwrap();
+        std::fs::write(root.join("new.log"), "x".repeat(3000)).unwrap();
+        let old = std::fs::File::create(root.join("old.log")).unwrap();
+        old.set_modified(SystemTime::now() - Duration::from_secs(10 * 86400))
+            .unwrap();
+        let root_str = root.to_string_lossy().into_owned();
+        let rel = |pattern: &str| -> Result<Vec<String>, String> {
+            let expanded = expand_with(
+                &format!("{root_str}/{pattern}"),
+                true,
+                Default::default(),
+                None,
+            )?;
+            Ok(expanded
+                .into_iter()
+                .map(|path| path[root_str.len() + 1..].to_string())
+                .collect())
+        };
+
+        assert_eq!(rel("*.log(.)").unwrap(), ["new.log", "old.log"]);
+        assert_eq!(rel("*.log(/)").unwrap(), ["dir.log"]);
+        assert_eq!(rel("*.log(^/)").unwrap(), ["new.log", "old.log"]);
+        assert_eq!(rel("*.log(.mtime-7)").unwrap(), ["new.log"]);
+        assert_eq!(rel("*.log(.mh-1)").unwrap(), ["new.log"]);
+        assert_eq!(rel("*.log(mtime+7)").unwrap(), ["old.log"]);
+        assert_eq!(rel("*(.Lk+2)").unwrap(), ["new.log"]);
+        assert_eq!(rel("*(.size-1)").unwrap(), ["old.log"]);
+        assert_eq!(rel("*.txt(.)").unwrap(), ["*.txt(.)"]);
+        assert!(rel("*(.q)").is_err());
+        assert!(rel("*(L)").is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_glob_sort() {
+        use crate::glob::{expand_with, Order, Sort};
+        use std::time::{Duration, SystemTime};
+
+        let root = std::env::temp_dir().join(format!("globsort-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        for (name, size, days) in [("f10", 3, 1), ("f2", 1, 3), ("f1", 2, 0), ("f02x", 0, 2)] {
+            let file = std::fs::File::create(root.join(name)).unwrap();
+            file.set_len(size).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(days * 86400))
+                .unwrap();
+        }
+        let root_str = root.to_string_lossy().into_owned();
+        let sorted = |pattern: &str, sort: Option<&str>| -> Vec<String> {
+            let sort = sort.and_then(Sort::parse).unwrap_or_default();
+            expand_with(&format!("{root_str}/{pattern}"), true, sort, None)
+                .unwrap()
+                .into_iter()
+                .map(|path| path[root_str.len() + 1..].to_string())
+                .collect()
+        };
+
+        assert_eq!(sorted("*", None), ["f02x", "f1", "f10", "f2"]);
+        assert_eq!(sorted("*", Some("natural")), ["f1", "f2", "f02x", "f10"]);
+        assert_eq!(sorted("*", Some("-numeric")), ["f10", "f02x", "f2", "f1"]);
+        assert_eq!(sorted("*", Some("mtime")), ["f2", "f02x", "f10", "f1"]);
+        assert_eq!(sorted("*", Some("+size")), ["f02x", "f2", "f1", "f10"]);
+        assert_eq!(Sort::parse("bogus"), None);
+        assert_eq!(
+            Sort::parse("-size"),
+            Some(Sort {
+                order: Order::Size,
+                reverse: true
+            })
+        );
+
+        // Qualifiers override $GLOBSORT
+        assert_eq!(sorted("*(om)", Some("size")), ["f1", "f10", "f02x", "f2"]);
+        assert_eq!(sorted("*(OL)", None), ["f10", "f1", "f2", "f02x"]);
+        assert_eq!(sorted("*(nOn)", None), ["f10", "f02x", "f2", "f1"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    // Setting a directory's modification time goes through a handle to it
+    #[cfg(unix)]
+    #[test]
+    fn test_glob_cache() {
+        use crate::glob::{expand_with, DirCache};
+        use std::fs::File;
+
+        let root = std::env::temp_dir().join(format!("globcache-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        File::create(root.join("sub/a.rs")).unwrap();
+        let root_str = root.to_string_lossy().into_owned();
+        let cache = DirCache::default();
+        let names = |pattern: &str, cache: Option<&DirCache>| -> Vec<String> {
+            expand_with(
+                &format!("{root_str}/{pattern}"),
+                false,
+                Default::default(),
+                cache,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|path| path[root_str.len() + 1..].to_string())
+            .collect()
+        };
+        assert_eq!(names("sub/*.rs", Some(&cache)), ["sub/a.rs"]);
+        assert_eq!(names("**/*.rs", Some(&cache)), ["sub/a.rs"]);
+
+        // A listing is used while the directory looks unchanged
+        let modified = std::fs::metadata(root.join("sub"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        File::create(root.join("sub/b.rs")).unwrap();
+        File::open(root.join("sub"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(names("sub/*.rs", Some(&cache)), ["sub/a.rs"]);
+        assert_eq!(names("sub/*.rs", None), ["sub/a.rs", "sub/b.rs"]);
+
+        // And read again once it's changed, or the cache is cleared
+        File::create(root.join("sub/c.rs")).unwrap();
+        assert_eq!(names("sub/*.rs", Some(&cache)).len(), 3);
+        std::fs::remove_file(root.join("sub/c.rs")).unwrap();
+        File::open(root.join("sub"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        cache.clear();
+        assert_eq!(names("**/*.rs", Some(&cache)), ["sub/a.rs", "sub/b.rs"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_glob_qualifier_parsing() {
+        use crate::shell::ShellState;
+
+        assert_eq!(
+            parse_command("ls *.log(.m-7) \"*(x)\"").unwrap().argv,
+            vec![
+                Arg::Word("ls".to_string()),
+                Arg::Glob("*.log(.m-7)".to_string()),
+                Arg::Word("*(x)".to_string())
+            ]
+        );
+
+        let mut shell = ShellState::default();
+        assert_eq!(shell.eval("set -o glob-qualifiers; x=*(.q)").unwrap(), 0);
+        assert_ne!(shell.eval("echo *(.q)").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_expansion_limits() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("EXPANSION_MAX_WORDS=4; big=(a b c d e)")
+            .unwrap();
+        assert_eq!(shell.variables.get("big"), None);
+        assert_ne!(shell.eval("x=(a b c d); echo ${x[@]}").unwrap(), 0);
+
+        shell
+            .eval("EXPANSION_MAX_BYTES=8; y=12345678; z=${y}9")
+            .unwrap();
+        assert_eq!(shell.variables.get("y"), Some("12345678"));
+        assert_eq!(shell.variables.get("z"), None);
+
+        // Output that doesn't end is only read up to the limit
+        shell
+            .eval("EXPANSION_MAX_BYTES=1000; w=$(range 1 1000000000000)")
+            .unwrap();
+        assert_eq!(shell.variables.get("w"), None);
+        assert_ne!(shell.eval("capture w range 1 1000000000000").unwrap(), 0);
+        assert_eq!(shell.variables.get("w"), None);
+        shell.eval("EXPANSION_MAX_BYTES=8").unwrap();
+
+        // 0 takes the limit away
+        shell.eval("EXPANSION_MAX_BYTES=0; z=${y}9").unwrap();
+        assert_eq!(shell.variables.get("z"), Some("123456789"));
+    }
+
+    #[test]
+    fn test_command_substitution_and_capture() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("x=$(echo one; echo two); y=\"<$(echo a b)>\"; z=$(x=changed; echo $x)")
+            .unwrap();
+        assert_eq!(shell.variables.get("x"), Some("one\ntwo"));
+        assert_eq!(shell.variables.get("y"), Some("<a b>"));
+        assert_eq!(shell.variables.get("z"), Some("changed"));
+
+        assert_eq!(
+            shell
+                .eval("capture out echo hello; capture -a lines printf 'a\\nb\\n\\n'")
+                .unwrap(),
+            0
+        );
+        assert_eq!(shell.variables.get("out"), Some("hello"));
+        assert_eq!(
+            shell.variables.get_array("lines"),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+
+        // The command's status comes back, and it can't change the shell
+        assert_eq!(
+            shell
+                .eval("f() { x=inside; echo out; return 3; }; capture out f")
+                .unwrap(),
+            3
+        );
+        assert_eq!(shell.variables.get("out"), Some("out"));
+        assert_eq!(shell.variables.get("x"), Some("one\ntwo"));
+    }
+
+    #[test]
+    fn test_traps_and_line_numbers() {
+        use crate::shell::ShellState;
+
+        let script = std::env::temp_dir().join(format!("traps-{}.sh", std::process::id()));
+        std::fs::write(
+            &script,
+            "# failures are recorded with their line\n\
+             trap 'failed=\"$failed $LINENO:$?\"' ERR\n\
+             trap 'ran=$((ran + 1))' DEBUG\n\
+             \n\
+             false\n\
+             false && true\n\
+             true \\\n\
+             \x20 && false\n\
+             where=$SIGSH_SOURCE:$LINENO\n",
+        )
+        .unwrap();
+
+        let mut shell = ShellState::default();
+        shell
+            .eval(&format!("ran=0; source {}", script.display()))
+            .unwrap();
+        assert_eq!(shell.variables.get("failed"), Some(" 5:1 7:1"));
+        assert_eq!(shell.variables.get("ran"), Some("5"));
+        assert_eq!(
+            shell.variables.get("where"),
+            Some(format!("{}:9", script.display()).as_str())
+        );
+        assert_eq!(shell.location.file, None);
+
+        // `-` takes a trap away again
+        shell.eval("failed=; trap - ERR; false").unwrap();
+        assert_eq!(shell.variables.get("failed"), Some(""));
+
+        std::fs::remove_file(&script).unwrap();
+    }
+
+    #[test]
+    fn test_xtrace_to_an_fd_with_ps4() {
+        use crate::shell::ShellState;
+
+        let trace = std::env::temp_dir().join(format!("xtrace-{}", std::process::id()));
+        let script = std::env::temp_dir().join(format!("xtrace-{}.sh", std::process::id()));
+        std::fs::write(
+            &script,
+            "set -x\n\
+             echo \"a b\" $(true) >/dev/null\n\
+             false\n\
+             set +x\n",
+        )
+        .unwrap();
+
+        let mut shell = ShellState::default();
+        let setup = format!(
+            "exec 9>{}; SIGSH_XTRACEFD=9; PS4='+$LINENO$(false)> '",
+            trace.display()
+        );
+        assert_eq!(shell.eval(&setup).unwrap(), 0);
+        shell.eval(&format!("source {}", script.display())).unwrap();
+        assert_eq!(shell.variables.get("SUBSTATUS"), Some("0"));
+        shell.eval("exec 9>&-").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&trace).unwrap(),
+            "+2> true\n+2> echo 'a b'\n+3> false\n+4> set +x\n"
+        );
+
+        std::fs::remove_file(&script).unwrap();
+        std::fs::remove_file(&trace).unwrap();
+    }
+
+    #[test]
+    fn test_traps_in_subshells() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("n=0; trap 'n=$((n + 1))' ERR; trap '' DEBUG")
+            .unwrap();
+
+        // The subshell's own failure doesn't set off the trap, only its status
+        shell.eval("(false; true; false)").unwrap();
+        assert_eq!(shell.variables.get("n"), Some("1"));
+
+        // Traps a subshell sets or removes stay its own
+        shell.eval("(trap 'x=1' ERR; trap - DEBUG)").unwrap();
+        assert_eq!(shell.traps.len(), 2);
+        assert_eq!(
+            shell.traps.get("ERR").map(String::as_str),
+            Some("n=$((n + 1))")
+        );
+
+        // What `trap -p` prints sets them again, `--` and all
+        shell
+            .eval(r#"trap - ERR DEBUG; trap -- 'echo "it'\''s"' ERR"#)
+            .unwrap();
+        assert_eq!(
+            shell.traps.get("ERR").map(String::as_str),
+            Some(r#"echo "it's""#)
+        );
+        assert!(!matches!(shell.eval("trap -p INT"), Ok(0)));
+    }
+
+    #[test]
+    fn test_profiler() {
+        use crate::profiler::Profiler;
+        use crate::shell::ShellState;
+
+        let script = std::env::temp_dir().join(format!("profile-{}.sh", std::process::id()));
+        std::fs::write(&script, "f() { sleep 0.02; }\nx=1\nf\nf | f\n").unwrap();
+
+        let mut shell = ShellState {
+            profiler: Some(Profiler::default()),
+            ..Default::default()
+        };
+        shell.source(&script).unwrap();
+        let report = shell.profiler.unwrap().report();
+
+        // Calls, location and command, slowest first
+        let script = script.display();
+        let rows: Vec<String> = report
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let fields: Vec<_> = line.split_whitespace().collect();
+                format!("{} {} {}", fields[1], fields[3], fields[4..].join(" "))
+            })
+            .collect();
+        // The pipeline's first stage is forked, so only its last stage's body
+        // is counted on its own, but each call's time is in its caller's too
+        assert_eq!(rows[0], format!("2 {script}:1 {{ ... }}"));
+        assert_eq!(rows[1], format!("2 {script}:1 sleep"));
+        assert!(rows.contains(&format!("1 {script}:4 f | f")));
+        assert!(rows.contains(&format!("1 {script}:3 f")));
+        assert!(rows.contains(&format!("1 {script}:2 x=")));
+
+        std::fs::remove_file(script.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_multiline_history_entries() {
+        use crate::history::History;
+
+        let path = std::env::temp_dir().join(format!("multiline-history-{}", std::process::id()));
+        let commands = [
+            "for i in 1 2\ndo echo $i\ndone",
+            "echo ends in a backslash\\",
+            "echo \\\\\necho after two",
+            "true",
+        ];
+        let mut history = History::open(Some(path.clone()));
+        for command in commands {
+            history.add(command).unwrap();
+        }
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written.lines().count(), 7);
+        let reloaded = History::open(Some(path.clone()));
+        let entries: Vec<_> = reloaded
+            .entries()
+            .iter()
+            .map(|e| e.command.as_str())
+            .collect();
+        assert_eq!(entries, commands);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_history_search() {
+        use crate::history::History;
+
+        let mut history = History::default();
+        for i in 0..100_000 {
+            history.add(&format!("echo {i}")).unwrap();
+        }
+        history.add("make test").unwrap();
+        history.add("ls").unwrap();
+
+        let found: Vec<_> = history.search("99999").collect();
+        assert_eq!(found, vec![99_999]);
+        assert_eq!(history.search("make").next(), Some(100_000));
+        assert_eq!(history.search("ls").next_back(), Some(100_001));
+        assert_eq!(history.search("not there").next(), None);
+
+        // Deleting renumbers what's after, and replacing is searchable at once
+        history.delete(0).unwrap();
+        assert_eq!(history.search("make").next(), Some(99_999));
+        history.replace_last("cargo build").unwrap();
+        assert_eq!(history.search("cargo").next(), Some(100_000));
+        assert_eq!(history.search("ls").next(), None);
+    }
+
+    #[test]
+    fn test_history_redaction() {
+        use crate::history::{History, Redaction};
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval(r"HISTIGNORE='*secret*:vault *'; HISTREDACT=('TOKEN=(\S+)' '--password[= ](\S+)' 'hunter2')")
+            .unwrap();
+        let mut history = History::default();
+        history.set_redaction(Redaction::from_variables(&shell.variables).unwrap());
+        for command in [
+            "TOKEN=abc API_TOKEN=def deploy",
+            "login --password pw1 --user me --password=pw2",
+            "echo hunter2",
+            "cat secret.txt",
+            "vault read kv",
+            "ls",
+        ] {
+            history.add(command).unwrap();
+        }
+        let commands: Vec<_> = history
+            .entries()
+            .iter()
+            .map(|e| e.command.as_str())
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "TOKEN=*** API_TOKEN=*** deploy",
+                "login --password *** --user me --password=***",
+                "echo ***",
+                "ls",
+            ]
+        );
+
+        // Replacing the last entry goes through the same rules
+        history.replace_last("echo TOKEN=xyz").unwrap();
+        assert_eq!(history.entries()[3].command, "echo TOKEN=***");
+        history.replace_last("vault write").unwrap();
+        assert_eq!(history.entries().len(), 3);
+
+        shell.eval("HISTREDACT=('[unclosed')").unwrap();
+        assert!(Redaction::from_variables(&shell.variables).is_err());
+    }
+
+    #[test]
+    fn test_history_merges_other_shells() {
+        use crate::history::History;
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("history-{}", std::process::id()));
+        std::fs::write(&path, "100 echo one\n300 echo three\n").unwrap();
+        let other = |line: &str| {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            writeln!(file, "{line}").unwrap();
+        };
+        let commands = |history: &History| -> Vec<String> {
+            history
+                .entries()
+                .iter()
+                .map(|e| e.command.clone())
+                .collect()
+        };
+
+        let mut history = History::open(Some(path.clone()));
+        other("200 echo two");
+        history.add("echo mine").unwrap();
+
+        // Rewriting keeps what the other shell added, in order, and ours once
+        history.delete(0).unwrap();
+        assert_eq!(commands(&history), ["echo two", "echo three", "echo mine"]);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.matches("echo mine").count(), 1);
+        assert!(contents.starts_with("200 echo two\n300 echo three\n"));
+
+        // So does saving at exit, if the file grew
+        other("250 echo later");
+        history.save().unwrap();
+        let reloaded = History::open(Some(path.clone()));
+        assert_eq!(
+            commands(&reloaded),
+            ["echo two", "echo later", "echo three", "echo mine"]
+        );
+        history.save().unwrap();
+        assert_eq!(commands(&History::open(Some(path.clone()))).len(), 4);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_assignment_parsing() {
+        let input = "FOO=bar BAZ=\"$HOME\"/bin env";
+        let command = parse_command(input).expect("Failed to parse command");
+
+        assert_eq!(command.argv, vec![Arg::Word("env".to_string())]);
+        assert_eq!(
+            command.assignments,
+            vec![
+                Assignment {
+                    name: "FOO".to_string(),
+                    value: Arg::Word("bar".to_string()),
+                },
+                Assignment {
+                    name: "BAZ".to_string(),
+                    value: Arg::Concat(vec![
+                        Arg::Quoted(vec![Arg::Variable("HOME".to_string())]),
+                        Arg::Word("/bin".to_string()),
+                    ]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_and_function_parsing() {
+        let command = parse_command("f() { echo hi; }").expect("Failed to parse command");
+        let body = Command {
+            compound: Some(Box::new(Compound::Group(Command {
+                argv: vec![Arg::Word("echo".to_string()), Arg::Word("hi".to_string())],
+                ..Default::default()
+            }))),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            command.compound,
+            Some(Box::new(Compound::FunctionDef {
+                name: "f".to_string(),
+                body,
+            }))
+        );
+
+        assert!(parse_command("{ echo hi;").is_none());
+        assert!(parse_command("echo }").is_some());
+        assert!(parse_command("}").is_none());
+    }
+
+    #[test]
+    fn test_spawn_parsing() {
+        let command =
+            parse_command("spawn build { make | tee log; }").expect("Failed to parse command");
+        let Some(Compound::Spawn { name, body }) = command.compound.as_deref() else {
+            panic!("expected spawn");
+        };
+        assert_eq!(name, "build");
+        assert!(
+            matches!(body.compound.as_deref(), Some(Compound::Group(group)) if group.pipe_to.is_some())
+        );
+        assert_eq!(command.to_string(), "spawn build { make | tee log; }");
+
+        // Without a body it's just a command
+        let command = parse_command("spawn build").expect("Failed to parse command");
+        assert_eq!(
+            command.argv,
+            vec![
+                Arg::Word("spawn".to_string()),
+                Arg::Word("build".to_string())
+            ]
+        );
+        assert!(parse_command("spawn build { make").is_none());
+    }
+
+    #[test]
+    fn test_subshell_group_parsing() {
+        let command = parse_command("(cd /tmp; ls)").expect("Failed to parse command");
+
+        let Some(compound) = command.compound else {
+            panic!("expected a compound command");
+        };
+        let Compound::Subshell(body) = *compound else {
+            panic!("expected a subshell");
+        };
+        assert_eq!(
+            body.argv,
+            vec![Arg::Word("cd".to_string()), Arg::Word("/tmp".to_string())]
+        );
+        assert!(body.and_then.is_some());
+    }
+
+    #[test]
+    fn test_alias_expansion() {
+        let mut aliases = Aliases::new();
+        aliases.insert("ls".to_string(), "ls -F".to_string());
+        aliases.insert("ll".to_string(), "ls -l".to_string());
+
+        let command = Command::parse_with_aliases("ll src; echo ll", &aliases).unwrap();
+        assert_eq!(
+            command.argv,
+            vec![
+                Arg::Word("ls".to_string()),
+                Arg::Word("-F".to_string()),
+                Arg::Word("-l".to_string()),
+                Arg::Word("src".to_string()),
+            ]
+        );
+        let next = &command.and_then.as_ref().unwrap().target;
+        assert_eq!(
+            next.argv,
+            vec![Arg::Word("echo".to_string()), Arg::Word("ll".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell.eval("X=1; alias a=b").unwrap();
+        let snapshot = shell.snapshot().unwrap();
+
+        shell
+            .eval("X=2; unalias a; set -u; f() { return 1; }; exec 9>/dev/null")
+            .unwrap();
+        assert_eq!(shell.variables.get("X"), Some("2"));
+        assert!(shell.functions.contains_key("f"));
+        assert!(shell.fds.contains_key(&9));
+
+        shell.restore(snapshot).unwrap();
+        assert_eq!(shell.variables.get("X"), Some("1"));
+        assert_eq!(shell.aliases.get("a").map(String::as_str), Some("b"));
+        assert!(!shell.options.nounset);
+        assert!(shell.functions.is_empty());
+        assert!(shell.fds.is_empty());
+        assert!(!crate::platform::is_open(9));
+    }
+
+    #[test]
+    fn test_builtin_subshell_runs_in_process() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        let status = shell.eval("X=1; (X=2; set -x; exit 3)").unwrap();
+        assert_eq!(status, 3);
+        assert_eq!(shell.variables.get("X"), Some("1"));
+        assert!(!shell.options.xtrace);
+        assert_eq!(shell.exit, None);
+
+        assert_eq!(shell.eval_isolated("Y=1; exit 4").unwrap(), 4);
+        assert_eq!(shell.variables.get("Y"), None);
+        assert_eq!(shell.exit, None);
+    }
+
+    #[test]
+    fn test_array_assignment_parsing() {
+        let command = parse_command("arr=(a \"$b\") arr[2]=c").expect("Failed to parse command");
+
+        assert_eq!(
+            command.assignments,
+            vec![
+                Assignment {
+                    name: "arr".to_string(),
+                    value: Arg::Array(vec![
+                        Arg::Word("a".to_string()),
+                        Arg::Concat(vec![
+                            Arg::Word(String::new()),
+                            Arg::Quoted(vec![Arg::Variable("b".to_string())]),
+                        ]),
+                    ]),
+                },
+                Assignment {
+                    name: "arr[2]".to_string(),
+                    value: Arg::Word("c".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        use crate::arith;
+        use crate::vars::Variables;
+
+        let mut vars = Variables::default();
+        assert_eq!(arith::eval("1 + 2 * 3", &mut vars), Ok(7));
+        assert_eq!(arith::eval("-2 ** 2", &mut vars), Ok(4));
+        assert_eq!(arith::eval("x = 5, x += 2, x++", &mut vars), Ok(7));
+        assert_eq!(vars.get("x"), Some("8"));
+        assert_eq!(arith::eval("x > 7 ? 0x10 : 0", &mut vars), Ok(16));
+        assert_eq!(arith::eval("0 && y++", &mut vars), Ok(0));
+        assert_eq!(vars.get("y"), None);
+        assert!(arith::eval("1 / 0", &mut vars).is_err());
+        assert!(arith::eval("1 +", &mut vars).is_err());
+
+        assert_eq!(
+            arith::eval("0x1F + 0o755 + 0b11", &mut vars),
+            Ok(31 + 493 + 3)
+        );
+        assert_eq!(arith::eval("2#1010 * 16#ff", &mut vars), Ok(10 * 255));
+        assert_eq!(
+            arith::eval("36#Z + 64#_ + 64#Z + 64#@", &mut vars),
+            Ok(35 + 63 + 61 + 62)
+        );
+        assert!(arith::eval("8#9", &mut vars).is_err());
+        assert!(arith::eval("65#1", &mut vars).is_err());
+        assert!(arith::eval("0x", &mut vars).is_err());
+
+        let nested = format!("{}1{}", "(".repeat(3000), ")".repeat(3000));
+        assert!(arith::eval(&nested, &mut vars).is_err());
+        assert!(arith::eval(&format!("{}1", "-".repeat(3000)), &mut vars).is_err());
+        assert!(arith::eval(&vec!["1"; 3000].join("**"), &mut vars).is_err());
+        let nested = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(arith::eval(&nested, &mut vars), Ok(1));
+        // Only nesting is limited, however long a chain of operators
+        assert_eq!(arith::eval(&vec!["1"; 1500].join("+"), &mut vars), Ok(1500));
+        let long = vec!["1"; 100_000].join(" && ");
+        assert_eq!(arith::eval(&long, &mut vars), Ok(1));
+        assert_eq!(
+            arith::eval(&vec!["x = 1"; 100_000].join(","), &mut vars),
+            Ok(1)
+        );
+
+        vars.set("a", "a + 1").unwrap();
+        assert!(arith::eval("a", &mut vars).is_err());
+    }
+
+    #[test]
+    fn test_let() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        assert_eq!(shell.eval("let a=2#101 'b = a * 2'").unwrap(), 0);
+        assert_eq!(shell.variables.get("a"), Some("5"));
+        assert_eq!(shell.variables.get("b"), Some("10"));
+        assert_eq!(shell.eval("let b-=10").unwrap(), 1);
+        assert_eq!(shell.eval("let 1/0").unwrap(), 1);
+        assert_eq!(shell.eval("let").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_regex() {
+        use crate::regex::Regex;
+
+        fn captures<'a>(regex: &str, text: &'a str) -> Option<Vec<Option<&'a str>>> {
+            let captures = Regex::new(regex).unwrap().captures(text)?;
+            let groups = captures
+                .into_iter()
+                .map(|range| range.map(|range| &text[range]));
+            Some(groups.collect())
+        }
+        assert_eq!(
+            captures("([a-z]+)-([0-9]{2,3})(x)?", "id: abc-1234"),
+            Some(vec![Some("abc-123"), Some("abc"), Some("123"), None])
+        );
+        assert_eq!(
+            captures("^[[:upper:]]\\w*$", "Hello_1"),
+            Some(vec![Some("Hello_1")])
+        );
+        assert_eq!(captures("^[[:upper:]]", "hello"), None);
+        assert_eq!(captures("cat|dog", "hotdog"), Some(vec![Some("dog")]));
+        assert_eq!(captures("a{b", "a{b"), Some(vec![Some("a{b")]));
+        assert_eq!(captures("(a*)*c", &"a".repeat(1000)), None);
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+
+        let nested = format!("{}a{}", "(".repeat(3000), ")".repeat(3000));
+        assert!(Regex::new(&nested).is_err());
+        assert!(Regex::new(&format!("a{}", "?".repeat(100_000))).is_err());
+        let nested = format!("{}a{}", "(".repeat(100), ")*".repeat(100));
+        assert!(captures(&nested, "aa").is_some());
+    }
+
+    #[test]
+    fn test_conditional() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        assert_eq!(shell.eval("[[ abc == a* ]]").unwrap(), 0);
+        assert_eq!(shell.eval("[[ abc == 'a*' ]]").unwrap(), 1);
+        assert_eq!(shell.eval("[[ 'a*' == \"a*\" ]]").unwrap(), 0);
+        assert_eq!(shell.eval("p='*c'; [[ abc == $p ]]").unwrap(), 0);
+        assert_eq!(shell.eval("[[ ! -z 'a b' ]]").unwrap(), 0);
+        assert_eq!(shell.eval("[[ 2+2 -eq 4 ]]").unwrap(), 0);
+        assert_eq!(shell.eval("[[ apple > banana ]]").unwrap(), 1);
+
+        shell.eval("v='key=value, other=x'").unwrap();
+        assert_eq!(
+            shell.eval("[[ $v =~ ^([a-z]+)=([^,]*)(,)?(!)? ]]").unwrap(),
+            0
+        );
+        assert_eq!(shell.variables.get("MATCH"), Some("key=value,"));
+        assert_eq!(
+            shell.variables.get_array("match"),
+            Some(vec![
+                "key".to_string(),
+                "value".to_string(),
+                ",".to_string(),
+                String::new()
+            ])
+        );
+        assert_eq!(shell.eval("[[ $v =~ ^[0-9]+$ ]]").unwrap(), 1);
+        assert_eq!(shell.variables.get("MATCH"), None);
+
+        assert_eq!(shell.eval("[[ a =~ ( ]]").unwrap(), 2);
+
+        // Backslashes escape, and quoted parts match literally
+        assert_eq!(shell.eval("[[ abc =~ a\\.c ]]").unwrap(), 1);
+        assert_eq!(shell.eval("[[ a+b =~ ^a\\+b$ ]]").unwrap(), 0);
+        assert_eq!(shell.eval("[[ abc =~ \"a.c\" ]]").unwrap(), 1);
+        assert_eq!(shell.eval("[[ a.c =~ ^'a.c'$ ]]").unwrap(), 0);
+        assert_eq!(shell.eval("[[ x =~ \"(\" ]]").unwrap(), 1);
+        assert_eq!(shell.eval("[[ 'f(x)' =~ ^[a-z]\"(\"x ]]").unwrap(), 0);
+        assert_eq!(shell.eval("re='a.c'; [[ abc =~ $re ]]").unwrap(), 0);
+        assert_eq!(shell.eval("[[ abc =~ \"$re\" ]]").unwrap(), 1);
+
+        assert_eq!(shell.eval("[[ a b ]]").unwrap(), 2);
+        assert_eq!(shell.eval("[[ a").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_intercept() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("handler() { CALLS=$((CALLS+1)); SEEN=\"$*\"; true \"$@\"; }")
+            .unwrap();
+        shell.eval("intercept true handler -v").unwrap();
+        shell.eval("intercept -w '-*f*' -x true").unwrap();
+
+        // The handler gets the whole argv, and can run the real command itself
+        assert_eq!(shell.eval("/bin/true a b").unwrap(), 0);
+        assert_eq!(shell.variables.get("SEEN"), Some("-v /bin/true a b"));
+        assert_eq!(shell.variables.get("CALLS"), Some("1"));
+
+        assert_eq!(shell.eval("true -rf /").unwrap(), 1);
+        assert_eq!(shell.variables.get("CALLS"), Some("1"));
+
+        assert_eq!(shell.eval("intercept -r true").unwrap(), 0);
+        assert_eq!(shell.eval("true -f").unwrap(), 0);
+        assert_eq!(shell.variables.get("CALLS"), Some("1"));
+        assert_eq!(shell.eval("intercept -r true").unwrap(), 1);
+        assert_eq!(shell.eval("intercept -x").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_plugins() {
+        use crate::plugin::Plugin;
+        use crate::shell::ShellState;
+
+        /// Drops a leading `please`, and counts the commands it sees
+        struct Please(usize);
+
+        impl Plugin for Please {
+            fn name(&self) -> &str {
+                "please"
+            }
+
+            fn transform(
+                &mut self,
+                _shell: &ShellState,
+                command: &mut Command,
+            ) -> Result<(), String> {
+                self.0 += 1;
+                if command.argv.first() == Some(&Arg::Word("please".to_string())) {
+                    command.argv.remove(0);
+                }
+                Ok(())
+            }
+        }
+
+        struct Policy;
+
+        impl Plugin for Policy {
+            fn name(&self) -> &str {
+                "policy"
+            }
+
+            fn transform(
+                &mut self,
+                shell: &ShellState,
+                command: &mut Command,
+            ) -> Result<(), String> {
+                match shell.variables.get("FORBID") {
+                    Some(word) if command.argv.contains(&Arg::Word(word.to_string())) => {
+                        Err(format!("{word} isn't allowed"))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let mut shell = ShellState::default();
+        shell.add_plugin(Box::new(Please(0)));
+        shell.add_plugin(Box::new(Policy));
+        assert_eq!(
+            shell.plugin_names().collect::<Vec<_>>(),
+            ["please", "policy"]
+        );
+
+        assert_eq!(shell.eval_interactive("please declare x=1").unwrap(), 0);
+        assert_eq!(shell.variables.get("x"), Some("1"));
+        // Only what's typed at the prompt goes through plugins
+        assert_ne!(shell.eval("please declare y=1").unwrap(), 0);
+        assert_eq!(shell.variables.get("y"), None);
+
+        shell.eval_interactive("FORBID=danger").unwrap();
+        let err = shell.eval_interactive("please echo danger").unwrap_err();
+        assert_eq!(err.to_string(), "policy: danger isn't allowed");
+        assert_eq!(shell.last_status, 1);
+    }
+
+    #[test]
+    fn test_declare_attributes() {
+        use crate::shell::ShellState;
+        use crate::vars::Value;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("declare -i n=2+3; declare -u up=abc; declare -l low=ABC; declare -r ro=1")
+            .unwrap();
+        assert_eq!(shell.variables.get("n"), Some("5"));
+        assert_eq!(shell.variables.get("up"), Some("ABC"));
+        assert_eq!(shell.variables.get("low"), Some("abc"));
+
+        shell.eval("n=n*2; up=def").unwrap();
+        assert_eq!(shell.variables.get("n"), Some("10"));
+        assert_eq!(shell.variables.get("up"), Some("DEF"));
+
+        assert_ne!(shell.eval("ro=2").unwrap(), 0);
+        assert_ne!(shell.eval("unset ro").unwrap(), 0);
+        assert_eq!(shell.variables.get("ro"), Some("1"));
+
+        shell
+            .eval("declare -A m=([a]=1 [b]=2); m[c]=3; declare -a arr=(x y); arr[3]=z")
+            .unwrap();
+        assert_eq!(
+            shell.variables.var("m").map(|var| &var.value),
+            Some(&Value::Assoc(
+                [("a", "1"), ("b", "2"), ("c", "3")]
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .into()
+            ))
+        );
+        assert_eq!(
+            shell.variables.get_array("arr"),
+            Some(vec!["x".into(), "y".into(), String::new(), "z".into()])
+        );
+        assert_ne!(shell.eval("declare -a m").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_readonly_builtin() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell.eval("X=1; readonly X Y=2").unwrap();
+        assert!(shell
+            .variables
+            .var("X")
+            .is_some_and(|var| var.attrs.readonly));
+
+        assert_ne!(shell.eval("X=3").unwrap(), 0);
+        assert_ne!(shell.eval("export Y=3").unwrap(), 0);
+        assert_ne!(shell.eval("unset Y").unwrap(), 0);
+        assert_ne!(shell.eval("declare +r X").unwrap(), 0);
+        assert_eq!(shell.variables.get("X"), Some("1"));
+        assert_eq!(shell.variables.get("Y"), Some("2"));
+    }
+
+    #[test]
+    fn test_local_variables() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval(
+                "x=global; inner() { y=$x; x=inner; }; f() { local x=local; local -; set -u; inner; }",
+            )
+            .unwrap();
+        shell.eval("f").unwrap();
+        // `inner` saw and changed f's local, not the global
+        assert_eq!(shell.variables.get("y"), Some("local"));
+        assert_eq!(shell.variables.get("x"), Some("global"));
+        assert!(!shell.options.nounset);
+
+        assert_ne!(shell.eval("local z=1").unwrap(), 0);
+        assert_eq!(shell.variables.get("z"), None);
+    }
+
+    #[test]
+    fn test_prompt_hooks() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("precmd() { order=${order}p; }; PROMPT_COMMAND=('order=${order}1' 'order=${order}2')")
+            .unwrap();
+        shell.eval("declare -i n=1/0").unwrap();
+        let status = shell.last_status;
+        shell.run_prompt_hooks();
+        assert_eq!(shell.variables.get("order"), Some("p12"));
+        assert_eq!(shell.last_status, status);
+
+        shell.eval("PROMPT_COMMAND='exit 3'").unwrap();
+        shell.run_prompt_hooks();
+        assert_eq!(shell.exit, Some(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        assert_eq!(shell.eval("timeout 0.1 sleep 5").unwrap(), 124);
+        assert_eq!(shell.eval("timeout 5 sleep 0").unwrap(), 0);
+
+        // Functions and builtins run in a process of their own
+        assert_eq!(
+            shell.eval("f() { X=1; sleep 5; }; timeout 0.1 f").unwrap(),
+            124
+        );
+        assert_eq!(shell.eval("timeout 1 exit 7").unwrap(), 7);
+        assert_eq!(shell.variables.get("X"), None);
+        assert_eq!(shell.exit, None);
+
+        assert_eq!(shell.eval("timeout 1m").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_listing_columns() {
+        use crate::listing::{columns, Cell};
+
+        let cells: Vec<Cell> = ["a", "bb", "ccc", "d", "e"]
+            .iter()
+            .map(|name| Cell::plain(name))
+            .collect();
+        // Each column is as wide as the widest name plus two spaces
+        assert_eq!(columns(&cells, 12), "a    d\nbb   e\nccc\n");
+        assert_eq!(columns(&cells, 0), "a\nbb\nccc\nd\ne\n");
+        assert_eq!(columns(&cells, 80), "a    bb   ccc  d    e\n");
+    }
+
+    #[test]
+    fn test_builtins_describe_themselves() {
+        for name in crate::builtins::names() {
+            let builtin = crate::builtins::find(name).unwrap();
+            assert!(
+                builtin.description().ends_with('.'),
+                "{name} has no description"
+            );
+            assert!(builtin.usage().starts_with(&format!("usage: {name}")));
+        }
+    }
+
+    #[test]
+    fn test_completion_matching() {
+        use crate::complete::{complete, Matcher};
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        let exact = Matcher::new(&shell.options, "foo");
+        assert!(exact.matches("foo_bar", "foo"));
+        assert!(!exact.matches("Foo", "foo"));
+        assert!(!exact.matches("fo", "foo"));
+
+        shell.eval("set -o completion-smart-case").unwrap();
+        assert!(Matcher::new(&shell.options, "make").matches("Makefile", "make"));
+        assert!(!Matcher::new(&shell.options, "Mo").matches("more", "Mo"));
+
+        shell
+            .eval("set +o completion-smart-case -o completion-ignore-case -o completion-map-case")
+            .unwrap();
+        let matcher = Matcher::new(&shell.options, "Foo-B");
+        assert!(matcher.matches("foo_bar", "Foo-B"));
+
+        shell.eval("MY_VAR=1").unwrap();
+        let line: Vec<char> = "echo $my-v".chars().collect();
+        let completion = complete(&mut shell, &line, line.len());
+        assert_eq!(completion.start, 5);
+        assert_eq!(completion.candidates, vec!["$MY_VAR".to_string()]);
+        assert!(!completion.files);
+    }
+
+    #[test]
+    fn test_completion_context() {
+        use crate::complete::complete;
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell.eval("MY_VAR=1").unwrap();
+        let mut complete_line = |line: &str| {
+            let line: Vec<char> = line.chars().collect();
+            complete(&mut shell, &line, line.len())
+        };
+
+        // Commands after an operator, or inside $( and (
+        for line in ["ls | ec", "true && ec", "false; ec", "echo \"$(ec", "(ec"] {
+            let completion = complete_line(line);
+            assert_eq!(completion.start, line.len() - 2, "{line}");
+            assert!(
+                completion.candidates.contains(&"echo".to_string()),
+                "{line}"
+            );
+            assert!(!completion.files, "{line}");
+        }
+        // Back to the arguments of the command around $(...)
+        let completion = complete_line("echo $(true) $MY_");
+        assert_eq!(completion.candidates, vec!["$MY_VAR".to_string()]);
+
+        // Files after a redirection, even where a command would go
+        for line in ["ec > ec", "cat <ec", "ec 2>ec", "ec &>>ec", "> ec"] {
+            let completion = complete_line(line);
+            assert_eq!(completion.start, line.len() - 2, "{line}");
+            assert!(completion.files, "{line}");
+        }
+        // The target isn't one of the command's words
+        let completion = complete_line("> out ec");
+        assert!(completion.candidates.contains(&"echo".to_string()));
+        assert!(!completion.files);
+
+        let completion = complete_line("echo pre${MY_");
+        assert_eq!(completion.start, 5);
+        assert_eq!(completion.candidates, vec!["pre${MY_VAR}".to_string()]);
+        assert!(!completion.files);
+    }
+
+    #[test]
+    fn test_bracket_pairs() {
+        use crate::editor::{get_pair_match, pairs};
+
+        let line: Vec<char> = r#"echo "$(ls '(')" ${x} [a\]] }"#.chars().collect();
+        let (found, unmatched) = pairs(&line);
+        let partner = |c: char, nth: usize| {
+            let index = line
+                .iter()
+                .enumerate()
+                .filter(|&(_, &other)| other == c)
+                .nth(nth)
+                .unwrap()
+                .0;
+            get_pair_match(&found, index).map(|other| line[other])
+        };
+        assert_eq!(partner('"', 0), Some('"'));
+        assert_eq!(partner('(', 0), Some(')'));
+        // Inside single quotes, a bracket is just a character
+        assert_eq!(partner('(', 1), None);
+        assert_eq!(partner('\'', 0), Some('\''));
+        assert_eq!(partner('{', 0), Some('}'));
+        assert_eq!(partner('[', 0), Some(']'));
+        // The escaped `]` closes nothing, so the next one closes `[`
+        assert_eq!(get_pair_match(&found, 26), Some(22));
+        assert_eq!(unmatched, vec![line.len() - 1]);
+
+        let (_, unmatched) = pairs(&"(a]) {".chars().collect::<Vec<_>>());
+        assert_eq!(unmatched, vec![2]);
+    }
+
+    #[test]
+    fn test_unparse_round_trip() {
+        for input in [
+            "echo 'a b' \"$HOME/x \\$y\" ${z:-d} $((1 + 2)) *.rs a$b'c'",
+            "X=1 arr=(a 'b c') cmd <in >out 2>>err 3<&- | tr a b |& cat && f; g",
+            "f() { echo $(date) \"$(ls | wc -l)\"; (cd /; pwd) > log; }",
+            "[[ $a == x* ]] && echo \"it's\"",
+        ] {
+            let command = parse_command(input).unwrap();
+            let source = command.to_string();
+            assert_eq!(parse_command(&source), Some(command), "{input} -> {source}");
+        }
+    }
+
+    #[test]
+    fn test_pretty_functions() {
+        use crate::shell::ShellState;
+        use crate::unparse::pretty;
+
+        let input = "f() { a && b | c; (cd /; pwd) > log; { x=1; }; }";
+        let command = parse_command(input).unwrap();
+        assert_eq!(
+            pretty(&command, 0),
+            "f() {\n    a && b | c\n    (\n        cd /\n        pwd\n    ) >log\n    {\n        x=1\n    }\n}\n"
+        );
+
+        // Edited as laid out, and joined up again when saved
+        let mut shell = ShellState::default();
+        shell.eval(input).unwrap();
+        shell
+            .eval("EDITOR='sed -i s/pwd/ls/'; functions -e f")
+            .unwrap();
+        let body = shell.functions.get("f").unwrap().body.to_string();
+        assert_eq!(body, "{ a && b | c; (cd /; ls) >log; { x=1; }; }");
+
+        // A new function starts out empty
+        shell
+            .eval(r"EDITOR='sed -i s/}/echo\ new\;}/'; functions -e g")
+            .unwrap();
+        let body = shell.functions.get("g").unwrap().body.to_string();
+        assert_eq!(body, "{ echo new; }");
+    }
+
+    #[test]
+    fn test_safeexpand() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        let words = |shell: &mut ShellState, line: &str| {
+            shell.eval(&format!("words=({line})")).unwrap();
+            shell.variables.get_array("words").unwrap()
+        };
+        shell.eval("v='a  b'; g='/*'; arr=('1 2' 3); e=").unwrap();
+        assert_eq!(words(&mut shell, "$v"), ["a", "b"]);
+        assert!(words(&mut shell, "$g").len() > 1);
+
+        shell.eval("set -o safeexpand").unwrap();
+        assert_eq!(
+            words(&mut shell, "$v x$v $g $e ${arr[@]} $(echo c d)"),
+            ["a  b", "xa  b", "/*", "1 2", "3", "c", "d"]
+        );
+        // Globs as written still expand
+        assert!(words(&mut shell, "/*").len() > 1);
+    }
+
+    #[test]
+    fn test_noglob() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        let words = |shell: &mut ShellState, line: &str| {
+            shell.eval(&format!("words=({line})")).unwrap();
+            shell.variables.get_array("words").unwrap()
+        };
+        shell.eval("noglob printf -v out '%s|' /* x").unwrap();
+        assert_eq!(shell.variables.get("out"), Some("/*|x|"));
+        // Only for that command
+        assert!(words(&mut shell, "/*").len() > 1);
+
+        shell.eval("set -f; g='/*'").unwrap();
+        assert_eq!(words(&mut shell, "/* $g"), ["/*", "/*"]);
+        shell.eval("flags=$-; set +f").unwrap();
+        assert_eq!(shell.variables.get("flags"), Some("f"));
+        assert!(words(&mut shell, "/*").len() > 1);
+    }
+
+    #[test]
+    fn test_pipefail_and_failglob() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell.eval("false | true").unwrap();
+        assert_eq!(shell.last_status, 0);
+        shell.eval("set -o pipefail; false | true").unwrap();
+        assert_eq!(shell.last_status, 1);
+        shell.eval("true | true").unwrap();
+        assert_eq!(shell.last_status, 0);
+
+        shell.eval("words=(/no-such-dir-*)").unwrap();
+        assert_eq!(
+            shell.variables.get_array("words").unwrap(),
+            ["/no-such-dir-*"]
+        );
+        shell.eval("set -o failglob; unset words").unwrap();
+        assert!(!matches!(shell.eval("words=(/no-such-dir-*)"), Ok(0)));
+        assert!(shell.variables.get_array("words").is_none());
+        shell.eval("printf -v x %s /no-such-dir-*").unwrap();
+        assert_eq!(shell.last_status, 1);
+        assert!(matches!(shell.eval("words=('/no-such-dir-*' /*)"), Ok(0)));
+    }
+
+    #[test]
+    fn test_substitution_statuses() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        // An assignment on its own has the status of its last substitution
+        shell.eval("x=$(false)").unwrap();
+        assert_eq!(shell.last_status, 1);
+        shell.eval("x=$(false) y=$(true)").unwrap();
+        assert_eq!(shell.last_status, 0);
+
+        shell
+            .eval("printf -v out '%s' $(exit 3) \"$(x=$(exit 4))\"; s=${SUBSTATUS[*]}")
+            .unwrap();
+        assert_eq!(shell.variables.get("s"), Some("3 4"));
+        assert_eq!(shell.last_status, 0);
+
+        // Failures only stop the command with errexit-subst, and under set -e
+        shell
+            .eval("set -o errexit-subst; out=; printf -v out x$(false)")
+            .unwrap();
+        assert_eq!(shell.variables.get("out"), Some("x"));
+        assert!(!matches!(
+            shell.eval("set -e; out=; printf -v out y$(false)"),
+            Ok(0)
+        ));
+        assert_eq!(shell.variables.get("out"), Some(""));
+        assert_eq!(shell.variables.get_array("SUBSTATUS").unwrap(), ["1"]);
+    }
+
+    #[test]
+    fn test_substitution_lexing() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        // Parentheses in quotes and nested substitutions don't end one early
+        shell
+            .eval(r#"x=$(echo ")" "$(echo '(')"); y="$(echo "$(echo 'a)b')")""#)
+            .unwrap();
+        assert_eq!(shell.variables.get("x"), Some(") ("));
+        assert_eq!(shell.variables.get("y"), Some("a)b"));
+
+        // Lines inside one are separate commands
+        shell
+            .eval("z=$(echo a\n  # comment )\n echo b |\n tr b c\n)")
+            .unwrap();
+        assert_eq!(shell.variables.get("z"), Some("a\nc"));
+        assert!(Command::is_incomplete("x=$(echo a"));
+        assert!(Command::is_incomplete("echo \"$(echo ')'\""));
+        assert!(!Command::is_incomplete("echo a)"));
+
+        let path = std::env::temp_dir().join(format!("substitution-{}", std::process::id()));
+        std::fs::write(&path, "one\ntwo\n\n").unwrap();
+        shell
+            .eval(&format!("contents=$(< {})", path.display()))
+            .unwrap();
+        assert_eq!(shell.variables.get("contents"), Some("one\ntwo"));
+        std::fs::remove_file(&path).unwrap();
+        shell
+            .eval(&format!("contents=$(<{})", path.display()))
+            .unwrap();
+        assert_eq!(shell.last_status, 1);
+        assert_eq!(shell.variables.get("contents"), Some(""));
+    }
+
+    #[test]
+    fn test_children_are_tracked() {
+        use crate::jobs::JobState;
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("env true | env false; (exit 3) | env true")
+            .unwrap();
+        let children: Vec<(&str, JobState)> = shell
+            .children
+            .iter()
+            .map(|child| (child.command.as_str(), child.state))
+            .collect();
+        assert_eq!(
+            children,
+            [
+                ("env true", JobState::Done(0)),
+                ("env false", JobState::Done(1)),
+                ("( ... )", JobState::Done(3)),
+                ("env true", JobState::Done(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_slicing() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("arr=(a b c d e f); s=hello; set -- one two three; n=2")
+            .unwrap();
+        let expand = |shell: &mut ShellState, word: &str| {
+            shell.eval(&format!("out=\"{word}\"")).unwrap();
+            shell.variables.get("out").unwrap_or_default().to_string()
+        };
+        assert_eq!(expand(&mut shell, "${arr[*]:2:3}"), "c d e");
+        assert_eq!(expand(&mut shell, "${arr[*]: -2}"), "e f");
+        assert_eq!(expand(&mut shell, "${arr[*]:n+1:1}"), "d");
+        assert_eq!(expand(&mut shell, "${arr[*]:10}|${arr[*]: -10}"), "|");
+        assert_eq!(
+            expand(&mut shell, "${*:2}|${*: -1}|${#arr[@]}"),
+            "two three|three|6"
+        );
+        assert_eq!(
+            expand(&mut shell, "${s:1:3} ${s: -3} ${s:1:-1} ${s:(-2):1}"),
+            "ell llo ell l"
+        );
+
+        // Quoted, each element is still a word of its own
+        shell.eval("words=(\"${arr[@]:4}\" \"${@:1:2}\")").unwrap();
+        assert_eq!(
+            shell.variables.get_array("words").unwrap(),
+            ["e", "f", "one", "two"]
+        );
+        assert!(!matches!(shell.eval("out=${arr[@]:1:-1}"), Ok(0)));
+        assert!(!matches!(shell.eval("out=${s:-default}"), Ok(0)));
+    }
+
+    #[test]
+    fn test_quoting_expansions() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        shell
+            .eval("x=\"a 'b' \\$c\"; q=${x@Q}; arr=(1 'two three'); e=")
+            .unwrap();
+        assert_eq!(shell.variables.get("q"), Some(r"'a '\''b'\'' $c'"));
+        // What it quoted reads back the same
+        let source = format!("y={}", shell.variables.get("q").unwrap());
+        shell.eval(&source).unwrap();
+        assert_eq!(shell.variables.get("y"), shell.variables.get("x"));
+        shell
+            .eval("fields=(\"${arr[@]@Q}\"); empty=${e@Q}; unset=${nothing@Q}")
+            .unwrap();
+        assert_eq!(
+            shell.variables.get_array("fields"),
+            Some(vec!["'1'".to_string(), "'two three'".to_string()])
+        );
+        assert_eq!(shell.variables.get("empty"), Some("''"));
+        assert_eq!(shell.variables.get("unset"), Some(""));
+
+        let printf = |shell: &mut ShellState, args: &str| {
+            let status = shell.eval(&format!("printf -v out {args}")).unwrap();
+            (
+                status,
+                shell.variables.get("out").unwrap_or_default().to_string(),
+            )
+        };
+        assert_eq!(
+            printf(&mut shell, r#"'%q %q %q|' plain 'a b' "it's""#),
+            (0, r"plain 'a b' 'it'\''s'|".to_string())
+        );
+        assert_eq!(
+            printf(
+                &mut shell,
+                r"'%s=%-3s|%05.1f|%#x|%+d|%.3e|%g\n' a b 2.25 255 7 1234.5 0.0001"
+            ),
+            (0, "a=b  |002.2|0xff|+7|1.234e+03|0.0001\n".to_string())
+        );
+        // The format repeats for the rest of the arguments
+        assert_eq!(
+            printf(&mut shell, "'<%s>' a b c"),
+            (0, "<a><b><c>".to_string())
+        );
+        assert_eq!(
+            printf(&mut shell, r"'%b|%s' 'a\tb\0101\c' ignored"),
+            (0, "a\tbA".to_string())
+        );
+        assert_eq!(printf(&mut shell, "'%d' 12x"), (1, "0".to_string()));
+
+        // Times, where the platform can format them
+        if cfg!(unix) {
+            assert_eq!(
+                printf(&mut shell, "'%(%s)T|%6.2(%s)T' 86400 1000"),
+                (0, "86400|    10".to_string())
+            );
+            let (status, now) = printf(&mut shell, "'%(%s)T'");
+            let realtime = shell.eval("out=$EPOCHREALTIME").map(|_| {
+                let value = shell.variables.get("out").unwrap_or_default();
+                value.parse::<f64>().unwrap()
+            });
+            assert_eq!(status, 0);
+            assert!((realtime.unwrap() - now.parse::<f64>().unwrap()).abs() < 5.0);
+        }
+        assert_eq!(printf(&mut shell, "'%(%s'").0, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spawn_errors() {
+        use crate::platform::spawn_error;
+        use std::io::Error as IOError;
+
+        let args = vec!["prog".to_string(), "x".repeat(200_000)];
+        let env = vec![("A".to_string(), "b".to_string())];
+
+        let e = spawn_error(&args, &env, IOError::from_raw_os_error(libc::EMFILE));
+        let message = e.to_string();
+        assert!(
+            message.starts_with("prog: too many open files: "),
+            "{message}"
+        );
+        assert!(message.contains("(ulimit -n)"), "{message}");
+
+        let message =
+            spawn_error(&args[..1], &env, IOError::from_raw_os_error(libc::E2BIG)).to_string();
+        assert!(
+            message.contains("1 arguments take 13 bytes and the environment 12 more"),
+            "{message}"
+        );
+        if cfg!(target_os = "linux") {
+            let message =
+                spawn_error(&args, &env, IOError::from_raw_os_error(libc::E2BIG)).to_string();
+            assert!(
+                message.contains("one argument is 200000 bytes"),
+                "{message}"
+            );
+        }
+
+        // Anything else is left as it is
+        let e = spawn_error(&args, &env, IOError::from_raw_os_error(libc::EACCES));
+        assert_eq!(e.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(e.to_string(), "prog: Permission denied (os error 13)");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_split_args() {
+        use crate::platform::{args_fit, split_args};
+
+        let env = vec![("A".to_string(), "b".to_string())];
+        let mut args: Vec<String> = ["cmd", "-v", "--"].map(str::to_string).to_vec();
+        args.extend((0..300_000).map(|n| format!("-item{n}")));
+        assert!(!args_fit(&args, &env));
+
+        let chunks = split_args(&args, &env).unwrap();
+        assert!(chunks.len() > 1);
+        let mut rest = Vec::new();
+        for chunk in &chunks {
+            assert!(args_fit(chunk, &env));
+            assert_eq!(chunk[..3], args[..3]);
+            rest.extend_from_slice(&chunk[3..]);
+        }
+        assert_eq!(rest, args[3..]);
+
+        assert!(args_fit(&args[..10], &env));
+        assert_eq!(split_args(&args[..3], &env), None);
+    }
+
+    #[test]
+    fn test_direnv() {
+        use crate::direnv::{self, FILE_NAME};
+        use crate::shell::ShellState;
+
+        let root = std::env::temp_dir().join(format!("direnv-{}", std::process::id()));
+        let sub = root.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let file = root.join(FILE_NAME);
+        // Changing into its own tree mustn't load it again
+        let contents = format!(
+            "export PROJECT=demo EXTRA=1\nLOCAL=1; f() {{ :; }}; set -u; cd {}\n",
+            sub.display()
+        );
+        std::fs::write(&file, contents).unwrap();
+
+        let mut shell = ShellState::default();
+        shell.eval("set -o direnv; export PROJECT=old").unwrap();
+        let cwd = std::env::current_dir().unwrap();
+
+        // Nothing runs until the file is allowed
+        direnv::enter(&mut shell, &sub);
+        assert_eq!(shell.variables.get("PROJECT"), Some("old"));
+        assert_eq!(shell.direnv.loaded(), None);
+
+        shell.direnv.allow(&file).unwrap();
+        direnv::enter(&mut shell, &sub);
+        assert_eq!(shell.direnv.loaded(), Some(file.as_path()));
+        assert_eq!(shell.variables.get("PROJECT"), Some("demo"));
+        assert_eq!(shell.variables.get("EXTRA"), Some("1"));
+        // Only exports are kept
+        assert_eq!(shell.variables.get("LOCAL"), None);
+        assert!(!shell.functions.contains_key("f"));
+        assert!(!shell.options.nounset);
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+
+        // Leaving the tree puts things back
+        direnv::enter(&mut shell, &std::env::temp_dir());
+        assert_eq!(shell.direnv.loaded(), None);
+        assert_eq!(shell.variables.get("PROJECT"), Some("old"));
+        assert!(shell.variables.var("PROJECT").unwrap().exported);
+        assert_eq!(shell.variables.get("EXTRA"), None);
+
+        // Changing the file takes it out of trust until it's allowed again
+        std::fs::write(&file, "export PROJECT=changed\n").unwrap();
+        direnv::enter(&mut shell, &root);
+        assert_eq!(shell.variables.get("PROJECT"), Some("old"));
+
+        shell.direnv.deny(&file).unwrap();
+        direnv::enter(&mut shell, &root);
+        assert_eq!(shell.direnv.loaded(), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        // Trust goes by SHA-256
+        let hex = |hash: [u8; 32]| -> String { hash.iter().map(|b| format!("{b:02x}")).collect() };
+        assert_eq!(
+            hex(direnv::hash(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(direnv::hash(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_pipestatus() {
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        let pipestatus = |shell: &ShellState| shell.variables.get_array("PIPESTATUS").unwrap();
+
+        shell.eval("true | false | sh -c 'exit 3'").unwrap();
+        assert_eq!(pipestatus(&shell), vec!["0", "1", "3"]);
+        assert_eq!(shell.last_status, 3);
+
+        // Stages the shell runs itself count too
+        shell.eval("return 4 | true").unwrap();
+        assert_eq!(pipestatus(&shell), vec!["1", "0"]);
+        shell.eval("false").unwrap();
+        assert_eq!(pipestatus(&shell), vec!["1"]);
+
+        // Traps don't disturb it
+        shell.eval("trap 'true | true | true' DEBUG").unwrap();
+        shell.eval("false | true; X=${PIPESTATUS[0]}").unwrap();
+        assert_eq!(shell.variables.get("X"), Some("1"));
+    }
+
+    #[test]
+    fn test_prompt() {
+        use crate::prompt::{environments, render};
+        use crate::shell::ShellState;
+
+        let mut shell = ShellState::default();
+        for name in [
+            "PS1",
+            "VIRTUAL_ENV",
+            "VIRTUAL_ENV_PROMPT",
+            "CONDA_DEFAULT_ENV",
+            "IN_NIX_SHELL",
+        ] {
+            shell.variables.unset(name).unwrap();
+        }
+        assert_eq!(render(&shell), "> ");
+        assert!(environments(&shell).is_empty());
+
+        shell
+            .eval("HOME=/home/sam PWD=/home/sam/src/sigsh USER=sam; PS1='\\V\\u:\\w \\W \\? \\\\\\x> '")
+            .unwrap();
+        assert_eq!(render(&shell), "sam:~/src/sigsh sigsh 0 \\\\x> ");
+
+        shell
+            .eval(
+                "VIRTUAL_ENV=/home/sam/src/sigsh/.venv CONDA_DEFAULT_ENV=base IN_NIX_SHELL=impure",
+            )
+            .unwrap();
+        assert_eq!(
+            render(&shell),
+            "(.venv) (base) (nix-shell) sam:~/src/sigsh sigsh 0 \\\\x> "
+        );
+        let kinds: Vec<_> = environments(&shell).iter().map(|env| env.kind).collect();
+        assert_eq!(kinds, ["virtualenv", "conda", "nix-shell"]);
+
+        shell
+            .eval("VIRTUAL_ENV_PROMPT='(sigsh) ' PWD=/home/sam; PS1='\\V\\w\\W'")
+            .unwrap();
+        assert_eq!(render(&shell), "(sigsh) (base) (nix-shell) ~~");
+    }
+
+    #[test]
+    fn test_fd_redirection_parsing() {
+        let command =
+            parse_command("exec 3< in 4>> log 5>&- 2> err").expect("Failed to parse command");
+        let redirect = |redirect_type, target: &str, append| FileRedir {
+            redirect_type,
+            target: PathBuf::from(target),
+            append,
+            clobber: false,
+        };
+        assert_eq!(command.argv, vec![Arg::Word("exec".to_string())]);
+        assert_eq!(
+            command.redirect_to,
+            vec![
+                redirect(RedirType::FdIn(3), "in", false),
+                redirect(RedirType::FdOut(4), "log", true),
+                redirect(RedirType::FdClose(5), "", false),
+                redirect(RedirType::Stderr, "err", false),
+            ]
+        );
+
+        // Only a digit starting a word is a descriptor
+        let command = parse_command("echo a3>out").expect("Failed to parse command");
+        assert_eq!(command.argv[1], Arg::Word("a3".to_string()));
+        assert_eq!(command.redirect_to[0].redirect_type, RedirType::Stdout);
+    }
 }
@@ -0,0 +1,185 @@
+//! Universal variables, which are shared by every session of the shell and
+//! survive restarts, like fish's `set -U`.
+//!
+//! They live in `$XDG_CONFIG_HOME/sigsh/universal_variables` (or under
+//! `~/.config`), one `NAME=value` per line with backslashes and newlines
+//! escaped. Each session checks the file's modification time before running a
+//! command and picks up whatever other sessions have written since. Changes
+//! are made holding a lock on `universal_variables.lock` alongside, so two
+//! sessions setting variables at once don't lose one of them.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Enough to tell whether the file has changed since we last read it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = fs::metadata(path).ok()?;
+    Some(Stamp {
+        modified: meta.modified().ok()?,
+        len: meta.len(),
+    })
+}
+
+/// What changed when the file was re-read: new values, and names which were
+/// erased.
+pub type Changes = (Vec<(String, String)>, Vec<String>);
+
+#[derive(Debug, Default)]
+pub struct UniversalVars {
+    values: BTreeMap<String, String>,
+    path: Option<PathBuf>,
+    stamp: Option<Stamp>,
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("sigsh").join("universal_variables"))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn parse(contents: &str) -> BTreeMap<String, String> {
+    contents
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.to_string(), unescape(value)))
+        .collect()
+}
+
+impl UniversalVars {
+    /// Load the universal variables file, if there's anywhere to keep one.
+    pub fn load() -> UniversalVars {
+        let mut vars = UniversalVars {
+            path: default_path(),
+            ..Default::default()
+        };
+        let _ = vars.refresh();
+        vars
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Re-read the file if another session has written to it since we last
+    /// looked, returning what changed.
+    pub fn refresh(&mut self) -> io::Result<Changes> {
+        let Some(path) = &self.path else {
+            return Ok(Changes::default());
+        };
+        let stamp = stamp(path);
+        if stamp == self.stamp {
+            return Ok(Changes::default());
+        }
+
+        let values = match fs::read_to_string(path) {
+            Ok(contents) => parse(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        self.stamp = stamp;
+
+        let set = values
+            .iter()
+            .filter(|(name, value)| self.values.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        let erased = self
+            .values
+            .keys()
+            .filter(|name| !values.contains_key(*name))
+            .cloned()
+            .collect();
+        self.values = values;
+        Ok((set, erased))
+    }
+
+    /// Set `name` for every session, returning what other sessions changed
+    /// since we last looked, as [`UniversalVars::refresh`] does.
+    pub fn set(&mut self, name: &str, value: &str) -> io::Result<Changes> {
+        self.update(|values| {
+            values.insert(name.to_string(), value.to_string());
+        })
+    }
+
+    /// Erase `name` for every session, returning what other sessions changed
+    /// since we last looked.
+    pub fn erase(&mut self, name: &str) -> io::Result<Changes> {
+        self.update(|values| {
+            values.remove(name);
+        })
+    }
+
+    /// Apply `f` on top of the latest contents of the file, so that we don't
+    /// throw away other sessions' changes, then write it back. Returns the
+    /// changes other sessions made that `f` left standing.
+    fn update(&mut self, f: impl FnOnce(&mut BTreeMap<String, String>)) -> io::Result<Changes> {
+        let Some(path) = self.path.clone() else {
+            f(&mut self.values);
+            return Ok(Changes::default());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Unlocked when the file is closed
+        let lock = File::create(path.with_extension("lock"))?;
+        lock.lock()?;
+
+        let (set, erased) = self.refresh()?;
+        f(&mut self.values);
+        let set = set
+            .into_iter()
+            .filter(|(name, value)| self.values.get(name) == Some(value))
+            .collect();
+        let erased = erased
+            .into_iter()
+            .filter(|name| !self.values.contains_key(name))
+            .collect();
+
+        let contents: String = self
+            .values
+            .iter()
+            .map(|(name, value)| format!("{}={}\n", name, escape(value)))
+            .collect();
+
+        // Write then rename, so other sessions never read half a file
+        let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &path)?;
+        self.stamp = stamp(&path);
+        Ok((set, erased))
+    }
+}
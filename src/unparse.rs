@@ -0,0 +1,129 @@
+//! Writing parsed commands back out as source the parser reads back the
+//! same, for passing functions on to other shells, or spread over lines for
+//! people to read.
+
+use std::fmt;
+
+use crate::exec::{quote_word, redirect_operator};
+use crate::parser::{Arg, Command, Compound, RedirType};
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = stage(self, |compound| match compound {
+            Compound::Group(body) => format!("{{ {body}; }}"),
+            Compound::Subshell(body) => format!("({body})"),
+            Compound::FunctionDef { name, body } => format!("{name}() {body}"),
+            Compound::Spawn { name, body } => format!("spawn {name} {body}"),
+        });
+        write!(f, "{words}")?;
+
+        if let Some(pipe) = &self.pipe_to {
+            write!(f, " {} {}", pipe_operator(&pipe.pipe_type), pipe.target)?;
+        }
+        if let Some(next) = &self.and_then {
+            let operator = if next.conditional { " &&" } else { ";" };
+            write!(f, "{operator} {}", next.target)?;
+        }
+        Ok(())
+    }
+}
+
+/// `cmd` itself, without what's piped or chained after it, with `compound`
+/// writing it if it's a compound command.
+fn stage(cmd: &Command, compound: impl FnOnce(&Compound) -> String) -> String {
+    let mut words = Vec::new();
+    for assignment in &cmd.assignments {
+        words.push(format!("{}={}", assignment.name, assignment.value));
+    }
+    match cmd.compound.as_deref() {
+        Some(body) => words.push(compound(body)),
+        None => words.extend(cmd.argv.iter().map(Arg::to_string)),
+    }
+    for redirect in &cmd.redirect_to {
+        let target = match redirect.redirect_type {
+            RedirType::FdClose(_) => String::new(),
+            _ => quote_word(&redirect.target.to_string_lossy()),
+        };
+        words.push(format!("{}{target}", redirect_operator(redirect)));
+    }
+    words.join(" ")
+}
+
+fn pipe_operator(pipe_type: &RedirType) -> &'static str {
+    match pipe_type {
+        RedirType::Both => "|&",
+        _ => "|",
+    }
+}
+
+/// `cmd` and what's chained after it, one command to a line indented by
+/// `depth` levels, with the bodies of groups and subshells on lines of
+/// their own, as `functions` shows a function.
+pub(crate) fn pretty(cmd: &Command, depth: usize) -> String {
+    let indent = "    ".repeat(depth);
+    let mut out = String::new();
+    let mut next = Some(cmd);
+    let mut line_start = true;
+    while let Some(cmd) = next {
+        if line_start {
+            out.push_str(&indent);
+        }
+        // What comes after a pipeline hangs off its last command
+        let mut tail = cmd;
+        out.push_str(&pretty_stage(tail, depth));
+        while let Some(pipe) = &tail.pipe_to {
+            tail = &pipe.target;
+            let operator = pipe_operator(&pipe.pipe_type);
+            out.push_str(&format!(" {operator} {}", pretty_stage(tail, depth)));
+        }
+        next = tail.and_then.as_ref().map(|next| &*next.target);
+        line_start = !tail.and_then.as_ref().is_some_and(|next| next.conditional);
+        out.push_str(if line_start { "\n" } else { " && " });
+    }
+    out
+}
+
+fn pretty_stage(cmd: &Command, depth: usize) -> String {
+    let indent = "    ".repeat(depth);
+    stage(cmd, |compound| match compound {
+        Compound::Group(body) => format!("{{\n{}{indent}}}", pretty(body, depth + 1)),
+        Compound::Subshell(body) => format!("(\n{}{indent})", pretty(body, depth + 1)),
+        Compound::FunctionDef { name, body } => format!("{name}() {}", pretty_stage(body, depth)),
+        Compound::Spawn { name, body } => format!("spawn {name} {}", pretty_stage(body, depth)),
+    })
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arg::Word(word) => write!(f, "{}", quote_word(word)),
+            // Quoted glob characters are already escaped
+            Arg::Glob(pattern) => write!(f, "{pattern}"),
+            Arg::Variable(name) => write!(f, "${{{name}}}"),
+            Arg::Subshell(command) => write!(f, "$({command})"),
+            Arg::Arith(expr) => write!(f, "$(({expr}))"),
+            Arg::Array(words) => {
+                let words: Vec<String> = words.iter().map(Arg::to_string).collect();
+                write!(f, "({})", words.join(" "))
+            }
+            Arg::Quoted(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        Arg::Word(text) => {
+                            for c in text.chars() {
+                                if matches!(c, '$' | '`' | '"' | '\\') {
+                                    write!(f, "\\")?;
+                                }
+                                write!(f, "{c}")?;
+                            }
+                        }
+                        part => write!(f, "{part}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Arg::Concat(parts) => parts.iter().try_for_each(|part| write!(f, "{part}")),
+        }
+    }
+}
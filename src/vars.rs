@@ -0,0 +1,432 @@
+//! Shell variables and positional parameters.
+//!
+//! Attributes given with `declare` are enforced here, so every way of
+//! assigning a variable gets the same integer evaluation, case coercion and
+//! readonly checks.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use crate::arith::{self, ArithError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Scalar(String),
+    Array(Vec<String>),
+    /// An associative array, from `declare -A`
+    Assoc(BTreeMap<String, String>),
+}
+
+impl Value {
+    /// The value as a plain string; for an array, like other shells, that's
+    /// its first element.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Value::Scalar(value) => value,
+            Value::Array(values) => values.first().map_or("", String::as_str),
+            Value::Assoc(values) => values.get("0").map_or("", String::as_str),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Attributes {
+    /// Assignments are evaluated as arithmetic
+    pub integer: bool,
+    pub readonly: bool,
+    pub lowercase: bool,
+    pub uppercase: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub value: Value,
+    /// Whether the variable is passed on to the environment of commands
+    pub exported: bool,
+    pub attrs: Attributes,
+}
+
+impl Variable {
+    pub fn new(value: Value) -> Self {
+        Variable {
+            value,
+            exported: false,
+            attrs: Attributes::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VarError {
+    Readonly(String),
+    Arith(ArithError),
+    BadSubscript(String),
+}
+
+impl fmt::Display for VarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarError::Readonly(name) => write!(f, "{name}: readonly variable"),
+            VarError::Arith(e) => write!(f, "{e}"),
+            VarError::BadSubscript(subscript) => write!(f, "{subscript}: bad array subscript"),
+        }
+    }
+}
+
+impl std::error::Error for VarError {}
+
+impl From<ArithError> for VarError {
+    fn from(e: ArithError) -> Self {
+        VarError::Arith(e)
+    }
+}
+
+impl From<VarError> for io::Error {
+    fn from(e: VarError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+/// Split `name[subscript]` into its parts.
+pub fn split_subscript(target: &str) -> Option<(&str, &str)> {
+    target.strip_suffix(']')?.split_once('[')
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variables {
+    vars: BTreeMap<String, Variable>,
+    /// `$1`, `$2`, ... of the running script or function
+    positional: Vec<String>,
+    /// For each running function, the variables it made local along with
+    /// what they shadow, to be put back when it returns
+    scopes: Vec<Vec<(String, Option<Variable>)>>,
+}
+
+impl Variables {
+    /// Start out with the shell's own environment, all of it exported.
+    pub fn from_env() -> Self {
+        let vars = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .map(|(name, value)| {
+                (
+                    name,
+                    Variable {
+                        exported: true,
+                        ..Variable::new(Value::Scalar(value))
+                    },
+                )
+            })
+            .collect();
+        Variables {
+            vars,
+            ..Default::default()
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(|var| var.value.as_str())
+    }
+
+    /// All the elements of an array, or a scalar as a one-element array.
+    pub fn get_array(&self, name: &str) -> Option<Vec<String>> {
+        self.vars.get(name).map(|var| match &var.value {
+            Value::Scalar(value) => vec![value.clone()],
+            Value::Array(values) => values.clone(),
+            Value::Assoc(values) => values.values().cloned().collect(),
+        })
+    }
+
+    /// The indices of an array, or the keys of an associative one.
+    pub fn keys(&self, name: &str) -> Vec<String> {
+        match self.vars.get(name).map(|var| &var.value) {
+            None => Vec::new(),
+            Some(Value::Scalar(_)) => vec!["0".to_string()],
+            Some(Value::Array(values)) => (0..values.len()).map(|i| i.to_string()).collect(),
+            Some(Value::Assoc(values)) => values.keys().cloned().collect(),
+        }
+    }
+
+    /// One element of an array. Indices are evaluated as arithmetic, and
+    /// negative ones count back from the end; keys of associative arrays are
+    /// taken as they are.
+    pub fn get_element(&mut self, name: &str, subscript: &str) -> Result<Option<String>, VarError> {
+        if let Some(Value::Assoc(values)) = self.vars.get(name).map(|var| &var.value) {
+            return Ok(values.get(subscript).cloned());
+        }
+
+        let index = arith::eval(subscript, self)?;
+        let elements = self.get_array(name).unwrap_or_default();
+        let index = if index < 0 {
+            elements.len() as i64 + index
+        } else {
+            index
+        };
+        Ok(usize::try_from(index)
+            .ok()
+            .and_then(|index| elements.get(index).cloned()))
+    }
+
+    pub fn var(&self, name: &str) -> Option<&Variable> {
+        self.vars.get(name)
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, var: Variable) {
+        self.vars.insert(name.into(), var);
+    }
+
+    /// The variable called `name` so its attributes can be changed, creating
+    /// it empty if it doesn't exist.
+    pub fn declare(&mut self, name: impl Into<String>) -> &mut Variable {
+        self.vars
+            .entry(name.into())
+            .or_insert_with(|| Variable::new(Value::Scalar(String::new())))
+    }
+
+    /// Set a variable, keeping whether it was exported. Setting an array
+    /// this way sets its first element.
+    pub fn set(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), VarError> {
+        let name = name.into();
+        let value = self.coerce(&name, value.into())?;
+        match self.vars.get_mut(&name) {
+            Some(Variable {
+                value: Value::Array(values),
+                ..
+            }) => match values.first_mut() {
+                Some(first) => *first = value,
+                None => values.push(value),
+            },
+            Some(Variable {
+                value: Value::Assoc(values),
+                ..
+            }) => {
+                values.insert("0".to_string(), value);
+            }
+            Some(var) => var.value = Value::Scalar(value),
+            None => self.insert(name, Variable::new(Value::Scalar(value))),
+        }
+        Ok(())
+    }
+
+    /// Replace a variable's value with the elements of an array.
+    pub fn set_array(
+        &mut self,
+        name: impl Into<String>,
+        values: Vec<String>,
+    ) -> Result<(), VarError> {
+        let elements = values.into_iter().map(|value| (None, value)).collect();
+        self.set_elements(name.into(), elements)
+    }
+
+    /// Replace a variable's value with the words of a `(...)` list, any of
+    /// which may look like `[subscript]=value`. Associative arrays need a
+    /// subscript for every element.
+    pub fn assign_list(
+        &mut self,
+        name: impl Into<String>,
+        words: Vec<String>,
+    ) -> Result<(), VarError> {
+        let elements = words
+            .into_iter()
+            .map(|word| {
+                match word
+                    .strip_prefix('[')
+                    .and_then(|rest| rest.split_once("]="))
+                {
+                    Some((subscript, value)) => (Some(subscript.to_string()), value.to_string()),
+                    None => (None, word),
+                }
+            })
+            .collect();
+        self.set_elements(name.into(), elements)
+    }
+
+    fn set_elements(
+        &mut self,
+        name: String,
+        elements: Vec<(Option<String>, String)>,
+    ) -> Result<(), VarError> {
+        self.check_writable(&name)?;
+        let mut value = match self.var(&name).map(|var| &var.value) {
+            Some(Value::Assoc(_)) => Value::Assoc(BTreeMap::new()),
+            _ => Value::Array(Vec::new()),
+        };
+
+        let mut next = 0;
+        for (subscript, element) in elements {
+            let element = self.coerce(&name, element)?;
+            match &mut value {
+                Value::Assoc(values) => {
+                    let key = subscript.ok_or_else(|| VarError::BadSubscript(element.clone()))?;
+                    values.insert(key, element);
+                }
+                Value::Array(values) => {
+                    if let Some(subscript) = subscript {
+                        next = self.index(&subscript)?;
+                    }
+                    if values.len() <= next {
+                        values.resize(next + 1, String::new());
+                    }
+                    values[next] = element;
+                    next += 1;
+                }
+                Value::Scalar(_) => unreachable!(),
+            }
+        }
+
+        match self.vars.get_mut(&name) {
+            Some(var) => var.value = value,
+            None => self.insert(name, Variable::new(value)),
+        }
+        Ok(())
+    }
+
+    /// Set one element of an array, turning a scalar into an array if need be.
+    pub fn set_element(
+        &mut self,
+        name: &str,
+        subscript: &str,
+        value: String,
+    ) -> Result<(), VarError> {
+        let value = self.coerce(name, value)?;
+        if let Some(Value::Assoc(values)) = self.vars.get_mut(name).map(|var| &mut var.value) {
+            values.insert(subscript.to_string(), value);
+            return Ok(());
+        }
+
+        let index = self.index(subscript)?;
+        let var = self.declare(name);
+        if let Value::Scalar(scalar) = &var.value {
+            var.value = Value::Array(vec![scalar.clone()]);
+        }
+        if let Value::Array(values) = &mut var.value {
+            if values.len() <= index {
+                values.resize(index + 1, String::new());
+            }
+            values[index] = value;
+        }
+        Ok(())
+    }
+
+    /// Assign to `target`, which is either a name or `name[subscript]`. An
+    /// array value is the words of a `(...)` list, as for [`Self::assign_list`].
+    pub fn assign(&mut self, target: &str, value: Value) -> Result<(), VarError> {
+        match (split_subscript(target), value) {
+            (Some((name, subscript)), Value::Scalar(value)) => {
+                self.set_element(name, subscript, value)
+            }
+            (Some(_), _) => Err(VarError::BadSubscript(target.to_string())),
+            (None, Value::Scalar(value)) => self.set(target, value),
+            (None, Value::Array(words)) => self.assign_list(target, words),
+            (None, Value::Assoc(values)) => {
+                let elements = values.into_iter().map(|(key, value)| (Some(key), value));
+                self.set_elements(target.to_string(), elements.collect())
+            }
+        }
+    }
+
+    fn index(&mut self, subscript: &str) -> Result<usize, VarError> {
+        let index = arith::eval(subscript, self)?;
+        usize::try_from(index).map_err(|_| VarError::BadSubscript(subscript.to_string()))
+    }
+
+    fn check_writable(&self, name: &str) -> Result<(), VarError> {
+        match self.vars.get(name) {
+            Some(var) if var.attrs.readonly => Err(VarError::Readonly(name.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply a variable's attributes to a value about to be assigned to it.
+    fn coerce(&mut self, name: &str, value: String) -> Result<String, VarError> {
+        self.check_writable(name)?;
+        let Some(attrs) = self.vars.get(name).map(|var| var.attrs) else {
+            return Ok(value);
+        };
+
+        let value = if attrs.integer {
+            arith::eval(&value, self)?.to_string()
+        } else {
+            value
+        };
+        Ok(if attrs.lowercase {
+            value.to_lowercase()
+        } else if attrs.uppercase {
+            value.to_uppercase()
+        } else {
+            value
+        })
+    }
+
+    /// Mark a variable for export, creating it empty if it doesn't exist.
+    pub fn export(&mut self, name: impl Into<String>) {
+        self.declare(name).exported = true;
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<Option<Variable>, VarError> {
+        self.check_writable(name)?;
+        Ok(self.vars.remove(name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Variable)> {
+        self.vars.iter().map(|(name, var)| (name.as_str(), var))
+    }
+
+    /// The environment to hand to a command. Arrays can't be exported.
+    pub fn exported(&self) -> Vec<(String, String)> {
+        self.iter()
+            .filter_map(|(name, var)| match &var.value {
+                Value::Scalar(value) if var.exported => Some((name.to_string(), value.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Start a function call's scope for [`Self::make_local`].
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// End a function call's scope, putting back whatever its locals shadowed.
+    pub fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for (name, var) in scope.into_iter().rev() {
+            match var {
+                Some(var) => self.insert(name, var),
+                None => {
+                    self.vars.remove(&name);
+                }
+            }
+        }
+    }
+
+    /// Make `name` local to the running function, shadowing any variable of
+    /// the same name until the function returns. Functions it calls see the
+    /// local, as scoping is dynamic. Outside a function this does nothing.
+    pub fn make_local(&mut self, name: &str) -> Result<(), VarError> {
+        self.check_writable(name)?;
+        let Some(scope) = self.scopes.last_mut() else {
+            return Ok(());
+        };
+        if scope.iter().all(|(local, _)| local != name) {
+            scope.push((name.to_string(), self.vars.remove(name)));
+        }
+        Ok(())
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    /// Replace the positional parameters, returning the old ones so a function
+    /// call can put them back.
+    pub fn set_positional(&mut self, params: Vec<String>) -> Vec<String> {
+        std::mem::replace(&mut self.positional, params)
+    }
+}
@@ -0,0 +1,347 @@
+//! End-to-end tests of builtins which compute things and print them.
+#![cfg(unix)]
+
+mod support;
+
+use support::{keys, PtyShell};
+
+#[test]
+fn math_evaluates_floating_point() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("math -s 2 10/3");
+    pty.expect("3.33\r\n");
+
+    pty.send_line("math 'sqrt(16) + pow(2, 0.5) * 0 + round(2.4) - 1e1'");
+    pty.expect("-4\r\n");
+
+    pty.send_line("math '(1 + 2) * 2 ^ 2 / 8'");
+    pty.expect("1.5\r\n");
+
+    pty.send_line("math 1/0; echo status=$?");
+    pty.expect("division by zero");
+    pty.expect("status=1\r\n");
+
+    let nested = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+    std::fs::write(pty.home().join("nested"), nested).unwrap();
+    pty.send_line("math \"$(<nested)\"; echo status=$?");
+    pty.expect("expression nested too deeply");
+    pty.expect("status=1\r\n");
+}
+
+#[test]
+fn string_manipulates_arguments_and_stdin() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("string length héllo ''; echo status=$?");
+    pty.expect("5\r\n0\r\nstatus=0\r\n");
+
+    pty.send_line("string sub -s 2 -l 3 abcdef; string sub -s -2 abcdef");
+    pty.expect("bcd\r\nef\r\n");
+
+    pty.send_line("string split -m 1 -r . a.b.c | string join +");
+    pty.expect("a.b+c\r\n");
+
+    pty.send_line("string replace -a o 0 foo boo; string upper MiXeD x");
+    pty.expect("f00\r\nb00\r\nMIXED\r\nX\r\n");
+
+    pty.send_line("string match -i '*.RS' main.rs lib.c; string match -v -q '*' z; echo status=$?");
+    pty.expect("main.rs\r\nstatus=1\r\n");
+
+    pty.send_line("string trim -c x- x-kept-x; string pad -w 4 -c 0 7");
+    pty.expect("kept\r\n0007\r\n");
+
+    pty.send_line("string frob; echo status=$?");
+    pty.expect("frob: unknown subcommand");
+    pty.expect("status=2\r\n");
+}
+
+#[test]
+fn json_extracts_fields_from_stdin() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    std::fs::write(
+        pty.home().join("doc.json"),
+        r#"{"items": [{"name": "first é", "n": 1.5}, {"name": "second", "tags": [true, null]}],
+            "odd key": {"a": 1, "b": 2}}"#,
+    )
+    .unwrap();
+
+    pty.send_line("json get .items[0].name < doc.json");
+    pty.expect("first é\r\n");
+
+    pty.send_line("json get '.items[-1]' < doc.json");
+    pty.expect("{\"name\":\"second\",\"tags\":[true,null]}\r\n");
+
+    pty.send_line("json keys '.[\"odd key\"]' < doc.json | string join ,");
+    pty.expect("a,b\r\n");
+
+    pty.send_line("cat doc.json | json length .items; json type .items[1].tags[1] < doc.json");
+    pty.expect("2\r\nnull\r\n");
+
+    pty.send_line("json get .missing < doc.json; echo status=$?");
+    pty.expect("status=1\r\n");
+
+    pty.send_line("echo '{\"a\": }' | json get .a; echo status=$?");
+    pty.expect("unexpected character at offset 6");
+    pty.expect("status=2\r\n");
+
+    std::fs::write(pty.home().join("deep.json"), "[".repeat(100_000)).unwrap();
+    pty.send_line("json type . < deep.json; echo status=$?");
+    pty.expect("nested too deeply at offset 128");
+    pty.expect("status=2\r\n");
+}
+
+#[test]
+fn argparse_sets_flag_variables() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line(
+        "f() { argparse -n f -X 2 'h/help' 'v/verbose' 'o/output=' 'I=+' 'dry-run' -- \"$@\" && \
+         echo \"h=$_flag_h v=$_flag_verbose o=$_flag_o I=${_flag_I[*]} d=$_flag_dry_run rest=$*\"; }",
+    );
+    pty.send_line("f -vv -oout.txt -I a --dry-run x -I b -- -y");
+    pty.expect("h= v=2 o=out.txt I=a b d=1 rest=x -y\r\n");
+
+    // The variables are local to the function
+    pty.send_line("f --output=o2 --help; echo flags=$_flag_o$_flag_h");
+    pty.expect("h=1 v= o=o2 I= d= rest=\r\nflags=\r\n");
+
+    pty.send_line("f -q; echo status=$?");
+    pty.expect("f: -q: unknown option\r\n");
+    pty.expect(
+        "usage: f [-h|--help] [-v|--verbose] [-o|--output VALUE] [-I VALUE] [--dry-run]\r\n",
+    );
+    pty.expect("status=1\r\n");
+
+    pty.send_line("f a b c; f --help=x; argparse 'bad spec' -- x; echo status=$?");
+    pty.expect("f: expected at most 2 arguments, got 3\r\n");
+    pty.expect("f: --help: option doesn't take a value\r\n");
+    pty.expect("argparse: bad spec: invalid option spec\r\n");
+    pty.expect("status=2\r\n");
+}
+
+#[test]
+fn range_counts_numbers_and_letters() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("first=1 last=20; range -s , $first $last 5; range -s ' ' 3");
+    pty.expect("1,6,11,16\r\n1 2 3\r\n");
+
+    pty.send_line("range -s / -w 1.5 -0.5 0.5; range -s '' a i 3");
+    pty.expect("01.5/01.0/00.5/00.0/-0.5\r\nadg\r\n");
+
+    // Output is produced as it's read, so an endless-looking range ends early
+    pty.send_line("range 1 1000000000000 | head -n 2 | string join +");
+    pty.expect("1+2\r\n");
+
+    pty.send_line("range 1 5 0; echo status=$?");
+    pty.expect("range: 0: invalid step");
+    pty.expect("status=2\r\n");
+}
+
+#[test]
+fn list_shows_directory_contents() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    let home = pty.home().to_path_buf();
+    std::fs::create_dir(home.join("sub")).unwrap();
+    std::fs::write(home.join("sub/Beta"), "1234").unwrap();
+    std::fs::write(home.join("sub/alpha"), "1").unwrap();
+    std::fs::write(home.join("sub/.hidden"), "").unwrap();
+    std::fs::create_dir(home.join("sub/gamma")).unwrap();
+
+    pty.send_line("list -F sub | string join ,");
+    pty.expect("alpha,Beta,gamma/\r\n");
+
+    pty.send_line("list -1 -a -S sub | string join ,");
+    pty.expect("gamma,Beta,alpha,.hidden\r\n");
+
+    pty.send_line("list --color=always sub/gamma sub/alpha | cat -v");
+    pty.expect("sub/alpha\r\n\r\nsub/gamma:\r\n");
+
+    pty.send_line("list missing; echo status=$?");
+    pty.expect("list: missing: No such file or directory\r\n");
+    pty.expect("status=1\r\n");
+}
+
+#[test]
+fn help_describes_builtins() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("help | string match 'sleep *'");
+    pty.expect("sleep      Wait for the total of the durations.\r\n");
+
+    pty.send_line("help math");
+    pty.expect(
+        "usage: math [-s scale] expression\r\n\r\n    Evaluate a floating-point expression.\r\n",
+    );
+
+    pty.send_line("help strict");
+    pty.expect("usage: sigsh --strict script [arg ...]\r\n\r\n    Run a script");
+    pty.expect("failglob, nounset, pipefail, safeexpand.\r\n");
+
+    pty.send_line("help frobnicate; echo status=$?");
+    pty.expect("help: frobnicate: no such builtin\r\n");
+    pty.expect("status=1\r\n");
+}
+
+#[test]
+fn where_lists_every_resolution_in_order() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = std::env::temp_dir().join(format!("sigsh-test-where-{}", std::process::id()));
+    for dir in ["a", "b"] {
+        let program = root.join(dir).join("tool");
+        std::fs::create_dir_all(program.parent().unwrap()).unwrap();
+        std::fs::write(&program, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let a = root.join("a").display().to_string();
+    let b = root.join("b").display().to_string();
+    let path = format!("{a}:{b}:{a}:/usr/bin:/bin");
+    let mut pty = PtyShell::spawn_with(&[], &[("PATH", &path)]);
+    pty.expect_prompt();
+
+    pty.send_line("tool() { :; }; alias tool='tool -v'; where tool");
+    pty.expect(&format!(
+        "tool: aliased to tool -v\r\ntool: shell function\r\n{a}/tool\r\n{b}/tool\r\n"
+    ));
+
+    pty.send_line("where history nothing-here; echo status=$?");
+    pty.expect("history: shell builtin\r\n");
+    pty.expect("where: nothing-here: not found\r\nstatus=1\r\n");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn exec_opens_the_lowest_free_descriptor() {
+    let mut pty = PtyShell::spawn();
+    std::fs::write(pty.home().join("in.txt"), "one\ntwo\n").unwrap();
+    pty.expect_prompt();
+
+    // Opening the file takes fd 3 itself, as the lowest free one
+    pty.send_line("exec 3< in.txt; mapfile -u 3 lines; echo ${lines[1]}; fdlist");
+    pty.expect("two\r\n3<\tin.txt\r\n");
+    pty.send_line("exec 3>&-; exec 3> out; SIGSH_XTRACEFD=3; set -x; true; set +x; cat out");
+    pty.expect("+ true\r\n+ set +x\r\n");
+
+    // A subshell's are its own
+    pty.send_line("(exec 4> sub); fdlist; echo end");
+    pty.expect("3>\tout\r\nend\r\n");
+}
+
+#[test]
+fn exec_opens_descriptors_for_builtins_only() {
+    let mut pty = PtyShell::spawn();
+    std::fs::write(pty.home().join("in.txt"), "one\ntwo\n").unwrap();
+    pty.expect_prompt();
+
+    pty.send_line("exec 5< in.txt 6>> log; mapfile -u 5 lines; echo ${lines[1]}; fdlist");
+    pty.expect("two\r\n5<\tin.txt\r\n6>>\tlog\r\n");
+
+    // Programs don't inherit them
+    pty.send_line("sh -c 'test -e /dev/fd/5 && echo leaked || echo closed'");
+    pty.expect("closed\r\n");
+
+    pty.send_line("exec 5<&- 6>&-; fdlist; mapfile -u 5 x; echo status=$?");
+    pty.expect("status=1\r\n");
+    pty.send_line("echo hi 3> out; echo status=$?");
+    pty.expect("3: only exec can redirect descriptors past 2\r\nstatus=1\r\n");
+
+    pty.send_line("exec sh -c 'echo replaced; exit 7'");
+    pty.expect("replaced\r\n");
+    assert_eq!(pty.wait_exit(), Some(7));
+}
+
+#[test]
+fn explain_shows_how_a_line_would_run() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("cd; touch a.rs b.rs; n=1");
+    pty.expect_prompt();
+    pty.send_line("explain 'ls *.rs | wc -l >out && echo \"$((n++))\" $(rm a.rs)'");
+    pty.expect("tokens:\r\n  word          ls\r\n  pattern       *.rs\r\n  pipe          |\r\n");
+    pty.expect("  separator     &&\r\n");
+    pty.expect("  substitution  $(rm a.rs)\r\n");
+    pty.expect(
+        "parsed:\r\n  pipeline 1:\r\n    stage 1: ls *.rs\r\n    \
+         stage 2: wc -l >out, fed the stdout of stage 1\r\n  \
+         pipeline 2, if pipeline 1 succeeds:\r\n",
+    );
+    pty.expect("expanded, as things stand now:\r\n  pipeline 1:\r\n    stage 1: program ");
+    pty.expect("      args: ls a.rs b.rs\r\n      stdout: pipe to stage 2\r\n");
+    pty.expect("      stdin: pipe from stage 1\r\n      stdout: out, truncated (>)\r\n");
+    pty.expect("    stage 1: not expanded, as a command substitution would run\r\n");
+    pty.expect_prompt();
+
+    // Nothing ran, and nothing was assigned
+    pty.send_line("explain 'echo $((n++))' >/dev/null; echo n=$n *.rs");
+    pty.expect("n=1 a.rs b.rs\r\n");
+    pty.expect_prompt();
+
+    pty.send_line("explain 'echo \"oops'; echo status=$?");
+    pty.expect("explain: unterminated string literal\r\n");
+    pty.expect("status=2\r\n");
+}
+
+#[test]
+fn choose_picks_from_a_filtered_menu() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("choose -p 'fruit: ' apple banana cherry; echo status=$?");
+    pty.expect_screen("the menu", |screen| {
+        let contents = screen.contents();
+        contents.contains("> apple") && contents.contains("  cherry")
+    });
+    pty.send("an");
+    pty.expect_screen("only banana", |screen| {
+        !screen.contents().contains("  cherry")
+    });
+    pty.send(keys::ENTER);
+    pty.expect("banana\r\nstatus=0");
+
+    // Candidates from a pipe, and the choice captured
+    pty.send_line("x=$(printf 'one\\ntwo\\n' | choose); echo got=$x");
+    pty.expect_screen("the menu", |screen| screen.contents().contains("  two"));
+    pty.send(keys::DOWN);
+    pty.send(keys::ENTER);
+    pty.expect("got=two");
+
+    pty.send_line("choose a b; echo status=$?");
+    pty.expect_screen("the menu", |screen| screen.contents().contains("  b"));
+    pty.send(keys::CTRL_G);
+    pty.expect("status=1");
+}
+
+#[test]
+fn trap_lists_traps_to_set_again() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line(r#"trap 'echo "it'\''s"' ERR; trap '' DEBUG; trap -p ERR"#);
+    pty.expect(r#"trap -- 'echo "it'\''s"' ERR"#);
+    pty.expect_prompt();
+
+    // Subshells list the traps they came from until they set their own
+    pty.send_line(r#"saved=$(trap); echo "[$saved]"; echo "[$(trap x ERR; trap)]""#);
+    pty.expect("[trap -- '' DEBUG\r\ntrap -- 'echo \"it'\\''s\"' ERR]\r\n");
+    pty.expect("[trap -- '' DEBUG\r\ntrap -- 'x' ERR]\r\n");
+    pty.expect_prompt();
+
+    pty.send_line("trap > traps.sh; trap - ERR DEBUG; source traps.sh; trap | wc -l");
+    pty.expect("2\r\n");
+    pty.expect_prompt();
+
+    pty.send_line("trap -l | grep -c SIGINT");
+    pty.expect("1\r\n");
+}
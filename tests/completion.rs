@@ -0,0 +1,289 @@
+#![cfg(unix)]
+
+mod support;
+
+use support::{keys, PtyShell};
+
+#[test]
+fn completes_commands_and_files() {
+    let mut pty = PtyShell::spawn();
+    std::fs::create_dir(pty.home().join("some_dir")).unwrap();
+    std::fs::write(pty.home().join("some_file.txt"), "").unwrap();
+    pty.expect_prompt();
+
+    pty.send("histor");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> history");
+    assert_eq!(pty.screen.cursor().1, "> history ".len());
+
+    pty.send(keys::CTRL_A);
+    pty.send("echo ");
+    pty.send(keys::CTRL_E);
+    pty.send("some_f");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> echo history some_file.txt");
+
+    pty.send("so");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> echo history some_file.txt some_");
+    pty.send(keys::TAB);
+    pty.expect_screen("both candidates listed", |screen| {
+        screen
+            .contents()
+            .lines()
+            .any(|line| line.starts_with("some_dir/") && line.ends_with("some_file.txt"))
+    });
+    pty.send("d");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> echo history some_file.txt some_dir/");
+}
+
+#[test]
+fn completes_from_word_list_spec() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("complete -W 'start stop status' svc");
+    pty.send_line("complete -p svc");
+    pty.expect("complete -W 'start stop status' svc");
+
+    pty.send("svc sta");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> svc sta");
+    pty.send("r");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> svc start");
+}
+
+#[test]
+fn compgen_prints_candidates_of_each_kind() {
+    let mut pty = PtyShell::spawn();
+    std::fs::create_dir(pty.home().join("some_dir")).unwrap();
+    std::fs::write(pty.home().join("some_file.txt"), "").unwrap();
+    pty.expect_prompt();
+
+    pty.send_line("compgen -c histo");
+    pty.expect("history\r\n");
+    pty.send_line("compgen -f some; compgen -d s");
+    pty.expect("some_dir\r\nsome_file.txt\r\nsome_dir\r\n");
+    pty.send_line("some_var=1; compgen -v some_ -W 'some_word other'");
+    pty.expect("some_var\r\nsome_word\r\n");
+    pty.send_line("compgen -W 'a b' z; echo status=$?");
+    pty.expect("status=1");
+}
+
+#[test]
+fn completes_through_bash_completion_scripts() {
+    let mut pty = PtyShell::spawn();
+    let completions = pty.home().join(".local/share/bash-completion/completions");
+    std::fs::create_dir_all(&completions).unwrap();
+    std::fs::write(
+        completions.join("frob"),
+        r#"_frob() {
+    if [ "$COMP_CWORD" -eq 1 ]; then
+        COMPREPLY=($(compgen -W "alpha beta" -- "$2"))
+    else
+        COMPREPLY=($(compgen -W "after-$3" -- "$2"))
+    fi
+}
+complete -F _frob frob
+"#,
+    )
+    .unwrap();
+    pty.expect_prompt();
+
+    pty.send("frob al");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> frob alpha");
+
+    pty.send(keys::TAB);
+    pty.expect_current_line("> frob alpha after-alpha");
+}
+
+#[test]
+fn completion_files_load_the_first_time_theyre_needed() {
+    let mut pty = PtyShell::spawn();
+    let completions = pty.home().join(".config/sigsh/completions");
+    std::fs::create_dir_all(&completions).unwrap();
+    std::fs::write(
+        completions.join("deploy"),
+        "loads=$((loads + 1))\ncomplete -W 'staging production' deploy\n",
+    )
+    .unwrap();
+    pty.expect_prompt();
+
+    // Nothing is loaded until it's needed
+    pty.send_line("echo \"loads=[$loads]\"");
+    pty.expect("loads=[]\r\n");
+    pty.expect_prompt();
+
+    pty.send("deploy st");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> deploy staging");
+    pty.send(keys::CTRL_C);
+    pty.expect_current_line(">");
+    pty.send("deploy p");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> deploy production");
+    pty.send(keys::CTRL_C);
+    pty.expect_current_line(">");
+    pty.send_line("echo \"loads=[$loads]\"");
+    pty.expect("loads=[1]\r\n");
+}
+
+#[test]
+fn command_names_are_cached_until_path_changes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bin = std::env::temp_dir().join(format!("sigsh-test-bin-{}", std::process::id()));
+    std::fs::create_dir_all(&bin).unwrap();
+    let add_program = |name: &str| {
+        let path = bin.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    };
+    add_program("mytool_a");
+
+    let path = format!("{}:/usr/bin:/bin", bin.display());
+    let mut pty = PtyShell::spawn_with(&[], &[("PATH", &path)]);
+    pty.expect_prompt();
+
+    pty.send("mytoo");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> mytool_a");
+
+    // Adding a program changes the directory, so it's scanned again
+    add_program("mytool_b");
+    pty.send(&keys::BACKSPACE.repeat(3));
+    pty.send(keys::TAB);
+    pty.expect_current_line("> mytool_");
+
+    let cache = std::fs::read_to_string(pty.home().join(".cache/sigsh/commands")).unwrap();
+    assert!(cache.contains("\tmytool_b\n"));
+    std::fs::remove_dir_all(&bin).unwrap();
+}
+
+#[test]
+fn completes_cd_indexes_from_directory_history_and_stack() {
+    let mut pty = PtyShell::spawn();
+    let home = pty.home().to_str().unwrap().to_string();
+    std::fs::create_dir_all(pty.home().join("a/b")).unwrap();
+    pty.expect_prompt();
+
+    pty.send_line("cd a; cd b; pushd /; echo \"hist=${cdhist[@]} stack=${dirstack[@]}\"");
+    pty.expect(&format!(
+        "hist={home}/a/b {home}/a {home} stack={home}/a/b\r\n"
+    ));
+    pty.expect_prompt();
+
+    pty.send("cd -");
+    pty.send(keys::TAB);
+    pty.expect_screen("history listed with its indexes", |screen| {
+        let contents = screen.contents();
+        contents.contains(&format!("-0  {home}/a/b")) && contents.contains(&format!("-2  {home}"))
+    });
+    pty.send("1");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> cd -1");
+    assert_eq!(pty.screen.cursor().1, "> cd -1 ".len());
+    pty.send(keys::ENTER);
+    pty.expect(&format!("{home}/a\r\n"));
+    pty.expect_prompt();
+
+    pty.send("cd +");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> cd +0");
+    assert_eq!(pty.screen.cursor().1, "> cd +0 ".len());
+    pty.send(keys::ENTER);
+    pty.expect_prompt();
+    pty.send_line("popd; dirs; echo \"pwd=$PWD\"");
+    pty.expect(&format!("pwd={home}/a/b\r\n"));
+}
+
+#[test]
+fn completes_option_names_for_set_o() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("set -o | grep '^nounset'; set +o | grep noclobber");
+    pty.expect("nounset                 off  Treat expanding an unset variable as an error\r\n");
+    pty.expect("set +o noclobber\r\n");
+    pty.expect_prompt();
+
+    pty.send("set -o glob");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> set -o glob-");
+    pty.send(keys::TAB);
+    pty.expect_screen("options listed with what they do", |screen| {
+        let contents = screen.contents();
+        contents.contains("glob-cache  Keep directories") && contents.contains("glob-qualifiers")
+    });
+    pty.send("q");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> set -o glob-qualifiers");
+    pty.send(keys::ENTER);
+    pty.expect_prompt();
+    pty.send_line("set -o | grep '^glob-qualifiers'");
+    pty.expect("glob-qualifiers         on ");
+}
+
+#[test]
+fn completes_assignments_and_path_lists() {
+    let mut pty = PtyShell::spawn();
+    std::fs::create_dir_all(pty.home().join("tools/bin")).unwrap();
+    std::fs::write(pty.home().join("notes.txt"), "").unwrap();
+    pty.expect_prompt();
+
+    pty.send_line("MY_SETTING_ONE=1; MY_SETTING_TWO=2");
+    pty.expect_prompt();
+
+    // A variable's value is a filename, or for a list, the last directory
+    pty.send("X=no");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> X=notes.txt");
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+    pty.send("PATH=/bin:to");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> PATH=/bin:tools/");
+    pty.send("b");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> PATH=/bin:tools/bin/");
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+
+    // After env, a word may be a variable to set
+    pty.send("env -i MY_SETTING_O");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> env -i MY_SETTING_ONE=");
+    assert_eq!(pty.screen.cursor().1, "> env -i MY_SETTING_ONE=".len());
+    pty.send("1 MY_SETTING_");
+    pty.send(keys::TAB);
+    pty.send(keys::TAB);
+    pty.expect_screen("both variables listed", |screen| {
+        let contents = screen.contents();
+        contents.contains("MY_SETTING_ONE=") && contents.contains("MY_SETTING_TWO=")
+    });
+}
+
+#[test]
+fn autocd_completes_and_changes_into_directories() {
+    let mut pty = PtyShell::spawn();
+    std::fs::create_dir_all(pty.home().join("zqprojects/app")).unwrap();
+    pty.expect_prompt();
+
+    pty.send_line("set -o autocd");
+    pty.expect_prompt();
+    pty.send("zqpro");
+    pty.send(keys::TAB);
+    pty.expect_current_line("> zqprojects/");
+    pty.send(keys::ENTER);
+    pty.expect_prompt();
+    pty.send_line("pwd");
+    pty.expect("zqprojects\r\n");
+    pty.expect_prompt();
+
+    // Without it, a directory is no command
+    pty.send_line("set +o autocd; app; echo status=$?");
+    pty.expect("status=127");
+}
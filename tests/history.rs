@@ -0,0 +1,239 @@
+#![cfg(unix)]
+
+mod support;
+
+use support::{keys, PtyShell};
+
+#[test]
+fn up_recalls_previous_commands() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("echo first");
+    pty.expect("first\r\n");
+    pty.send_line("echo second");
+    pty.expect("second\r\n");
+
+    pty.send(keys::UP);
+    pty.expect_current_line("> echo second");
+    pty.send(keys::UP);
+    pty.expect_current_line("> echo first");
+    pty.send(keys::DOWN);
+    pty.send(keys::DOWN);
+    pty.expect_current_line(">");
+}
+
+#[test]
+fn up_and_down_only_show_commands_starting_with_whats_typed() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    for line in ["echo one", "true", "echo two", "echo two", "false"] {
+        pty.send_line(line);
+        pty.expect_prompt();
+    }
+
+    pty.send("ec");
+    pty.send(keys::UP);
+    pty.expect_current_line("> echo two");
+    // The same command twice running is only shown once
+    pty.send(keys::UP);
+    pty.expect_current_line("> echo one");
+    pty.send(keys::UP);
+    pty.expect_current_line("> echo one");
+    pty.send(keys::DOWN);
+    pty.expect_current_line("> echo two");
+    pty.send(keys::DOWN);
+    pty.expect_current_line("> ec");
+}
+
+#[test]
+fn import_zsh_extended_history() {
+    let mut pty = PtyShell::spawn();
+    std::fs::write(
+        pty.home().join(".zsh_history"),
+        b": 1700000000:0;echo from zsh\n: 1700000005:3;for x in a b\\\ndo echo $x; done\n: 1700000009:0;echo \xe2\x83\xa6\x83\xb2\n",
+    )
+    .unwrap();
+    pty.expect_prompt();
+
+    pty.send_line("history import --from zsh");
+    pty.expect("imported 3 entries");
+
+    let saved = std::fs::read_to_string(pty.home().join(".sigsh_history")).unwrap();
+    assert!(saved.contains("1700000000 echo from zsh\n"));
+    assert!(saved.contains("1700000005 for x in a b\\\ndo echo $x; done\n"));
+    // Metafied bytes are decoded back into the original UTF-8
+    assert!(saved.contains("1700000009 echo \u{2192}\n"));
+}
+
+#[test]
+fn import_bash_history_with_timestamps() {
+    let mut pty = PtyShell::spawn();
+    std::fs::write(
+        pty.home().join("old_history"),
+        "#1600000000\nls -la\ngit status\n",
+    )
+    .unwrap();
+    pty.expect_prompt();
+
+    pty.send_line("history import --from bash old_history");
+    pty.expect("imported 2 entries");
+
+    pty.send(keys::UP);
+    pty.expect_current_line("> git status");
+    pty.send(keys::UP);
+    pty.expect_current_line("> ls -la");
+}
+
+#[test]
+fn history_lists_deletes_and_reruns() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("echo one");
+    pty.expect("one\r\n");
+    pty.send_line("echo two");
+    pty.expect("two\r\n");
+    pty.send_line("history 2");
+    pty.expect("    2  echo two\r\n    3  history 2\r\n");
+
+    pty.send_line("history -d 1; history search echo");
+    pty.expect("    1  echo two\r\n");
+
+    // `fc -s` replaces itself in the history with what it ran
+    pty.send_line("fc -s two=three ec");
+    pty.expect("echo three\r\nthree\r\n");
+    pty.send_line("history 2");
+    pty.expect("    4  echo three\r\n    5  history 2\r\n");
+
+    pty.send_line("history -c; history; fc -s; echo status=$?");
+    pty.expect("status=1\r\n");
+    let saved = std::fs::read_to_string(pty.home().join(".sigsh_history")).unwrap();
+    assert!(!saved.contains("echo"));
+}
+
+#[test]
+fn ctrl_r_searches_back_through_history() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("echo alpha");
+    pty.expect("alpha\r\n");
+    pty.send_line("echo beta");
+    pty.expect("beta\r\n");
+    pty.send_line("echo alphabet");
+    pty.expect("alphabet\r\n");
+    pty.expect_prompt();
+
+    pty.send("ls");
+    pty.send(keys::CTRL_R);
+    pty.send("alp");
+    pty.expect_current_line("(reverse-i-search)`alp': echo alphabet");
+    pty.send(keys::CTRL_R);
+    pty.expect_current_line("(reverse-i-search)`alp': echo alpha");
+    pty.send(keys::CTRL_R);
+    pty.expect_current_line("(failed reverse-i-search)`alp': echo alpha");
+
+    // Cancelling puts back what was typed
+    pty.send(keys::CTRL_G);
+    pty.expect_current_line("> ls");
+
+    pty.send(keys::BACKSPACE);
+    pty.send(keys::BACKSPACE);
+    pty.send(keys::CTRL_R);
+    pty.send("beta");
+    pty.expect_current_line("(reverse-i-search)`beta': echo beta");
+    pty.send(keys::ENTER);
+    pty.expect("beta\r\n");
+}
+
+#[test]
+fn alt_s_toggles_sudo_on_the_last_command() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("echo one");
+    pty.expect("one\r\n");
+    pty.expect_prompt();
+
+    pty.send(keys::ALT_S);
+    pty.expect_current_line("> sudo echo one");
+    pty.send(keys::ALT_S);
+    pty.expect_current_line("> echo one");
+}
+
+#[test]
+fn fc_and_ctrl_x_ctrl_e_edit_commands_in_an_editor() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("FCEDIT='sed -i s/one/two/'");
+    pty.send_line("echo one");
+    pty.expect("one\r\n");
+    pty.send_line("fc");
+    pty.expect("echo two\r\ntwo\r\n");
+    pty.send_line("history 2");
+    pty.expect("    3  echo two\r\n    4  history 2\r\n");
+
+    pty.send("echo one");
+    pty.send(keys::CTRL_X);
+    pty.send(keys::CTRL_E);
+    // What's saved comes back to be looked over before it runs
+    pty.expect_current_line("> echo two");
+    pty.send(keys::ENTER);
+    pty.expect("two\r\n");
+
+    // A failing editor runs nothing
+    pty.send_line("fc -e false; echo status=$?");
+    pty.expect("status=1\r\n");
+}
+
+#[test]
+fn fc_lists_and_edits_ranges() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    for word in ["one", "two", "three"] {
+        pty.send_line(&format!("echo {word}"));
+        pty.expect(&format!("{word}\r\n"));
+    }
+    pty.send_line("fc -l");
+    pty.expect("    1  echo one\r\n    2  echo two\r\n    3  echo three\r\n");
+    pty.send_line("fc -lnr 2 echo\\ t");
+    pty.expect("\techo three\r\n\techo two\r\n");
+    pty.send_line("fc -l 9; echo status=$?");
+    pty.expect("fc: 9: no command found\r\nstatus=1\r\n");
+
+    // Each command in the range is a line of the file, and what's saved
+    // runs as one entry in the history
+    pty.send_line("fc -e 'sed -i s/o$/0/' 1 2");
+    pty.expect("echo one; echo tw0\r\none\r\ntw0\r\n");
+    pty.send_line("fc -l -1");
+    pty.expect("    7  echo one; echo tw0\r\n");
+}
+
+#[test]
+fn multiline_commands_are_recalled_whole() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("echo $(echo one");
+    pty.send_line("echo two)");
+    pty.expect("one two\r\n");
+    pty.expect_prompt();
+
+    pty.send(keys::UP);
+    pty.expect_screen("both lines", |screen| {
+        let (row, _) = screen.cursor();
+        row > 0 && screen.line(row - 1) == "> echo $(echo one" && screen.line(row) == "> echo two)"
+    });
+    // Up moves within the command before going further back
+    pty.send(keys::UP);
+    pty.expect_current_line("> echo $(echo one");
+    pty.send(keys::DOWN);
+    pty.send(" three");
+    pty.send(keys::ENTER);
+    pty.expect("one two three\r\n");
+    pty.expect_prompt();
+}
@@ -0,0 +1,242 @@
+//! End-to-end tests of the interactive line editor and prompt.
+#![cfg(unix)]
+
+mod support;
+
+use support::{keys, PtyShell};
+
+#[test]
+fn prompt_is_rendered() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_current_line(">");
+}
+
+#[test]
+fn cursor_movement_edits_mid_line() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send("echo helo");
+    pty.send(keys::LEFT);
+    pty.send("l");
+    pty.expect_current_line("> echo hello");
+
+    pty.send(keys::CTRL_A);
+    pty.send(keys::RIGHT);
+    pty.send(keys::BACKSPACE);
+    pty.send(keys::CTRL_E);
+    pty.expect_current_line("> cho hello");
+    assert_eq!(pty.screen.cursor().1, "> cho hello".len());
+
+    pty.send(keys::CTRL_A);
+    pty.send("e");
+    pty.send(keys::ENTER);
+    pty.expect("hello\r\n");
+}
+
+#[test]
+fn ctrl_d_on_empty_line_exits() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("false");
+    pty.settle();
+    pty.send(keys::CTRL_D);
+    assert_eq!(pty.wait_exit(), Some(1));
+}
+
+#[test]
+fn ctrl_d_deletes_mid_line() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send("echo abxc");
+    pty.send(keys::LEFT);
+    pty.send(keys::LEFT);
+    pty.send(keys::CTRL_D);
+    pty.expect_current_line("> echo abc");
+    pty.send(keys::ENTER);
+    pty.expect("abc\r\n");
+}
+
+#[test]
+fn ignoreeof_takes_several_ctrl_ds_to_exit() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("set -o ignoreeof; IGNOREEOF=3; echo set");
+    pty.expect("set\r\n");
+    pty.settle();
+    pty.send(keys::CTRL_D);
+    pty.expect("Use \"exit\" to leave the shell.");
+    pty.settle();
+    pty.send(keys::CTRL_D);
+    pty.expect("Use \"exit\" to leave the shell.");
+    pty.settle();
+    pty.send(keys::CTRL_D);
+    assert_eq!(pty.wait_exit(), Some(0));
+}
+
+#[test]
+fn ctrl_c_discards_the_line_and_redraws_the_prompt() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send("echo half typed");
+    pty.send(keys::LEFT);
+    pty.send(keys::CTRL_C);
+    pty.expect("^C");
+    pty.expect_prompt();
+    pty.expect_current_line(">");
+
+    // At an empty prompt too, and in the middle of a history search
+    pty.send(keys::CTRL_C);
+    pty.expect("^C");
+    pty.send(keys::CTRL_R);
+    pty.send("ec");
+    pty.send(keys::CTRL_C);
+    pty.expect("^C");
+    pty.expect_prompt();
+
+    pty.send_line("echo still here");
+    pty.expect("still here\r\n");
+    pty.send_line("history");
+    pty.expect("echo still here");
+}
+
+#[test]
+fn long_lines_wrap_and_rewrap_when_resized() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    let digits = "0123456789".repeat(10);
+    pty.send(&format!("echo {digits}"));
+    pty.expect_screen("the line wrapped", |screen| screen.line(1) == digits[73..]);
+    for _ in 0..30 {
+        pty.send(keys::LEFT);
+    }
+    pty.send("x");
+    let typed = format!("echo {}x{}", &digits[..70], &digits[70..]);
+    let text = format!("> {typed}");
+    pty.expect_screen("x inserted on the first row", |screen| {
+        screen.line(0) == text[..80] && screen.line(1) == text[80..] && screen.cursor() == (0, 78)
+    });
+
+    pty.resize(24, 40);
+    pty.expect_screen("the line rewrapped to 40 columns", |screen| {
+        (0..3).all(|row| screen.line(row) == text[row * 40..(text.len()).min(row * 40 + 40)])
+            && screen.line(3).is_empty()
+            && screen.cursor() == (1, 38)
+    });
+
+    // Ctrl-C leaves the whole line above the new prompt
+    pty.send(keys::CTRL_C);
+    pty.expect_screen("a fresh prompt below the line", |screen| {
+        screen.line(2).ends_with("^C") && screen.current_line() == ">" && screen.cursor().0 == 3
+    });
+
+    pty.send_line(&format!("echo {digits}"));
+    pty.expect(&format!("{digits}\r\n"));
+}
+
+#[test]
+fn autopair_types_closers_with_openers() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+    pty.send_line("set -o autopair");
+    pty.expect_prompt();
+
+    // Closers come along and are typed over, but not inside quotes
+    pty.send("echo \"$(echo 'a b' [x");
+    pty.expect_current_line("> echo \"$(echo 'a b' [x])\"");
+    pty.send("]");
+    pty.expect_current_line("> echo \"$(echo 'a b' [x])\"");
+    pty.send(keys::CTRL_E);
+    pty.send(keys::ENTER);
+    pty.expect("a b [x]\r\n");
+    pty.expect_prompt();
+
+    // Deleting an opener takes its closer with it
+    pty.send("echo [");
+    pty.expect_current_line("> echo []");
+    pty.send(keys::BACKSPACE);
+    pty.send("ok");
+    pty.expect_current_line("> echo ok");
+    pty.send(keys::ENTER);
+    pty.expect("ok\r\n");
+}
+
+#[test]
+fn long_commands_notify_when_they_finish() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+    pty.send_line("precmd() { took=$CMD_DURATION; }; set -o notify-long; NOTIFY_SECONDS=0.3");
+    pty.expect_prompt();
+
+    pty.send_line("sleep 0.4");
+    pty.expect("\x07\x1b]777;notify;sig-systems-shell;sleep 0.4 finished after 0.");
+    pty.expect_prompt();
+    // The time it took is there for precmd
+    pty.send_line("echo long=$((took >= 400))");
+    pty.expect("long=1\r\n");
+    pty.expect_prompt();
+
+    // A hook can send it elsewhere, without touching $?
+    pty.send_line("note() { echo \"note: $1\"; }; NOTIFY_COMMAND=note");
+    pty.expect_prompt();
+    pty.send_line("sleep 0.4 && false");
+    pty.expect("note: sleep 0.4 && false failed with status 1 after 0.");
+    pty.expect_prompt();
+    pty.send_line("echo status=$?");
+    pty.expect("status=1\r\n");
+}
+
+#[test]
+fn report_time_prints_what_commands_used() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+    pty.send_line("set -o report-time; REPORTTIME=0");
+    pty.expect_prompt();
+
+    pty.send_line("sh -c true");
+    pty.expect("sh -c true  ");
+    pty.expect(" total, ");
+    pty.expect(" KB max RSS\r\n");
+    pty.expect_prompt();
+    // And it's kept for the prompt, whether reported or not
+    pty.send_line("set +o report-time; sh -c true");
+    pty.expect_prompt();
+    pty.send_line("echo rss=$((CMD_MAX_RSS > 0)) cpu=$((CMD_USER_TIME >= 0))");
+    pty.expect("rss=1 cpu=1\r\n");
+    pty.expect_prompt();
+    // Builtins alone don't wait for anything
+    pty.send_line("cd .");
+    pty.expect_prompt();
+    pty.send_line("echo rss=\"$CMD_MAX_RSS\"");
+    pty.expect("rss=\r\n");
+}
+
+#[test]
+fn open_substitutions_continue_on_the_next_line() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("PS2='more> '");
+    pty.expect_prompt();
+    pty.send_line("echo $(echo one");
+    pty.expect_current_line("more>");
+    pty.send_line("echo two)");
+    pty.expect("one two\r\n");
+    pty.expect_prompt();
+}
+
+#[test]
+fn redraws_are_synchronized_updates() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send("echo");
+    pty.expect("\x1b[?2026h\r\x1b[J> echo\r\x1b[6C\x1b[?2026l");
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+}
@@ -0,0 +1,278 @@
+//! Job control needs a real terminal, so these drive the shell through the
+//! pty harness.
+#![cfg(unix)]
+
+mod support;
+
+use support::{keys, PtyShell};
+
+#[test]
+fn stop_and_resume_foreground_job() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    // `sleep` itself is a builtin, which can't be stopped
+    pty.send_line("env sleep 30");
+    pty.settle();
+    pty.send(keys::CTRL_Z);
+    pty.expect("Stopped");
+    pty.expect_prompt();
+
+    pty.send_line("jobs");
+    pty.expect("[1]+  Stopped   env sleep 30");
+
+    pty.send_line("fg");
+    pty.expect("env sleep 30");
+    pty.settle();
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+
+    pty.send_line("echo still here");
+    pty.expect("still here");
+}
+
+#[test]
+fn ctrl_c_only_reaches_foreground_job() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("sleep 30");
+    pty.settle();
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+
+    pty.send_line("echo alive");
+    pty.expect("alive");
+}
+
+#[test]
+fn bg_resumes_stopped_job() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    // `sleep` itself is a builtin, which can't be stopped
+    pty.send_line("env sleep 30");
+    pty.settle();
+    pty.send(keys::CTRL_Z);
+    pty.expect("Stopped");
+
+    pty.send_line("bg");
+    pty.expect("[1] env sleep 30 &");
+    pty.send_line("jobs");
+    pty.expect("[1]+  Running   env sleep 30");
+}
+
+#[test]
+fn every_stops_on_ctrl_c() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("every 0.2 echo tick");
+    pty.expect("Every 200ms: echo tick");
+    pty.expect("tick");
+    pty.settle();
+    pty.send(keys::CTRL_C);
+
+    pty.send_line("echo stopped=yes");
+    pty.expect("stopped=yes");
+}
+
+#[test]
+fn sleep_builtin_stops_on_ctrl_c() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("sleep 1m 30s; echo status=$?");
+    pty.settle();
+    pty.send(keys::CTRL_C);
+    pty.expect("status=130\r\n");
+
+    pty.send_line("sleep 0.1s; echo status=$?; sleep 1x");
+    pty.expect("status=0\r\n");
+    pty.expect("sleep: 1x: invalid duration");
+}
+
+#[test]
+fn exit_warns_about_stopped_jobs_first() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("env sleep 30");
+    pty.settle();
+    pty.send(keys::CTRL_Z);
+    pty.expect("Stopped");
+    pty.expect_prompt();
+
+    pty.send_line("exit");
+    pty.expect("There are stopped jobs.");
+    pty.expect_prompt();
+    // Anything else in between means being warned again
+    pty.send_line("true");
+    pty.settle();
+    pty.send(keys::CTRL_D);
+    pty.expect("There are stopped jobs.");
+    pty.settle();
+    pty.send(keys::CTRL_D);
+    pty.wait_exit();
+}
+
+/// Wait for `path` to have `needle` written to it, which a job may only get
+/// round to after the shell has gone.
+fn wait_for_file(path: &std::path::Path, needle: &str) -> String {
+    for _ in 0..50 {
+        if let Ok(contents) = std::fs::read_to_string(path) {
+            if contents.contains(needle) {
+                return contents;
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    panic!("{} never appeared", path.display());
+}
+
+#[test]
+fn huponexit_hangs_up_jobs_except_disowned_ones() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    for name in ["first", "second", "third"] {
+        pty.send_line(&format!(
+            "sh -c 'trap \"echo hup > {name}; exit\" HUP; while :; do env sleep 0.1; done'"
+        ));
+        pty.settle();
+        pty.send(keys::CTRL_Z);
+        pty.expect("Stopped");
+        pty.expect_prompt();
+    }
+
+    // The first keeps running untracked, the second stays listed but is
+    // spared, and only the third is hung up
+    pty.send_line("disown %1; disown -h %2; bg %2; bg %3; jobs");
+    pty.expect("[3]+  Running");
+    pty.send_line("set -o huponexit; exit");
+    pty.wait_exit();
+
+    assert_eq!(wait_for_file(&pty.home().join("third"), "hup"), "hup\n");
+    assert!(!pty.home().join("first").exists());
+    assert!(!pty.home().join("second").exists());
+    let _ = std::process::Command::new("pkill")
+        .args(["-f", "trap \"echo hup > (first|second)"])
+        .status();
+}
+
+#[test]
+fn disown_starts_a_command_like_nohup() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("disown sh -c 'echo detached; tty'");
+    pty.expect("disown: appending output to nohup.out");
+    pty.expect("disown: started process");
+
+    // It's left without a terminal, so nothing can hang it up
+    let output = wait_for_file(&pty.home().join("nohup.out"), "not a tty");
+    assert!(output.starts_with("detached\n"), "{output}");
+
+    pty.send_line("disown no-such-program; echo status=$?");
+    pty.expect("status=127\r\n");
+}
+
+#[test]
+fn spawned_pipelines_run_in_the_background_until_waited_for() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("spawn slow { env sleep 30 | cat; }");
+    pty.expect_prompt();
+    pty.send_line("jobs; echo \"slow=${SPAWNSTATUS[slow]}\"");
+    pty.expect("[1]+  Running   spawn slow");
+    pty.expect("slow=running\r\n");
+    pty.expect_prompt();
+
+    // Ctrl-C is for the foreground only
+    pty.send_line("wait-for slow");
+    pty.settle();
+    pty.send(keys::CTRL_C);
+    pty.send_line("fg");
+    pty.expect("spawn slow");
+    pty.settle();
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+
+    // Those that finish on their own are collected at the prompt
+    pty.send_line("spawn quick ( exit 3 ); spawn quick { true; }");
+    pty.expect("spawn: quick: already running");
+    pty.send_line("env sleep 0.2");
+    pty.expect("Exit 3    spawn quick");
+    pty.expect_prompt();
+    pty.send_line("wait-for quick; echo \"status=$? ${SPAWNSTATUS[quick]}\"");
+    pty.expect("status=3 3\r\n");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn jobs_t_shows_each_jobs_process_tree() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("spawn stuck { env sleep 30 | cat; }");
+    pty.expect_prompt();
+    pty.send_line("env sleep 0.2; jobs -t");
+    pty.expect("[1]+  Running   spawn stuck\r\n");
+    pty.expect("├─ sleep 30\r\n");
+    pty.expect("sleeping     0.00s  └─ cat\r\n");
+    pty.expect_prompt();
+
+    pty.send_line("jobs -x");
+    pty.expect("usage: jobs [-t]");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn activity_shows_what_a_quiet_command_is_doing() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("set -o activity; ACTIVITY_DELAY=0.3");
+    pty.expect_prompt();
+    pty.send_line("env sleep 2; echo done");
+    pty.expect_screen("the command shown waiting", |screen| {
+        screen.contents().contains("· waiting")
+    });
+    pty.expect("done\r\n");
+    pty.expect_prompt();
+    assert!(!pty.screen.contents().contains("· waiting"));
+
+    pty.send_line("env sh -c 'while :; do :; done'");
+    pty.expect_screen("the command shown running", |screen| {
+        screen.contents().contains("% CPU")
+    });
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+}
+
+#[test]
+fn procs_lists_what_the_shell_started() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("env false");
+    pty.expect_prompt();
+    pty.send_line("env sleep 30");
+    pty.settle();
+    pty.send(keys::CTRL_Z);
+    pty.expect("Stopped");
+    pty.expect_prompt();
+
+    pty.send_line("procs -s");
+    pty.expect_screen("the stopped job listed", |screen| {
+        let contents = screen.contents();
+        contents.contains("stopped") && contents.contains("s  env sleep 30")
+    });
+    pty.expect_prompt();
+    assert!(!pty.screen.contents().contains("exit 1"));
+
+    pty.send_line("procs -d");
+    pty.expect("exit 1");
+    pty.expect("env false");
+}
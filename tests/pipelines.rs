@@ -0,0 +1,115 @@
+//! End-to-end tests of pipelines, redirections and the builtins that read
+//! from them.
+#![cfg(unix)]
+
+mod support;
+
+use support::{keys, PtyShell};
+
+#[test]
+fn pipes_and_redirections() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("echo hi there | tr a-z A-Z");
+    pty.expect("HI THERE\r\n");
+
+    pty.send_line("echo one > out; echo two >> out; ls /nonexistent 2> err; echo status=$?");
+    pty.expect("status=2\r\n");
+    let out = std::fs::read_to_string(pty.home().join("out")).unwrap();
+    assert_eq!(out, "one\ntwo\n");
+    let err = std::fs::read_to_string(pty.home().join("err")).unwrap();
+    assert!(err.contains("nonexistent"));
+
+    pty.send_line("tr a-z A-Z < out | cat");
+    pty.expect("ONE\r\nTWO\r\n");
+
+    pty.send_line("ls /nonexistent |& wc -l | tr -d ' '");
+    pty.expect("1\r\n");
+
+    // Builtins and functions work on either side of a pipe
+    pty.send_line("f() { echo from $1; }; f f | cat; alias | wc -l | tr -d ' '");
+    pty.expect("from f\r\n");
+    pty.expect("0\r\n");
+}
+
+#[test]
+fn mapfile_reads_lines_into_an_array() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("printf 'a\\nb b\\nc\\n' | mapfile -t lines; echo status=$?");
+    pty.expect("status=0\r\n");
+    pty.send_line("echo \"${#lines[@]} [${lines[1]}] [${lines[-1]}]\"");
+    pty.expect("3 [b b] [c]\r\n");
+
+    std::fs::write(pty.home().join("input"), "1:2:3:4:5").unwrap();
+    pty.send_line("readarray -d : -t -s 1 -n 2 nums < input; echo \"${nums[@]}\"");
+    pty.expect("2 3\r\n");
+
+    // -O keeps what's before the origin
+    pty.send_line("printf 'x\\ny\\n' | mapfile -t -O 1 nums; echo \"${nums[*]}\"");
+    pty.expect("2 x y\r\n");
+
+    pty.send_line("mapfile -q");
+    pty.expect("mapfile: -q: invalid option");
+}
+
+#[test]
+fn builtin_pipelines_run_in_the_shell() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("string split , a,b,c | string upper | string join +");
+    pty.expect("A+B+C\r\n");
+
+    // Earlier stages are still subshells
+    pty.send_line("x=1; x=2 | string join; cd / | string length; echo x=$x; pwd");
+    pty.expect("x=1\r\n");
+    pty.send_line("pwd | string match -q '/' ; echo root=$?");
+    pty.expect("root=1\r\n");
+
+    pty.send_line("range 3 | string join , | tr , :");
+    pty.expect("1:2:3\r\n");
+
+    pty.send_line("string frob |& string length; echo status=$?");
+    pty.expect("status=0\r\n");
+
+    pty.send_line("exit 3 | string length -q; echo status=$?");
+    pty.expect("status=1\r\n");
+}
+
+#[test]
+fn builtins_that_go_on_writing_stream_into_the_next_stage() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("every 0.1 echo hi | string upper");
+    pty.expect("HI\r\nHI\r\n");
+    pty.send(keys::CTRL_C);
+    pty.expect_prompt();
+
+    pty.send_line("range 8 1000000000000 | string length | string upper | head -n 3");
+    pty.expect("1\r\n1\r\n2\r\n");
+    pty.expect_prompt();
+}
+
+#[test]
+fn color_stderr_marks_what_commands_write_there() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+    pty.send_line("set -o color-stderr");
+    pty.expect_prompt();
+
+    pty.send_line("sh -c 'echo out; echo err >&2' | cat");
+    pty.expect("\x1b[31merr\x1b[0m\r\n");
+
+    pty.send_line("STDERR_COLOR= STDERR_PREFIX='stderr: '; sh -c 'echo a >&2; echo b >&2'");
+    pty.expect("stderr: a\r\nstderr: b\r\n");
+
+    // Only what would have gone to the terminal
+    pty.send_line("sh -c 'echo piped >&2' |& tr a-z A-Z; sh -c 'echo saved >&2' 2> err");
+    pty.expect("PIPED\r\n");
+    pty.send_line("cat err");
+    pty.expect("\r\nsaved\r\n");
+}
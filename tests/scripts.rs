@@ -0,0 +1,235 @@
+//! End-to-end tests of running scripts, and of what they report when they
+//! fail.
+#![cfg(unix)]
+
+mod support;
+
+use support::PtyShell;
+
+#[test]
+fn errors_in_functions_show_the_call_stack() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    let script = pty.home().join("script.sh");
+    std::fs::write(
+        &script,
+        "inner() { echo \"in ${FUNCNAME[@]}\"; nosuch; }\n\
+         outer() { inner; }\n\
+         \n\
+         outer\n\
+         set -e\n\
+         outer() { inner; false; }\n\
+         false && true; outer\n",
+    )
+    .unwrap();
+    let script = script.display();
+
+    pty.send_line(&format!("source {script}"));
+    pty.expect("in inner outer\r\n");
+    pty.expect(&format!(
+        "{script}:1: nosuch: command not found\r\n  \
+         in inner called at {script}:2\r\n  \
+         in outer called at {script}:4\r\n"
+    ));
+
+    // Under `set -e` the first failure ends it, saying where that was
+    pty.expect(&format!(
+        "{script}:1: nosuch: command not found\r\n  \
+         in inner called at {script}:6\r\n  \
+         in outer called at {script}:7\r\n"
+    ));
+    assert_eq!(pty.wait_exit(), Some(127));
+}
+
+#[test]
+fn set_e_says_where_it_stopped() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    let script = pty.home().join("script.sh");
+    std::fs::write(&script, "f() { false; }\nset -e\nf\necho unreachable\n").unwrap();
+    let script = script.display();
+
+    pty.send_line(&format!("source {script}"));
+    pty.expect(&format!(
+        "{script}:1: exiting with status 1 (set -e)\r\n  \
+         in f called at {script}:3\r\n"
+    ));
+    assert_eq!(pty.wait_exit(), Some(1));
+}
+
+#[test]
+fn set_e_leaves_saying_so_to_the_outer_shell() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    let script = pty.home().join("script.sh");
+    std::fs::write(
+        &script,
+        "set -e\necho \"[$(false)]\"\n(ls /nonexistent 2>/dev/null; echo no)\necho unreachable\n",
+    )
+    .unwrap();
+    let script = script.display();
+
+    pty.send_line(&format!("source {script}"));
+    pty.expect(&format!(
+        "[]\r\n{script}:3: exiting with status 2 (set -e)\r\n"
+    ));
+    assert_eq!(pty.wait_exit(), Some(2));
+    let screen = pty.screen.contents();
+    assert_eq!(screen.matches("(set -e)").count(), 1);
+    assert!(!screen.contains("unreachable"));
+}
+
+#[test]
+fn debugger_steps_through_a_script() {
+    let home = std::env::temp_dir().join(format!("sigdb-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let script = home.join("script.sh");
+    std::fs::write(
+        &script,
+        "greet() { echo \"hello $1\"; echo bye; }\n\
+         x=1\n\
+         greet $x\n\
+         echo \"x is $x\"\n\
+         greet again\n\
+         echo done\n",
+    )
+    .unwrap();
+    let path = script.display().to_string();
+    let mut pty = PtyShell::spawn_with(&["--debug", &path], &[]);
+
+    // It stops before the first command, showing it expanded
+    pty.expect(&format!("{path}:2: x=1\r\n(sigdb) "));
+    pty.send_line("next");
+    pty.expect(&format!("{path}:3: greet 1\r\n(sigdb) "));
+
+    pty.send_line("p x");
+    pty.expect("x=1\r\n(sigdb) ");
+    pty.send_line("e x=42");
+    pty.send_line("b greet");
+    pty.send_line("c");
+    pty.expect(&format!("{path}:1: echo hello 1\r\n(sigdb) "));
+    pty.send_line("where");
+    pty.expect(&format!("in greet called at {path}:3\r\n(sigdb) "));
+
+    // An empty line steps over the rest again
+    pty.send_line("n");
+    pty.expect(&format!("hello 1\r\n{path}:1: echo bye\r\n(sigdb) "));
+    pty.send_line("");
+    pty.expect(&format!("bye\r\n{path}:4: echo x is 42\r\n(sigdb) "));
+
+    pty.send_line("c");
+    pty.expect(&format!(
+        "x is 42\r\n{path}:1: echo hello again\r\n(sigdb) "
+    ));
+    pty.send_line("q");
+    assert_eq!(pty.wait_exit(), Some(1));
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn dry_run_prints_commands_without_running_them() {
+    let home = std::env::temp_dir().join(format!("dry-run-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let script = home.join("script.sh");
+    let victim = home.join("victim");
+    std::fs::write(&victim, "").unwrap();
+    std::fs::write(
+        &script,
+        format!(
+            "dir=\"my files\"\n\
+             rm -rf \"$dir\" {victim} >/dev/null 2>> err.log\n\
+             x=$(echo inside) echo \"$x\" | grep -v x >| out\n\
+             echo ok && cd /\n",
+            victim = victim.display()
+        ),
+    )
+    .unwrap();
+    let path = script.display().to_string();
+    let mut pty = PtyShell::spawn_with(&["--dry-run", &path], &[]);
+
+    pty.expect(&format!(
+        "rm -rf 'my files' {} >/dev/null 2>>err.log\r\n",
+        victim.display()
+    ));
+    // A substitution's commands are shown, and it expands to nothing
+    pty.expect("echo inside\r\nx='' echo '' | grep -v x >|out\r\n");
+    pty.expect("echo ok\r\ncd /\r\n");
+    assert_eq!(pty.wait_exit(), Some(0));
+    assert!(victim.exists());
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn strict_stops_scripts_at_the_first_failure() {
+    let home = std::env::temp_dir().join(format!("strict-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let script = home.join("script.sh");
+    std::fs::write(
+        &script,
+        "set -o | grep -c '^[a-z-]* *on '\n\
+         x='a b'; printf '<%s>' $x; echo\n\
+         false | true\n\
+         echo not reached\n",
+    )
+    .unwrap();
+    let path = script.display().to_string();
+    let mut pty = PtyShell::spawn_with(&["--strict", &path], &[]);
+
+    pty.expect("6\r\n<a b>\r\n");
+    pty.expect(&format!("{path}:3: exiting with status 1 (set -e)"));
+    assert_eq!(pty.wait_exit(), Some(1));
+    assert!(!pty.screen.contents().contains("not reached"));
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn exported_functions_reach_scripts() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    let script = pty.home().join("child.sh");
+    std::fs::write(&script, "greet world; hidden\n").unwrap();
+    let sigsh = env!("CARGO_BIN_EXE_sig-systems-shell");
+
+    pty.send_line("greet() { echo \"hi $1\" | tr a-z A-Z; }; hidden() { echo no; }");
+    pty.send_line("export -f greet; export -f");
+    pty.expect("export -f greet\r\n");
+    pty.send_line(&format!("{sigsh} {}", script.display()));
+    pty.expect("HI WORLD\r\n");
+    pty.expect("hidden: command not found\r\n");
+
+    // Only a function body is taken in, never a command after it
+    pty.send_line(&format!(
+        "SIGSH_FUNCTION_f='{{ :; }}; echo ran' {sigsh} {}; echo done",
+        script.display()
+    ));
+    pty.expect("HI WORLD\r\n");
+    pty.expect("hidden: command not found\r\ndone\r\n");
+}
+
+#[test]
+fn commands_killed_by_signals_say_which() {
+    let mut pty = PtyShell::spawn();
+    pty.expect_prompt();
+
+    pty.send_line("sh -c 'kill -TERM $$'; echo status=$?");
+    pty.expect("sh -c kill -TERM $$: terminated by SIGTERM\r\n");
+    pty.expect("status=143\r\n");
+    pty.expect_prompt();
+
+    pty.send_line("sh -c 'ulimit -c 0; kill -SEGV $$'; echo status=$?");
+    pty.expect("$$: terminated by SIGSEGV\r\n");
+    pty.expect("status=139\r\n");
+    pty.expect_prompt();
+
+    // Nothing is said of Ctrl-C
+    pty.send_line("sh -c 'kill -INT $$'; echo status=$?");
+    pty.expect("status=130\r\n");
+    assert!(!pty.screen.contents().contains("SIGINT"));
+}
@@ -0,0 +1,405 @@
+//! Test harness which runs the shell on a pseudo-terminal, so tests can type
+//! at it like a user would and check what ends up on the screen.
+//!
+//! Besides the raw output stream, everything the shell prints is fed through a
+//! small VT emulator, enough to follow the editor's cursor movement and line
+//! erasing, so assertions can be made against what's actually visible.
+#![allow(dead_code)]
+
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub mod keys {
+    pub const CTRL_A: &str = "\x01";
+    pub const CTRL_C: &str = "\x03";
+    pub const CTRL_D: &str = "\x04";
+    pub const CTRL_E: &str = "\x05";
+    pub const CTRL_G: &str = "\x07";
+    pub const CTRL_R: &str = "\x12";
+    pub const CTRL_X: &str = "\x18";
+    pub const CTRL_Z: &str = "\x1a";
+    pub const TAB: &str = "\t";
+    pub const ENTER: &str = "\r";
+    pub const BACKSPACE: &str = "\x7f";
+    pub const UP: &str = "\x1b[A";
+    pub const DOWN: &str = "\x1b[B";
+    pub const RIGHT: &str = "\x1b[C";
+    pub const LEFT: &str = "\x1b[D";
+    pub const ALT_S: &str = "\x1bs";
+}
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const ROWS: usize = 24;
+pub const COLS: usize = 80;
+
+/// Just enough of a terminal to replay the shell's output onto.
+pub struct Screen {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Vec<char>>,
+    row: usize,
+    col: usize,
+    pending_escape: String,
+}
+
+impl Screen {
+    fn new(rows: usize, cols: usize) -> Screen {
+        Screen {
+            rows,
+            cols,
+            cells: vec![vec![' '; cols]; rows],
+            row: 0,
+            col: 0,
+            pending_escape: String::new(),
+        }
+    }
+
+    fn feed(&mut self, text: &str) {
+        for c in text.chars() {
+            if !self.pending_escape.is_empty() {
+                self.pending_escape.push(c);
+                if self.escape_complete() {
+                    let escape = std::mem::take(&mut self.pending_escape);
+                    self.apply_escape(&escape);
+                }
+                continue;
+            }
+
+            match c {
+                '\x1b' => self.pending_escape.push(c),
+                '\r' => self.col = 0,
+                '\n' => self.line_feed(),
+                '\x08' => self.col = self.col.saturating_sub(1),
+                '\x07' => (),
+                c => {
+                    if self.col >= self.cols {
+                        self.col = 0;
+                        self.line_feed();
+                    }
+                    self.cells[self.row][self.col] = c;
+                    self.col += 1;
+                }
+            }
+        }
+    }
+
+    fn escape_complete(&self) -> bool {
+        let mut chars = self.pending_escape.chars().skip(1);
+        match chars.next() {
+            None => false,
+            // CSI: parameters then a final byte in '@'..='~'
+            Some('[') => chars.any(|c| ('@'..='~').contains(&c)),
+            // OSC: terminated by BEL or ST
+            Some(']') => {
+                self.pending_escape.ends_with('\x07') || self.pending_escape.ends_with("\x1b\\")
+            }
+            Some(_) => true,
+        }
+    }
+
+    fn apply_escape(&mut self, escape: &str) {
+        let Some(body) = escape.strip_prefix("\x1b[") else {
+            return;
+        };
+        let Some(command) = body.chars().last() else {
+            return;
+        };
+        let params: Vec<usize> = body[..body.len() - 1]
+            .trim_start_matches('?')
+            .split(';')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect();
+        let n = params.first().copied().filter(|&n| n > 0).unwrap_or(1);
+
+        match command {
+            'A' => self.row = self.row.saturating_sub(n),
+            'B' => self.row = (self.row + n).min(self.rows - 1),
+            'C' => self.col = (self.col + n).min(self.cols - 1),
+            'D' => self.col = self.col.saturating_sub(n),
+            'G' => self.col = (n - 1).min(self.cols - 1),
+            'H' => {
+                self.row = (n - 1).min(self.rows - 1);
+                self.col = (params.get(1).copied().unwrap_or(1).max(1) - 1).min(self.cols - 1);
+            }
+            'J' => {
+                let from = if params[0] == 2 { 0 } else { self.row + 1 };
+                for row in &mut self.cells[from..] {
+                    row.fill(' ');
+                }
+                if params[0] != 2 {
+                    self.cells[self.row][self.col.min(self.cols)..].fill(' ');
+                }
+            }
+            'K' => {
+                let col = self.col.min(self.cols);
+                self.cells[self.row][col..].fill(' ');
+            }
+            // Colors, mode switches and the like don't change the text
+            _ => (),
+        }
+    }
+
+    /// Change size like xterm does: rows and columns are cut off or padded
+    /// where they are, without rewrapping.
+    fn resize(&mut self, rows: usize, cols: usize) {
+        for row in &mut self.cells {
+            row.resize(cols, ' ');
+        }
+        self.cells.resize(rows, vec![' '; cols]);
+        self.rows = rows;
+        self.cols = cols;
+        self.row = self.row.min(rows - 1);
+        self.col = self.col.min(cols);
+    }
+
+    fn line_feed(&mut self) {
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.cells.remove(0);
+            self.cells.push(vec![' '; self.cols]);
+        }
+    }
+
+    pub fn line(&self, row: usize) -> String {
+        self.cells[row]
+            .iter()
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    /// The line the cursor is on.
+    pub fn current_line(&self) -> String {
+        self.line(self.row)
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    pub fn contents(&self) -> String {
+        (0..self.rows)
+            .map(|row| self.line(row))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end()
+            .to_string()
+    }
+}
+
+/// A running shell attached to a pseudo-terminal.
+pub struct PtyShell {
+    master: File,
+    child: Child,
+    home: PathBuf,
+    /// Output not yet consumed by `expect`.
+    output: String,
+    pub screen: Screen,
+}
+
+static NEXT_HOME: AtomicUsize = AtomicUsize::new(0);
+
+impl PtyShell {
+    pub fn spawn() -> PtyShell {
+        PtyShell::spawn_with(&[], &[])
+    }
+
+    /// Start the shell with extra arguments and environment variables. Each
+    /// shell gets its own empty `HOME`, so tests can't see each other's (or the
+    /// developer's) history, config and caches.
+    pub fn spawn_with(args: &[&str], env: &[(&str, &str)]) -> PtyShell {
+        let home = std::env::temp_dir().join(format!(
+            "sigsh-test-{}-{}",
+            std::process::id(),
+            NEXT_HOME.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(&home).unwrap();
+
+        let master = unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0, "posix_openpt failed");
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+            // Or the shell inherits it, on the lowest free descriptor
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            File::from_raw_fd(fd)
+        };
+        set_window_size(&master, ROWS, COLS);
+
+        let slave_name = unsafe { CStr::from_ptr(libc::ptsname(master.as_raw_fd())) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(slave_name)
+            .unwrap();
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_sig-systems-shell"));
+        command
+            .args(args)
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_CACHE_HOME")
+            .env("TERM", "xterm")
+            .envs(env.iter().copied())
+            .current_dir(&home)
+            .stdin(Stdio::from(slave.try_clone().unwrap()))
+            .stdout(Stdio::from(slave.try_clone().unwrap()))
+            .stderr(Stdio::from(slave));
+        unsafe {
+            command.pre_exec(|| {
+                // New session with the pty as its controlling terminal
+                libc::setsid();
+                libc::ioctl(0, libc::TIOCSCTTY as _, 0);
+                Ok(())
+            });
+        }
+
+        unsafe { libc::fcntl(master.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+
+        PtyShell {
+            master,
+            child: command.spawn().unwrap(),
+            home,
+            output: String::new(),
+            screen: Screen::new(ROWS, COLS),
+        }
+    }
+
+    /// The shell's private `HOME` directory, which is also where it starts.
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    pub fn send(&mut self, keys: &str) {
+        self.master.write_all(keys.as_bytes()).unwrap();
+    }
+
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.send(keys::ENTER);
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.screen.resize(rows, cols);
+        set_window_size(&self.master, rows, cols);
+    }
+
+    /// Read whatever output is available right now.
+    fn pump(&mut self) -> bool {
+        let mut buf = [0u8; 4096];
+        match self.master.read(&mut buf) {
+            Ok(n) if n > 0 => {
+                let text = String::from_utf8_lossy(&buf[..n]).into_owned();
+                self.screen.feed(&text);
+                self.output.push_str(&text);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Wait until `needle` shows up in the output after everything already
+    /// matched, panicking with the output so far if it doesn't.
+    pub fn expect(&mut self, needle: &str) {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(index) = self.output.find(needle) {
+                self.output.drain(..index + needle.len());
+                return;
+            }
+            if Instant::now() > deadline {
+                panic!("timed out waiting for {needle:?}, got {:?}", self.output);
+            }
+            if !self.pump() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    pub fn expect_prompt(&mut self) {
+        self.expect("> ");
+    }
+
+    /// Wait until the screen satisfies `check`.
+    pub fn expect_screen(&mut self, what: &str, check: impl Fn(&Screen) -> bool) {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if check(&self.screen) {
+                return;
+            }
+            if Instant::now() > deadline {
+                panic!(
+                    "timed out waiting for screen to show {what}, screen was:\n{}",
+                    self.screen.contents()
+                );
+            }
+            if !self.pump() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    /// Wait until the line the cursor is on reads exactly `line`.
+    pub fn expect_current_line(&mut self, line: &str) {
+        self.expect_screen(&format!("{line:?} on the cursor line"), |screen| {
+            screen.current_line() == line
+        });
+    }
+
+    /// Let the shell settle for a moment, e.g. so a command has started
+    /// before a signal is sent to it.
+    pub fn settle(&mut self) {
+        let until = Instant::now() + Duration::from_millis(200);
+        while Instant::now() < until {
+            if !self.pump() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    /// Wait for the shell to exit, returning its exit code.
+    pub fn wait_exit(&mut self) -> Option<i32> {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            self.pump();
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.code();
+            }
+            if Instant::now() > deadline {
+                panic!("shell didn't exit, output: {:?}", self.output);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for PtyShell {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.home);
+    }
+}
+
+fn set_window_size(master: &File, rows: usize, cols: usize) {
+    let size = libc::winsize {
+        ws_row: rows as u16,
+        ws_col: cols as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+}
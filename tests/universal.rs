@@ -0,0 +1,119 @@
+#![cfg(unix)]
+
+mod support;
+
+use support::PtyShell;
+
+#[test]
+fn universal_variables_reach_other_sessions() {
+    let mut first = PtyShell::spawn();
+    // Point the other sessions at the first one's `~/.config`
+    let config = first.home().join(".config");
+    let config = config.to_str().unwrap().to_string();
+    let mut second = PtyShell::spawn_with(&[], &[("XDG_CONFIG_HOME", &config)]);
+
+    first.expect_prompt();
+    second.expect_prompt();
+
+    // Wait for output rather than the prompt, which is redrawn as we type
+    first.send_line("set -U EDITOR 'vim -u NONE'; echo set=$?");
+    first.expect("set=0\r\n");
+
+    second.send_line("echo \"editor=$EDITOR\"");
+    second.expect("editor=vim -u NONE\r\n");
+    second.send_line("sh -c 'echo exported=$EDITOR'");
+    second.expect("exported=vim -u NONE\r\n");
+
+    second.send_line("set -U -e EDITOR; echo erased=$?");
+    second.expect("erased=0\r\n");
+    first.send_line("echo \"editor=$EDITOR.\"");
+    first.expect("editor=.\r\n");
+
+    // A new session starts out with whatever's left
+    first.send_line("set -U PAGER less; echo set=$?");
+    first.expect("set=0\r\n");
+    let mut third = PtyShell::spawn_with(&[], &[("XDG_CONFIG_HOME", &config)]);
+    third.expect_prompt();
+    third.send_line("echo \"pager=$PAGER\"");
+    third.expect("pager=less\r\n");
+    third.send_line("set -U");
+    third.expect("set -U PAGER 'less'\r\n");
+
+    third.send_line("set -U 'a=b' c; set -U '' q; echo status=$?");
+    third.expect("set: `a=b': not a valid identifier\r\n");
+    third.expect("set: `': not a valid identifier\r\nstatus=1\r\n");
+}
+
+#[test]
+fn setting_a_universal_variable_picks_up_what_others_just_set() {
+    let home = std::env::temp_dir().join(format!("universal-just-set-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let sigsh = env!("CARGO_BIN_EXE_sig-systems-shell");
+    let script = |name: &str, line: &str| {
+        let script = home.join(name);
+        std::fs::write(&script, line).unwrap();
+        let mut command = std::process::Command::new(sigsh);
+        command
+            .arg(script)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .stdout(std::process::Stdio::piped());
+        command
+    };
+
+    // The first session's line is already running when the second sets B
+    let (ready, go) = (home.join("ready"), home.join("go"));
+    let first = script(
+        "first.sh",
+        &format!(
+            "touch {}; sh -c 'while [ ! -e {} ]; do sleep 0.05; done'; set -U A 1; \
+             echo \"b=$B.\"\n",
+            ready.display(),
+            go.display()
+        ),
+    )
+    .spawn()
+    .unwrap();
+    while !ready.exists() {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    let second = script("second.sh", "set -U B 2\n").status().unwrap();
+    assert!(second.success());
+    std::fs::write(&go, "").unwrap();
+
+    let output = first.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "b=2.\n");
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn sessions_setting_universal_variables_at_once_keep_them_all() {
+    let home = std::env::temp_dir().join(format!("universal-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let sigsh = env!("CARGO_BIN_EXE_sig-systems-shell");
+
+    let sessions: Vec<_> = ["A", "B"]
+        .iter()
+        .map(|prefix| {
+            let script = home.join(format!("{prefix}.sh"));
+            let lines: String = (0..100)
+                .map(|i| format!("set -U {prefix}_{i} x\n"))
+                .collect();
+            std::fs::write(&script, lines).unwrap();
+            std::process::Command::new(sigsh)
+                .arg(&script)
+                .env("XDG_CONFIG_HOME", home.join(".config"))
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut session in sessions {
+        assert!(session.wait().unwrap().success());
+    }
+
+    let file = home.join(".config/sigsh/universal_variables");
+    let contents = std::fs::read_to_string(file).unwrap();
+    assert_eq!(contents.lines().count(), 200);
+
+    std::fs::remove_dir_all(&home).unwrap();
+}